@@ -27,4 +27,7 @@ cargo test --manifest-path ./embassy-stm32/Cargo.toml --no-default-features --fe
 cargo test --manifest-path ./embassy-stm32/Cargo.toml --no-default-features --features stm32f732ze,exti,time-driver-any,exti
 cargo test --manifest-path ./embassy-stm32/Cargo.toml --no-default-features --features stm32f769ni,exti,time-driver-any,exti
 
+cargo test --manifest-path ./embassy-stm32-wpan/Cargo.toml --no-default-features --features stm32wb55rg,ble,gatt-server,l2cap-signaling,periodic-adv,hci-capture
+cargo test --manifest-path ./embassy-stm32-wpan/Cargo.toml --no-default-features --features stm32wb55rg,zigbee
+
 cargo test --manifest-path ./embassy-net-adin1110/Cargo.toml
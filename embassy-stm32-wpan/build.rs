@@ -17,6 +17,10 @@ fn main() {
     // ========
     // stm32wb tl_mbox link sections
 
+    if env::var_os("CARGO_FEATURE_CUSTOM_LINKER_SCRIPT").is_some() {
+        return;
+    }
+
     let out_file = out_dir.join("tl_mbox.x").to_string_lossy().to_string();
     let in_file;
     if env::var_os("CARGO_FEATURE_EXTENDED").is_some() {
@@ -0,0 +1,706 @@
+//! Fragmentation and reassembly of L2CAP PDUs carried over HCI ACL data packets
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use super::consts::L2CAP_BASIC_HEADER_SIZE;
+use crate::consts::TL_ACL_DATA_MAX_PAYLOAD_SIZE;
+
+/// Size of the HCI ACL data header: handle and flags, then data length
+pub const ACL_HEADER_SIZE: usize = 4;
+
+pub(crate) const HANDLE_MASK: u16 = 0x0FFF;
+
+/// Packet boundary flag of an HCI ACL data packet
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PacketBoundary {
+    /// First fragment of a PDU, sent from the host
+    FirstNonFlushable = 0b00,
+    /// Continuation of a PDU
+    Continuing = 0b01,
+    /// First fragment of a PDU, as received from the controller
+    FirstFlushable = 0b10,
+}
+
+impl PacketBoundary {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0b00 => Some(Self::FirstNonFlushable),
+            0b01 => Some(Self::Continuing),
+            0b10 => Some(Self::FirstFlushable),
+            _ => None,
+        }
+    }
+}
+
+/// Combine a connection handle and a packet boundary flag into the first field of an ACL data packet
+pub const fn pack_handle(handle: u16, boundary: PacketBoundary) -> u16 {
+    (handle & HANDLE_MASK) | ((boundary as u16) << 12)
+}
+
+/// Split the first field of an ACL data packet into a connection handle and a packet boundary flag
+pub fn unpack_handle(field: u16) -> Option<(u16, PacketBoundary)> {
+    PacketBoundary::from_bits(((field >> 12) & 0b11) as u8).map(|boundary| (field & HANDLE_MASK, boundary))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AclError {
+    /// The packet is shorter than its header or than the length it announces
+    Malformed,
+    /// A continuation fragment was received on a connection with no PDU in progress
+    UnexpectedContinuation { handle: u16 },
+    /// The PDU does not fit in the reassembly buffer and was dropped
+    Overflow { handle: u16 },
+    /// All the reassembly buffers are in use by other connections
+    NoFreeBuffer { handle: u16 },
+    /// The payload does not fit in the ACL data buffer shared with CPU2
+    TooLong,
+}
+
+/// HCI ACL data packet, as sent to or received from the controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AclPacket<'a> {
+    handle: u16,
+    boundary: PacketBoundary,
+    broadcast: u8,
+    payload: &'a [u8],
+}
+
+impl<'a> AclPacket<'a> {
+    /// Packet to send on a connection
+    ///
+    /// Returns [`AclError::TooLong`] if `payload` does not fit in the ACL data buffer.
+    pub fn new(handle: u16, boundary: PacketBoundary, payload: &'a [u8]) -> Result<Self, AclError> {
+        if payload.len() > TL_ACL_DATA_MAX_PAYLOAD_SIZE {
+            return Err(AclError::TooLong);
+        }
+
+        Ok(Self {
+            handle: handle & HANDLE_MASK,
+            boundary,
+            broadcast: 0,
+            payload,
+        })
+    }
+
+    /// Decode a received packet, starting with its handle field
+    ///
+    /// Bytes following the length announced in the header are ignored.
+    pub fn parse(packet: &'a [u8]) -> Result<Self, AclError> {
+        if packet.len() < ACL_HEADER_SIZE {
+            return Err(AclError::Malformed);
+        }
+
+        let field = u16::from_le_bytes([packet[0], packet[1]]);
+        let (handle, boundary) = unpack_handle(field).ok_or(AclError::Malformed)?;
+        let len = u16::from_le_bytes([packet[2], packet[3]]) as usize;
+
+        Ok(Self {
+            handle,
+            boundary,
+            broadcast: (field >> 14) as u8,
+            payload: packet
+                .get(ACL_HEADER_SIZE..ACL_HEADER_SIZE + len)
+                .ok_or(AclError::Malformed)?,
+        })
+    }
+
+    /// Connection handle, without the flags
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    pub fn boundary(&self) -> PacketBoundary {
+        self.boundary
+    }
+
+    /// Broadcast flag, always 0 on LE connections
+    pub fn broadcast(&self) -> u8 {
+        self.broadcast
+    }
+
+    /// First field of the packet: the connection handle with the packet boundary and broadcast flags
+    pub fn handle_field(&self) -> u16 {
+        pack_handle(self.handle, self.boundary) | ((self.broadcast as u16) << 14)
+    }
+
+    /// Data of the packet, without the header
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+}
+
+/// Iterator over the ACL fragments of an outgoing L2CAP PDU
+///
+/// Each item is the packet boundary flag and data of one ACL data packet.
+pub struct Fragments<'a> {
+    chunks: core::slice::Chunks<'a, u8>,
+    first: bool,
+}
+
+impl<'a> Fragments<'a> {
+    /// `max_len` is the ACL data packet length reported by `HCI_LE_Read_Buffer_Size`
+    pub fn new(pdu: &'a [u8], max_len: usize) -> Self {
+        assert!(max_len > 0);
+
+        Self {
+            chunks: pdu.chunks(max_len),
+            first: true,
+        }
+    }
+}
+
+impl<'a> Iterator for Fragments<'a> {
+    type Item = (PacketBoundary, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.chunks.next()?;
+        let boundary = if self.first {
+            PacketBoundary::FirstNonFlushable
+        } else {
+            PacketBoundary::Continuing
+        };
+        self.first = false;
+
+        Some((boundary, chunk))
+    }
+}
+
+/// A complete L2CAP frame received on a connection
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct L2capFrame<'a> {
+    pub handle: u16,
+    pub channel_id: u16,
+    /// Information payload, without the basic L2CAP header
+    pub payload: &'a [u8],
+}
+
+#[derive(Clone, Copy)]
+struct Slot<const N: usize> {
+    handle: Option<u16>,
+    len: usize,
+    expected: usize,
+    buf: [u8; N],
+}
+
+impl<const N: usize> Slot<N> {
+    const EMPTY: Self = Self {
+        handle: None,
+        len: 0,
+        expected: 0,
+        buf: [0; N],
+    };
+}
+
+/// Reassembles incoming ACL fragments into L2CAP frames
+///
+/// `N` is the largest L2CAP frame accepted, header included, and `C` the number of connections
+/// that can have a frame in progress at the same time.
+pub struct Reassembler<const N: usize, const C: usize> {
+    slots: [Slot<N>; C],
+}
+
+impl<const N: usize, const C: usize> Reassembler<N, C> {
+    pub const fn new() -> Self {
+        Self {
+            slots: [Slot::EMPTY; C],
+        }
+    }
+
+    /// Drop the frame in progress on a connection, e.g. after a disconnection
+    pub fn reset(&mut self, handle: u16) {
+        if let Some(slot) = self.slots.iter_mut().find(|slot| slot.handle == Some(handle)) {
+            *slot = Slot::EMPTY;
+        }
+    }
+
+    /// Feed a received HCI ACL data packet, starting with its handle field
+    ///
+    /// Returns the L2CAP frame once its last fragment has been received. On error, the frame in
+    /// progress on the connection is dropped.
+    pub fn push(&mut self, packet: &[u8]) -> Result<Option<L2capFrame<'_>>, AclError> {
+        let packet = AclPacket::parse(packet)?;
+        let (handle, boundary, data) = (packet.handle, packet.boundary, packet.payload);
+
+        let index = match boundary {
+            PacketBoundary::Continuing => self
+                .slots
+                .iter()
+                .position(|slot| slot.handle == Some(handle))
+                .ok_or(AclError::UnexpectedContinuation { handle })?,
+            _ => {
+                // a new start fragment replaces any frame left incomplete on the connection
+                self.reset(handle);
+
+                let index = self
+                    .slots
+                    .iter()
+                    .position(|slot| slot.handle.is_none())
+                    .ok_or(AclError::NoFreeBuffer { handle })?;
+                self.slots[index].handle = Some(handle);
+
+                index
+            }
+        };
+
+        let slot = &mut self.slots[index];
+        if slot.len + data.len() > N {
+            warn!(
+                "acl: frame on handle {:x} exceeds the reassembly buffer, dropping it",
+                handle
+            );
+            *slot = Slot::EMPTY;
+
+            return Err(AclError::Overflow { handle });
+        }
+
+        slot.buf[slot.len..slot.len + data.len()].copy_from_slice(data);
+        slot.len += data.len();
+
+        if slot.expected == 0 && slot.len >= L2CAP_BASIC_HEADER_SIZE {
+            slot.expected = u16::from_le_bytes([slot.buf[0], slot.buf[1]]) as usize + L2CAP_BASIC_HEADER_SIZE;
+            if slot.expected > N {
+                warn!(
+                    "acl: frame on handle {:x} exceeds the reassembly buffer, dropping it",
+                    handle
+                );
+                *slot = Slot::EMPTY;
+
+                return Err(AclError::Overflow { handle });
+            }
+        }
+
+        if slot.expected == 0 || slot.len < slot.expected {
+            return Ok(None);
+        }
+
+        if slot.len > slot.expected {
+            *slot = Slot::EMPTY;
+
+            return Err(AclError::Malformed);
+        }
+
+        let len = slot.len;
+        slot.handle = None;
+        slot.len = 0;
+        slot.expected = 0;
+
+        Ok(Some(L2capFrame {
+            handle,
+            channel_id: u16::from_le_bytes([slot.buf[2], slot.buf[3]]),
+            payload: &slot.buf[L2CAP_BASIC_HEADER_SIZE..len],
+        }))
+    }
+}
+
+impl<const N: usize, const C: usize> Default for Reassembler<N, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Count of the ACL data packets the controller can still accept
+///
+/// Packets are not flow controlled until [`AclCredits::reset`] gives the number of ACL buffers of the
+/// controller. Then each packet sent takes a credit, given back when the controller reports it completed.
+pub struct AclCredits {
+    credits: AtomicUsize,
+    enabled: AtomicBool,
+}
+
+impl AclCredits {
+    pub const fn new() -> Self {
+        Self {
+            credits: AtomicUsize::new(0),
+            enabled: AtomicBool::new(false),
+        }
+    }
+
+    /// Stop the flow control, e.g. while the controller is reset
+    pub fn disable(&self) {
+        self.enabled.store(false, Ordering::Relaxed);
+    }
+
+    /// Start the flow control with `buffers` free buffers in the controller
+    pub fn reset(&self, buffers: usize) {
+        self.credits.store(buffers, Ordering::Relaxed);
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Number of packets that can be sent, 0 when not flow controlled
+    pub fn available(&self) -> usize {
+        self.credits.load(Ordering::Relaxed)
+    }
+
+    /// Take a credit to send a packet, returns `false` if the controller has no free buffer
+    pub fn try_acquire(&self) -> bool {
+        !self.enabled.load(Ordering::Relaxed)
+            || self
+                .credits
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |credits| credits.checked_sub(1))
+                .is_ok()
+    }
+
+    /// Give back the credits of the packets reported completed by the controller
+    ///
+    /// Packets sent while not flow controlled took no credit, and give none back.
+    pub fn release(&self, completed: usize) {
+        if self.enabled.load(Ordering::Relaxed) {
+            self.credits.fetch_add(completed, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for AclCredits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Entry of [`CompletedPackets`] not used by any connection
+const NO_CONNECTION: u32 = u32::MAX;
+
+/// Number of ACL data packets completed on each connection, as reported by `Number Of Completed Packets`
+///
+/// Counts up to `N` connections at once, and wraps around on overflow. Counters are meant to be sampled
+/// periodically to measure the throughput of a connection.
+pub struct CompletedPackets<const N: usize> {
+    handles: [AtomicU32; N],
+    counts: [AtomicU32; N],
+}
+
+impl<const N: usize> CompletedPackets<N> {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const NONE: AtomicU32 = AtomicU32::new(NO_CONNECTION);
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU32 = AtomicU32::new(0);
+
+        Self {
+            handles: [NONE; N],
+            counts: [ZERO; N],
+        }
+    }
+
+    fn position(&self, handle: u16) -> Option<usize> {
+        self.handles
+            .iter()
+            .position(|entry| entry.load(Ordering::Relaxed) == handle as u32)
+    }
+
+    /// Add `count` packets completed on `handle`
+    ///
+    /// Returns `false` if the connection is not counted yet and all the counters are in use.
+    pub fn record(&self, handle: u16, count: u16) -> bool {
+        let index = self.position(handle).or_else(|| {
+            let index = self
+                .handles
+                .iter()
+                .position(|entry| entry.load(Ordering::Relaxed) == NO_CONNECTION)?;
+            self.counts[index].store(0, Ordering::Relaxed);
+            self.handles[index].store(handle as u32, Ordering::Relaxed);
+            Some(index)
+        });
+
+        match index {
+            Some(index) => {
+                self.counts[index].fetch_add(count as u32, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of packets completed on `handle` since the connection was established
+    pub fn get(&self, handle: u16) -> u32 {
+        self.position(handle)
+            .map_or(0, |index| self.counts[index].load(Ordering::Relaxed))
+    }
+
+    /// Forget the counter of a disconnected connection
+    pub fn remove(&self, handle: u16) {
+        if let Some(index) = self.position(handle) {
+            self.handles[index].store(NO_CONNECTION, Ordering::Relaxed);
+        }
+    }
+
+    /// Forget the counters of all the connections
+    pub fn clear(&self) {
+        for entry in self.handles.iter() {
+            entry.store(NO_CONNECTION, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<const N: usize> Default for CompletedPackets<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Number of ACL data packets sent on each connection and not reported completed yet
+///
+/// The controller does not report the packets of a connection once it is closed, so that their
+/// [`AclCredits`] are given back on disconnection. Tracks up to `N` connections at once.
+pub struct OutstandingPackets<const N: usize> {
+    handles: [AtomicU32; N],
+    counts: [AtomicU32; N],
+}
+
+impl<const N: usize> OutstandingPackets<N> {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const NONE: AtomicU32 = AtomicU32::new(NO_CONNECTION);
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU32 = AtomicU32::new(0);
+
+        Self {
+            handles: [NONE; N],
+            counts: [ZERO; N],
+        }
+    }
+
+    fn position(&self, handle: u16) -> Option<usize> {
+        self.handles
+            .iter()
+            .position(|entry| entry.load(Ordering::Relaxed) == handle as u32)
+    }
+
+    /// Count a packet sent on `handle`
+    ///
+    /// Returns `false` if the connection is not tracked yet and all the entries are in use.
+    pub fn sent(&self, handle: u16) -> bool {
+        let index = self.position(handle).or_else(|| {
+            let index = self
+                .handles
+                .iter()
+                .position(|entry| entry.load(Ordering::Relaxed) == NO_CONNECTION)?;
+            self.counts[index].store(0, Ordering::Relaxed);
+            self.handles[index].store(handle as u32, Ordering::Relaxed);
+            Some(index)
+        });
+
+        match index {
+            Some(index) => {
+                self.counts[index].fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget `count` packets of `handle`, reported completed or not sent after all
+    pub fn completed(&self, handle: u16, count: u16) {
+        if let Some(index) = self.position(handle) {
+            let _ = self.counts[index].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |outstanding| {
+                Some(outstanding.saturating_sub(count as u32))
+            });
+        }
+    }
+
+    /// Forget a disconnected connection, returning the number of its packets never reported completed
+    pub fn remove(&self, handle: u16) -> usize {
+        match self.position(handle) {
+            Some(index) => {
+                self.handles[index].store(NO_CONNECTION, Ordering::Relaxed);
+                self.counts[index].swap(0, Ordering::Relaxed) as usize
+            }
+            None => 0,
+        }
+    }
+
+    /// Forget all the connections
+    pub fn clear(&self) {
+        for entry in self.handles.iter() {
+            entry.store(NO_CONNECTION, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<const N: usize> Default for OutstandingPackets<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acl_packet(handle: u16, boundary: PacketBoundary, data: &[u8]) -> ([u8; 64], usize) {
+        let mut packet = [0; 64];
+        packet[..2].copy_from_slice(&pack_handle(handle, boundary).to_le_bytes());
+        packet[2..4].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        packet[4..4 + data.len()].copy_from_slice(data);
+
+        (packet, ACL_HEADER_SIZE + data.len())
+    }
+
+    #[test]
+    fn fragment_pdu() {
+        let pdu = [0u8; 10];
+        let mut fragments = Fragments::new(&pdu, 4);
+
+        assert_eq!(
+            fragments.next().map(|(b, d)| (b, d.len())),
+            Some((PacketBoundary::FirstNonFlushable, 4))
+        );
+        assert_eq!(
+            fragments.next().map(|(b, d)| (b, d.len())),
+            Some((PacketBoundary::Continuing, 4))
+        );
+        assert_eq!(
+            fragments.next().map(|(b, d)| (b, d.len())),
+            Some((PacketBoundary::Continuing, 2))
+        );
+        assert!(fragments.next().is_none());
+    }
+
+    #[test]
+    fn handle_flags() {
+        let field = pack_handle(0x0801, PacketBoundary::Continuing);
+
+        assert_eq!(field, 0x1801);
+        assert_eq!(unpack_handle(field), Some((0x0801, PacketBoundary::Continuing)));
+        assert_eq!(unpack_handle(0x3001), None);
+    }
+
+    #[test]
+    fn acl_packet_view() {
+        let (packet, len) = acl_packet(0x0801, PacketBoundary::FirstFlushable, &[1, 2, 3]);
+        let packet = AclPacket::parse(&packet[..len]).unwrap();
+
+        assert_eq!(packet.handle(), 0x0801);
+        assert_eq!(packet.boundary(), PacketBoundary::FirstFlushable);
+        assert_eq!(packet.payload(), &[1, 2, 3]);
+        assert_eq!(packet.handle_field(), 0x2801);
+
+        assert_eq!(AclPacket::parse(&[0x01, 0x28, 4, 0, 1]), Err(AclError::Malformed));
+        assert_eq!(
+            AclPacket::new(0x0801, PacketBoundary::FirstNonFlushable, &[0; 252]),
+            Err(AclError::TooLong)
+        );
+    }
+
+    #[test]
+    fn reassemble_interleaved() {
+        let mut reassembler = Reassembler::<32, 2>::new();
+
+        // 5 bytes of payload on channel 4, in two fragments on each connection
+        let (p, n) = acl_packet(1, PacketBoundary::FirstFlushable, &[5, 0, 4, 0, 1, 2]);
+        assert!(reassembler.push(&p[..n]).unwrap().is_none());
+        let (p, n) = acl_packet(2, PacketBoundary::FirstFlushable, &[5, 0, 4, 0, 6]);
+        assert!(reassembler.push(&p[..n]).unwrap().is_none());
+
+        let (p, n) = acl_packet(1, PacketBoundary::Continuing, &[3, 4, 5]);
+        let frame = reassembler.push(&p[..n]).unwrap().unwrap();
+        assert_eq!(
+            (frame.handle, frame.channel_id, frame.payload),
+            (1, 4, &[1, 2, 3, 4, 5][..])
+        );
+
+        let (p, n) = acl_packet(2, PacketBoundary::Continuing, &[7, 8, 9, 10]);
+        let frame = reassembler.push(&p[..n]).unwrap().unwrap();
+        assert_eq!(
+            (frame.handle, frame.channel_id, frame.payload),
+            (2, 4, &[6, 7, 8, 9, 10][..])
+        );
+    }
+
+    #[test]
+    fn reassembly_errors() {
+        let mut reassembler = Reassembler::<8, 1>::new();
+
+        let (p, n) = acl_packet(1, PacketBoundary::Continuing, &[1]);
+        assert_eq!(
+            reassembler.push(&p[..n]).unwrap_err(),
+            AclError::UnexpectedContinuation { handle: 1 }
+        );
+
+        let (p, n) = acl_packet(1, PacketBoundary::FirstFlushable, &[10, 0, 4, 0]);
+        assert_eq!(reassembler.push(&p[..n]).unwrap_err(), AclError::Overflow { handle: 1 });
+
+        let (p, n) = acl_packet(1, PacketBoundary::FirstFlushable, &[2, 0, 4, 0]);
+        assert!(reassembler.push(&p[..n]).unwrap().is_none());
+        let (p, n) = acl_packet(2, PacketBoundary::FirstFlushable, &[2, 0, 4, 0]);
+        assert_eq!(
+            reassembler.push(&p[..n]).unwrap_err(),
+            AclError::NoFreeBuffer { handle: 2 }
+        );
+
+        assert_eq!(reassembler.push(&[0, 0, 1]).unwrap_err(), AclError::Malformed);
+    }
+
+    #[test]
+    fn acl_credits() {
+        let credits = AclCredits::new();
+
+        // not flow controlled before the controller is configured
+        assert!(credits.try_acquire());
+        credits.release(1);
+        assert_eq!(credits.available(), 0);
+
+        credits.reset(2);
+        assert!(credits.try_acquire());
+        assert!(credits.try_acquire());
+        assert!(!credits.try_acquire());
+
+        credits.release(1);
+        assert_eq!(credits.available(), 1);
+        assert!(credits.try_acquire());
+        assert!(!credits.try_acquire());
+    }
+
+    #[test]
+    fn completed_packets() {
+        let completed = CompletedPackets::<2>::new();
+
+        assert!(completed.record(1, 3));
+        assert!(completed.record(2, 1));
+        assert!(completed.record(1, 2));
+        assert_eq!(completed.get(1), 5);
+        assert_eq!(completed.get(2), 1);
+        assert_eq!(completed.get(3), 0);
+
+        // all the counters are in use
+        assert!(!completed.record(3, 1));
+
+        // a disconnected connection frees its counter
+        completed.remove(1);
+        assert_eq!(completed.get(1), 0);
+        assert!(completed.record(3, 1));
+        assert_eq!(completed.get(3), 1);
+
+        completed.clear();
+        assert_eq!(completed.get(2), 0);
+    }
+
+    #[test]
+    fn outstanding_packets() {
+        let outstanding = OutstandingPackets::<2>::new();
+
+        assert!(outstanding.sent(1));
+        assert!(outstanding.sent(1));
+        assert!(outstanding.sent(1));
+        assert!(outstanding.sent(2));
+        assert!(!outstanding.sent(3));
+
+        // completions never take a connection below zero
+        outstanding.completed(1, 1);
+        outstanding.completed(2, 3);
+        outstanding.completed(3, 1);
+
+        // the packets still outstanding on a closed connection are handed back
+        assert_eq!(outstanding.remove(1), 2);
+        assert_eq!(outstanding.remove(1), 0);
+        assert_eq!(outstanding.remove(2), 0);
+        assert!(outstanding.sent(3));
+
+        outstanding.clear();
+        assert_eq!(outstanding.remove(3), 0);
+    }
+}
@@ -0,0 +1,229 @@
+//! Encoding and decoding of the AD structures of advertising and scan response data
+
+use super::consts::{
+    AD_TYPE_COMPLETE_16_BIT_UUIDS, AD_TYPE_COMPLETE_LOCAL_NAME, AD_TYPE_FLAGS, AD_TYPE_INCOMPLETE_16_BIT_UUIDS,
+    AD_TYPE_MANUFACTURER_SPECIFIC_DATA, AD_TYPE_SHORTENED_LOCAL_NAME, MAX_ADV_DATA_LEN,
+};
+use super::typedefs::BleError;
+
+/// AD structure: an AD type and its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdStructure<'a> {
+    pub ad_type: u8,
+    pub data: &'a [u8],
+}
+
+/// Iterator over the AD structures of advertising or scan response data
+///
+/// Iteration stops at the first empty or truncated structure, which also ends the significant part
+/// of the data.
+#[derive(Debug, Clone)]
+pub struct AdStructures<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> AdStructures<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { buf: data }
+    }
+
+    /// Data of the first structure of type `ad_type`
+    pub fn find_type(&self, ad_type: u8) -> Option<&'a [u8]> {
+        self.clone().find(|ad| ad.ad_type == ad_type).map(|ad| ad.data)
+    }
+
+    /// Complete local name, or the shortened one if the complete name is not advertised
+    ///
+    /// Returns `None` if the name is not valid UTF-8.
+    pub fn local_name(&self) -> Option<&'a str> {
+        let name = self
+            .find_type(AD_TYPE_COMPLETE_LOCAL_NAME)
+            .or_else(|| self.find_type(AD_TYPE_SHORTENED_LOCAL_NAME))?;
+
+        core::str::from_utf8(name).ok()
+    }
+
+    /// 16-bit service UUIDs, from the complete and incomplete lists
+    pub fn service_uuids_16(&self) -> impl Iterator<Item = u16> + 'a {
+        self.clone()
+            .filter(|ad| {
+                matches!(
+                    ad.ad_type,
+                    AD_TYPE_INCOMPLETE_16_BIT_UUIDS | AD_TYPE_COMPLETE_16_BIT_UUIDS
+                )
+            })
+            .flat_map(|ad| ad.data.chunks_exact(2))
+            .map(|uuid| u16::from_le_bytes([uuid[0], uuid[1]]))
+    }
+
+    /// Company identifier and data of the first manufacturer specific data
+    pub fn manufacturer_data(&self) -> Option<(u16, &'a [u8])> {
+        let data = self.find_type(AD_TYPE_MANUFACTURER_SPECIFIC_DATA)?;
+        let company = data.get(..2)?;
+
+        Some((u16::from_le_bytes([company[0], company[1]]), &data[2..]))
+    }
+}
+
+impl<'a> Iterator for AdStructures<'a> {
+    type Item = AdStructure<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = *self.buf.first()? as usize;
+        if len == 0 || self.buf.len() < 1 + len {
+            self.buf = &[];
+            return None;
+        }
+
+        let ad = AdStructure {
+            ad_type: self.buf[1],
+            data: &self.buf[2..1 + len],
+        };
+        self.buf = &self.buf[1 + len..];
+
+        Some(ad)
+    }
+}
+
+/// Legacy advertising or scan response data, built from AD structures
+///
+/// Each method appends a structure, and returns [`BleError::InvalidParameters`] without appending it if
+/// the data would exceed [`MAX_ADV_DATA_LEN`] bytes.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdvDataBuilder {
+    len: usize,
+    buf: [u8; MAX_ADV_DATA_LEN],
+}
+
+impl AdvDataBuilder {
+    pub const fn new() -> Self {
+        Self {
+            len: 0,
+            buf: [0; MAX_ADV_DATA_LEN],
+        }
+    }
+
+    /// Append an AD structure of `len` bytes of data, written by `fill`
+    fn push_with(&mut self, ad_type: u8, len: usize, fill: impl FnOnce(&mut [u8])) -> Result<&mut Self, BleError> {
+        let end = self.len + 2 + len;
+        if end > MAX_ADV_DATA_LEN {
+            return Err(BleError::InvalidParameters);
+        }
+
+        self.buf[self.len] = 1 + len as u8;
+        self.buf[self.len + 1] = ad_type;
+        fill(&mut self.buf[self.len + 2..end]);
+        self.len = end;
+
+        Ok(self)
+    }
+
+    /// Append an AD structure of any type
+    pub fn push(&mut self, ad_type: u8, data: &[u8]) -> Result<&mut Self, BleError> {
+        self.push_with(ad_type, data.len(), |buf| buf.copy_from_slice(data))
+    }
+
+    /// Append the flags, a combination of the `AD_FLAG_*` constants
+    pub fn flags(&mut self, flags: u8) -> Result<&mut Self, BleError> {
+        self.push(AD_TYPE_FLAGS, &[flags])
+    }
+
+    /// Append the complete local name
+    pub fn local_name(&mut self, name: &str) -> Result<&mut Self, BleError> {
+        self.push(AD_TYPE_COMPLETE_LOCAL_NAME, name.as_bytes())
+    }
+
+    /// Append the shortened local name, the first bytes of the complete name
+    pub fn shortened_local_name(&mut self, name: &str) -> Result<&mut Self, BleError> {
+        self.push(AD_TYPE_SHORTENED_LOCAL_NAME, name.as_bytes())
+    }
+
+    /// Append the complete list of 16-bit service UUIDs
+    pub fn service_uuids_16(&mut self, uuids: &[u16]) -> Result<&mut Self, BleError> {
+        self.push_with(AD_TYPE_COMPLETE_16_BIT_UUIDS, uuids.len() * 2, |buf| {
+            for (bytes, uuid) in buf.chunks_exact_mut(2).zip(uuids) {
+                bytes.copy_from_slice(&uuid.to_le_bytes());
+            }
+        })
+    }
+
+    /// Append manufacturer specific data, after the company identifier
+    pub fn manufacturer_data(&mut self, company: u16, data: &[u8]) -> Result<&mut Self, BleError> {
+        self.push_with(AD_TYPE_MANUFACTURER_SPECIFIC_DATA, 2 + data.len(), |buf| {
+            buf[..2].copy_from_slice(&company.to_le_bytes());
+            buf[2..].copy_from_slice(data);
+        })
+    }
+
+    /// Data built so far
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Default for AdvDataBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ad_structures() {
+        let data = [
+            0x02, 0x01, 0x06, // flags
+            0x05, 0x03, 0x0F, 0x18, 0x0A, 0x18, // complete 16-bit UUIDs
+            0x05, 0x08, b'e', b'm', b'b', b'a', // shortened local name
+            0x05, 0xFF, 0x30, 0x00, 0xAB, 0xCD, // manufacturer data of STMicroelectronics
+            0x00, 0x00, // padding
+        ];
+        let ads = AdStructures::new(&data);
+
+        assert_eq!(ads.clone().count(), 4);
+        assert_eq!(ads.find_type(0x01), Some(&[0x06][..]));
+        assert_eq!(ads.local_name(), Some("emba"));
+        assert!(ads.service_uuids_16().eq([0x180F, 0x180A]));
+        assert_eq!(ads.manufacturer_data(), Some((0x0030, &[0xAB, 0xCD][..])));
+
+        // truncated structure
+        let mut ads = AdStructures::new(&data[..13]);
+        assert_eq!(ads.nth(1).map(|ad| ad.ad_type), Some(0x03));
+        assert_eq!(ads.next(), None);
+    }
+
+    #[test]
+    fn adv_data_builder() {
+        use crate::ble::consts::{AD_FLAG_BR_EDR_NOT_SUPPORTED, AD_FLAG_LE_GENERAL_DISCOVERABLE};
+
+        let mut data = AdvDataBuilder::new();
+        data.flags(AD_FLAG_LE_GENERAL_DISCOVERABLE | AD_FLAG_BR_EDR_NOT_SUPPORTED)
+            .unwrap()
+            .service_uuids_16(&[0x180F, 0x180A])
+            .unwrap()
+            .shortened_local_name("emba")
+            .unwrap()
+            .manufacturer_data(0x0030, &[0xAB, 0xCD])
+            .unwrap();
+
+        let ads = AdStructures::new(data.as_bytes());
+        assert_eq!(data.as_bytes().len(), 21);
+        assert_eq!(ads.find_type(AD_TYPE_FLAGS), Some(&[0x06][..]));
+        assert!(ads.service_uuids_16().eq([0x180F, 0x180A]));
+        assert_eq!(ads.local_name(), Some("emba"));
+        assert_eq!(ads.manufacturer_data(), Some((0x0030, &[0xAB, 0xCD][..])));
+
+        // the 10 bytes left only fit 8 bytes of data
+        assert!(matches!(
+            data.local_name("embassy-rs"),
+            Err(BleError::InvalidParameters)
+        ));
+        assert_eq!(data.as_bytes().len(), 21);
+        data.local_name("embassy!").unwrap();
+        assert_eq!(data.as_bytes().len(), MAX_ADV_DATA_LEN);
+    }
+}
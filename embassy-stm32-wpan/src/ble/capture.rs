@@ -0,0 +1,67 @@
+//! Capture of the HCI packets exchanged with CPU2, for debugging
+//!
+//! A capture function installed with [`set_capture`] is called with every command and ACL data packet
+//! sent to the controller, and every event received from it. The packets are given in the H4 format,
+//! starting with the packet type indicator, which is the packet format of the btsnoop files read by
+//! Wireshark: only the record headers and timestamps have to be added.
+//!
+//! The capture function is called from the task sending or reading the packet, and should only copy
+//! the packet, e.g. into a ring buffer drained by another task.
+
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// H4 packet type indicator of HCI commands
+pub const H4_COMMAND: u8 = 0x01;
+/// H4 packet type indicator of HCI ACL data packets
+pub const H4_ACL_DATA: u8 = 0x02;
+
+/// Direction of a captured packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// Sent to the controller
+    Sent,
+    /// Received from the controller
+    Received,
+}
+
+/// HCI packet in the H4 format, split in two parts to avoid copying it
+///
+/// The packet is `header` followed by `data`.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HciPacket<'a> {
+    pub direction: Direction,
+    /// Packet type indicator and HCI header
+    pub header: &'a [u8],
+    /// Parameters or data following the header
+    pub data: &'a [u8],
+}
+
+pub type CaptureFn = fn(&HciPacket);
+
+static CAPTURE: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Install the function called with every HCI packet, or remove it with `None`
+pub fn set_capture(capture: Option<CaptureFn>) {
+    let ptr = capture.map_or(ptr::null_mut(), |f| f as *mut ());
+
+    CAPTURE.store(ptr, Ordering::Release);
+}
+
+pub(crate) fn record(direction: Direction, header: &[u8], data: &[u8]) {
+    let ptr = CAPTURE.load(Ordering::Acquire);
+    if ptr.is_null() {
+        return;
+    }
+
+    // SAFETY: only function pointers of type `CaptureFn` are stored
+    let capture: CaptureFn = unsafe { mem::transmute(ptr) };
+    capture(&HciPacket {
+        direction,
+        header,
+        data,
+    });
+}
@@ -0,0 +1,784 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::{mem, slice};
+
+use super::consts::{
+    CONNECT_SCAN_INTERVAL, DATA_LENGTH_OCTETS, DATA_LENGTH_TIME, MAX_ADV_DATA_LEN, MAX_ADV_SETS,
+    MAX_EXT_ADV_DATA_FRAGMENT_LEN,
+};
+use super::opcodes::HciOpcode;
+use super::typedefs::{
+    AdvChannelMap, AdvEventProperties, AdvFilterPolicy, BdAddr, BleError, ConnParams, ExtAdvDataOperation,
+    OwnAddressType, PeerAddressType, Phy, PhyCoding,
+};
+use crate::wait_queue::WaitQueue;
+
+pub trait BleCommand: Sized {
+    const OPCODE: HciOpcode;
+
+    fn payload<'a>(&'a self) -> &'a [u8] {
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<Self>()) }
+    }
+}
+
+/// HCI command assembled parameter by parameter, for commands without a typed struct
+///
+/// `N` is the capacity for the parameters, at most 255 bytes. Multi-byte parameters are written in
+/// little endian, and the length of the command is kept in sync with the parameters.
+#[derive(Debug, Clone)]
+pub struct HciCommand<const N: usize> {
+    opcode: u16,
+    len: usize,
+    buf: [u8; N],
+}
+
+impl<const N: usize> HciCommand<N> {
+    const CAPACITY_CHECK: () = assert!(N <= u8::MAX as usize, "HCI command parameters are limited to 255 bytes");
+
+    /// Start a command with the given opcode group and command fields
+    pub const fn opcode(ogf: u16, ocf: u16) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::CAPACITY_CHECK;
+
+        Self {
+            opcode: (ogf << 10) | (ocf & 0x03FF),
+            len: 0,
+            buf: [0; N],
+        }
+    }
+
+    /// Start a command with an opcode known to this crate
+    pub const fn new(opcode: HciOpcode) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::CAPACITY_CHECK;
+
+        Self {
+            opcode: opcode as u16,
+            len: 0,
+            buf: [0; N],
+        }
+    }
+
+    pub fn push_u8(&mut self, value: u8) -> Result<&mut Self, BleError> {
+        self.push_slice(&[value])
+    }
+
+    pub fn push_u16(&mut self, value: u16) -> Result<&mut Self, BleError> {
+        self.push_slice(&value.to_le_bytes())
+    }
+
+    /// Push the 3 low bytes of `value`, e.g. for advertising intervals
+    pub fn push_u24(&mut self, value: u32) -> Result<&mut Self, BleError> {
+        self.push_slice(&value.to_le_bytes()[..3])
+    }
+
+    pub fn push_u32(&mut self, value: u32) -> Result<&mut Self, BleError> {
+        self.push_slice(&value.to_le_bytes())
+    }
+
+    pub fn push_slice(&mut self, data: &[u8]) -> Result<&mut Self, BleError> {
+        let end = self.len + data.len();
+        if end > N {
+            return Err(BleError::InvalidParameters);
+        }
+
+        self.buf[self.len..end].copy_from_slice(data);
+        self.len = end;
+
+        Ok(self)
+    }
+
+    pub fn opcode_bits(&self) -> u16 {
+        self.opcode
+    }
+
+    /// Parameters pushed so far
+    pub fn payload(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// `HCI_LE_Set_Advertising_Parameters`, used to configure legacy advertising
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdvParams {
+    /// minimum advertising interval, in units of 0.625 ms
+    pub adv_interval_min: [u8; 2],
+    /// maximum advertising interval, in units of 0.625 ms
+    pub adv_interval_max: [u8; 2],
+    /// `ADV_IND` (0x00), `ADV_DIRECT_IND` (0x01 and 0x04), `ADV_SCAN_IND` (0x02) or `ADV_NONCONN_IND` (0x03)
+    pub adv_type: u8,
+    pub own_address_type: OwnAddressType,
+    pub peer_address_type: PeerAddressType,
+    pub peer_address: BdAddr,
+    pub adv_channel_map: AdvChannelMap,
+    pub adv_filter_policy: AdvFilterPolicy,
+}
+
+impl BleCommand for AdvParams {
+    const OPCODE: HciOpcode = HciOpcode::LeSetAdvertisingParameters;
+}
+
+/// `HCI_LE_Set_Advertising_Data`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdvData {
+    data_len: u8,
+    data: [u8; MAX_ADV_DATA_LEN],
+}
+
+impl AdvData {
+    pub fn new(data: &[u8]) -> Self {
+        assert!(data.len() <= MAX_ADV_DATA_LEN);
+
+        let mut this = Self {
+            data_len: data.len() as u8,
+            data: [0; MAX_ADV_DATA_LEN],
+        };
+        this.data[..data.len()].copy_from_slice(data);
+
+        this
+    }
+}
+
+impl BleCommand for AdvData {
+    const OPCODE: HciOpcode = HciOpcode::LeSetAdvertisingData;
+}
+
+/// `HCI_LE_Set_Scan_Response_Data`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanRspData {
+    data_len: u8,
+    data: [u8; MAX_ADV_DATA_LEN],
+}
+
+impl ScanRspData {
+    pub fn new(data: &[u8]) -> Self {
+        assert!(data.len() <= MAX_ADV_DATA_LEN);
+
+        let mut this = Self {
+            data_len: data.len() as u8,
+            data: [0; MAX_ADV_DATA_LEN],
+        };
+        this.data[..data.len()].copy_from_slice(data);
+
+        this
+    }
+}
+
+impl BleCommand for ScanRspData {
+    const OPCODE: HciOpcode = HciOpcode::LeSetScanResponseData;
+}
+
+/// `HCI_LE_Set_Scan_Parameters`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanParams {
+    /// `0x00` for passive scanning, `0x01` for active scanning
+    pub scan_type: u8,
+    /// time between two scans, in units of 0.625 ms
+    pub scan_interval: [u8; 2],
+    /// duration of a scan, in units of 0.625 ms
+    pub scan_window: [u8; 2],
+    pub own_address_type: OwnAddressType,
+    /// `0x00` to accept all advertising packets
+    pub scanning_filter_policy: u8,
+}
+
+impl BleCommand for ScanParams {
+    const OPCODE: HciOpcode = HciOpcode::LeSetScanParameters;
+}
+
+/// `HCI_LE_Set_Scan_Enable`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanEnable {
+    pub enable: bool,
+    pub filter_duplicates: bool,
+}
+
+impl BleCommand for ScanEnable {
+    const OPCODE: HciOpcode = HciOpcode::LeSetScanEnable;
+}
+
+/// `HCI_LE_Create_Connection`, used by a central to connect to an advertising peripheral
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CreateConnection {
+    /// time between two scans, in units of 0.625 ms
+    pub scan_interval: [u8; 2],
+    /// duration of a scan, in units of 0.625 ms
+    pub scan_window: [u8; 2],
+    /// `0x00` to connect to the peer address, `0x01` to any device of the filter accept list
+    pub initiator_filter_policy: u8,
+    pub peer_address_type: PeerAddressType,
+    pub peer_address: BdAddr,
+    pub own_address_type: OwnAddressType,
+    /// minimum connection interval, in units of 1.25 ms
+    pub interval_min: [u8; 2],
+    /// maximum connection interval, in units of 1.25 ms
+    pub interval_max: [u8; 2],
+    pub max_latency: [u8; 2],
+    /// supervision timeout, in units of 10 ms
+    pub supervision_timeout: [u8; 2],
+    /// minimum length of a connection event, in units of 0.625 ms
+    pub min_ce_length: [u8; 2],
+    /// maximum length of a connection event, in units of 0.625 ms
+    pub max_ce_length: [u8; 2],
+}
+
+impl CreateConnection {
+    pub fn new(peer_address_type: PeerAddressType, peer_address: &BdAddr, params: &ConnParams) -> Self {
+        Self {
+            scan_interval: CONNECT_SCAN_INTERVAL.to_le_bytes(),
+            scan_window: CONNECT_SCAN_INTERVAL.to_le_bytes(),
+            initiator_filter_policy: 0x00,
+            peer_address_type,
+            peer_address: *peer_address,
+            own_address_type: OwnAddressType::Public,
+            interval_min: params.interval_min.to_le_bytes(),
+            interval_max: params.interval_max.to_le_bytes(),
+            max_latency: params.max_latency.to_le_bytes(),
+            supervision_timeout: params.supervision_timeout.to_le_bytes(),
+            min_ce_length: [0; 2],
+            max_ce_length: [0; 2],
+        }
+    }
+}
+
+impl BleCommand for CreateConnection {
+    const OPCODE: HciOpcode = HciOpcode::LeCreateConnection;
+}
+
+/// `HCI_LE_Connection_Update`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionUpdate {
+    pub connection_handle: [u8; 2],
+    /// minimum connection interval, in units of 1.25 ms
+    pub interval_min: [u8; 2],
+    /// maximum connection interval, in units of 1.25 ms
+    pub interval_max: [u8; 2],
+    pub max_latency: [u8; 2],
+    /// supervision timeout, in units of 10 ms
+    pub supervision_timeout: [u8; 2],
+    /// minimum length of a connection event, in units of 0.625 ms
+    pub min_ce_length: [u8; 2],
+    /// maximum length of a connection event, in units of 0.625 ms
+    pub max_ce_length: [u8; 2],
+}
+
+impl ConnectionUpdate {
+    pub fn new(connection_handle: u16, params: &ConnParams) -> Self {
+        Self {
+            connection_handle: connection_handle.to_le_bytes(),
+            interval_min: params.interval_min.to_le_bytes(),
+            interval_max: params.interval_max.to_le_bytes(),
+            max_latency: params.max_latency.to_le_bytes(),
+            supervision_timeout: params.supervision_timeout.to_le_bytes(),
+            min_ce_length: [0; 2],
+            max_ce_length: [0; 2],
+        }
+    }
+}
+
+impl BleCommand for ConnectionUpdate {
+    const OPCODE: HciOpcode = HciOpcode::LeConnectionUpdate;
+}
+
+/// `HCI_LE_Set_Data_Length`, used to suggest the largest link layer packets sent on a connection
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SetDataLength {
+    pub connection_handle: [u8; 2],
+    /// maximum payload of the transmitted packets, in bytes
+    pub tx_octets: [u8; 2],
+    /// maximum duration of the transmitted packets, in microseconds
+    pub tx_time: [u8; 2],
+}
+
+impl SetDataLength {
+    /// Returns [`BleError::InvalidParameters`] if the sizes are outside of the ranges allowed by the
+    /// specification, [`DATA_LENGTH_OCTETS`] and [`DATA_LENGTH_TIME`].
+    pub fn new(connection_handle: u16, tx_octets: u16, tx_time: u16) -> Result<Self, BleError> {
+        if !DATA_LENGTH_OCTETS.contains(&tx_octets) || !DATA_LENGTH_TIME.contains(&tx_time) {
+            return Err(BleError::InvalidParameters);
+        }
+
+        Ok(Self {
+            connection_handle: connection_handle.to_le_bytes(),
+            tx_octets: tx_octets.to_le_bytes(),
+            tx_time: tx_time.to_le_bytes(),
+        })
+    }
+}
+
+impl BleCommand for SetDataLength {
+    const OPCODE: HciOpcode = HciOpcode::LeSetDataLength;
+}
+
+/// `HCI_LE_Set_PHY`, used to request the PHYs of a connection
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SetPhy {
+    pub connection_handle: [u8; 2],
+    pub all_phys: u8,
+    /// PHYs preferred for transmission, as a mask of [`Phy::mask`]
+    pub tx_phys: u8,
+    /// PHYs preferred for reception, as a mask of [`Phy::mask`]
+    pub rx_phys: u8,
+    pub phy_options: [u8; 2],
+}
+
+impl SetPhy {
+    pub fn new(connection_handle: u16, tx: Phy, rx: Phy, coding: PhyCoding) -> Self {
+        Self {
+            connection_handle: connection_handle.to_le_bytes(),
+            all_phys: 0,
+            tx_phys: tx.mask(),
+            rx_phys: rx.mask(),
+            phy_options: (coding as u16).to_le_bytes(),
+        }
+    }
+}
+
+impl BleCommand for SetPhy {
+    const OPCODE: HciOpcode = HciOpcode::LeSetPhy;
+}
+
+/// `HCI_LE_Add_Device_To_Filter_Accept_List`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AddToFilterAcceptList {
+    pub address_type: PeerAddressType,
+    pub address: BdAddr,
+}
+
+impl AddToFilterAcceptList {
+    pub fn new(address_type: PeerAddressType, address: &BdAddr) -> Self {
+        Self {
+            address_type,
+            address: *address,
+        }
+    }
+}
+
+impl BleCommand for AddToFilterAcceptList {
+    const OPCODE: HciOpcode = HciOpcode::LeAddDeviceToFilterAcceptList;
+}
+
+/// `HCI_LE_Add_Device_To_Resolving_List`, used to resolve the private addresses of a bonded device
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AddToResolvingList {
+    pub peer_identity_address_type: PeerAddressType,
+    pub peer_identity_address: BdAddr,
+    /// identity resolving key of the peer
+    pub peer_irk: [u8; 16],
+    /// identity resolving key of the local device
+    pub local_irk: [u8; 16],
+}
+
+impl AddToResolvingList {
+    pub fn new(address_type: PeerAddressType, address: &BdAddr, peer_irk: [u8; 16], local_irk: [u8; 16]) -> Self {
+        Self {
+            peer_identity_address_type: address_type,
+            peer_identity_address: *address,
+            peer_irk,
+            local_irk,
+        }
+    }
+}
+
+impl BleCommand for AddToResolvingList {
+    const OPCODE: HciOpcode = HciOpcode::LeAddDeviceToResolvingList;
+}
+
+/// `HCI_LE_Set_Extended_Advertising_Parameters`, used to configure an advertising set
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtAdvParams {
+    /// identifies the advertising set
+    pub adv_handle: u8,
+    /// type of advertising events of the set
+    pub adv_event_properties: AdvEventProperties,
+    /// minimum advertising interval, in units of 0.625 ms
+    pub primary_adv_interval_min: [u8; 3],
+    /// maximum advertising interval, in units of 0.625 ms
+    pub primary_adv_interval_max: [u8; 3],
+    /// channels used on the primary advertising PHY
+    pub primary_adv_channel_map: AdvChannelMap,
+    /// type of the advertiser address
+    pub own_address_type: OwnAddressType,
+    /// type of the peer address, for directed advertising
+    pub peer_address_type: PeerAddressType,
+    /// peer address, for directed advertising
+    pub peer_address: BdAddr,
+    /// use of the filter accept list
+    pub adv_filter_policy: AdvFilterPolicy,
+    /// maximum TX power in dBm, `0x7F` if the host has no preference
+    pub adv_tx_power: i8,
+    /// PHY of the primary advertising channels, either 1M or coded
+    pub primary_adv_phy: Phy,
+    /// maximum number of advertising events that can be skipped on the secondary channel
+    pub secondary_adv_max_skip: u8,
+    /// PHY of the secondary advertising channels
+    pub secondary_adv_phy: Phy,
+    /// value of the advertising SID subfield
+    pub adv_sid: u8,
+    /// report the reception of scan requests
+    pub scan_request_notification_enable: bool,
+}
+
+impl ExtAdvParams {
+    /// Set the primary advertising interval bounds, in units of 0.625 ms
+    pub fn set_primary_adv_interval(&mut self, min: u32, max: u32) {
+        let min = min.to_le_bytes();
+        let max = max.to_le_bytes();
+
+        self.primary_adv_interval_min = [min[0], min[1], min[2]];
+        self.primary_adv_interval_max = [max[0], max[1], max[2]];
+    }
+}
+
+impl Default for ExtAdvParams {
+    fn default() -> Self {
+        let mut params = Self {
+            adv_handle: 0,
+            adv_event_properties: AdvEventProperties::CONNECTABLE,
+            primary_adv_interval_min: [0; 3],
+            primary_adv_interval_max: [0; 3],
+            primary_adv_channel_map: AdvChannelMap::ALL,
+            own_address_type: OwnAddressType::Public,
+            peer_address_type: PeerAddressType::Public,
+            peer_address: BdAddr::default(),
+            adv_filter_policy: AdvFilterPolicy::None,
+            adv_tx_power: 0x7F,
+            primary_adv_phy: Phy::Le1M,
+            secondary_adv_max_skip: 0,
+            secondary_adv_phy: Phy::Le1M,
+            adv_sid: 0,
+            scan_request_notification_enable: false,
+        };
+
+        // 100 ms
+        params.set_primary_adv_interval(160, 160);
+
+        params
+    }
+}
+
+impl BleCommand for ExtAdvParams {
+    const OPCODE: HciOpcode = HciOpcode::LeSetExtendedAdvertisingParameters;
+}
+
+/// `HCI_LE_Set_Extended_Advertising_Data`, used to set one fragment of the advertising data of a set
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtAdvData {
+    /// identifies the advertising set
+    pub adv_handle: u8,
+    /// position of this fragment in the advertising data
+    pub operation: ExtAdvDataOperation,
+    /// `0x01` if the controller should not fragment the data further
+    pub fragment_preference: u8,
+    data_len: u8,
+    data: [u8; MAX_EXT_ADV_DATA_FRAGMENT_LEN],
+}
+
+impl ExtAdvData {
+    pub fn new(adv_handle: u8, operation: ExtAdvDataOperation, data: &[u8]) -> Self {
+        assert!(data.len() <= MAX_EXT_ADV_DATA_FRAGMENT_LEN);
+
+        let mut this = Self {
+            adv_handle,
+            operation,
+            fragment_preference: 0x00,
+            data_len: data.len() as u8,
+            data: [0; MAX_EXT_ADV_DATA_FRAGMENT_LEN],
+        };
+        this.data[..data.len()].copy_from_slice(data);
+
+        this
+    }
+}
+
+impl BleCommand for ExtAdvData {
+    const OPCODE: HciOpcode = HciOpcode::LeSetExtendedAdvertisingData;
+
+    fn payload(&self) -> &[u8] {
+        let len = mem::size_of::<Self>() - MAX_EXT_ADV_DATA_FRAGMENT_LEN + self.data_len as usize;
+
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, len) }
+    }
+}
+
+/// Advertising set enabled or disabled by [`ExtAdvEnable`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtAdvSet {
+    /// identifies the advertising set
+    pub adv_handle: u8,
+    /// advertising duration in units of 10 ms, 0 to advertise until disabled
+    pub duration: [u8; 2],
+    /// maximum number of extended advertising events, 0 for no limit
+    pub max_ext_adv_events: u8,
+}
+
+/// `HCI_LE_Set_Extended_Advertising_Enable`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtAdvEnable {
+    pub enable: bool,
+    num_sets: u8,
+    sets: [ExtAdvSet; MAX_ADV_SETS],
+}
+
+impl ExtAdvEnable {
+    /// An empty list of sets disables all the advertising sets
+    pub fn new(enable: bool, sets: &[ExtAdvSet]) -> Self {
+        assert!(sets.len() <= MAX_ADV_SETS);
+
+        let mut this = Self {
+            enable,
+            num_sets: sets.len() as u8,
+            sets: [ExtAdvSet::default(); MAX_ADV_SETS],
+        };
+        this.sets[..sets.len()].copy_from_slice(sets);
+
+        this
+    }
+}
+
+impl BleCommand for ExtAdvEnable {
+    const OPCODE: HciOpcode = HciOpcode::LeSetExtendedAdvertisingEnable;
+
+    fn payload(&self) -> &[u8] {
+        let len = 2 + self.num_sets as usize * mem::size_of::<ExtAdvSet>();
+
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, len) }
+    }
+}
+
+/// Tracks the command waiting for its `Command Complete` or `Command Status` event
+///
+/// The controller accepts a single command at a time, and a new command would overwrite the command
+/// buffer before CPU2 has processed the previous one. The slot holds the opcode of the command in
+/// flight, so that the answer to a command which gave up waiting does not free it. Every task waiting
+/// for the slot is woken when it is released, and all but the first one to take it wait again.
+pub struct CommandSlot {
+    in_flight: AtomicU32,
+    waiters: WaitQueue,
+}
+
+/// Value of [`CommandSlot::in_flight`] when no command is in flight, opcodes are 16 bits
+const NO_COMMAND: u32 = u32::MAX;
+
+impl CommandSlot {
+    pub const fn new() -> Self {
+        Self {
+            in_flight: AtomicU32::new(NO_COMMAND),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Take the slot to send the command `opcode`, returns `false` if a command is still in flight
+    ///
+    /// The caller is not woken when the slot is released, see [`CommandSlot::acquire`] to wait for it.
+    pub fn try_acquire(&self, opcode: u16) -> bool {
+        self.in_flight
+            .compare_exchange(NO_COMMAND, opcode as u32, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Wait until the slot is free and take it to send the command `opcode`
+    pub async fn acquire(&self, opcode: u16) {
+        self.waiters.wait_until(|| self.try_acquire(opcode)).await
+    }
+
+    /// Free the slot if `opcode` is the command in flight, returns whether it was
+    ///
+    /// This is called for each `Command Complete` or `Command Status` event, which may answer a command
+    /// sent before the one in flight.
+    pub fn answered(&self, opcode: u16) -> bool {
+        let answered = self
+            .in_flight
+            .compare_exchange(opcode as u32, NO_COMMAND, Ordering::Release, Ordering::Relaxed)
+            .is_ok();
+        if answered {
+            self.waiters.wake_all();
+        }
+
+        answered
+    }
+
+    /// Free the slot whichever command is in flight, e.g. if it could not be sent
+    pub fn release(&self) {
+        self.in_flight.store(NO_COMMAND, Ordering::Release);
+        self.waiters.wake_all();
+    }
+
+    /// Opcode of the command in flight
+    pub fn in_flight(&self) -> Option<u16> {
+        match self.in_flight.load(Ordering::Relaxed) {
+            NO_COMMAND => None,
+            opcode => Some(opcode as u16),
+        }
+    }
+
+    pub fn is_in_flight(&self) -> bool {
+        self.in_flight().is_some()
+    }
+}
+
+impl Default for CommandSlot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const _: () = assert!(mem::size_of::<AdvParams>() == 15);
+const _: () = assert!(mem::size_of::<AdvData>() == 32);
+const _: () = assert!(mem::size_of::<ScanParams>() == 7);
+const _: () = assert!(mem::size_of::<CreateConnection>() == 25);
+const _: () = assert!(mem::size_of::<ConnectionUpdate>() == 14);
+const _: () = assert!(mem::size_of::<SetDataLength>() == 6);
+const _: () = assert!(mem::size_of::<SetPhy>() == 7);
+const _: () = assert!(mem::size_of::<AddToFilterAcceptList>() == 7);
+const _: () = assert!(mem::size_of::<AddToResolvingList>() == 39);
+const _: () = assert!(mem::size_of::<ExtAdvParams>() == 25);
+const _: () = assert!(mem::size_of::<ExtAdvSet>() == 4);
+
+#[cfg(test)]
+mod tests {
+    use core::future::Future;
+    use core::pin::pin;
+    use core::sync::atomic::AtomicUsize;
+    use core::task::{Context, Waker};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    use super::*;
+
+    #[test]
+    fn hci_command_builder() {
+        let mut cmd = HciCommand::<8>::opcode(0x08, 0x0006);
+        cmd.push_u16(0x00A0)
+            .unwrap()
+            .push_u24(0x0100A0)
+            .unwrap()
+            .push_u8(3)
+            .unwrap();
+
+        assert_eq!(cmd.opcode_bits(), 0x2006);
+        assert_eq!(cmd.payload(), &[0xA0, 0x00, 0xA0, 0x00, 0x01, 0x03]);
+
+        assert_eq!(cmd.push_u32(0).unwrap_err(), BleError::InvalidParameters);
+        assert_eq!(cmd.payload().len(), 6);
+    }
+
+    #[test]
+    fn device_lists() {
+        let addr = BdAddr([0x01, 0x02, 0x03, 0x04, 0x05, 0xC6]);
+
+        let cmd = AddToFilterAcceptList::new(PeerAddressType::Random, &addr);
+        assert_eq!(cmd.payload(), &[0x01, 0x01, 0x02, 0x03, 0x04, 0x05, 0xC6]);
+
+        let cmd = AddToResolvingList::new(PeerAddressType::Public, &addr, [0xAA; 16], [0x55; 16]);
+        let payload = cmd.payload();
+        assert_eq!(payload[..7], [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0xC6]);
+        assert_eq!(payload[7..23], [0xAA; 16]);
+        assert_eq!(payload[23..], [0x55; 16]);
+
+        assert_eq!(PeerAddressType::try_from(0x01), Ok(PeerAddressType::Random));
+        assert_eq!(PeerAddressType::try_from(0x02), Err(()));
+    }
+
+    #[test]
+    fn back_to_back_commands() {
+        let slot = CommandSlot::new();
+
+        assert!(slot.try_acquire(0x0C03));
+        // the second command must not be written while the first one is unanswered
+        assert!(!slot.try_acquire(0x1009));
+        assert_eq!(slot.in_flight(), Some(0x0C03));
+
+        slot.release();
+        assert!(slot.try_acquire(0x1009));
+    }
+
+    #[test]
+    fn stale_answer() {
+        let slot = CommandSlot::new();
+
+        assert!(slot.try_acquire(0x1009));
+        // answer to a command which gave up waiting
+        assert!(!slot.answered(0x0C03));
+        assert!(slot.is_in_flight());
+
+        assert!(slot.answered(0x1009));
+        assert!(!slot.is_in_flight());
+    }
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn counting_waker() -> (Arc<CountingWaker>, Waker) {
+        let count = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(count.clone());
+
+        (count, waker)
+    }
+
+    #[test]
+    fn contending_commands() {
+        let slot = CommandSlot::new();
+        let (_, waker_a) = counting_waker();
+        let (woken_b, waker_b) = counting_waker();
+        let (woken_c, waker_c) = counting_waker();
+
+        let mut a = pin!(slot.acquire(0x1009));
+        let mut b = pin!(slot.acquire(0x1009));
+        let mut c = pin!(slot.acquire(0x1009));
+
+        assert!(a.as_mut().poll(&mut Context::from_waker(&waker_a)).is_ready());
+        assert!(b.as_mut().poll(&mut Context::from_waker(&waker_b)).is_pending());
+        assert!(c.as_mut().poll(&mut Context::from_waker(&waker_c)).is_pending());
+
+        // a non-blocking attempt must not steal the registrations of the waiting tasks
+        assert!(!slot.try_acquire(0x1009));
+
+        slot.release();
+        assert_eq!(woken_b.0.load(Ordering::Relaxed), 1);
+        assert_eq!(woken_c.0.load(Ordering::Relaxed), 1);
+
+        // the task which does not get the slot waits again, and is woken by the next release
+        assert!(c.as_mut().poll(&mut Context::from_waker(&waker_c)).is_ready());
+        assert!(b.as_mut().poll(&mut Context::from_waker(&waker_b)).is_pending());
+
+        slot.release();
+        assert_eq!(woken_b.0.load(Ordering::Relaxed), 2);
+        assert!(b.as_mut().poll(&mut Context::from_waker(&waker_b)).is_ready());
+        assert!(slot.is_in_flight());
+    }
+}
@@ -0,0 +1,265 @@
+//! Link state of the connections, gathered from the HCI events
+
+use super::consts::{DEFAULT_ATT_MTU, DEFAULT_DATA_LENGTH_OCTETS, DEFAULT_DATA_LENGTH_TIME};
+use super::event::{ConnectionComplete, ConnectionUpdateComplete, DataLengthChange, PhyUpdateComplete};
+use super::typedefs::Phy;
+
+/// Link state of a connection, as reported by the controller and the wireless stack
+///
+/// The parameters of a new connection come from its `LE Connection Complete` event, and the link layer
+/// uses the 1M PHY and the smallest packets until a PHY update or a data length change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionState {
+    pub connection_handle: u16,
+    /// `0x00` if the local device is the central, `0x01` if it is the peripheral
+    pub role: u8,
+    /// Connection interval in use, in units of 1.25 ms
+    pub interval: u16,
+    /// Peripheral latency in use, in connection events
+    pub latency: u16,
+    /// Supervision timeout in use, in units of 10 ms
+    pub supervision_timeout: u16,
+    /// PHY used for transmission
+    pub tx_phy: Phy,
+    /// PHY used for reception
+    pub rx_phy: Phy,
+    /// Maximum payload of the transmitted packets, in bytes
+    pub max_tx_octets: u16,
+    /// Maximum duration of the transmitted packets, in microseconds
+    pub max_tx_time: u16,
+    /// Maximum payload of the received packets, in bytes
+    pub max_rx_octets: u16,
+    /// Maximum duration of the received packets, in microseconds
+    pub max_rx_time: u16,
+    /// ATT_MTU exchanged on the connection
+    pub att_mtu: u16,
+}
+
+impl ConnectionState {
+    /// State of a connection just established
+    pub fn new(complete: &ConnectionComplete) -> Self {
+        Self {
+            connection_handle: complete.connection_handle,
+            role: complete.role,
+            interval: complete.interval,
+            latency: complete.latency,
+            supervision_timeout: complete.supervision_timeout,
+            tx_phy: Phy::Le1M,
+            rx_phy: Phy::Le1M,
+            max_tx_octets: DEFAULT_DATA_LENGTH_OCTETS,
+            max_tx_time: DEFAULT_DATA_LENGTH_TIME,
+            max_rx_octets: DEFAULT_DATA_LENGTH_OCTETS,
+            max_rx_time: DEFAULT_DATA_LENGTH_TIME,
+            att_mtu: DEFAULT_ATT_MTU,
+        }
+    }
+
+    /// Supervision timeout in use, in milliseconds
+    pub fn supervision_timeout_ms(&self) -> u32 {
+        self.supervision_timeout as u32 * 10
+    }
+}
+
+/// Link state of up to `N` connections at once
+///
+/// Events of failed procedures are ignored, and so are the events of connections not in the table.
+/// The last time data flowed on each connection is kept as well, as a timestamp in the unit chosen by
+/// the caller of [`record_activity`](Self::record_activity).
+pub struct ConnectionStates<const N: usize> {
+    states: [Option<ConnectionState>; N],
+    last_activity: [u64; N],
+}
+
+impl<const N: usize> ConnectionStates<N> {
+    pub const fn new() -> Self {
+        Self {
+            states: [None; N],
+            last_activity: [0; N],
+        }
+    }
+
+    fn index(&self, handle: u16) -> Option<usize> {
+        self.states
+            .iter()
+            .position(|state| state.is_some_and(|state| state.connection_handle == handle))
+    }
+
+    fn get_mut(&mut self, handle: u16) -> Option<&mut ConnectionState> {
+        self.states
+            .iter_mut()
+            .flatten()
+            .find(|state| state.connection_handle == handle)
+    }
+
+    /// Start tracking a new connection
+    ///
+    /// Returns `false` if all the entries are in use.
+    pub fn connect(&mut self, complete: &ConnectionComplete) -> bool {
+        if complete.status != 0 {
+            return true;
+        }
+
+        // the handle of a connection whose disconnection was missed may be reused
+        self.remove(complete.connection_handle);
+        match self.states.iter().position(|state| state.is_none()) {
+            Some(index) => {
+                self.states[index] = Some(ConnectionState::new(complete));
+                self.last_activity[index] = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn update_params(&mut self, update: &ConnectionUpdateComplete) {
+        if update.status != 0 {
+            return;
+        }
+
+        if let Some(state) = self.get_mut(update.connection_handle) {
+            state.interval = update.interval;
+            state.latency = update.latency;
+            state.supervision_timeout = update.supervision_timeout;
+        }
+    }
+
+    pub fn update_phy(&mut self, update: &PhyUpdateComplete) {
+        if update.status != 0 {
+            return;
+        }
+
+        if let Some(state) = self.get_mut(update.connection_handle) {
+            state.tx_phy = update.tx_phy;
+            state.rx_phy = update.rx_phy;
+        }
+    }
+
+    pub fn update_data_length(&mut self, change: &DataLengthChange) {
+        if let Some(state) = self.get_mut(change.connection_handle) {
+            state.max_tx_octets = change.max_tx_octets;
+            state.max_tx_time = change.max_tx_time;
+            state.max_rx_octets = change.max_rx_octets;
+            state.max_rx_time = change.max_rx_time;
+        }
+    }
+
+    pub fn update_att_mtu(&mut self, handle: u16, mtu: u16) {
+        if let Some(state) = self.get_mut(handle) {
+            state.att_mtu = mtu;
+        }
+    }
+
+    pub fn get(&self, handle: u16) -> Option<ConnectionState> {
+        self.states
+            .iter()
+            .flatten()
+            .find(|state| state.connection_handle == handle)
+            .copied()
+    }
+
+    /// Note that data flowed on a connection at `now`
+    pub fn record_activity(&mut self, handle: u16, now: u64) {
+        if let Some(index) = self.index(handle) {
+            self.last_activity[index] = now;
+        }
+    }
+
+    /// Last time data flowed on a connection, as given to [`record_activity`](Self::record_activity)
+    pub fn last_activity(&self, handle: u16) -> Option<u64> {
+        self.index(handle).map(|index| self.last_activity[index])
+    }
+
+    /// Forget the state of a disconnected connection
+    pub fn remove(&mut self, handle: u16) {
+        if let Some(entry) = self
+            .states
+            .iter_mut()
+            .find(|state| state.is_some_and(|state| state.connection_handle == handle))
+        {
+            *entry = None;
+        }
+    }
+
+    /// Forget the state of all the connections
+    pub fn clear(&mut self) {
+        self.states = [None; N];
+    }
+}
+
+impl<const N: usize> Default for ConnectionStates<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ble::typedefs::{AddrType, BdAddr};
+
+    fn complete(handle: u16) -> ConnectionComplete {
+        ConnectionComplete {
+            status: 0,
+            connection_handle: handle,
+            role: 0x01,
+            peer_address_type: AddrType::Public,
+            peer_address: BdAddr([1, 2, 3, 4, 5, 6]),
+            interval: 24,
+            latency: 0,
+            supervision_timeout: 72,
+        }
+    }
+
+    #[test]
+    fn connection_states() {
+        let mut states = ConnectionStates::<1>::new();
+
+        assert!(states.connect(&complete(0x0801)));
+        assert!(!states.connect(&complete(0x0802)));
+        assert_eq!(states.get(0x0801).map(|state| state.max_tx_octets), Some(27));
+        assert_eq!(states.get(0x0802), None);
+
+        states.update_phy(&PhyUpdateComplete {
+            status: 0,
+            connection_handle: 0x0801,
+            tx_phy: Phy::Le2M,
+            rx_phy: Phy::Le2M,
+        });
+        states.update_data_length(&DataLengthChange {
+            connection_handle: 0x0801,
+            max_tx_octets: 251,
+            max_tx_time: 2120,
+            max_rx_octets: 251,
+            max_rx_time: 2120,
+        });
+        states.update_params(&ConnectionUpdateComplete {
+            status: 0x3B,
+            connection_handle: 0x0801,
+            interval: 6,
+            latency: 0,
+            supervision_timeout: 100,
+        });
+        states.update_att_mtu(0x0801, 247);
+
+        let state = states.get(0x0801).unwrap();
+        assert_eq!((state.tx_phy, state.rx_phy), (Phy::Le2M, Phy::Le2M));
+        assert_eq!((state.max_tx_octets, state.max_rx_time), (251, 2120));
+        assert_eq!((state.interval, state.supervision_timeout), (24, 72));
+        assert_eq!(state.att_mtu, 247);
+
+        assert_eq!(state.supervision_timeout_ms(), 720);
+
+        states.record_activity(0x0801, 1000);
+        states.record_activity(0x0802, 2000);
+        assert_eq!(states.last_activity(0x0801), Some(1000));
+        assert_eq!(states.last_activity(0x0802), None);
+
+        // the entry is reused once disconnected
+        states.remove(0x0801);
+        assert!(states.connect(&complete(0x0802)));
+        assert_eq!(states.get(0x0801), None);
+        assert_eq!(states.get(0x0802).map(|state| state.tx_phy), Some(Phy::Le1M));
+        assert_eq!(states.last_activity(0x0802), Some(0));
+    }
+}
@@ -0,0 +1,152 @@
+/// Default HCI event mask: the specification default (bits 0 to 44) plus the encryption key refresh
+/// complete (bit 47) and LE meta (bit 61) events
+pub const DEFAULT_EVENT_MASK: u64 = 0x2000_9FFF_FFFF_FFFF;
+/// Default LE event mask, as defined by the specification
+pub const DEFAULT_LE_EVENT_MASK: u64 = 0x0000_0000_0000_001F;
+
+/// Event code of the HCI `Disconnection Complete` event
+pub const EVT_DISCONNECTION_COMPLETE: u8 = 0x05;
+/// Event code of the HCI `Encryption Change` event
+pub const EVT_ENCRYPTION_CHANGE: u8 = 0x08;
+/// Event code of the HCI `Number Of Completed Packets` event
+pub const EVT_NUMBER_OF_COMPLETED_PACKETS: u8 = 0x13;
+/// Event code of the HCI `Encryption Key Refresh Complete` event
+pub const EVT_ENCRYPTION_KEY_REFRESH_COMPLETE: u8 = 0x30;
+
+/// Event code of the HCI `LE Meta` event
+pub const EVT_LE_META: u8 = 0x3E;
+/// Sub event code of the `LE Connection Complete` event
+pub const EVT_LE_CONNECTION_COMPLETE: u8 = 0x01;
+/// Sub event code of the `LE Advertising Report` event
+pub const EVT_LE_ADVERTISING_REPORT: u8 = 0x02;
+/// Sub event code of the `LE Connection Update Complete` event
+pub const EVT_LE_CONNECTION_UPDATE_COMPLETE: u8 = 0x03;
+/// Sub event code of the `LE Read Remote Features Complete` event
+pub const EVT_LE_READ_REMOTE_FEATURES_COMPLETE: u8 = 0x04;
+/// Sub event code of the `LE Long Term Key Request` event
+pub const EVT_LE_LONG_TERM_KEY_REQUEST: u8 = 0x05;
+/// Sub event code of the `LE Data Length Change` event
+pub const EVT_LE_DATA_LENGTH_CHANGE: u8 = 0x07;
+/// Sub event code of the `LE Enhanced Connection Complete` event
+pub const EVT_LE_ENHANCED_CONNECTION_COMPLETE: u8 = 0x0A;
+/// Sub event code of the `LE PHY Update Complete` event
+pub const EVT_LE_PHY_UPDATE_COMPLETE: u8 = 0x0C;
+/// Sub event code of the `LE Extended Advertising Report` event
+pub const EVT_LE_EXTENDED_ADVERTISING_REPORT: u8 = 0x0D;
+/// Sub event code of the `LE Periodic Advertising Sync Established` event
+pub const EVT_LE_PERIODIC_ADVERTISING_SYNC_ESTABLISHED: u8 = 0x0E;
+/// Sub event code of the `LE Periodic Advertising Report` event
+pub const EVT_LE_PERIODIC_ADVERTISING_REPORT: u8 = 0x0F;
+/// Sub event code of the `LE Periodic Advertising Sync Lost` event
+pub const EVT_LE_PERIODIC_ADVERTISING_SYNC_LOST: u8 = 0x10;
+
+/// Event code of `ACI_GAP_Pairing_Complete_Event`, carried by a vendor specific event
+pub const ACI_GAP_PAIRING_COMPLETE_EVENT: u16 = 0x0401;
+/// Event code of `ACI_GAP_Pass_Key_Req_Event`, carried by a vendor specific event
+pub const ACI_GAP_PASS_KEY_REQ_EVENT: u16 = 0x0402;
+/// Event code of `ACI_GAP_Bond_Lost_Event`, carried by a vendor specific event
+pub const ACI_GAP_BOND_LOST_EVENT: u16 = 0x0405;
+/// Event code of `ACI_GAP_Numeric_Comparison_Value_Event`, carried by a vendor specific event
+pub const ACI_GAP_NUMERIC_COMPARISON_VALUE_EVENT: u16 = 0x0409;
+/// Event code of `ACI_ATT_Exchange_MTU_Resp_Event`, carried by a vendor specific event
+pub const ACI_ATT_EXCHANGE_MTU_RESP_EVENT: u16 = 0x0C05;
+/// Event code of `ACI_GATT_Attribute_Modified_Event`, carried by a vendor specific event
+pub const ACI_GATT_ATTRIBUTE_MODIFIED_EVENT: u16 = 0x0C01;
+/// Event code of `ACI_GATT_Proc_Complete_Event`, carried by a vendor specific event
+pub const ACI_GATT_PROC_COMPLETE_EVENT: u16 = 0x0C10;
+/// Event code of `ACI_GATT_Read_Permit_Req_Event`, carried by a vendor specific event
+pub const ACI_GATT_READ_PERMIT_REQ_EVENT: u16 = 0x0C14;
+/// Event code of `ACI_GATT_Write_Permit_Req_Event`, carried by a vendor specific event
+pub const ACI_GATT_WRITE_PERMIT_REQ_EVENT: u16 = 0x0C15;
+/// Event code of `ACI_GATT_Tx_Pool_Available_Event`, carried by a vendor specific event
+pub const ACI_GATT_TX_POOL_AVAILABLE_EVENT: u16 = 0x0C16;
+
+/// Largest pass key accepted by `ACI_GAP_Pass_Key_Resp`, pass keys having 6 decimal digits
+pub const MAX_PASS_KEY: u32 = 999_999;
+
+/// HCI error code of a command not allowed in the current state of the controller
+pub const HCI_COMMAND_DISALLOWED: u8 = 0x0C;
+/// HCI error code reporting a connection attempt cancelled by `HCI_LE_Create_Connection_Cancel`
+pub const HCI_UNKNOWN_CONNECTION_ID: u8 = 0x02;
+
+/// Scan interval and window used while creating a connection, in units of 0.625 ms (60 ms)
+pub const CONNECT_SCAN_INTERVAL: u16 = 0x0060;
+
+/// Status returned by the vendor commands when the wireless stack runs out of buffers
+pub const BLE_STATUS_INSUFFICIENT_RESOURCES: u8 = 0x64;
+
+/// Size of the basic L2CAP header: PDU length and channel identifier
+pub const L2CAP_BASIC_HEADER_SIZE: usize = 4;
+
+/// AD type of the flags, a single byte of `AD_FLAG_*` bits
+pub const AD_TYPE_FLAGS: u8 = 0x01;
+/// AD type of the incomplete list of 16-bit service UUIDs
+pub const AD_TYPE_INCOMPLETE_16_BIT_UUIDS: u8 = 0x02;
+/// AD type of the complete list of 16-bit service UUIDs
+pub const AD_TYPE_COMPLETE_16_BIT_UUIDS: u8 = 0x03;
+/// AD type of the shortened local name
+pub const AD_TYPE_SHORTENED_LOCAL_NAME: u8 = 0x08;
+/// AD type of the complete local name
+pub const AD_TYPE_COMPLETE_LOCAL_NAME: u8 = 0x09;
+/// AD type of the manufacturer specific data, starting with the company identifier
+pub const AD_TYPE_MANUFACTURER_SPECIFIC_DATA: u8 = 0xFF;
+
+/// Flag of a device discoverable for a limited time
+pub const AD_FLAG_LE_LIMITED_DISCOVERABLE: u8 = 0x01;
+/// Flag of a device discoverable until told otherwise
+pub const AD_FLAG_LE_GENERAL_DISCOVERABLE: u8 = 0x02;
+/// Flag of a device supporting LE only, which is always the case of the wireless stack
+pub const AD_FLAG_BR_EDR_NOT_SUPPORTED: u8 = 0x04;
+
+/// Largest legacy advertising or scan response data
+pub const MAX_ADV_DATA_LEN: usize = 31;
+/// Advertising reports decoded from one event and not yet consumed by a scan stream
+pub const MAX_PENDING_ADV_REPORTS: usize = 4;
+
+/// Largest advertising data fragment carried by a single `HCI_LE_Set_Extended_Advertising_Data` command
+pub const MAX_EXT_ADV_DATA_FRAGMENT_LEN: usize = 251;
+/// Largest extended advertising or scan response data, reassembled from several reports
+pub const MAX_EXT_ADV_DATA_LEN: usize = 1650;
+/// Maximum number of advertising sets supported by the wireless stack
+pub const MAX_ADV_SETS: usize = 8;
+
+/// `ACI_HAL_Read_Config_Data` offset of the public address
+pub const CONFIG_DATA_PUBADDR_OFFSET: u8 = 0x00;
+/// `ACI_HAL_Read_Config_Data` offset of the encryption root key
+pub const CONFIG_DATA_ER_OFFSET: u8 = 0x08;
+/// `ACI_HAL_Read_Config_Data` offset of the identity root key
+pub const CONFIG_DATA_IR_OFFSET: u8 = 0x18;
+/// `ACI_HAL_Read_Config_Data` offset of the static random address generated by the wireless stack
+pub const CONFIG_DATA_RANDOM_ADDRESS_OFFSET: u8 = 0x2E;
+
+/// Range of the link layer payload sizes, in bytes, accepted by `HCI_LE_Set_Data_Length`
+pub const DATA_LENGTH_OCTETS: core::ops::RangeInclusive<u16> = 0x001B..=0x00FB;
+/// Range of the link layer packet durations, in microseconds, accepted by `HCI_LE_Set_Data_Length`
+pub const DATA_LENGTH_TIME: core::ops::RangeInclusive<u16> = 0x0148..=0x4290;
+/// Link layer payload size of a connection until its data length changes, in bytes
+pub const DEFAULT_DATA_LENGTH_OCTETS: u16 = 27;
+/// Link layer packet duration of a connection until its data length changes, in microseconds
+pub const DEFAULT_DATA_LENGTH_TIME: u16 = 328;
+
+/// Received ACL data packets held for [`Ble::read_acl`](crate::sub::ble::Ble::read_acl)
+///
+/// Each packet holds a buffer of the event pool until it is read and dropped, packets received while the
+/// queue is full are dropped.
+pub const ACL_RX_QUEUE_SIZE: usize = 4;
+/// Events held for [`Ble::tl_read`](crate::sub::ble::Ble::tl_read) while other tasks read the events
+///
+/// Events read while waiting for a command answer or another event are kept until the application reads
+/// them. Each event holds a buffer of the event pool until it is read and dropped, the oldest event is
+/// dropped when the queue is full.
+pub const PENDING_EVENTS_SIZE: usize = 4;
+/// Procedures waiting for the event reporting their outcome at the same time, e.g. [`Ble::connect`]
+///
+/// Starting a procedure while as many are waiting fails with [`BleError::Busy`].
+///
+/// [`Ble::connect`]: crate::sub::ble::Ble::connect
+/// [`BleError::Busy`]: crate::ble::typedefs::BleError::Busy
+pub const MAX_PENDING_PROCEDURES: usize = 8;
+/// Connections tracked by the crate at the same time, see [`CFG_BLE_NUM_LINK`](crate::consts::CFG_BLE_NUM_LINK)
+pub const MAX_CONNECTIONS: usize = crate::consts::CFG_BLE_NUM_LINK as usize;
+/// ATT_MTU of a connection until it is exchanged
+pub const DEFAULT_ATT_MTU: u16 = 23;
@@ -0,0 +1,1011 @@
+use super::consts::{
+    ACI_ATT_EXCHANGE_MTU_RESP_EVENT, ACI_GAP_BOND_LOST_EVENT, ACI_GAP_NUMERIC_COMPARISON_VALUE_EVENT,
+    ACI_GAP_PAIRING_COMPLETE_EVENT, ACI_GAP_PASS_KEY_REQ_EVENT, ACI_GATT_PROC_COMPLETE_EVENT,
+    ACI_GATT_TX_POOL_AVAILABLE_EVENT, EVT_DISCONNECTION_COMPLETE, EVT_ENCRYPTION_CHANGE,
+    EVT_ENCRYPTION_KEY_REFRESH_COMPLETE, EVT_LE_ADVERTISING_REPORT, EVT_LE_CONNECTION_COMPLETE,
+    EVT_LE_CONNECTION_UPDATE_COMPLETE, EVT_LE_DATA_LENGTH_CHANGE, EVT_LE_ENHANCED_CONNECTION_COMPLETE,
+    EVT_LE_LONG_TERM_KEY_REQUEST, EVT_LE_META, EVT_LE_PHY_UPDATE_COMPLETE, EVT_LE_READ_REMOTE_FEATURES_COMPLETE,
+    EVT_NUMBER_OF_COMPLETED_PACKETS,
+};
+use super::typedefs::{AddrType, AdvReport, BdAddr, BleError, DisconnectReason, LeFeatures, PairingStatus, Phy};
+use crate::consts::{TL_BLEEVT_CC_OPCODE, TL_BLEEVT_CS_OPCODE, TL_BLEEVT_VS_OPCODE};
+
+/// HCI `Command Complete` event
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CommandComplete<'a> {
+    pub num_hci_command_packets: u8,
+    pub opcode: u16,
+    /// Status of the command, the first return parameter
+    pub status: u8,
+    /// Return parameters following the status
+    pub params: &'a [u8],
+}
+
+impl<'a> CommandComplete<'a> {
+    pub fn parse(evt_code: u8, payload: &'a [u8]) -> Option<Self> {
+        if evt_code != TL_BLEEVT_CC_OPCODE || payload.len() < 4 {
+            return None;
+        }
+
+        Some(Self {
+            num_hci_command_packets: payload[0],
+            opcode: u16::from_le_bytes([payload[1], payload[2]]),
+            status: payload[3],
+            params: &payload[4..],
+        })
+    }
+}
+
+/// HCI `Command Status` event
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CommandStatus {
+    pub status: u8,
+    pub num_hci_command_packets: u8,
+    pub opcode: u16,
+}
+
+impl CommandStatus {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != TL_BLEEVT_CS_OPCODE || payload.len() < 4 {
+            return None;
+        }
+
+        Some(Self {
+            status: payload[0],
+            num_hci_command_packets: payload[1],
+            opcode: u16::from_le_bytes([payload[2], payload[3]]),
+        })
+    }
+}
+
+/// HCI `Number Of Completed Packets` event
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NumberOfCompletedPackets<'a> {
+    /// Connection handle and completed packet count pairs, 4 bytes each
+    entries: &'a [u8],
+}
+
+impl<'a> NumberOfCompletedPackets<'a> {
+    pub fn parse(evt_code: u8, payload: &'a [u8]) -> Option<Self> {
+        if evt_code != EVT_NUMBER_OF_COMPLETED_PACKETS || payload.is_empty() {
+            return None;
+        }
+
+        let entries = payload.get(1..1 + payload[0] as usize * 4)?;
+
+        Some(Self { entries })
+    }
+
+    /// Iterate over the connection handles and the number of packets completed on each of them
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u16)> + 'a {
+        self.entries.chunks_exact(4).map(|entry| {
+            (
+                u16::from_le_bytes([entry[0], entry[1]]),
+                u16::from_le_bytes([entry[2], entry[3]]),
+            )
+        })
+    }
+
+    /// Number of packets completed on all the connections
+    pub fn total(&self) -> usize {
+        self.iter().map(|(_, count)| count as usize).sum()
+    }
+}
+
+/// HCI `LE Advertising Report` event, iterating over the reports it holds
+///
+/// Reports are decoded one after the other, and decoding stops at the first malformed report.
+#[derive(Debug)]
+pub struct AdvertisingReports<'a> {
+    remaining: u8,
+    buf: &'a [u8],
+}
+
+impl<'a> AdvertisingReports<'a> {
+    pub fn parse(evt_code: u8, payload: &'a [u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META || payload.len() < 2 || payload[0] != EVT_LE_ADVERTISING_REPORT {
+            return None;
+        }
+
+        Some(Self {
+            remaining: payload[1],
+            buf: &payload[2..],
+        })
+    }
+}
+
+impl<'a> Iterator for AdvertisingReports<'a> {
+    type Item = AdvReport;
+
+    fn next(&mut self) -> Option<AdvReport> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        match AdvReport::from_bytes(self.buf) {
+            Ok((report, len)) => {
+                self.remaining -= 1;
+                self.buf = &self.buf[len..];
+
+                Some(report)
+            }
+            Err(_) => {
+                self.remaining = 0;
+
+                None
+            }
+        }
+    }
+}
+
+/// HCI `Disconnection Complete` event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DisconnectionComplete {
+    pub status: u8,
+    pub connection_handle: u16,
+    pub reason: DisconnectReason,
+}
+
+impl DisconnectionComplete {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_DISCONNECTION_COMPLETE || payload.len() < 4 {
+            return None;
+        }
+
+        Some(Self {
+            status: payload[0],
+            connection_handle: u16::from_le_bytes([payload[1], payload[2]]),
+            reason: payload[3].into(),
+        })
+    }
+}
+
+/// HCI `Encryption Change` event, sent when encryption is started or stopped on a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EncryptionChange {
+    pub status: u8,
+    pub connection_handle: u16,
+    /// Whether the link is now encrypted, only meaningful if the status is zero
+    pub enabled: bool,
+}
+
+impl EncryptionChange {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_ENCRYPTION_CHANGE || payload.len() < 4 {
+            return None;
+        }
+
+        Some(Self {
+            status: payload[0],
+            connection_handle: u16::from_le_bytes([payload[1], payload[2]]),
+            enabled: payload[3] != 0,
+        })
+    }
+}
+
+/// HCI `Encryption Key Refresh Complete` event, sent instead of [`EncryptionChange`] when encryption is
+/// restarted on a link which was already encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EncryptionKeyRefreshComplete {
+    pub status: u8,
+    pub connection_handle: u16,
+}
+
+impl EncryptionKeyRefreshComplete {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_ENCRYPTION_KEY_REFRESH_COMPLETE || payload.len() < 3 {
+            return None;
+        }
+
+        Some(Self {
+            status: payload[0],
+            connection_handle: u16::from_le_bytes([payload[1], payload[2]]),
+        })
+    }
+}
+
+/// HCI `LE Connection Complete` or `LE Enhanced Connection Complete` event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionComplete {
+    pub status: u8,
+    pub connection_handle: u16,
+    /// `0x00` if the local device is the central, `0x01` if it is the peripheral
+    pub role: u8,
+    pub peer_address_type: AddrType,
+    pub peer_address: BdAddr,
+    /// Connection interval in use, in units of 1.25 ms
+    pub interval: u16,
+    /// Peripheral latency in use, in connection events
+    pub latency: u16,
+    /// Supervision timeout in use, in units of 10 ms
+    pub supervision_timeout: u16,
+}
+
+impl ConnectionComplete {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META {
+            return None;
+        }
+
+        // the enhanced event has the local and peer resolvable private addresses before the parameters
+        let params = match *payload.first()? {
+            EVT_LE_CONNECTION_COMPLETE if payload.len() >= 19 => 12,
+            EVT_LE_ENHANCED_CONNECTION_COMPLETE if payload.len() >= 31 => 24,
+            _ => return None,
+        };
+
+        Some(Self {
+            status: payload[1],
+            connection_handle: u16::from_le_bytes([payload[2], payload[3]]),
+            role: payload[4],
+            peer_address_type: payload[5].try_into().ok()?,
+            peer_address: BdAddr::from_bytes(&payload[6..]).ok()?,
+            interval: u16::from_le_bytes([payload[params], payload[params + 1]]),
+            latency: u16::from_le_bytes([payload[params + 2], payload[params + 3]]),
+            supervision_timeout: u16::from_le_bytes([payload[params + 4], payload[params + 5]]),
+        })
+    }
+}
+
+/// HCI `LE Connection Update Complete` event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionUpdateComplete {
+    pub status: u8,
+    pub connection_handle: u16,
+    /// Connection interval in use, in units of 1.25 ms
+    pub interval: u16,
+    /// Peripheral latency in use, in connection events
+    pub latency: u16,
+    /// Supervision timeout in use, in units of 10 ms
+    pub supervision_timeout: u16,
+}
+
+impl ConnectionUpdateComplete {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META || payload.len() < 10 || payload[0] != EVT_LE_CONNECTION_UPDATE_COMPLETE {
+            return None;
+        }
+
+        Some(Self {
+            status: payload[1],
+            connection_handle: u16::from_le_bytes([payload[2], payload[3]]),
+            interval: u16::from_le_bytes([payload[4], payload[5]]),
+            latency: u16::from_le_bytes([payload[6], payload[7]]),
+            supervision_timeout: u16::from_le_bytes([payload[8], payload[9]]),
+        })
+    }
+}
+
+/// HCI `LE Data Length Change` event, reporting the largest link layer packets used on a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DataLengthChange {
+    pub connection_handle: u16,
+    /// Maximum payload of the transmitted packets, in bytes
+    pub max_tx_octets: u16,
+    /// Maximum duration of the transmitted packets, in microseconds
+    pub max_tx_time: u16,
+    /// Maximum payload of the received packets, in bytes
+    pub max_rx_octets: u16,
+    /// Maximum duration of the received packets, in microseconds
+    pub max_rx_time: u16,
+}
+
+impl DataLengthChange {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META || payload.len() < 11 || payload[0] != EVT_LE_DATA_LENGTH_CHANGE {
+            return None;
+        }
+
+        Some(Self {
+            connection_handle: u16::from_le_bytes([payload[1], payload[2]]),
+            max_tx_octets: u16::from_le_bytes([payload[3], payload[4]]),
+            max_tx_time: u16::from_le_bytes([payload[5], payload[6]]),
+            max_rx_octets: u16::from_le_bytes([payload[7], payload[8]]),
+            max_rx_time: u16::from_le_bytes([payload[9], payload[10]]),
+        })
+    }
+}
+
+/// HCI `LE Read Remote Features Complete` event, giving the LE features supported by the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReadRemoteFeaturesComplete {
+    pub status: u8,
+    pub connection_handle: u16,
+    /// Features of the peer, only meaningful if the status is zero
+    pub features: LeFeatures,
+}
+
+impl ReadRemoteFeaturesComplete {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META || payload.len() < 12 || payload[0] != EVT_LE_READ_REMOTE_FEATURES_COMPLETE {
+            return None;
+        }
+
+        Some(Self {
+            status: payload[1],
+            connection_handle: u16::from_le_bytes([payload[2], payload[3]]),
+            features: LeFeatures::from_bytes(&payload[4..]).ok()?,
+        })
+    }
+}
+
+/// HCI `LE PHY Update Complete` event, reporting the PHYs used on a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PhyUpdateComplete {
+    pub status: u8,
+    pub connection_handle: u16,
+    /// PHY used for transmission, only meaningful if the status is zero
+    pub tx_phy: Phy,
+    /// PHY used for reception, only meaningful if the status is zero
+    pub rx_phy: Phy,
+}
+
+impl PhyUpdateComplete {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META || payload.len() < 6 || payload[0] != EVT_LE_PHY_UPDATE_COMPLETE {
+            return None;
+        }
+
+        Some(Self {
+            status: payload[1],
+            connection_handle: u16::from_le_bytes([payload[2], payload[3]]),
+            tx_phy: payload[4].try_into().unwrap_or_default(),
+            rx_phy: payload[5].try_into().unwrap_or_default(),
+        })
+    }
+}
+
+/// HCI `LE Long Term Key Request` event, sent to a peripheral when the central starts encryption
+///
+/// The host answers with [`Ble::ltk_request_reply`](crate::sub::ble::Ble::ltk_request_reply) or
+/// [`Ble::ltk_request_negative_reply`](crate::sub::ble::Ble::ltk_request_negative_reply).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LongTermKeyRequest {
+    pub connection_handle: u16,
+    /// `Rand` value of the key, zero for LE Secure Connections
+    pub random_number: u64,
+    /// `EDIV` value of the key, zero for LE Secure Connections
+    pub encrypted_diversifier: u16,
+}
+
+impl LongTermKeyRequest {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META || payload.len() < 13 || payload[0] != EVT_LE_LONG_TERM_KEY_REQUEST {
+            return None;
+        }
+
+        Some(Self {
+            connection_handle: u16::from_le_bytes([payload[1], payload[2]]),
+            random_number: u64::from_le_bytes(payload[3..11].try_into().unwrap()),
+            encrypted_diversifier: u16::from_le_bytes([payload[11], payload[12]]),
+        })
+    }
+}
+
+/// Vendor specific event sent by the wireless stack
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VendorEvent<'a> {
+    /// Event code of the ACI event
+    pub ecode: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> VendorEvent<'a> {
+    pub fn parse(evt_code: u8, payload: &'a [u8]) -> Option<Self> {
+        if evt_code != TL_BLEEVT_VS_OPCODE || payload.len() < 2 {
+            return None;
+        }
+
+        Some(Self {
+            ecode: u16::from_le_bytes([payload[0], payload[1]]),
+            data: &payload[2..],
+        })
+    }
+}
+
+/// `ACI_GAP_Pairing_Complete_Event`
+///
+/// On success with bonding, the keys have been stored in the security database of the wireless stack.
+/// CPU2 then reports the update of its NVM through a system event, see
+/// [`SysNvmEvent`](crate::shci::SysNvmEvent), which is when the bonds should be persisted to flash.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PairingComplete {
+    pub connection_handle: u16,
+    pub status: PairingStatus,
+    /// SMP error code when the status is [`PairingStatus::PairingFailed`]
+    pub reason: u8,
+}
+
+impl PairingComplete {
+    pub fn parse(evt: &VendorEvent) -> Option<Self> {
+        if evt.ecode != ACI_GAP_PAIRING_COMPLETE_EVENT || evt.data.len() < 4 {
+            return None;
+        }
+
+        Some(Self {
+            connection_handle: u16::from_le_bytes([evt.data[0], evt.data[1]]),
+            status: evt.data[2].try_into().ok()?,
+            reason: evt.data[3],
+        })
+    }
+}
+
+/// `ACI_GAP_Pass_Key_Req_Event`, sent when the pairing needs the pass key of the connection
+///
+/// It is answered with [`Ble::pass_key_response`](crate::sub::ble::Ble::pass_key_response).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PassKeyReq {
+    pub connection_handle: u16,
+}
+
+impl PassKeyReq {
+    pub fn parse(evt: &VendorEvent) -> Option<Self> {
+        if evt.ecode != ACI_GAP_PASS_KEY_REQ_EVENT || evt.data.len() < 2 {
+            return None;
+        }
+
+        Some(Self {
+            connection_handle: u16::from_le_bytes([evt.data[0], evt.data[1]]),
+        })
+    }
+}
+
+/// `ACI_GAP_Numeric_Comparison_Value_Event`, sent during a LE Secure Connections pairing with numeric
+/// comparison
+///
+/// The value is shown to the user, and the answer given with
+/// [`Ble::numeric_comparison_confirm`](crate::sub::ble::Ble::numeric_comparison_confirm).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NumericComparisonValue {
+    pub connection_handle: u16,
+    /// Value to compare, from 0 to 999999
+    pub numeric_value: u32,
+}
+
+impl NumericComparisonValue {
+    pub fn parse(evt: &VendorEvent) -> Option<Self> {
+        if evt.ecode != ACI_GAP_NUMERIC_COMPARISON_VALUE_EVENT || evt.data.len() < 6 {
+            return None;
+        }
+
+        Some(Self {
+            connection_handle: u16::from_le_bytes([evt.data[0], evt.data[1]]),
+            numeric_value: u32::from_le_bytes([evt.data[2], evt.data[3], evt.data[4], evt.data[5]]),
+        })
+    }
+}
+
+/// `ACI_GAP_Bond_Lost_Event`, sent when a bonded peer asks to pair again
+///
+/// The wireless stack rejects the pairing unless [`Ble::allow_rebond`](crate::sub::ble::Ble::allow_rebond)
+/// is called. The event does not give the connection it applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BondLost;
+
+impl BondLost {
+    pub fn parse(evt: &VendorEvent) -> Option<Self> {
+        (evt.ecode == ACI_GAP_BOND_LOST_EVENT).then_some(Self)
+    }
+}
+
+/// `ACI_ATT_Exchange_MTU_Resp_Event`, reporting the ATT_MTU agreed on a connection
+///
+/// It is sent whichever device started the exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AttExchangeMtuResp {
+    pub connection_handle: u16,
+    pub mtu: u16,
+}
+
+impl AttExchangeMtuResp {
+    pub fn parse(evt: &VendorEvent) -> Option<Self> {
+        if evt.ecode != ACI_ATT_EXCHANGE_MTU_RESP_EVENT || evt.data.len() < 4 {
+            return None;
+        }
+
+        Some(Self {
+            connection_handle: u16::from_le_bytes([evt.data[0], evt.data[1]]),
+            mtu: u16::from_le_bytes([evt.data[2], evt.data[3]]),
+        })
+    }
+}
+
+/// `ACI_GATT_Proc_Complete_Event`, sent when a GATT procedure started by the application is over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GattProcComplete {
+    pub connection_handle: u16,
+    /// `0x00` on success, or an HCI error code
+    pub error_code: u8,
+}
+
+impl GattProcComplete {
+    pub fn parse(evt: &VendorEvent) -> Option<Self> {
+        if evt.ecode != ACI_GATT_PROC_COMPLETE_EVENT || evt.data.len() < 3 {
+            return None;
+        }
+
+        Some(Self {
+            connection_handle: u16::from_le_bytes([evt.data[0], evt.data[1]]),
+            error_code: evt.data[2],
+        })
+    }
+}
+
+/// `ACI_GATT_Tx_Pool_Available_Event`, sent when buffers are available again after a notification or
+/// indication failed for lack of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TxPoolAvailable {
+    pub connection_handle: u16,
+    /// Number of buffers available for notifications and indications
+    pub available_buffers: u16,
+}
+
+impl TxPoolAvailable {
+    pub fn parse(evt: &VendorEvent) -> Option<Self> {
+        if evt.ecode != ACI_GATT_TX_POOL_AVAILABLE_EVENT || evt.data.len() < 4 {
+            return None;
+        }
+
+        Some(Self {
+            connection_handle: u16::from_le_bytes([evt.data[0], evt.data[1]]),
+            available_buffers: u16::from_le_bytes([evt.data[2], evt.data[3]]),
+        })
+    }
+}
+
+/// Event answering a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CommandReply {
+    /// `Command Complete`: the command is over, and its return parameters follow the status
+    Complete,
+    /// `Command Status`: the command has started, its outcome is reported later by a dedicated event
+    Status,
+}
+
+/// Answer to the command `opcode`, if the event is one
+///
+/// A command is answered by either a `Command Complete` or a `Command Status` event, depending on the
+/// command. A non-zero status is returned as an error, whichever event reports it.
+pub fn command_result(opcode: u16, evt_code: u8, payload: &[u8]) -> Option<Result<CommandReply, BleError>> {
+    let (reply, status) = if let Some(cc) = CommandComplete::parse(evt_code, payload) {
+        (cc.opcode == opcode).then_some((CommandReply::Complete, cc.status))?
+    } else {
+        let cs = CommandStatus::parse(evt_code, payload)?;
+        (cs.opcode == opcode).then_some((CommandReply::Status, cs.status))?
+    };
+
+    match status {
+        0 => Some(Ok(reply)),
+        status => Some(Err(BleError::Status(status))),
+    }
+}
+
+/// Connection handle of a connection-scoped event
+///
+/// This covers the HCI events of a connection, the GAP events reporting pairing, and the GATT and ATT
+/// vendor events, which all start with the handle. Returns `None` for the other events, such as
+/// advertising reports or command completions.
+pub fn connection_handle(evt_code: u8, payload: &[u8]) -> Option<u16> {
+    let offset = match evt_code {
+        // status, then handle
+        EVT_DISCONNECTION_COMPLETE | EVT_ENCRYPTION_CHANGE | EVT_ENCRYPTION_KEY_REFRESH_COMPLETE => 1,
+        EVT_LE_META => match *payload.first()? {
+            EVT_LE_LONG_TERM_KEY_REQUEST | EVT_LE_DATA_LENGTH_CHANGE => 1,
+            // status, then handle
+            EVT_LE_CONNECTION_COMPLETE
+            | EVT_LE_CONNECTION_UPDATE_COMPLETE
+            | EVT_LE_READ_REMOTE_FEATURES_COMPLETE
+            | EVT_LE_ENHANCED_CONNECTION_COMPLETE
+            | EVT_LE_PHY_UPDATE_COMPLETE => 2,
+            _ => return None,
+        },
+        TL_BLEEVT_VS_OPCODE => match VendorEvent::parse(evt_code, payload)?.ecode {
+            // pairing, pass key, authorization, numeric comparison and keypress events, then GATT and ATT
+            0x0401..=0x0403 | 0x0409 | 0x040A | 0x0C00..=0x0CFF => 2,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let handle = payload.get(offset..offset + 2)?;
+
+    Some(u16::from_le_bytes([handle[0], handle[1]]) & 0x0FFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_of_completed_packets() {
+        let payload = [2, 0x01, 0x00, 3, 0, 0x02, 0x00, 1, 0];
+        let nocp = NumberOfCompletedPackets::parse(EVT_NUMBER_OF_COMPLETED_PACKETS, &payload).unwrap();
+
+        let mut entries = nocp.iter();
+        assert_eq!(entries.next(), Some((1, 3)));
+        assert_eq!(entries.next(), Some((2, 1)));
+        assert_eq!(entries.next(), None);
+        assert_eq!(nocp.total(), 4);
+
+        // truncated event
+        assert!(NumberOfCompletedPackets::parse(EVT_NUMBER_OF_COMPLETED_PACKETS, &payload[..7]).is_none());
+        assert!(NumberOfCompletedPackets::parse(TL_BLEEVT_CC_OPCODE, &payload).is_none());
+    }
+
+    #[test]
+    fn command_results() {
+        // LE Set Scan Enable complete, then status events of HCI_Disconnect
+        let complete = [1, 0x0C, 0x20, 0x00];
+        assert_eq!(
+            command_result(0x200C, TL_BLEEVT_CC_OPCODE, &complete),
+            Some(Ok(CommandReply::Complete))
+        );
+        assert_eq!(command_result(0x200B, TL_BLEEVT_CC_OPCODE, &complete), None);
+        assert_eq!(
+            command_result(0x200C, TL_BLEEVT_CC_OPCODE, &[1, 0x0C, 0x20, 0x0C]),
+            Some(Err(BleError::Status(0x0C)))
+        );
+
+        assert_eq!(
+            command_result(0x0406, TL_BLEEVT_CS_OPCODE, &[0x00, 1, 0x06, 0x04]),
+            Some(Ok(CommandReply::Status))
+        );
+        assert_eq!(
+            command_result(0x0406, TL_BLEEVT_CS_OPCODE, &[0x02, 1, 0x06, 0x04]),
+            Some(Err(BleError::Status(0x02)))
+        );
+        assert_eq!(command_result(0x0406, TL_BLEEVT_VS_OPCODE, &complete), None);
+    }
+
+    #[test]
+    fn status_then_meta_event() {
+        // HCI_LE_Connection_Update starts, then completes with an LE meta event
+        let status = [0x00, 1, 0x13, 0x20];
+        let complete = [
+            EVT_LE_CONNECTION_UPDATE_COMPLETE,
+            0x00,
+            0x01,
+            0x08,
+            0x18,
+            0x00,
+            0x00,
+            0x00,
+            0x48,
+            0x00,
+        ];
+
+        assert_eq!(
+            command_result(0x2013, TL_BLEEVT_CS_OPCODE, &status),
+            Some(Ok(CommandReply::Status))
+        );
+        assert_eq!(command_result(0x2013, EVT_LE_META, &complete), None);
+
+        let evt = ConnectionUpdateComplete::parse(EVT_LE_META, &complete).unwrap();
+        assert_eq!((evt.status, evt.connection_handle, evt.interval), (0, 0x0801, 24));
+    }
+
+    #[test]
+    fn connection_complete() {
+        let mut payload = [0u8; 31];
+        payload[..12].copy_from_slice(&[
+            EVT_LE_CONNECTION_COMPLETE,
+            0x00,
+            0x01,
+            0x08,
+            0x00,
+            0x01,
+            1,
+            2,
+            3,
+            4,
+            5,
+            6,
+        ]);
+        payload[12..18].copy_from_slice(&[0x18, 0x00, 0x00, 0x00, 0x48, 0x00]);
+
+        let evt = ConnectionComplete::parse(EVT_LE_META, &payload[..19]).unwrap();
+        assert_eq!((evt.status, evt.connection_handle, evt.role), (0, 0x0801, 0));
+        assert_eq!(evt.peer_address, BdAddr([1, 2, 3, 4, 5, 6]));
+        assert_eq!((evt.interval, evt.supervision_timeout), (24, 72));
+        assert!(ConnectionComplete::parse(EVT_LE_META, &payload[..18]).is_none());
+
+        // the enhanced event carries the resolvable private addresses before the parameters
+        payload[0] = EVT_LE_ENHANCED_CONNECTION_COMPLETE;
+        payload.copy_within(12..18, 24);
+        payload[12..24].fill(0);
+        assert_eq!(ConnectionComplete::parse(EVT_LE_META, &payload), Some(evt));
+    }
+
+    #[test]
+    fn disconnection_complete() {
+        let payload = [0x00, 0x01, 0x08, 0x13];
+        let evt = DisconnectionComplete::parse(EVT_DISCONNECTION_COMPLETE, &payload).unwrap();
+
+        assert_eq!(evt.connection_handle, 0x0801);
+        assert_eq!(evt.reason, DisconnectReason::RemoteUserTerminated);
+        assert_eq!(evt.reason.code(), 0x13);
+        assert!(DisconnectionComplete::parse(EVT_DISCONNECTION_COMPLETE, &payload[..3]).is_none());
+    }
+
+    #[test]
+    fn encryption_events() {
+        let payload = [0x00, 0x01, 0x08, 0x01];
+        let evt = EncryptionChange::parse(EVT_ENCRYPTION_CHANGE, &payload).unwrap();
+
+        assert_eq!((evt.status, evt.connection_handle, evt.enabled), (0, 0x0801, true));
+        assert!(EncryptionChange::parse(EVT_ENCRYPTION_CHANGE, &payload[..3]).is_none());
+
+        let evt = EncryptionKeyRefreshComplete::parse(EVT_ENCRYPTION_KEY_REFRESH_COMPLETE, &payload[..3]).unwrap();
+        assert_eq!((evt.status, evt.connection_handle), (0, 0x0801));
+        assert_eq!(
+            connection_handle(EVT_ENCRYPTION_KEY_REFRESH_COMPLETE, &payload[..3]),
+            Some(0x0801)
+        );
+        assert!(EncryptionKeyRefreshComplete::parse(EVT_ENCRYPTION_CHANGE, &payload).is_none());
+    }
+
+    #[test]
+    fn exchange_mtu() {
+        let payload = [0x05, 0x0C, 0x01, 0x08, 0xF7, 0x00];
+        let evt = VendorEvent::parse(TL_BLEEVT_VS_OPCODE, &payload).unwrap();
+
+        assert_eq!(
+            AttExchangeMtuResp::parse(&evt),
+            Some(AttExchangeMtuResp {
+                connection_handle: 0x0801,
+                mtu: 247
+            })
+        );
+        assert_eq!(GattProcComplete::parse(&evt), None);
+    }
+
+    #[test]
+    fn tx_pool_available() {
+        let payload = [0x16, 0x0C, 0x01, 0x08, 0x03, 0x00];
+        let evt = VendorEvent::parse(TL_BLEEVT_VS_OPCODE, &payload).unwrap();
+
+        assert_eq!(
+            TxPoolAvailable::parse(&evt),
+            Some(TxPoolAvailable {
+                connection_handle: 0x0801,
+                available_buffers: 3
+            })
+        );
+        assert_eq!(AttExchangeMtuResp::parse(&evt), None);
+    }
+
+    #[test]
+    fn security_requests() {
+        let payload = [0x02, 0x04, 0x01, 0x08];
+        let evt = VendorEvent::parse(TL_BLEEVT_VS_OPCODE, &payload).unwrap();
+        assert_eq!(
+            PassKeyReq::parse(&evt),
+            Some(PassKeyReq {
+                connection_handle: 0x0801
+            })
+        );
+        assert_eq!(NumericComparisonValue::parse(&evt), None);
+
+        let payload = [0x09, 0x04, 0x01, 0x08, 0x3F, 0x42, 0x0F, 0x00];
+        let evt = VendorEvent::parse(TL_BLEEVT_VS_OPCODE, &payload).unwrap();
+        assert_eq!(
+            NumericComparisonValue::parse(&evt),
+            Some(NumericComparisonValue {
+                connection_handle: 0x0801,
+                numeric_value: 999_999
+            })
+        );
+        assert_eq!(connection_handle(TL_BLEEVT_VS_OPCODE, &payload), Some(0x0801));
+
+        let evt = VendorEvent::parse(TL_BLEEVT_VS_OPCODE, &[0x05, 0x04]).unwrap();
+        assert_eq!(BondLost::parse(&evt), Some(BondLost));
+        assert_eq!(PassKeyReq::parse(&evt), None);
+    }
+
+    #[test]
+    fn event_connection_handle() {
+        // disconnection complete, phy update complete, exchange MTU response, advertising report
+        assert_eq!(
+            connection_handle(EVT_DISCONNECTION_COMPLETE, &[0x00, 0x01, 0x08, 0x13]),
+            Some(0x0801)
+        );
+        assert_eq!(
+            connection_handle(EVT_LE_META, &[EVT_LE_PHY_UPDATE_COMPLETE, 0x00, 0x02, 0x08, 2, 2]),
+            Some(0x0802)
+        );
+        assert_eq!(
+            connection_handle(TL_BLEEVT_VS_OPCODE, &[0x05, 0x0C, 0x03, 0x08, 0xF7, 0x00]),
+            Some(0x0803)
+        );
+        assert_eq!(connection_handle(EVT_LE_META, &[EVT_LE_ADVERTISING_REPORT, 1]), None);
+    }
+
+    #[test]
+    fn data_length_change() {
+        let payload = [
+            EVT_LE_DATA_LENGTH_CHANGE,
+            0x01,
+            0x08,
+            0xFB,
+            0x00,
+            0x48,
+            0x08,
+            0x1B,
+            0x00,
+            0x48,
+            0x01,
+        ];
+        let evt = DataLengthChange::parse(EVT_LE_META, &payload).unwrap();
+
+        assert_eq!(evt.connection_handle, 0x0801);
+        assert_eq!((evt.max_tx_octets, evt.max_tx_time), (251, 2120));
+        assert_eq!((evt.max_rx_octets, evt.max_rx_time), (27, 328));
+        assert!(DataLengthChange::parse(EVT_LE_META, &payload[..10]).is_none());
+
+        assert!(crate::ble::commands::SetDataLength::new(0x0801, 251, 2120).is_ok());
+        assert!(crate::ble::commands::SetDataLength::new(0x0801, 252, 2120).is_err());
+        assert!(crate::ble::commands::SetDataLength::new(0x0801, 27, 327).is_err());
+    }
+
+    #[test]
+    fn read_remote_features_complete() {
+        let payload = [
+            EVT_LE_READ_REMOTE_FEATURES_COMPLETE,
+            0x00,
+            0x01,
+            0x08,
+            0x21,
+            0x09,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        let evt = ReadRemoteFeaturesComplete::parse(EVT_LE_META, &payload).unwrap();
+
+        assert_eq!((evt.status, evt.connection_handle), (0, 0x0801));
+        assert!(evt.features.le_encryption() && evt.features.le_data_packet_length_extension());
+        assert!(evt.features.le_2m_phy() && evt.features.le_coded_phy());
+        assert!(!evt.features.le_ping());
+        assert_eq!(connection_handle(EVT_LE_META, &payload), Some(0x0801));
+        assert!(ReadRemoteFeaturesComplete::parse(EVT_LE_META, &payload[..11]).is_none());
+    }
+
+    #[test]
+    fn phy_update_complete() {
+        use crate::ble::commands::{BleCommand, SetPhy};
+        use crate::ble::typedefs::PhyCoding;
+
+        let payload = [EVT_LE_PHY_UPDATE_COMPLETE, 0x00, 0x01, 0x08, 0x02, 0x03];
+        let evt = PhyUpdateComplete::parse(EVT_LE_META, &payload).unwrap();
+
+        assert_eq!(evt.connection_handle, 0x0801);
+        assert_eq!((evt.tx_phy, evt.rx_phy), (Phy::Le2M, Phy::LeCoded));
+        assert!(PhyUpdateComplete::parse(EVT_LE_META, &payload[..5]).is_none());
+
+        let cmd = SetPhy::new(0x0801, Phy::Le2M, Phy::LeCoded, PhyCoding::S8);
+        assert_eq!(cmd.payload(), &[0x01, 0x08, 0x00, 0x02, 0x04, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn long_term_key_request() {
+        let payload = [
+            EVT_LE_LONG_TERM_KEY_REQUEST,
+            0x01,
+            0x08,
+            1,
+            2,
+            3,
+            4,
+            5,
+            6,
+            7,
+            8,
+            0x34,
+            0x12,
+        ];
+        let evt = LongTermKeyRequest::parse(EVT_LE_META, &payload).unwrap();
+
+        assert_eq!(evt.connection_handle, 0x0801);
+        assert_eq!(evt.random_number, 0x0807_0605_0403_0201);
+        assert_eq!(evt.encrypted_diversifier, 0x1234);
+        assert_eq!(connection_handle(EVT_LE_META, &payload), Some(0x0801));
+        assert!(LongTermKeyRequest::parse(EVT_LE_META, &payload[..12]).is_none());
+    }
+
+    #[test]
+    fn pairing_complete() {
+        let payload = [0x01, 0x04, 0x01, 0x08, 0x02, 0x05];
+        let evt = VendorEvent::parse(TL_BLEEVT_VS_OPCODE, &payload).unwrap();
+        let pairing = PairingComplete::parse(&evt).unwrap();
+
+        assert_eq!(pairing.connection_handle, 0x0801);
+        assert_eq!(pairing.status, PairingStatus::PairingFailed);
+        assert_eq!(pairing.reason, 0x05);
+    }
+
+    #[test]
+    fn advertising_reports() {
+        let payload = [
+            EVT_LE_ADVERTISING_REPORT,
+            2,
+            // non connectable report with 3 bytes of data
+            0x03,
+            0x00,
+            1,
+            2,
+            3,
+            4,
+            5,
+            6,
+            3,
+            0x02,
+            0x01,
+            0x06,
+            0xC4,
+            // truncated report
+            0x00,
+            0x00,
+        ];
+        let mut reports = AdvertisingReports::parse(EVT_LE_META, &payload).unwrap();
+
+        let report = reports.next().unwrap();
+        assert_eq!(report.event_type, crate::ble::typedefs::AdvReportType::AdvNonconnInd);
+        assert_eq!(report.address, BdAddr([1, 2, 3, 4, 5, 6]));
+        assert_eq!(report.data(), &[0x02, 0x01, 0x06]);
+        assert_eq!(report.rssi, -60);
+
+        assert!(reports.next().is_none());
+    }
+
+    #[test]
+    fn connection_update_complete() {
+        let payload = [
+            EVT_LE_CONNECTION_UPDATE_COMPLETE,
+            0x00,
+            0x01,
+            0x08,
+            0x18,
+            0x00,
+            0x02,
+            0x00,
+            0x90,
+            0x01,
+        ];
+
+        assert_eq!(
+            ConnectionUpdateComplete::parse(EVT_LE_META, &payload),
+            Some(ConnectionUpdateComplete {
+                status: 0,
+                connection_handle: 0x0801,
+                interval: 24,
+                latency: 2,
+                supervision_timeout: 400,
+            })
+        );
+        assert!(ConnectionUpdateComplete::parse(EVT_LE_META, &payload[..9]).is_none());
+    }
+}
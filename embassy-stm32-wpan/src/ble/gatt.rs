@@ -0,0 +1,591 @@
+//! ST vendor commands and events of the GATT server of the wireless stack
+//!
+//! The wireless stack hosts the attribute database: services and characteristics are added with
+//! `ACI_GATT_*` commands, which return the attribute handles assigned to them. The accesses of the
+//! clients are reported with `ACI_GATT_*` vendor events, according to the [`GattEventMask`] of each
+//! characteristic.
+
+use heapless::Vec;
+
+use super::commands::HciCommand;
+use super::consts::{
+    ACI_GATT_ATTRIBUTE_MODIFIED_EVENT, ACI_GATT_READ_PERMIT_REQ_EVENT, ACI_GATT_WRITE_PERMIT_REQ_EVENT,
+};
+use super::event::VendorEvent;
+use super::opcodes::HciOpcode;
+use super::typedefs::BleError;
+
+/// Largest value written by a single `ACI_GATT_Update_Char_Value` command
+pub const MAX_CHAR_VALUE_UPDATE_LEN: usize = 255 - 6;
+/// Largest value given back by a single `ACI_GATT_Write_Resp` command
+pub const MAX_WRITE_RESP_VALUE_LEN: usize = 255 - 7;
+
+/// UUID of a service or characteristic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Uuid {
+    /// 16-bit UUID assigned by the Bluetooth SIG
+    Uuid16(u16),
+    /// 128-bit UUID, least significant byte first
+    Uuid128([u8; 16]),
+}
+
+impl Uuid {
+    fn push_into<const N: usize>(&self, cmd: &mut HciCommand<N>) -> Result<(), BleError> {
+        match self {
+            Self::Uuid16(uuid) => cmd.push_u8(0x01)?.push_u16(*uuid)?,
+            Self::Uuid128(uuid) => cmd.push_u8(0x02)?.push_slice(uuid)?,
+        };
+
+        Ok(())
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ServiceType {
+    #[default]
+    Primary = 0x01,
+    Secondary = 0x02,
+}
+
+/// Properties of a characteristic, as exposed in its declaration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CharProperties(pub u8);
+
+impl CharProperties {
+    pub const BROADCAST: Self = Self(0x01);
+    pub const READ: Self = Self(0x02);
+    pub const WRITE_WITHOUT_RESPONSE: Self = Self(0x04);
+    pub const WRITE: Self = Self(0x08);
+    pub const NOTIFY: Self = Self(0x10);
+    pub const INDICATE: Self = Self(0x20);
+    pub const AUTHENTICATED_SIGNED_WRITES: Self = Self(0x40);
+    pub const EXTENDED_PROPERTIES: Self = Self(0x80);
+}
+
+impl core::ops::BitOr for CharProperties {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Security required to access the value of a characteristic
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SecurityPermissions(pub u8);
+
+impl SecurityPermissions {
+    pub const NONE: Self = Self(0x00);
+    pub const AUTHENTICATED_READ: Self = Self(0x01);
+    pub const AUTHORIZED_READ: Self = Self(0x02);
+    pub const ENCRYPTED_READ: Self = Self(0x04);
+    pub const AUTHENTICATED_WRITE: Self = Self(0x08);
+    pub const AUTHORIZED_WRITE: Self = Self(0x10);
+    pub const ENCRYPTED_WRITE: Self = Self(0x20);
+}
+
+impl core::ops::BitOr for SecurityPermissions {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Accesses to a characteristic reported to the application with vendor events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GattEventMask(pub u8);
+
+impl GattEventMask {
+    pub const NONE: Self = Self(0x00);
+    /// Report the writes to the value once done
+    pub const ATTRIBUTE_WRITE: Self = Self(0x01);
+    /// Report the write requests, which wait for the application to accept them
+    pub const WRITE_REQUEST_AND_WAIT: Self = Self(0x02);
+    /// Report the read requests, which wait for the application to allow them
+    pub const READ_REQUEST_AND_WAIT: Self = Self(0x04);
+}
+
+impl core::ops::BitOr for GattEventMask {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// GAP roles of the device, given to [`gap_init`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GapRole(pub u8);
+
+impl GapRole {
+    pub const PERIPHERAL: Self = Self(0x01);
+    pub const BROADCASTER: Self = Self(0x02);
+    pub const CENTRAL: Self = Self(0x04);
+    pub const OBSERVER: Self = Self(0x08);
+}
+
+impl core::ops::BitOr for GapRole {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Handles of the GAP service and of its characteristics, as returned by `ACI_GAP_Init`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GapHandles {
+    pub service: u16,
+    pub device_name: u16,
+    pub appearance: u16,
+}
+
+impl GapHandles {
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, BleError> {
+        if buf.len() < 6 {
+            return Err(BleError::InvalidResponse);
+        }
+
+        Ok(Self {
+            service: u16::from_le_bytes([buf[0], buf[1]]),
+            device_name: u16::from_le_bytes([buf[2], buf[3]]),
+            appearance: u16::from_le_bytes([buf[4], buf[5]]),
+        })
+    }
+}
+
+/// Declaration of a characteristic added with [`add_char`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CharConfig {
+    pub uuid: Uuid,
+    /// Maximum length of the value
+    pub value_len: u16,
+    pub properties: CharProperties,
+    pub permissions: SecurityPermissions,
+    pub event_mask: GattEventMask,
+    /// Minimum encryption key size required to access the value, from 7 to 16
+    pub encryption_key_size: u8,
+    /// Whether the value can be shorter than `value_len`
+    pub variable_len: bool,
+}
+
+/// `ACI_GATT_Add_Service`
+///
+/// `max_attribute_records` is the number of attributes reserved for the service, including its
+/// declaration: each characteristic takes two, three if it can be notified or indicated.
+pub fn add_service(uuid: &Uuid, service_type: ServiceType, max_attribute_records: u8) -> HciCommand<19> {
+    let mut cmd = HciCommand::new(HciOpcode::AciGattAddService);
+
+    // at most 19 bytes with a 128-bit UUID
+    unwrap!(uuid.push_into(&mut cmd));
+    unwrap!(cmd.push_u8(service_type as u8));
+    unwrap!(cmd.push_u8(max_attribute_records));
+
+    cmd
+}
+
+/// `ACI_GATT_Add_Char`
+pub fn add_char(service_handle: u16, config: &CharConfig) -> Result<HciCommand<26>, BleError> {
+    if !(7..=16).contains(&config.encryption_key_size) {
+        return Err(BleError::InvalidParameters);
+    }
+
+    let mut cmd = HciCommand::new(HciOpcode::AciGattAddChar);
+    cmd.push_u16(service_handle)?;
+    config.uuid.push_into(&mut cmd)?;
+    cmd.push_u16(config.value_len)?
+        .push_u8(config.properties.0)?
+        .push_u8(config.permissions.0)?
+        .push_u8(config.event_mask.0)?
+        .push_u8(config.encryption_key_size)?
+        .push_u8(config.variable_len as u8)?;
+
+    Ok(cmd)
+}
+
+/// `ACI_GAP_Init`, adding the GAP service with a device name characteristic of `name_len` bytes
+///
+/// Privacy is disabled, the device uses the address set with the HCI commands.
+pub fn gap_init(role: GapRole, name_len: usize) -> Result<HciCommand<3>, BleError> {
+    if role.0 == 0 || name_len == 0 || name_len > MAX_CHAR_VALUE_UPDATE_LEN {
+        return Err(BleError::InvalidParameters);
+    }
+
+    let mut cmd = HciCommand::new(HciOpcode::AciGapInit);
+    cmd.push_u8(role.0)?.push_u8(0x00)?.push_u8(name_len as u8)?;
+
+    Ok(cmd)
+}
+
+/// `ACI_GATT_Update_Char_Value`
+///
+/// Write `value` at `offset` in the value of a characteristic. The clients that enabled notifications or
+/// indications are notified of the new value.
+pub fn update_char_value(
+    service_handle: u16,
+    char_handle: u16,
+    offset: u8,
+    value: &[u8],
+) -> Result<HciCommand<255>, BleError> {
+    if value.len() > MAX_CHAR_VALUE_UPDATE_LEN {
+        return Err(BleError::InvalidParameters);
+    }
+
+    let mut cmd = HciCommand::new(HciOpcode::AciGattUpdateCharValue);
+    cmd.push_u16(service_handle)?
+        .push_u16(char_handle)?
+        .push_u8(offset)?
+        .push_u8(value.len() as u8)?
+        .push_slice(value)?;
+
+    Ok(cmd)
+}
+
+/// `ACI_GATT_Allow_Read`, letting the read request of a [`ReadPermitReq`] proceed
+pub fn allow_read(connection_handle: u16) -> HciCommand<2> {
+    let mut cmd = HciCommand::new(HciOpcode::AciGattAllowRead);
+    unwrap!(cmd.push_u16(connection_handle));
+
+    cmd
+}
+
+/// `ACI_GATT_Write_Resp`, answering the write request of a [`WritePermitReq`]
+///
+/// The write is accepted if `error_code` is `None`, and `value` is then written to the attribute, usually
+/// the data of the request. Otherwise the client receives the ATT error code.
+pub fn write_resp(
+    connection_handle: u16,
+    attr_handle: u16,
+    error_code: Option<u8>,
+    value: &[u8],
+) -> Result<HciCommand<255>, BleError> {
+    if value.len() > MAX_WRITE_RESP_VALUE_LEN {
+        return Err(BleError::InvalidParameters);
+    }
+
+    let mut cmd = HciCommand::new(HciOpcode::AciGattWriteResp);
+    cmd.push_u16(connection_handle)?
+        .push_u16(attr_handle)?
+        .push_u8(error_code.is_some() as u8)?
+        .push_u8(error_code.unwrap_or(0))?
+        .push_u8(value.len() as u8)?
+        .push_slice(value)?;
+
+    Ok(cmd)
+}
+
+/// `ACI_GATT_Attribute_Modified_Event`, sent once a client has written an attribute
+///
+/// Only sent for the characteristics with [`GattEventMask::ATTRIBUTE_WRITE`]. Writing a client
+/// characteristic configuration descriptor, to enable notifications, is reported as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AttributeModified<'a> {
+    pub connection_handle: u16,
+    pub attr_handle: u16,
+    /// Offset of `data` in the value, whose bit 15 is set if more data follows in another event
+    pub offset: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> AttributeModified<'a> {
+    pub fn parse(evt: &VendorEvent<'a>) -> Option<Self> {
+        if evt.ecode != ACI_GATT_ATTRIBUTE_MODIFIED_EVENT || evt.data.len() < 8 {
+            return None;
+        }
+
+        let len = u16::from_le_bytes([evt.data[6], evt.data[7]]) as usize;
+        Some(Self {
+            connection_handle: u16::from_le_bytes([evt.data[0], evt.data[1]]),
+            attr_handle: u16::from_le_bytes([evt.data[2], evt.data[3]]),
+            offset: u16::from_le_bytes([evt.data[4], evt.data[5]]),
+            data: evt.data.get(8..8 + len)?,
+        })
+    }
+}
+
+/// `ACI_GATT_Read_Permit_Req_Event`, sent when a client reads a characteristic with
+/// [`GattEventMask::READ_REQUEST_AND_WAIT`]
+///
+/// The value may be updated before letting the read proceed with [`allow_read`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReadPermitReq {
+    pub connection_handle: u16,
+    pub attr_handle: u16,
+    /// Offset of the read in the value
+    pub offset: u16,
+}
+
+impl ReadPermitReq {
+    pub fn parse(evt: &VendorEvent) -> Option<Self> {
+        if evt.ecode != ACI_GATT_READ_PERMIT_REQ_EVENT || evt.data.len() < 6 {
+            return None;
+        }
+
+        Some(Self {
+            connection_handle: u16::from_le_bytes([evt.data[0], evt.data[1]]),
+            attr_handle: u16::from_le_bytes([evt.data[2], evt.data[3]]),
+            offset: u16::from_le_bytes([evt.data[4], evt.data[5]]),
+        })
+    }
+}
+
+/// `ACI_GATT_Write_Permit_Req_Event`, sent when a client writes a characteristic with
+/// [`GattEventMask::WRITE_REQUEST_AND_WAIT`]
+///
+/// The write waits for [`write_resp`] to accept or reject it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WritePermitReq<'a> {
+    pub connection_handle: u16,
+    pub attr_handle: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> WritePermitReq<'a> {
+    pub fn parse(evt: &VendorEvent<'a>) -> Option<Self> {
+        if evt.ecode != ACI_GATT_WRITE_PERMIT_REQ_EVENT || evt.data.len() < 5 {
+            return None;
+        }
+
+        let len = evt.data[4] as usize;
+        Some(Self {
+            connection_handle: u16::from_le_bytes([evt.data[0], evt.data[1]]),
+            attr_handle: u16::from_le_bytes([evt.data[2], evt.data[3]]),
+            data: evt.data.get(5..5 + len)?,
+        })
+    }
+}
+
+/// Handle of a service added to the GATT server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServiceHandle(pub u16);
+
+/// Handle of a characteristic added to the GATT server, with the handle of its service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CharHandle {
+    pub service: ServiceHandle,
+    /// Handle of the characteristic declaration
+    pub declaration: u16,
+}
+
+impl CharHandle {
+    /// Handle of the characteristic value, which follows its declaration
+    pub const fn value(&self) -> u16 {
+        self.declaration + 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Attribute {
+    Service(ServiceHandle),
+    Char(CharHandle),
+}
+
+/// Handles assigned to the services and characteristics of the GATT server, looked up by UUID
+///
+/// The handles are recorded by [`Ble::gatt_db_add_service`](crate::sub::ble::Ble::gatt_db_add_service)
+/// and [`Ble::gatt_db_add_char`](crate::sub::ble::Ble::gatt_db_add_char), or with
+/// [`GattDb::insert_service`] and [`GattDb::insert_char`] for the attributes added otherwise. Up to `N`
+/// services and characteristics can be recorded.
+#[derive(Debug, Clone)]
+pub struct GattDb<const N: usize> {
+    attributes: Vec<(Uuid, Attribute), N>,
+}
+
+impl<const N: usize> GattDb<N> {
+    pub const fn new() -> Self {
+        Self { attributes: Vec::new() }
+    }
+
+    /// Record the handle of a service
+    ///
+    /// [`BleError::InvalidParameters`] is returned when the database is full.
+    pub fn insert_service(&mut self, uuid: Uuid, handle: ServiceHandle) -> Result<(), BleError> {
+        self.insert(uuid, Attribute::Service(handle))
+    }
+
+    /// Record the handle of a characteristic
+    ///
+    /// [`BleError::InvalidParameters`] is returned when the database is full.
+    pub fn insert_char(&mut self, uuid: Uuid, handle: CharHandle) -> Result<(), BleError> {
+        self.insert(uuid, Attribute::Char(handle))
+    }
+
+    fn insert(&mut self, uuid: Uuid, attribute: Attribute) -> Result<(), BleError> {
+        self.attributes
+            .push((uuid, attribute))
+            .map_err(|_| BleError::InvalidParameters)
+    }
+
+    /// Handle of the first service recorded with `uuid`
+    pub fn service(&self, uuid: &Uuid) -> Option<ServiceHandle> {
+        self.attributes.iter().find_map(|(u, attribute)| match attribute {
+            Attribute::Service(handle) if u == uuid => Some(*handle),
+            _ => None,
+        })
+    }
+
+    /// Handle of the characteristic recorded with `uuid` in `service`
+    pub fn char(&self, service: ServiceHandle, uuid: &Uuid) -> Option<CharHandle> {
+        self.attributes.iter().find_map(|(u, attribute)| match attribute {
+            Attribute::Char(handle) if u == uuid && handle.service == service => Some(*handle),
+            _ => None,
+        })
+    }
+
+    /// Handle of the first characteristic recorded with `uuid`, in any service
+    pub fn find_char(&self, uuid: &Uuid) -> Option<CharHandle> {
+        self.attributes.iter().find_map(|(u, attribute)| match attribute {
+            Attribute::Char(handle) if u == uuid => Some(*handle),
+            _ => None,
+        })
+    }
+
+    /// Number of services and characteristics recorded
+    pub fn len(&self) -> usize {
+        self.attributes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty()
+    }
+}
+
+impl<const N: usize> Default for GattDb<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_characteristic() {
+        let config = CharConfig {
+            uuid: Uuid::Uuid16(0x2A19),
+            value_len: 1,
+            properties: CharProperties::READ | CharProperties::NOTIFY,
+            permissions: SecurityPermissions::NONE,
+            event_mask: GattEventMask::NONE,
+            encryption_key_size: 16,
+            variable_len: false,
+        };
+
+        let cmd = add_char(0x000C, &config).unwrap();
+        assert_eq!(cmd.opcode_bits(), 0xFD04);
+        assert_eq!(
+            cmd.payload(),
+            &[0x0C, 0x00, 0x01, 0x19, 0x2A, 0x01, 0x00, 0x12, 0x00, 0x00, 0x10, 0x00]
+        );
+
+        let cmd = add_service(&Uuid::Uuid128([0xAA; 16]), ServiceType::Primary, 4);
+        assert_eq!(cmd.payload().len(), 19);
+
+        assert!(update_char_value(0x000C, 0x000E, 0, &[0; MAX_CHAR_VALUE_UPDATE_LEN + 1]).is_err());
+    }
+
+    #[test]
+    fn gap_service() {
+        let cmd = gap_init(GapRole::PERIPHERAL | GapRole::BROADCASTER, 7).unwrap();
+        assert_eq!(cmd.opcode_bits(), 0xFC8A);
+        assert_eq!(cmd.payload(), &[0x03, 0x00, 0x07]);
+        assert!(gap_init(GapRole::PERIPHERAL, 0).is_err());
+        assert!(gap_init(GapRole(0), 7).is_err());
+
+        assert_eq!(
+            GapHandles::from_bytes(&[0x04, 0x00, 0x06, 0x00, 0x08, 0x00]),
+            Ok(GapHandles {
+                service: 0x0004,
+                device_name: 0x0006,
+                appearance: 0x0008,
+            })
+        );
+        assert!(GapHandles::from_bytes(&[0x04, 0x00]).is_err());
+    }
+
+    #[test]
+    fn server_events() {
+        let payload = [0x01, 0x0C, 0x01, 0x08, 0x0E, 0x00, 0x00, 0x00, 0x02, 0x00, 0xAB, 0xCD];
+        let evt = VendorEvent::parse(0xFF, &payload).unwrap();
+        assert_eq!(
+            AttributeModified::parse(&evt),
+            Some(AttributeModified {
+                connection_handle: 0x0801,
+                attr_handle: 0x000E,
+                offset: 0,
+                data: &[0xAB, 0xCD],
+            })
+        );
+        let evt = VendorEvent::parse(0xFF, &payload[..11]).unwrap();
+        assert_eq!(AttributeModified::parse(&evt), None);
+
+        let payload = [0x14, 0x0C, 0x01, 0x08, 0x0E, 0x00, 0x16, 0x00];
+        let evt = VendorEvent::parse(0xFF, &payload).unwrap();
+        assert_eq!(
+            ReadPermitReq::parse(&evt),
+            Some(ReadPermitReq {
+                connection_handle: 0x0801,
+                attr_handle: 0x000E,
+                offset: 22,
+            })
+        );
+        assert_eq!(WritePermitReq::parse(&evt), None);
+
+        let payload = [0x15, 0x0C, 0x01, 0x08, 0x0E, 0x00, 0x01, 0x2A];
+        let evt = VendorEvent::parse(0xFF, &payload).unwrap();
+        let req = WritePermitReq::parse(&evt).unwrap();
+        assert_eq!((req.attr_handle, req.data), (0x000E, &[0x2A][..]));
+
+        let cmd = write_resp(req.connection_handle, req.attr_handle, Some(0x80), req.data).unwrap();
+        assert_eq!(cmd.opcode_bits(), 0xFD26);
+        assert_eq!(cmd.payload(), &[0x01, 0x08, 0x0E, 0x00, 0x01, 0x80, 0x01, 0x2A]);
+        assert_eq!(allow_read(0x0801).payload(), &[0x01, 0x08]);
+    }
+
+    #[test]
+    fn gatt_db_lookup() {
+        let battery = ServiceHandle(0x000C);
+        let other = ServiceHandle(0x0010);
+        let level = CharHandle {
+            service: battery,
+            declaration: 0x000D,
+        };
+        let other_level = CharHandle {
+            service: other,
+            declaration: 0x0011,
+        };
+
+        let mut db = GattDb::<3>::new();
+        db.insert_service(Uuid::Uuid16(0x180F), battery).unwrap();
+        db.insert_char(Uuid::Uuid16(0x2A19), level).unwrap();
+        db.insert_char(Uuid::Uuid16(0x2A19), other_level).unwrap();
+        assert_eq!(
+            db.insert_service(Uuid::Uuid16(0x180A), other),
+            Err(BleError::InvalidParameters)
+        );
+
+        assert_eq!(db.service(&Uuid::Uuid16(0x180F)), Some(battery));
+        assert_eq!(db.service(&Uuid::Uuid16(0x2A19)), None);
+        assert_eq!(db.char(other, &Uuid::Uuid16(0x2A19)), Some(other_level));
+        assert_eq!(db.find_char(&Uuid::Uuid16(0x2A19)), Some(level));
+        assert_eq!(level.value(), 0x000E);
+        assert_eq!(db.len(), 3);
+    }
+}
@@ -0,0 +1,175 @@
+//! Decoder for the LE L2CAP signaling channel
+//!
+//! The wireless stack does not answer the signaling requests sent by the peer over ACL data. A
+//! peripheral must at least reply to connection parameter update requests, or the central may
+//! disconnect when its request times out.
+
+use super::acl::L2capFrame;
+use super::consts::L2CAP_BASIC_HEADER_SIZE;
+
+/// Channel identifier of the LE signaling channel
+pub const LE_SIGNALING_CID: u16 = 0x0005;
+
+/// Size of the header of a signaling command: code, identifier and data length
+pub const SIGNALING_HEADER_SIZE: usize = 4;
+
+pub const CODE_COMMAND_REJECT: u8 = 0x01;
+pub const CODE_CONNECTION_PARAMETER_UPDATE_REQUEST: u8 = 0x12;
+pub const CODE_CONNECTION_PARAMETER_UPDATE_RESPONSE: u8 = 0x13;
+
+/// A command received on the LE signaling channel
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SignalingCommand<'a> {
+    pub code: u8,
+    /// Identifier to repeat in the response
+    pub identifier: u8,
+    pub data: &'a [u8],
+}
+
+impl<'a> SignalingCommand<'a> {
+    /// Decode the first command of a frame received on the LE signaling channel
+    pub fn parse(frame: &L2capFrame<'a>) -> Option<Self> {
+        if frame.channel_id != LE_SIGNALING_CID || frame.payload.len() < SIGNALING_HEADER_SIZE {
+            return None;
+        }
+
+        let payload = frame.payload;
+        let len = u16::from_le_bytes([payload[2], payload[3]]) as usize;
+
+        Some(Self {
+            code: payload[0],
+            identifier: payload[1],
+            data: payload.get(SIGNALING_HEADER_SIZE..SIGNALING_HEADER_SIZE + len)?,
+        })
+    }
+}
+
+/// `L2CAP_CONNECTION_PARAMETER_UPDATE_REQ`, sent by a peripheral to ask for new connection parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionParameterUpdateRequest {
+    pub identifier: u8,
+    /// Minimum connection interval, in units of 1.25 ms
+    pub interval_min: u16,
+    /// Maximum connection interval, in units of 1.25 ms
+    pub interval_max: u16,
+    /// Number of connection events the peripheral may skip
+    pub peripheral_latency: u16,
+    /// Supervision timeout, in units of 10 ms
+    pub timeout_multiplier: u16,
+}
+
+impl ConnectionParameterUpdateRequest {
+    pub fn parse(cmd: &SignalingCommand) -> Option<Self> {
+        if cmd.code != CODE_CONNECTION_PARAMETER_UPDATE_REQUEST || cmd.data.len() < 8 {
+            return None;
+        }
+
+        let field = |i: usize| u16::from_le_bytes([cmd.data[2 * i], cmd.data[2 * i + 1]]);
+
+        Some(Self {
+            identifier: cmd.identifier,
+            interval_min: field(0),
+            interval_max: field(1),
+            peripheral_latency: field(2),
+            timeout_multiplier: field(3),
+        })
+    }
+
+    /// Build the response to this request
+    pub fn respond(&self, accepted: bool) -> ConnectionParameterUpdateResponse {
+        ConnectionParameterUpdateResponse {
+            identifier: self.identifier,
+            accepted,
+        }
+    }
+}
+
+/// `L2CAP_CONNECTION_PARAMETER_UPDATE_RSP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionParameterUpdateResponse {
+    /// Identifier of the request
+    pub identifier: u8,
+    pub accepted: bool,
+}
+
+impl ConnectionParameterUpdateResponse {
+    /// Size of the L2CAP frame carrying the response
+    pub const FRAME_SIZE: usize = L2CAP_BASIC_HEADER_SIZE + SIGNALING_HEADER_SIZE + 2;
+
+    pub fn parse(cmd: &SignalingCommand) -> Option<Self> {
+        if cmd.code != CODE_CONNECTION_PARAMETER_UPDATE_RESPONSE || cmd.data.len() < 2 {
+            return None;
+        }
+
+        Some(Self {
+            identifier: cmd.identifier,
+            accepted: u16::from_le_bytes([cmd.data[0], cmd.data[1]]) == 0x0000,
+        })
+    }
+
+    /// Encode the L2CAP frame to send on the connection with [`Ble::acl_write_pdu`]
+    ///
+    /// [`Ble::acl_write_pdu`]: crate::sub::ble::Ble::acl_write_pdu
+    pub fn to_frame(&self) -> [u8; Self::FRAME_SIZE] {
+        let pdu_len = (SIGNALING_HEADER_SIZE + 2) as u16;
+        let result: u16 = if self.accepted { 0x0000 } else { 0x0001 };
+
+        let mut frame = [0; Self::FRAME_SIZE];
+        frame[0..2].copy_from_slice(&pdu_len.to_le_bytes());
+        frame[2..4].copy_from_slice(&LE_SIGNALING_CID.to_le_bytes());
+        frame[4] = CODE_CONNECTION_PARAMETER_UPDATE_RESPONSE;
+        frame[5] = self.identifier;
+        frame[6..8].copy_from_slice(&2u16.to_le_bytes());
+        frame[8..10].copy_from_slice(&result.to_le_bytes());
+
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_parameter_update() {
+        let payload = [0x12, 0x07, 8, 0, 0x06, 0x00, 0x0C, 0x00, 0x00, 0x00, 0xF4, 0x01];
+        let frame = L2capFrame {
+            handle: 1,
+            channel_id: LE_SIGNALING_CID,
+            payload: &payload,
+        };
+
+        let cmd = SignalingCommand::parse(&frame).unwrap();
+        let request = ConnectionParameterUpdateRequest::parse(&cmd).unwrap();
+        assert_eq!(
+            request,
+            ConnectionParameterUpdateRequest {
+                identifier: 7,
+                interval_min: 6,
+                interval_max: 12,
+                peripheral_latency: 0,
+                timeout_multiplier: 500,
+            }
+        );
+
+        let response = request.respond(false).to_frame();
+        assert_eq!(response, [6, 0, 5, 0, 0x13, 7, 2, 0, 1, 0]);
+
+        let frame = L2capFrame {
+            handle: 1,
+            channel_id: LE_SIGNALING_CID,
+            payload: &response[L2CAP_BASIC_HEADER_SIZE..],
+        };
+        let cmd = SignalingCommand::parse(&frame).unwrap();
+        assert_eq!(
+            ConnectionParameterUpdateResponse::parse(&cmd),
+            Some(ConnectionParameterUpdateResponse {
+                identifier: 7,
+                accepted: false
+            })
+        );
+    }
+}
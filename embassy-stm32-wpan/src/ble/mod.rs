@@ -0,0 +1,17 @@
+pub mod acl;
+pub mod adv;
+#[cfg(feature = "hci-capture")]
+pub mod capture;
+pub mod commands;
+pub mod conn;
+pub mod consts;
+pub mod event;
+#[cfg(feature = "gatt-server")]
+pub mod gatt;
+#[cfg(feature = "l2cap-signaling")]
+pub mod l2cap;
+pub mod opcodes;
+#[cfg(feature = "periodic-adv")]
+pub mod periodic;
+pub mod scan;
+pub mod typedefs;
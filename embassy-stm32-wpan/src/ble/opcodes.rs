@@ -0,0 +1,78 @@
+const OGF_LINK_CTL: u16 = 0x01;
+const OGF_HOST_CTL: u16 = 0x03;
+const OGF_INFO_PARAM: u16 = 0x04;
+const OGF_LE_CTL: u16 = 0x08;
+const OGF_VENDOR: u16 = 0x3F;
+
+const fn opcode(ogf: u16, ocf: u16) -> isize {
+    ((ogf << 10) + ocf) as isize
+}
+
+/// Opcode of the ST vendor command (`ACI_*`) with the given command field
+pub const fn vendor_opcode(ocf: u16) -> u16 {
+    (OGF_VENDOR << 10) | (ocf & 0x03FF)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HciOpcode {
+    Disconnect = opcode(OGF_LINK_CTL, 0x0006),
+    SetEventMask = opcode(OGF_HOST_CTL, 0x0001),
+    Reset = opcode(OGF_HOST_CTL, 0x0003),
+    ReadLocalVersionInformation = opcode(OGF_INFO_PARAM, 0x0001),
+    ReadBdAddr = opcode(OGF_INFO_PARAM, 0x0009),
+    LeSetEventMask = opcode(OGF_LE_CTL, 0x0001),
+    LeReadBufferSize = opcode(OGF_LE_CTL, 0x0002),
+    LeReadLocalSupportedFeatures = opcode(OGF_LE_CTL, 0x0003),
+    LeSetRandomAddress = opcode(OGF_LE_CTL, 0x0005),
+    LeSetAdvertisingParameters = opcode(OGF_LE_CTL, 0x0006),
+    LeSetAdvertisingData = opcode(OGF_LE_CTL, 0x0008),
+    LeSetScanResponseData = opcode(OGF_LE_CTL, 0x0009),
+    LeSetAdvertisingEnable = opcode(OGF_LE_CTL, 0x000A),
+    LeSetScanParameters = opcode(OGF_LE_CTL, 0x000B),
+    LeSetScanEnable = opcode(OGF_LE_CTL, 0x000C),
+    LeClearFilterAcceptList = opcode(OGF_LE_CTL, 0x0010),
+    LeAddDeviceToFilterAcceptList = opcode(OGF_LE_CTL, 0x0011),
+    LeCreateConnection = opcode(OGF_LE_CTL, 0x000D),
+    LeCreateConnectionCancel = opcode(OGF_LE_CTL, 0x000E),
+    LeConnectionUpdate = opcode(OGF_LE_CTL, 0x0013),
+    LeSetHostChannelClassification = opcode(OGF_LE_CTL, 0x0014),
+    LeReadChannelMap = opcode(OGF_LE_CTL, 0x0015),
+    LeReadRemoteFeatures = opcode(OGF_LE_CTL, 0x0016),
+    LeEncrypt = opcode(OGF_LE_CTL, 0x0017),
+    LeRand = opcode(OGF_LE_CTL, 0x0018),
+    LeEnableEncryption = opcode(OGF_LE_CTL, 0x0019),
+    LeLongTermKeyRequestReply = opcode(OGF_LE_CTL, 0x001A),
+    LeLongTermKeyRequestNegativeReply = opcode(OGF_LE_CTL, 0x001B),
+    LeSetDataLength = opcode(OGF_LE_CTL, 0x0022),
+    LeAddDeviceToResolvingList = opcode(OGF_LE_CTL, 0x0027),
+    LeClearResolvingList = opcode(OGF_LE_CTL, 0x0029),
+    LeSetPhy = opcode(OGF_LE_CTL, 0x0032),
+    LeSetExtendedAdvertisingParameters = opcode(OGF_LE_CTL, 0x0036),
+    LeSetExtendedAdvertisingData = opcode(OGF_LE_CTL, 0x0037),
+    LeSetExtendedAdvertisingEnable = opcode(OGF_LE_CTL, 0x0039),
+    LeSetPeriodicAdvertisingParameters = opcode(OGF_LE_CTL, 0x003E),
+    LeSetPeriodicAdvertisingData = opcode(OGF_LE_CTL, 0x003F),
+    LeSetPeriodicAdvertisingEnable = opcode(OGF_LE_CTL, 0x0040),
+    LeSetExtendedScanParameters = opcode(OGF_LE_CTL, 0x0041),
+    LeSetExtendedScanEnable = opcode(OGF_LE_CTL, 0x0042),
+    LePeriodicAdvertisingCreateSync = opcode(OGF_LE_CTL, 0x0044),
+    LePeriodicAdvertisingCreateSyncCancel = opcode(OGF_LE_CTL, 0x0045),
+    LePeriodicAdvertisingTerminateSync = opcode(OGF_LE_CTL, 0x0046),
+    LeReadTransmitPower = opcode(OGF_LE_CTL, 0x004B),
+    AciHalReadConfigData = opcode(OGF_VENDOR, 0x00D),
+    AciHalSetTxPowerLevel = opcode(OGF_VENDOR, 0x00F),
+    AciGapPassKeyResp = opcode(OGF_VENDOR, 0x088),
+    AciGapInit = opcode(OGF_VENDOR, 0x08A),
+    AciGapClearSecurityDb = opcode(OGF_VENDOR, 0x094),
+    AciGapGetBondedDevices = opcode(OGF_VENDOR, 0x0A3),
+    AciGapNumericComparisonValueConfirmYesNo = opcode(OGF_VENDOR, 0x0A5),
+    AciGapAllowRebond = opcode(OGF_VENDOR, 0x0A6),
+    AciGattInit = opcode(OGF_VENDOR, 0x101),
+    AciGattAddService = opcode(OGF_VENDOR, 0x102),
+    AciGattAddChar = opcode(OGF_VENDOR, 0x104),
+    AciGattUpdateCharValue = opcode(OGF_VENDOR, 0x106),
+    AciGattExchangeConfig = opcode(OGF_VENDOR, 0x10B),
+    AciGattWriteResp = opcode(OGF_VENDOR, 0x126),
+    AciGattAllowRead = opcode(OGF_VENDOR, 0x127),
+}
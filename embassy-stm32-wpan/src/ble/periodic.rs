@@ -0,0 +1,261 @@
+//! Periodic advertising, and synchronization to the periodic advertising of another device
+//!
+//! Periodic advertising is attached to an extended advertising set, configured with
+//! [`Ble::le_set_ext_adv_params`](crate::sub::ble::Ble::le_set_ext_adv_params) first: the extended
+//! advertisements tell the scanners where to find the periodic advertisements. These commands belong to
+//! the extended advertising command set, and cannot be mixed with the legacy advertising and scanning
+//! commands until the controller is reset.
+
+use super::commands::HciCommand;
+use super::consts::{
+    EVT_LE_META, EVT_LE_PERIODIC_ADVERTISING_REPORT, EVT_LE_PERIODIC_ADVERTISING_SYNC_ESTABLISHED,
+    EVT_LE_PERIODIC_ADVERTISING_SYNC_LOST,
+};
+use super::opcodes::HciOpcode;
+pub use super::typedefs::DataStatus;
+use super::typedefs::{AddrType, BdAddr, BleError, ExtAdvDataOperation, PeerAddressType, Phy};
+
+/// Largest periodic advertising data fragment carried by a single `HCI_LE_Set_Periodic_Advertising_Data`
+/// command
+pub const MAX_PERIODIC_ADV_DATA_FRAGMENT_LEN: usize = 252;
+
+/// `HCI_LE_Set_Periodic_Advertising_Parameters`
+///
+/// The intervals are in units of 1.25 ms, from 6 to 65535. `include_tx_power` adds the TX power to the
+/// periodic advertisements.
+pub fn set_params(
+    adv_handle: u8,
+    interval_min: u16,
+    interval_max: u16,
+    include_tx_power: bool,
+) -> Result<HciCommand<7>, BleError> {
+    if interval_min < 6 || interval_min > interval_max {
+        return Err(BleError::InvalidParameters);
+    }
+
+    let mut cmd = HciCommand::new(HciOpcode::LeSetPeriodicAdvertisingParameters);
+    cmd.push_u8(adv_handle)?
+        .push_u16(interval_min)?
+        .push_u16(interval_max)?
+        .push_u16((include_tx_power as u16) << 6)?;
+
+    Ok(cmd)
+}
+
+/// `HCI_LE_Set_Periodic_Advertising_Data`, used to set one fragment of the periodic advertising data
+///
+/// The [`ExtAdvDataOperation::Unchanged`] operation is not allowed for periodic advertising.
+pub fn set_data(adv_handle: u8, operation: ExtAdvDataOperation, data: &[u8]) -> Result<HciCommand<255>, BleError> {
+    if data.len() > MAX_PERIODIC_ADV_DATA_FRAGMENT_LEN || operation == ExtAdvDataOperation::Unchanged {
+        return Err(BleError::InvalidParameters);
+    }
+
+    let mut cmd = HciCommand::new(HciOpcode::LeSetPeriodicAdvertisingData);
+    cmd.push_u8(adv_handle)?
+        .push_u8(operation as u8)?
+        .push_u8(data.len() as u8)?
+        .push_slice(data)?;
+
+    Ok(cmd)
+}
+
+/// `HCI_LE_Set_Periodic_Advertising_Enable`
+pub fn set_enable(adv_handle: u8, enable: bool) -> HciCommand<2> {
+    let mut cmd = HciCommand::new(HciOpcode::LeSetPeriodicAdvertisingEnable);
+
+    unwrap!(cmd.push_u8(enable as u8));
+    unwrap!(cmd.push_u8(adv_handle));
+
+    cmd
+}
+
+/// Periodic advertising train to synchronize to with [`create_sync`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SyncConfig {
+    /// Advertising set identifier, given by the extended advertising reports of the advertiser
+    pub adv_sid: u8,
+    pub address_type: PeerAddressType,
+    pub address: BdAddr,
+    /// Number of periodic advertisements which can be skipped after a successful reception, at most 499
+    pub skip: u16,
+    /// Synchronization timeout, in units of 10 ms, from 10 to 16384
+    pub sync_timeout: u16,
+}
+
+/// `HCI_LE_Periodic_Advertising_Create_Sync`, answered by a `Command Status` event
+pub fn create_sync(config: &SyncConfig) -> Result<HciCommand<14>, BleError> {
+    if config.adv_sid > 0x0F || config.skip > 499 || !(10..=16384).contains(&config.sync_timeout) {
+        return Err(BleError::InvalidParameters);
+    }
+
+    let mut cmd = HciCommand::new(HciOpcode::LePeriodicAdvertisingCreateSync);
+    // use the advertiser given in the command rather than the periodic advertiser list
+    cmd.push_u8(0x00)?
+        .push_u8(config.adv_sid)?
+        .push_u8(config.address_type as u8)?
+        .push_slice(&config.address.0)?
+        .push_u16(config.skip)?
+        .push_u16(config.sync_timeout)?
+        // synchronize whatever the constant tone extension
+        .push_u8(0x00)?;
+
+    Ok(cmd)
+}
+
+/// `HCI_LE_Periodic_Advertising_Terminate_Sync`
+pub fn terminate_sync(sync_handle: u16) -> HciCommand<2> {
+    let mut cmd = HciCommand::new(HciOpcode::LePeriodicAdvertisingTerminateSync);
+
+    unwrap!(cmd.push_u16(sync_handle));
+
+    cmd
+}
+
+/// HCI `LE Periodic Advertising Sync Established` event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SyncEstablished {
+    pub status: u8,
+    /// Identifies the synchronization in the periodic advertising reports
+    pub sync_handle: u16,
+    pub adv_sid: u8,
+    pub address_type: AddrType,
+    pub address: BdAddr,
+    pub phy: Phy,
+    /// Periodic advertising interval, in units of 1.25 ms
+    pub interval: u16,
+}
+
+impl SyncEstablished {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META || payload.len() < 16 || payload[0] != EVT_LE_PERIODIC_ADVERTISING_SYNC_ESTABLISHED {
+            return None;
+        }
+
+        Some(Self {
+            status: payload[1],
+            sync_handle: u16::from_le_bytes([payload[2], payload[3]]),
+            adv_sid: payload[4],
+            address_type: payload[5].try_into().ok()?,
+            address: BdAddr::from_bytes(&payload[6..]).ok()?,
+            phy: payload[12].try_into().unwrap_or_default(),
+            interval: u16::from_le_bytes([payload[13], payload[14]]),
+        })
+    }
+}
+
+/// HCI `LE Periodic Advertising Report` event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PeriodicAdvReport<'a> {
+    pub sync_handle: u16,
+    /// TX power in dBm, `127` if not available
+    pub tx_power: i8,
+    /// Signal strength in dBm, `127` if not available
+    pub rssi: i8,
+    pub data_status: DataStatus,
+    pub data: &'a [u8],
+}
+
+impl<'a> PeriodicAdvReport<'a> {
+    pub fn parse(evt_code: u8, payload: &'a [u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META || payload.len() < 8 || payload[0] != EVT_LE_PERIODIC_ADVERTISING_REPORT {
+            return None;
+        }
+
+        let data_status = match payload[6] {
+            0x00 => DataStatus::Complete,
+            0x01 => DataStatus::Incomplete,
+            _ => DataStatus::Truncated,
+        };
+
+        Some(Self {
+            sync_handle: u16::from_le_bytes([payload[1], payload[2]]),
+            tx_power: payload[3] as i8,
+            rssi: payload[4] as i8,
+            data_status,
+            data: payload.get(8..8 + payload[7] as usize)?,
+        })
+    }
+}
+
+/// HCI `LE Periodic Advertising Sync Lost` event, returning the handle of the synchronization lost
+pub fn sync_lost(evt_code: u8, payload: &[u8]) -> Option<u16> {
+    match payload {
+        [EVT_LE_PERIODIC_ADVERTISING_SYNC_LOST, lo, hi, ..] if evt_code == EVT_LE_META => {
+            Some(u16::from_le_bytes([*lo, *hi]))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advertiser_commands() {
+        let cmd = set_params(1, 80, 160, true).unwrap();
+        assert_eq!(cmd.opcode_bits(), 0x203E);
+        assert_eq!(cmd.payload(), &[0x01, 80, 0x00, 160, 0x00, 0x40, 0x00]);
+        assert_eq!(set_params(1, 160, 80, false).unwrap_err(), BleError::InvalidParameters);
+
+        let cmd = set_data(1, ExtAdvDataOperation::Complete, &[0x02, 0x01, 0x06]).unwrap();
+        assert_eq!(cmd.payload(), &[0x01, 0x03, 0x03, 0x02, 0x01, 0x06]);
+        assert!(set_data(1, ExtAdvDataOperation::Unchanged, &[]).is_err());
+
+        assert_eq!(set_enable(1, true).payload(), &[0x01, 0x01]);
+    }
+
+    #[test]
+    fn sync_events() {
+        let payload = [
+            EVT_LE_PERIODIC_ADVERTISING_SYNC_ESTABLISHED,
+            0x00,
+            0x01,
+            0x00,
+            0x03,
+            0x01,
+            1,
+            2,
+            3,
+            4,
+            5,
+            0xC6,
+            0x02,
+            0x50,
+            0x00,
+            0x00,
+        ];
+        let evt = SyncEstablished::parse(EVT_LE_META, &payload).unwrap();
+        assert_eq!(
+            (evt.sync_handle, evt.adv_sid, evt.phy, evt.interval),
+            (1, 3, Phy::Le2M, 80)
+        );
+        assert!(SyncEstablished::parse(EVT_LE_META, &payload[..15]).is_none());
+
+        let payload = [
+            EVT_LE_PERIODIC_ADVERTISING_REPORT,
+            0x01,
+            0x00,
+            0x7F,
+            0xC4,
+            0xFF,
+            0x01,
+            2,
+            0xAA,
+            0xBB,
+        ];
+        let report = PeriodicAdvReport::parse(EVT_LE_META, &payload).unwrap();
+        assert_eq!((report.sync_handle, report.rssi), (1, -60));
+        assert_eq!(report.data_status, DataStatus::Incomplete);
+        assert_eq!(report.data, &[0xAA, 0xBB]);
+        assert!(PeriodicAdvReport::parse(EVT_LE_META, &payload[..9]).is_none());
+
+        assert_eq!(
+            sync_lost(EVT_LE_META, &[EVT_LE_PERIODIC_ADVERTISING_SYNC_LOST, 0x01, 0x00]),
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,494 @@
+//! Extended scanning, and decoding of the extended advertising reports
+//!
+//! These commands belong to the extended advertising command set, and cannot be mixed with the legacy
+//! advertising and scanning commands until the controller is reset. Legacy advertisements found while
+//! scanning are reported with extended advertising reports as well.
+
+use super::commands::HciCommand;
+use super::consts::{EVT_LE_ADVERTISING_REPORT, EVT_LE_EXTENDED_ADVERTISING_REPORT, EVT_LE_META, MAX_EXT_ADV_DATA_LEN};
+use super::opcodes::HciOpcode;
+use super::typedefs::{AddrType, AdvReport, BdAddr, BleError, DataStatus, OwnAddressType, Phy};
+use crate::ble::adv::AdStructures;
+
+/// Scanning parameters of one PHY
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtScanPhyParams {
+    /// Send scan requests to get the scan response data of the advertisers
+    pub active: bool,
+    /// Time between two scans, in units of 0.625 ms, at least 4
+    pub interval: u16,
+    /// Duration of a scan, in units of 0.625 ms, at most `interval`
+    pub window: u16,
+}
+
+impl Default for ExtScanPhyParams {
+    fn default() -> Self {
+        Self {
+            active: false,
+            // 100 ms
+            interval: 160,
+            window: 160,
+        }
+    }
+}
+
+/// Configuration of [`Ble::start_extended_scan`](crate::sub::ble::Ble::start_extended_scan)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtScanConfig {
+    pub own_address_type: OwnAddressType,
+    /// Scanning on the LE 1M PHY, `None` not to scan it
+    pub le_1m: Option<ExtScanPhyParams>,
+    /// Scanning on the LE Coded PHY, `None` not to scan it
+    pub le_coded: Option<ExtScanPhyParams>,
+    /// Let the controller drop duplicate advertising reports
+    pub filter_duplicates: bool,
+    /// Duration of the scan, in units of 10 ms, `0` to scan until stopped
+    pub duration: u16,
+}
+
+impl Default for ExtScanConfig {
+    fn default() -> Self {
+        Self {
+            own_address_type: OwnAddressType::Public,
+            le_1m: Some(ExtScanPhyParams::default()),
+            le_coded: None,
+            filter_duplicates: true,
+            duration: 0,
+        }
+    }
+}
+
+/// `HCI_LE_Set_Extended_Scan_Parameters`
+///
+/// At least one PHY must be scanned.
+pub fn set_ext_scan_params(config: &ExtScanConfig) -> Result<HciCommand<13>, BleError> {
+    let phys = [(Phy::Le1M, config.le_1m), (Phy::LeCoded, config.le_coded)];
+
+    let mut cmd = HciCommand::new(HciOpcode::LeSetExtendedScanParameters);
+    // no filter accept list
+    cmd.push_u8(config.own_address_type as u8)?.push_u8(0x00)?;
+
+    let mask = phys
+        .iter()
+        .filter(|(_, params)| params.is_some())
+        .fold(0, |mask, (phy, _)| mask | phy.mask());
+    if mask == 0 {
+        return Err(BleError::InvalidParameters);
+    }
+    cmd.push_u8(mask)?;
+
+    for params in phys.iter().filter_map(|(_, params)| params.as_ref()) {
+        if params.interval < 4 || params.window < 4 || params.window > params.interval {
+            return Err(BleError::InvalidParameters);
+        }
+
+        cmd.push_u8(params.active as u8)?
+            .push_u16(params.interval)?
+            .push_u16(params.window)?;
+    }
+
+    Ok(cmd)
+}
+
+/// `HCI_LE_Set_Extended_Scan_Enable`
+///
+/// The scan runs for `config.duration`, repeated periodically only when started from the host.
+pub fn set_ext_scan_enable(enable: bool, config: &ExtScanConfig) -> HciCommand<6> {
+    let mut cmd = HciCommand::new(HciOpcode::LeSetExtendedScanEnable);
+
+    unwrap!(cmd.push_u8(enable as u8));
+    unwrap!(cmd.push_u8(config.filter_duplicates as u8));
+    unwrap!(cmd.push_u16(config.duration));
+    // no period, the scan is not restarted by the controller
+    unwrap!(cmd.push_u16(0));
+
+    cmd
+}
+
+/// Event type of an extended advertising report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtAdvEventType(pub u16);
+
+impl ExtAdvEventType {
+    pub const CONNECTABLE: Self = Self(0x0001);
+    pub const SCANNABLE: Self = Self(0x0002);
+    pub const DIRECTED: Self = Self(0x0004);
+    pub const SCAN_RESPONSE: Self = Self(0x0008);
+    /// The advertisement uses legacy PDUs
+    pub const LEGACY: Self = Self(0x0010);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Completeness of the data of the report
+    pub fn data_status(self) -> DataStatus {
+        match (self.0 >> 5) & 0x03 {
+            0x00 => DataStatus::Complete,
+            0x01 => DataStatus::Incomplete,
+            _ => DataStatus::Truncated,
+        }
+    }
+
+    /// Event type of the extended report standing for a legacy report of type `event_type`
+    fn from_legacy(event_type: u8) -> Self {
+        Self(match event_type {
+            // ADV_IND
+            0x00 => 0x0013,
+            // ADV_DIRECT_IND
+            0x01 => 0x0015,
+            // ADV_SCAN_IND
+            0x02 => 0x0012,
+            // ADV_NONCONN_IND
+            0x03 => 0x0010,
+            // SCAN_RSP to an ADV_IND
+            _ => 0x001B,
+        })
+    }
+}
+
+impl core::ops::BitOr for ExtAdvEventType {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Extended advertising report received while scanning
+///
+/// The data of a report may span several events: [`ExtAdvReport::data_status`] tells whether more data
+/// follows. The scan stream of [`Ble::start_extended_scan`](crate::sub::ble::Ble::start_extended_scan)
+/// reassembles them with [`ExtAdvReassembly`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtAdvReport {
+    pub event_type: ExtAdvEventType,
+    pub address_type: AddrType,
+    pub address: BdAddr,
+    pub primary_phy: Phy,
+    /// PHY of the auxiliary packets, `None` for the advertisements without any
+    pub secondary_phy: Option<Phy>,
+    /// Advertising set identifier, `0xFF` if not available
+    pub adv_sid: u8,
+    /// TX power in dBm, `127` if not available
+    pub tx_power: i8,
+    /// Signal strength in dBm, `127` if not available
+    pub rssi: i8,
+    /// Interval of the periodic advertising of the set, in units of 1.25 ms, `0` if there is none
+    pub periodic_adv_interval: u16,
+    /// Address the directed advertisement is sent to
+    pub direct_address: Option<BdAddr>,
+    data_status: DataStatus,
+    data_len: u16,
+    data: [u8; MAX_EXT_ADV_DATA_LEN],
+}
+
+impl ExtAdvReport {
+    /// Size of a report without its data
+    const HEADER_SIZE: usize = 24;
+
+    /// Decode one report of an `LE Extended Advertising Report` event, returning it with the number of
+    /// bytes it spans
+    pub fn from_bytes(buf: &[u8]) -> Result<(Self, usize), BleError> {
+        let data_len = *buf.get(Self::HEADER_SIZE - 1).ok_or(BleError::InvalidResponse)? as usize;
+        let data = buf
+            .get(Self::HEADER_SIZE..Self::HEADER_SIZE + data_len)
+            .ok_or(BleError::InvalidResponse)?;
+
+        let event_type = ExtAdvEventType(u16::from_le_bytes([buf[0], buf[1]]));
+        let mut report = Self {
+            event_type,
+            address_type: buf[2].try_into().map_err(|_| BleError::InvalidResponse)?,
+            address: BdAddr::from_bytes(&buf[3..9])?,
+            primary_phy: buf[9].try_into().map_err(|_| BleError::InvalidResponse)?,
+            secondary_phy: buf[10].try_into().ok(),
+            adv_sid: buf[11],
+            tx_power: buf[12] as i8,
+            rssi: buf[13] as i8,
+            periodic_adv_interval: u16::from_le_bytes([buf[14], buf[15]]),
+            direct_address: if event_type.contains(ExtAdvEventType::DIRECTED) {
+                Some(BdAddr::from_bytes(&buf[17..23])?)
+            } else {
+                None
+            },
+            data_status: event_type.data_status(),
+            data_len: 0,
+            data: [0; MAX_EXT_ADV_DATA_LEN],
+        };
+        report.append(data);
+
+        Ok((report, Self::HEADER_SIZE + data_len))
+    }
+
+    /// Build the extended report of a legacy advertising report
+    pub fn from_legacy(report: &AdvReport) -> Self {
+        let mut ext = Self {
+            event_type: ExtAdvEventType::from_legacy(report.event_type as u8),
+            address_type: report.address_type,
+            address: report.address,
+            primary_phy: Phy::Le1M,
+            secondary_phy: None,
+            adv_sid: 0xFF,
+            tx_power: 127,
+            rssi: report.rssi,
+            periodic_adv_interval: 0,
+            direct_address: None,
+            data_status: DataStatus::Complete,
+            data_len: 0,
+            data: [0; MAX_EXT_ADV_DATA_LEN],
+        };
+        ext.append(report.data());
+
+        ext
+    }
+
+    /// Completeness of the data, after reassembly for the reports returned by the scan stream
+    pub fn data_status(&self) -> DataStatus {
+        self.data_status
+    }
+
+    /// Advertising or scan response data
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.data_len as usize]
+    }
+
+    /// AD structures of the advertising or scan response data
+    pub fn ad_structures(&self) -> AdStructures<'_> {
+        AdStructures::new(self.data())
+    }
+
+    /// Whether `other` is a fragment of the same advertisement
+    fn same_advertisement(&self, other: &Self) -> bool {
+        self.address == other.address
+            && self.address_type == other.address_type
+            && self.adv_sid == other.adv_sid
+            && self.event_type.contains(ExtAdvEventType::SCAN_RESPONSE)
+                == other.event_type.contains(ExtAdvEventType::SCAN_RESPONSE)
+    }
+
+    /// Append data, marking the report truncated if it does not fit
+    fn append(&mut self, data: &[u8]) {
+        let start = self.data_len as usize;
+        let len = data.len().min(MAX_EXT_ADV_DATA_LEN - start);
+
+        self.data[start..start + len].copy_from_slice(&data[..len]);
+        self.data_len += len as u16;
+        if len < data.len() {
+            self.data_status = DataStatus::Truncated;
+        }
+    }
+}
+
+/// HCI `LE Extended Advertising Report` or `LE Advertising Report` event, iterating over the reports it
+/// holds as extended reports
+///
+/// Reports are decoded one after the other, and decoding stops at the first malformed report.
+#[derive(Debug)]
+pub struct ExtAdvertisingReports<'a> {
+    legacy: bool,
+    remaining: u8,
+    buf: &'a [u8],
+}
+
+impl<'a> ExtAdvertisingReports<'a> {
+    pub fn parse(evt_code: u8, payload: &'a [u8]) -> Option<Self> {
+        if evt_code != EVT_LE_META || payload.len() < 2 {
+            return None;
+        }
+
+        let legacy = match payload[0] {
+            EVT_LE_EXTENDED_ADVERTISING_REPORT => false,
+            EVT_LE_ADVERTISING_REPORT => true,
+            _ => return None,
+        };
+
+        Some(Self {
+            legacy,
+            remaining: payload[1],
+            buf: &payload[2..],
+        })
+    }
+}
+
+impl<'a> Iterator for ExtAdvertisingReports<'a> {
+    type Item = ExtAdvReport;
+
+    fn next(&mut self) -> Option<ExtAdvReport> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let decoded = if self.legacy {
+            AdvReport::from_bytes(self.buf).map(|(report, len)| (ExtAdvReport::from_legacy(&report), len))
+        } else {
+            ExtAdvReport::from_bytes(self.buf)
+        };
+
+        match decoded {
+            Ok((report, len)) => {
+                self.remaining -= 1;
+                self.buf = &self.buf[len..];
+
+                Some(report)
+            }
+            Err(_) => {
+                self.remaining = 0;
+
+                None
+            }
+        }
+    }
+}
+
+/// Reassembly of the extended advertising reports whose data spans several events
+///
+/// The controller reports the fragments of an advertisement one after the other. A fragment of another
+/// advertisement received before the last fragment drops the partial report. Data longer than
+/// [`MAX_EXT_ADV_DATA_LEN`] is cut, and the report marked [`DataStatus::Truncated`].
+#[derive(Debug, Default)]
+pub struct ExtAdvReassembly {
+    partial: Option<ExtAdvReport>,
+}
+
+impl ExtAdvReassembly {
+    pub const fn new() -> Self {
+        Self { partial: None }
+    }
+
+    /// Add a report decoded from an event, returning the report once its data is complete or truncated
+    pub fn push(&mut self, report: ExtAdvReport) -> Option<ExtAdvReport> {
+        let report = match self.partial.take() {
+            Some(mut partial) if partial.same_advertisement(&report) => {
+                partial.append(report.data());
+                if partial.data_status != DataStatus::Truncated {
+                    partial.data_status = report.data_status;
+                }
+                partial.rssi = report.rssi;
+
+                partial
+            }
+            Some(partial) => {
+                warn!("ble: dropping the incomplete report of {}", partial.address);
+
+                report
+            }
+            None => report,
+        };
+
+        if report.data_status == DataStatus::Incomplete {
+            self.partial = Some(report);
+
+            None
+        } else {
+            Some(report)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext_report(event_type: u16, sid: u8, data: &[u8]) -> std::vec::Vec<u8> {
+        let mut buf = std::vec![];
+        buf.extend_from_slice(&event_type.to_le_bytes());
+        // random address
+        buf.extend_from_slice(&[0x01, 1, 2, 3, 4, 5, 0xC6]);
+        // LE 1M then LE 2M, SID, TX power, RSSI, no periodic advertising
+        buf.extend_from_slice(&[0x01, 0x02, sid, 0x7F, 0xC4, 0x00, 0x00]);
+        // no direct address
+        buf.extend_from_slice(&[0x00; 7]);
+        buf.push(data.len() as u8);
+        buf.extend_from_slice(data);
+
+        buf
+    }
+
+    #[test]
+    fn scan_commands() {
+        let config = ExtScanConfig {
+            le_coded: Some(ExtScanPhyParams {
+                active: true,
+                interval: 320,
+                window: 80,
+            }),
+            ..Default::default()
+        };
+        let cmd = set_ext_scan_params(&config).unwrap();
+        assert_eq!(cmd.opcode_bits(), 0x2041);
+        assert_eq!(
+            cmd.payload(),
+            &[0x00, 0x00, 0x05, 0x00, 0xA0, 0x00, 0xA0, 0x00, 0x01, 0x40, 0x01, 0x50, 0x00]
+        );
+
+        let none = ExtScanConfig {
+            le_1m: None,
+            ..Default::default()
+        };
+        assert_eq!(set_ext_scan_params(&none).unwrap_err(), BleError::InvalidParameters);
+
+        let cmd = set_ext_scan_enable(true, &config);
+        assert_eq!(cmd.opcode_bits(), 0x2042);
+        assert_eq!(cmd.payload(), &[0x01, 0x01, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn extended_reports() {
+        let mut payload = std::vec![EVT_LE_EXTENDED_ADVERTISING_REPORT, 2];
+        // connectable, incomplete
+        payload.extend(ext_report(0x0021, 3, &[0x02, 0x01]));
+        payload.extend(ext_report(0x0001, 3, &[0x06]));
+
+        let mut reports = ExtAdvertisingReports::parse(EVT_LE_META, &payload).unwrap();
+        let first = reports.next().unwrap();
+        assert_eq!(first.data_status(), DataStatus::Incomplete);
+        assert_eq!(
+            (first.primary_phy, first.secondary_phy, first.adv_sid, first.rssi),
+            (Phy::Le1M, Some(Phy::Le2M), 3, -60)
+        );
+        assert!(first.event_type.contains(ExtAdvEventType::CONNECTABLE));
+        assert_eq!(first.direct_address, None);
+        let second = reports.next().unwrap();
+        assert!(reports.next().is_none());
+
+        let mut reassembly = ExtAdvReassembly::new();
+        assert!(reassembly.push(first).is_none());
+        let report = reassembly.push(second).unwrap();
+        assert_eq!(report.data_status(), DataStatus::Complete);
+        assert_eq!(report.data(), &[0x02, 0x01, 0x06]);
+
+        // a fragment of another set drops the partial report
+        let (partial, _) = ExtAdvReport::from_bytes(&ext_report(0x0020, 1, &[0xAA])).unwrap();
+        assert!(reassembly.push(partial).is_none());
+        assert_eq!(reassembly.push(second).unwrap().data(), &[0x06]);
+
+        // legacy reports are converted
+        let payload = [
+            EVT_LE_ADVERTISING_REPORT,
+            0x01,
+            0x00,
+            0x00,
+            1,
+            2,
+            3,
+            4,
+            5,
+            6,
+            0x01,
+            0xAA,
+            0xC4,
+        ];
+        let report = ExtAdvertisingReports::parse(EVT_LE_META, &payload)
+            .unwrap()
+            .next()
+            .unwrap();
+        assert!(report
+            .event_type
+            .contains(ExtAdvEventType::LEGACY | ExtAdvEventType::CONNECTABLE));
+        assert_eq!((report.data(), report.rssi, report.adv_sid), (&[0xAA][..], -60, 0xFF));
+    }
+}
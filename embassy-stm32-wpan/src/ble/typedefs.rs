@@ -0,0 +1,1164 @@
+use super::adv::AdStructures;
+use super::consts::{
+    CONFIG_DATA_ER_OFFSET, CONFIG_DATA_IR_OFFSET, CONFIG_DATA_PUBADDR_OFFSET, CONFIG_DATA_RANDOM_ADDRESS_OFFSET,
+    DEFAULT_EVENT_MASK, DEFAULT_LE_EVENT_MASK, MAX_ADV_DATA_LEN,
+};
+
+/// Error of the BLE subsystem
+///
+/// The [`Ble`](crate::sub::ble::Ble) helpers keep returning it rather than [`Error`](crate::error::Error):
+/// it is what the HCI command builders and event parsers of [`ble`](crate::ble) return as well, without
+/// depending on the rest of the mailbox, and [`BleError::Status`] keeps the raw status byte, including the
+/// vendor codes. It converts into [`Error`](crate::error::Error), so that `?` works in functions returning
+/// the crate-wide error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BleError {
+    /// The controller completed the command with a non-zero HCI status code
+    Status(u8),
+    /// The controller response could not be decoded
+    InvalidResponse,
+    /// The running wireless stack does not support the requested feature
+    Unsupported,
+    /// The parameters were rejected before sending the command
+    InvalidParameters,
+    /// The wireless stack cannot accept the command or data yet, it can be retried later
+    Busy,
+    /// The wireless stack did not answer in time
+    Timeout,
+    /// CPU2 restarted before answering, see [`TlMbox::recover`](crate::TlMbox::recover)
+    Reset,
+}
+
+/// LE features supported by the controller, as reported by `HCI_LE_Read_Local_Supported_Features`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LeFeatures(pub u64);
+
+impl LeFeatures {
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, BleError> {
+        let bytes: [u8; 8] = buf
+            .get(..8)
+            .and_then(|b| b.try_into().ok())
+            .ok_or(BleError::InvalidResponse)?;
+
+        Ok(Self(u64::from_le_bytes(bytes)))
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    fn bit(&self, n: u8) -> bool {
+        self.0 & (1 << n) != 0
+    }
+
+    pub fn le_encryption(&self) -> bool {
+        self.bit(0)
+    }
+
+    pub fn connection_parameters_request(&self) -> bool {
+        self.bit(1)
+    }
+
+    pub fn extended_reject_indication(&self) -> bool {
+        self.bit(2)
+    }
+
+    pub fn peripheral_initiated_features_exchange(&self) -> bool {
+        self.bit(3)
+    }
+
+    pub fn le_ping(&self) -> bool {
+        self.bit(4)
+    }
+
+    pub fn le_data_packet_length_extension(&self) -> bool {
+        self.bit(5)
+    }
+
+    pub fn ll_privacy(&self) -> bool {
+        self.bit(6)
+    }
+
+    pub fn extended_scanner_filter_policies(&self) -> bool {
+        self.bit(7)
+    }
+
+    pub fn le_2m_phy(&self) -> bool {
+        self.bit(8)
+    }
+
+    pub fn stable_modulation_index_tx(&self) -> bool {
+        self.bit(9)
+    }
+
+    pub fn stable_modulation_index_rx(&self) -> bool {
+        self.bit(10)
+    }
+
+    pub fn le_coded_phy(&self) -> bool {
+        self.bit(11)
+    }
+
+    pub fn le_extended_advertising(&self) -> bool {
+        self.bit(12)
+    }
+
+    pub fn le_periodic_advertising(&self) -> bool {
+        self.bit(13)
+    }
+
+    pub fn channel_selection_algorithm_2(&self) -> bool {
+        self.bit(14)
+    }
+
+    pub fn le_power_class_1(&self) -> bool {
+        self.bit(15)
+    }
+
+    pub fn minimum_number_of_used_channels(&self) -> bool {
+        self.bit(16)
+    }
+}
+
+/// Controller version, as reported by `HCI_Read_Local_Version_Information`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LocalVersion {
+    /// Version of the HCI specification (e.g. `0x0B` for 5.2)
+    pub hci_version: u8,
+    pub hci_subversion: u16,
+    /// Version of the link layer specification
+    pub lmp_version: u8,
+    /// Bluetooth SIG company identifier of the controller manufacturer (`0x0030` for ST)
+    pub company_identifier: u16,
+    pub lmp_subversion: u16,
+}
+
+impl LocalVersion {
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, BleError> {
+        if buf.len() < 8 {
+            return Err(BleError::InvalidResponse);
+        }
+
+        Ok(Self {
+            hci_version: buf[0],
+            hci_subversion: u16::from_le_bytes([buf[1], buf[2]]),
+            lmp_version: buf[3],
+            company_identifier: u16::from_le_bytes([buf[4], buf[5]]),
+            lmp_subversion: u16::from_le_bytes([buf[6], buf[7]]),
+        })
+    }
+}
+
+/// ACL buffers of the controller, as reported by `HCI_LE_Read_Buffer_Size`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LeBufferSize {
+    /// Largest data payload of an ACL data packet sent to the controller
+    pub acl_data_packet_length: u16,
+    /// Number of ACL data packets the controller can buffer
+    pub total_num_acl_data_packets: u8,
+}
+
+impl LeBufferSize {
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, BleError> {
+        if buf.len() < 3 {
+            return Err(BleError::InvalidResponse);
+        }
+
+        Ok(Self {
+            acl_data_packet_length: u16::from_le_bytes([buf[0], buf[1]]),
+            total_num_acl_data_packets: buf[2],
+        })
+    }
+}
+
+/// TX power supported by the controller, in dBm, as reported by `HCI_LE_Read_Transmit_Power`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TxPowerRange {
+    pub min: i8,
+    pub max: i8,
+}
+
+impl TxPowerRange {
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, BleError> {
+        if buf.len() < 2 {
+            return Err(BleError::InvalidResponse);
+        }
+
+        Ok(Self {
+            min: buf[0] as i8,
+            max: buf[1] as i8,
+        })
+    }
+
+    pub fn contains(&self, power: TxPower) -> bool {
+        (self.min..=self.max).contains(&power.dbm())
+    }
+}
+
+/// Output power of the radio, as a PA level of `ACI_HAL_Set_Tx_Power_Level`
+///
+/// The levels below 0 dBm are within 1 dB of their name, e.g. -20.85 dBm for [`TxPower::Minus20Dbm`].
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TxPower {
+    Minus40Dbm = 0x00,
+    Minus20Dbm = 0x01,
+    Minus15Dbm = 0x06,
+    Minus10Dbm = 0x0B,
+    Minus5Dbm = 0x10,
+    ZeroDbm = 0x19,
+    Plus1Dbm = 0x1A,
+    Plus2Dbm = 0x1B,
+    Plus3Dbm = 0x1C,
+    Plus4Dbm = 0x1D,
+    Plus5Dbm = 0x1E,
+    Plus6Dbm = 0x1F,
+}
+
+impl TxPower {
+    /// Nominal output power, in dBm
+    pub fn dbm(self) -> i8 {
+        match self {
+            Self::Minus40Dbm => -40,
+            Self::Minus20Dbm => -20,
+            Self::Minus15Dbm => -15,
+            Self::Minus10Dbm => -10,
+            Self::Minus5Dbm => -5,
+            Self::ZeroDbm => 0,
+            Self::Plus1Dbm => 1,
+            Self::Plus2Dbm => 2,
+            Self::Plus3Dbm => 3,
+            Self::Plus4Dbm => 4,
+            Self::Plus5Dbm => 5,
+            Self::Plus6Dbm => 6,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Phy {
+    #[default]
+    Le1M = 0x01,
+    Le2M = 0x02,
+    LeCoded = 0x03,
+}
+
+impl Phy {
+    /// Bit of the PHY in the PHY masks of `HCI_LE_Set_PHY`
+    pub fn mask(self) -> u8 {
+        1 << (self as u8 - 1)
+    }
+}
+
+impl TryFrom<u8> for Phy {
+    type Error = ();
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            0x01 => Ok(Self::Le1M),
+            0x02 => Ok(Self::Le2M),
+            0x03 => Ok(Self::LeCoded),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Coding preferred when transmitting on the LE Coded PHY
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PhyCoding {
+    #[default]
+    NoPreference = 0x0000,
+    /// 2 symbols per bit, 500 kb/s
+    S2 = 0x0001,
+    /// 8 symbols per bit, 125 kb/s
+    S8 = 0x0002,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OwnAddressType {
+    #[default]
+    Public = 0x00,
+    Random = 0x01,
+    /// Resolvable private address, or the public address if no IRK is available
+    PrivateOrPublic = 0x02,
+    /// Resolvable private address, or the random address if no IRK is available
+    PrivateOrRandom = 0x03,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PeerAddressType {
+    #[default]
+    Public = 0x00,
+    Random = 0x01,
+}
+
+impl TryFrom<u8> for PeerAddressType {
+    type Error = ();
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            0x00 => Ok(Self::Public),
+            0x01 => Ok(Self::Random),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Type of an address received from the controller, in events and advertising reports
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddrType {
+    #[default]
+    Public = 0x00,
+    Random = 0x01,
+    /// Public identity address of a peer whose resolvable private address was resolved by the controller
+    PublicIdentity = 0x02,
+    /// Random identity address of a peer whose resolvable private address was resolved by the controller
+    RandomIdentity = 0x03,
+    /// No address, sent in anonymous extended advertising
+    Anonymous = 0xFF,
+}
+
+impl AddrType {
+    /// Type to give in commands to designate the same peer, identity addresses being either public or random
+    ///
+    /// Returns `None` for anonymous advertisers.
+    pub fn peer_address_type(self) -> Option<PeerAddressType> {
+        match self {
+            Self::Public | Self::PublicIdentity => Some(PeerAddressType::Public),
+            Self::Random | Self::RandomIdentity => Some(PeerAddressType::Random),
+            Self::Anonymous => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for AddrType {
+    type Error = ();
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            0x00 => Ok(Self::Public),
+            0x01 => Ok(Self::Random),
+            0x02 => Ok(Self::PublicIdentity),
+            0x03 => Ok(Self::RandomIdentity),
+            0xFF => Ok(Self::Anonymous),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Configuration data of the wireless stack, read with [`Ble::read_config_data`](crate::sub::ble::Ble::read_config_data)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigDataOffset {
+    /// Public address, zero until it is written by the application
+    PublicAddress = CONFIG_DATA_PUBADDR_OFFSET,
+    /// Encryption root key, used to derive the long term keys
+    EncryptionRoot = CONFIG_DATA_ER_OFFSET,
+    /// Identity root key, used to derive the identity resolving key
+    IdentityRoot = CONFIG_DATA_IR_OFFSET,
+    /// Static random address generated by the wireless stack
+    StaticRandomAddress = CONFIG_DATA_RANDOM_ADDRESS_OFFSET,
+}
+
+impl ConfigDataOffset {
+    /// Size of the data, in bytes
+    pub fn size(self) -> usize {
+        match self {
+            Self::PublicAddress | Self::StaticRandomAddress => 6,
+            Self::EncryptionRoot | Self::IdentityRoot => 16,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdvFilterPolicy {
+    /// Process scan and connection requests from all devices
+    #[default]
+    None = 0x00,
+    /// Process connection requests from all devices and scan requests from the filter accept list
+    FilterScan = 0x01,
+    /// Process scan requests from all devices and connection requests from the filter accept list
+    FilterConnection = 0x02,
+    /// Process scan and connection requests from the filter accept list only
+    FilterBoth = 0x03,
+}
+
+/// Advertising event properties of an extended advertising set
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdvEventProperties(pub [u8; 2]);
+
+impl AdvEventProperties {
+    pub const CONNECTABLE: Self = Self::from_bits(1 << 0);
+    pub const SCANNABLE: Self = Self::from_bits(1 << 1);
+    pub const DIRECTED: Self = Self::from_bits(1 << 2);
+    pub const HIGH_DUTY_CYCLE_DIRECTED: Self = Self::from_bits(1 << 3);
+    /// Use legacy advertising PDUs
+    pub const LEGACY: Self = Self::from_bits(1 << 4);
+    /// Omit the advertiser address from all PDUs
+    pub const ANONYMOUS: Self = Self::from_bits(1 << 5);
+    /// Include the TX power in the extended header of the advertising PDU
+    pub const INCLUDE_TX_POWER: Self = Self::from_bits(1 << 6);
+
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits.to_le_bytes())
+    }
+
+    pub const fn bits(&self) -> u16 {
+        u16::from_le_bytes(self.0)
+    }
+}
+
+impl core::ops::BitOr for AdvEventProperties {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self::from_bits(self.bits() | rhs.bits())
+    }
+}
+
+/// Primary advertising channels
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdvChannelMap(pub u8);
+
+impl AdvChannelMap {
+    pub const CHANNEL_37: Self = Self(1 << 0);
+    pub const CHANNEL_38: Self = Self(1 << 1);
+    pub const CHANNEL_39: Self = Self(1 << 2);
+    pub const ALL: Self = Self(0b111);
+}
+
+impl Default for AdvChannelMap {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl core::ops::BitOr for AdvChannelMap {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Data channels used by the adaptive frequency hopping of connections, one bit per channel 0 to 36
+///
+/// The host classifies a channel as bad by disabling it in the map given to
+/// [`Ble::set_channel_map`](crate::sub::ble::Ble::set_channel_map).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelMap(u64);
+
+impl ChannelMap {
+    pub const CHANNEL_COUNT: u8 = 37;
+    pub const ALL: Self = Self((1 << Self::CHANNEL_COUNT) - 1);
+    /// Fewest enabled channels accepted by `HCI_LE_Set_Host_Channel_Classification`
+    pub const MIN_ENABLED: u32 = 2;
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, BleError> {
+        let bytes = buf.get(..5).ok_or(BleError::InvalidResponse)?;
+
+        let mut value = [0; 8];
+        value[..5].copy_from_slice(bytes);
+
+        Ok(Self(u64::from_le_bytes(value) & Self::ALL.0))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 5] {
+        let bytes = self.0.to_le_bytes();
+
+        [bytes[0], bytes[1], bytes[2], bytes[3], bytes[4]]
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub fn is_enabled(&self, channel: u8) -> bool {
+        channel < Self::CHANNEL_COUNT && self.0 & (1 << channel) != 0
+    }
+
+    /// Mark a channel as usable, which is the classification by default
+    pub fn enable(&mut self, channel: u8) -> Result<&mut Self, BleError> {
+        if channel >= Self::CHANNEL_COUNT {
+            return Err(BleError::InvalidParameters);
+        }
+
+        self.0 |= 1 << channel;
+        Ok(self)
+    }
+
+    /// Mark a channel as bad
+    pub fn disable(&mut self, channel: u8) -> Result<&mut Self, BleError> {
+        if channel >= Self::CHANNEL_COUNT {
+            return Err(BleError::InvalidParameters);
+        }
+
+        self.0 &= !(1 << channel);
+        Ok(self)
+    }
+
+    pub fn enabled_count(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Check that enough channels are left enabled for the controller to accept the map
+    pub fn validate(&self) -> Result<(), BleError> {
+        if self.enabled_count() < Self::MIN_ENABLED {
+            return Err(BleError::InvalidParameters);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ChannelMap {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// Events reported by the controller, as programmed with `HCI_Set_Event_Mask`
+///
+/// The default is [`DEFAULT_EVENT_MASK`]: the specification default plus the encryption key refresh
+/// complete event, decoded by this crate, and the LE meta event, which carries all the LE events enabled
+/// by [`LeEventMask`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EventMask(pub u64);
+
+impl EventMask {
+    pub const DISCONNECTION_COMPLETE: Self = Self(1 << 4);
+    pub const ENCRYPTION_CHANGE: Self = Self(1 << 7);
+    pub const READ_REMOTE_VERSION_INFORMATION_COMPLETE: Self = Self(1 << 11);
+    pub const HARDWARE_ERROR: Self = Self(1 << 15);
+    pub const DATA_BUFFER_OVERFLOW: Self = Self(1 << 25);
+    pub const ENCRYPTION_KEY_REFRESH_COMPLETE: Self = Self(1 << 47);
+    pub const LE_META: Self = Self(1 << 61);
+}
+
+impl Default for EventMask {
+    fn default() -> Self {
+        Self(DEFAULT_EVENT_MASK)
+    }
+}
+
+impl core::ops::BitOr for EventMask {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// LE events reported by the controller, as programmed with `HCI_LE_Set_Event_Mask`
+///
+/// The default adds the data length change and PHY update events, decoded by this crate, to the
+/// specification default. The enhanced connection complete event replaces the connection complete
+/// event when enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LeEventMask(pub u64);
+
+impl LeEventMask {
+    pub const CONNECTION_COMPLETE: Self = Self(1 << 0);
+    pub const ADVERTISING_REPORT: Self = Self(1 << 1);
+    pub const CONNECTION_UPDATE_COMPLETE: Self = Self(1 << 2);
+    pub const READ_REMOTE_FEATURES_COMPLETE: Self = Self(1 << 3);
+    pub const LONG_TERM_KEY_REQUEST: Self = Self(1 << 4);
+    pub const REMOTE_CONNECTION_PARAMETER_REQUEST: Self = Self(1 << 5);
+    pub const DATA_LENGTH_CHANGE: Self = Self(1 << 6);
+    pub const READ_LOCAL_P256_PUBLIC_KEY_COMPLETE: Self = Self(1 << 7);
+    pub const GENERATE_DHKEY_COMPLETE: Self = Self(1 << 8);
+    pub const ENHANCED_CONNECTION_COMPLETE: Self = Self(1 << 9);
+    pub const DIRECTED_ADVERTISING_REPORT: Self = Self(1 << 10);
+    pub const PHY_UPDATE_COMPLETE: Self = Self(1 << 11);
+    pub const EXTENDED_ADVERTISING_REPORT: Self = Self(1 << 12);
+    pub const PERIODIC_ADVERTISING_SYNC_ESTABLISHED: Self = Self(1 << 13);
+    pub const PERIODIC_ADVERTISING_REPORT: Self = Self(1 << 14);
+    pub const PERIODIC_ADVERTISING_SYNC_LOST: Self = Self(1 << 15);
+    pub const SCAN_TIMEOUT: Self = Self(1 << 16);
+    pub const ADVERTISING_SET_TERMINATED: Self = Self(1 << 17);
+    pub const SCAN_REQUEST_RECEIVED: Self = Self(1 << 18);
+    pub const CHANNEL_SELECTION_ALGORITHM: Self = Self(1 << 19);
+}
+
+impl Default for LeEventMask {
+    fn default() -> Self {
+        Self(DEFAULT_LE_EVENT_MASK) | Self::DATA_LENGTH_CHANGE | Self::PHY_UPDATE_COMPLETE
+    }
+}
+
+impl core::ops::BitOr for LeEventMask {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// How the controller should handle a fragment of extended advertising data
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ExtAdvDataOperation {
+    IntermediateFragment = 0x00,
+    FirstFragment = 0x01,
+    LastFragment = 0x02,
+    #[default]
+    Complete = 0x03,
+    /// Keep the data but change the advertising DID
+    Unchanged = 0x04,
+}
+
+/// Outcome of a pairing procedure, as reported by `ACI_GAP_Pairing_Complete_Event`
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PairingStatus {
+    Success = 0x00,
+    SmpTimeout = 0x01,
+    /// Pairing failed, the reason is given by the SMP error code of the event
+    PairingFailed = 0x02,
+    /// Encryption failed because the local device has no LTK for the peer
+    EncryptionFailedLocalLtkMissing = 0x03,
+    /// Encryption failed because the peer has no LTK for the local device
+    EncryptionFailedPeerLtkMissing = 0x04,
+    EncryptionNotSupported = 0x05,
+}
+
+impl TryFrom<u8> for PairingStatus {
+    type Error = ();
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            0x00 => Ok(Self::Success),
+            0x01 => Ok(Self::SmpTimeout),
+            0x02 => Ok(Self::PairingFailed),
+            0x03 => Ok(Self::EncryptionFailedLocalLtkMissing),
+            0x04 => Ok(Self::EncryptionFailedPeerLtkMissing),
+            0x05 => Ok(Self::EncryptionNotSupported),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Reason of a disconnection, as an HCI error code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DisconnectReason {
+    AuthenticationFailure,
+    /// The link supervision timeout expired
+    ConnectionTimeout,
+    RemoteUserTerminated,
+    RemoteLowResources,
+    RemotePowerOff,
+    /// The connection was terminated by the local host, e.g. with [`Ble::disconnect`]
+    ///
+    /// [`Ble::disconnect`]: crate::sub::ble::Ble::disconnect
+    LocalHostTerminated,
+    UnsupportedRemoteFeature,
+    /// The peer did not answer a link layer procedure in time
+    LlResponseTimeout,
+    /// The instant of a link layer procedure was missed
+    InstantPassed,
+    PairingWithUnitKeyNotSupported,
+    UnacceptableConnectionParameters,
+    /// A packet failed the message integrity check of the encrypted link
+    MicFailure,
+    ConnectionFailedToBeEstablished,
+    /// Another HCI error code
+    Other(u8),
+}
+
+impl DisconnectReason {
+    /// HCI error code of the reason
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::AuthenticationFailure => 0x05,
+            Self::ConnectionTimeout => 0x08,
+            Self::RemoteUserTerminated => 0x13,
+            Self::RemoteLowResources => 0x14,
+            Self::RemotePowerOff => 0x15,
+            Self::LocalHostTerminated => 0x16,
+            Self::UnsupportedRemoteFeature => 0x1A,
+            Self::LlResponseTimeout => 0x22,
+            Self::InstantPassed => 0x28,
+            Self::PairingWithUnitKeyNotSupported => 0x29,
+            Self::UnacceptableConnectionParameters => 0x3B,
+            Self::MicFailure => 0x3D,
+            Self::ConnectionFailedToBeEstablished => 0x3E,
+            Self::Other(code) => *code,
+        }
+    }
+
+    /// Whether the host may use this reason to terminate a connection with `HCI_Disconnect`
+    pub fn is_valid_for_disconnect(&self) -> bool {
+        matches!(
+            self,
+            Self::AuthenticationFailure
+                | Self::RemoteUserTerminated
+                | Self::RemoteLowResources
+                | Self::RemotePowerOff
+                | Self::UnsupportedRemoteFeature
+                | Self::PairingWithUnitKeyNotSupported
+                | Self::UnacceptableConnectionParameters
+        )
+    }
+}
+
+impl From<u8> for DisconnectReason {
+    fn from(v: u8) -> Self {
+        match v {
+            0x05 => Self::AuthenticationFailure,
+            0x08 => Self::ConnectionTimeout,
+            0x13 => Self::RemoteUserTerminated,
+            0x14 => Self::RemoteLowResources,
+            0x15 => Self::RemotePowerOff,
+            0x16 => Self::LocalHostTerminated,
+            0x1A => Self::UnsupportedRemoteFeature,
+            0x22 => Self::LlResponseTimeout,
+            0x28 => Self::InstantPassed,
+            0x29 => Self::PairingWithUnitKeyNotSupported,
+            0x3B => Self::UnacceptableConnectionParameters,
+            0x3D => Self::MicFailure,
+            0x3E => Self::ConnectionFailedToBeEstablished,
+            v => Self::Other(v),
+        }
+    }
+}
+
+/// A Bluetooth device address
+///
+/// The bytes are stored least significant first, as sent over HCI. It is displayed most significant
+/// byte first, e.g. `00:80:E1:26:1B:4C`, and addresses are ordered the same way.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct BdAddr(pub [u8; 6]);
+
+impl BdAddr {
+    pub const fn from_le_bytes(bytes: [u8; 6]) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn to_le_bytes(self) -> [u8; 6] {
+        self.0
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, BleError> {
+        buf.get(..6)
+            .and_then(|b| b.try_into().ok())
+            .map(Self)
+            .ok_or(BleError::InvalidResponse)
+    }
+
+    /// Build the public address derived from the 64-bit unique device number, as ST's `BleGetBdAddress` does
+    ///
+    /// `udn`, `device_id` and `company_id` are the three fields of `UID64`. Returns `None` if the unique
+    /// device number is erased.
+    pub fn from_uid64(udn: u32, device_id: u8, company_id: u32) -> Option<Self> {
+        if udn == 0xFFFF_FFFF {
+            return None;
+        }
+
+        let udn = udn.to_le_bytes();
+        let company_id = company_id.to_le_bytes();
+
+        Some(Self([
+            udn[0],
+            udn[1],
+            device_id,
+            company_id[0],
+            company_id[1],
+            company_id[2],
+        ]))
+    }
+
+    /// Build a static random address from the 64-bit unique device number `UID64`
+    ///
+    /// The unique device number is folded into the 46 random bits, and the two most significant bits are
+    /// set as required for a static address. The 32-bit device number is kept whole, so that devices of
+    /// the same type get different addresses.
+    pub const fn static_random_from_uid64(uid: u64) -> Self {
+        const RANDOM_BITS: u64 = (1 << 46) - 1;
+
+        let folded = (uid ^ (uid >> 48)) & RANDOM_BITS;
+        // the random part may be neither all zeros nor all ones
+        let random = if folded == 0 || folded == RANDOM_BITS {
+            folded ^ 1
+        } else {
+            folded
+        };
+        let bytes = (random | 0b11 << 46).to_le_bytes();
+
+        Self([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5]])
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 6] {
+        &self.0
+    }
+}
+
+impl core::fmt::Display for BdAddr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let [b0, b1, b2, b3, b4, b5] = self.0;
+
+        write!(f, "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", b5, b4, b3, b2, b1, b0)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for BdAddr {
+    fn format(&self, fmt: defmt::Formatter) {
+        let [b0, b1, b2, b3, b4, b5] = self.0;
+
+        defmt::write!(
+            fmt,
+            "{=u8:02X}:{=u8:02X}:{=u8:02X}:{=u8:02X}:{=u8:02X}:{=u8:02X}",
+            b5,
+            b4,
+            b3,
+            b2,
+            b1,
+            b0
+        )
+    }
+}
+
+impl Ord for BdAddr {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for BdAddr {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A device whose keys are stored in the security database of the wireless stack
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BondedDevice {
+    pub address_type: PeerAddressType,
+    pub address: BdAddr,
+}
+
+impl BondedDevice {
+    /// Size of a device entry in the `ACI_GAP_Get_Bonded_Devices` return parameters
+    pub const SIZE: usize = 7;
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, BleError> {
+        if buf.len() < Self::SIZE {
+            return Err(BleError::InvalidResponse);
+        }
+
+        let address_type = buf[0].try_into().map_err(|_| BleError::InvalidResponse)?;
+        let address = BdAddr::from_bytes(&buf[1..7])?;
+
+        Ok(Self { address_type, address })
+    }
+}
+
+/// Configuration of [`Ble::start_advertising`](crate::sub::ble::Ble::start_advertising)
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdvConfig<'a> {
+    /// Minimum advertising interval, in units of 0.625 ms
+    pub interval_min: u16,
+    /// Maximum advertising interval, in units of 0.625 ms
+    pub interval_max: u16,
+    /// Accept connections, otherwise advertise as a non-connectable beacon
+    pub connectable: bool,
+    /// Advertising data, at most [`MAX_ADV_DATA_LEN`] bytes
+    pub data: &'a [u8],
+    /// Scan response data, at most [`MAX_ADV_DATA_LEN`] bytes, left unchanged if `None`
+    pub scan_response: Option<&'a [u8]>,
+}
+
+impl Default for AdvConfig<'_> {
+    fn default() -> Self {
+        Self {
+            // 100 ms
+            interval_min: 160,
+            interval_max: 160,
+            connectable: false,
+            data: &[],
+            scan_response: None,
+        }
+    }
+}
+
+/// Configuration of [`Ble::start_scan`](crate::sub::ble::Ble::start_scan)
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanConfig {
+    /// Time between two scans, in units of 0.625 ms
+    pub interval: u16,
+    /// Duration of a scan, in units of 0.625 ms, at most `interval`
+    pub window: u16,
+    /// Send scan requests to get the scan response data of the advertisers
+    pub active: bool,
+    /// Let the controller drop duplicate advertising reports
+    pub filter_duplicates: bool,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            // 100 ms
+            interval: 160,
+            window: 160,
+            active: false,
+            filter_duplicates: true,
+        }
+    }
+}
+
+/// Type of a received advertising PDU
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdvReportType {
+    /// Connectable and scannable undirected advertising
+    AdvInd = 0x00,
+    /// Connectable directed advertising
+    AdvDirectInd = 0x01,
+    /// Scannable undirected advertising
+    AdvScanInd = 0x02,
+    /// Non connectable undirected advertising
+    AdvNonconnInd = 0x03,
+    /// Scan response
+    ScanRsp = 0x04,
+}
+
+impl TryFrom<u8> for AdvReportType {
+    type Error = ();
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            0x00 => Ok(Self::AdvInd),
+            0x01 => Ok(Self::AdvDirectInd),
+            0x02 => Ok(Self::AdvScanInd),
+            0x03 => Ok(Self::AdvNonconnInd),
+            0x04 => Ok(Self::ScanRsp),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Completeness of the data of a periodic or extended advertising report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataStatus {
+    Complete,
+    /// More data follows in the next report
+    Incomplete,
+    /// The controller failed to receive the rest of the data
+    Truncated,
+}
+
+/// Advertising report received while scanning
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdvReport {
+    pub event_type: AdvReportType,
+    pub address_type: AddrType,
+    pub address: BdAddr,
+    /// Signal strength in dBm, `127` if not available
+    pub rssi: i8,
+    data_len: u8,
+    data: [u8; MAX_ADV_DATA_LEN],
+}
+
+impl AdvReport {
+    /// Decode one report, returning it with the number of bytes it spans
+    pub fn from_bytes(buf: &[u8]) -> Result<(Self, usize), BleError> {
+        let data_len = *buf.get(8).ok_or(BleError::InvalidResponse)? as usize;
+        if data_len > MAX_ADV_DATA_LEN || buf.len() < 10 + data_len {
+            return Err(BleError::InvalidResponse);
+        }
+
+        let mut report = Self {
+            event_type: buf[0].try_into().map_err(|_| BleError::InvalidResponse)?,
+            address_type: buf[1].try_into().map_err(|_| BleError::InvalidResponse)?,
+            address: BdAddr::from_bytes(&buf[2..8])?,
+            rssi: buf[9 + data_len] as i8,
+            data_len: data_len as u8,
+            data: [0; MAX_ADV_DATA_LEN],
+        };
+        report.data[..data_len].copy_from_slice(&buf[9..9 + data_len]);
+
+        Ok((report, 10 + data_len))
+    }
+
+    /// Advertising or scan response data
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.data_len as usize]
+    }
+
+    /// AD structures of the advertising or scan response data
+    pub fn ad_structures(&self) -> AdStructures<'_> {
+        AdStructures::new(self.data())
+    }
+}
+
+/// Connection parameters requested with [`Ble::update_connection_params`](crate::sub::ble::Ble::update_connection_params)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnParams {
+    /// Minimum connection interval, in units of 1.25 ms, from 6 to 3200
+    pub interval_min: u16,
+    /// Maximum connection interval, in units of 1.25 ms, from 6 to 3200
+    pub interval_max: u16,
+    /// Number of connection events the peripheral may skip, at most 499
+    pub max_latency: u16,
+    /// Supervision timeout, in units of 10 ms, from 10 to 3200
+    pub supervision_timeout: u16,
+}
+
+impl ConnParams {
+    /// Check the ranges defined by the specification, and that the supervision timeout is longer
+    /// than twice the time the peripheral may stay silent
+    pub fn validate(&self) -> Result<(), BleError> {
+        let interval_range = 6..=3200;
+        let valid = interval_range.contains(&self.interval_min)
+            && interval_range.contains(&self.interval_max)
+            && self.interval_min <= self.interval_max
+            && self.max_latency <= 499
+            && (10..=3200).contains(&self.supervision_timeout)
+            // timeout * 10 ms > (1 + latency) * interval_max * 1.25 ms * 2
+            && self.supervision_timeout as u32 * 4 > (1 + self.max_latency as u32) * self.interval_max as u32;
+
+        if valid {
+            Ok(())
+        } else {
+            Err(BleError::InvalidParameters)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_event_masks() {
+        assert_eq!(EventMask::default().0 & EventMask::LE_META.0, EventMask::LE_META.0);
+        assert_eq!(
+            EventMask::default().0 & EventMask::ENCRYPTION_KEY_REFRESH_COMPLETE.0,
+            EventMask::ENCRYPTION_KEY_REFRESH_COMPLETE.0
+        );
+        assert_eq!(LeEventMask::default().0, 0x085F);
+    }
+
+    #[test]
+    fn validate_conn_params() {
+        let mut params = ConnParams {
+            interval_min: 24,
+            interval_max: 40,
+            max_latency: 4,
+            supervision_timeout: 100,
+        };
+        assert_eq!(params.validate(), Ok(()));
+
+        // 500 ms timeout, not longer than twice the 250 ms the peripheral may stay silent
+        params.supervision_timeout = 50;
+        assert_eq!(params.validate(), Err(BleError::InvalidParameters));
+
+        params.supervision_timeout = 100;
+        params.interval_min = 48;
+        assert_eq!(params.validate(), Err(BleError::InvalidParameters));
+    }
+
+    #[test]
+    fn tx_power_range() {
+        let range = TxPowerRange::from_bytes(&[0xD8, 0x04]).unwrap();
+
+        assert_eq!(range, TxPowerRange { min: -40, max: 4 });
+        assert!(range.contains(TxPower::Minus40Dbm) && range.contains(TxPower::Plus4Dbm));
+        assert!(!range.contains(TxPower::Plus5Dbm));
+        assert!(TxPowerRange::from_bytes(&[0xD8]).is_err());
+    }
+
+    #[test]
+    fn channel_map() {
+        let mut map = ChannelMap::default();
+        assert_eq!(map.to_bytes(), [0xFF, 0xFF, 0xFF, 0xFF, 0x1F]);
+
+        map.disable(0).unwrap().disable(36).unwrap();
+        assert!(!map.is_enabled(0) && map.is_enabled(1) && !map.is_enabled(36));
+        assert_eq!(map.to_bytes(), [0xFE, 0xFF, 0xFF, 0xFF, 0x0F]);
+        assert_eq!(ChannelMap::from_bytes(&map.to_bytes()), Ok(map));
+        assert!(map.disable(37).is_err());
+
+        for channel in 2..36 {
+            map.disable(channel).unwrap();
+        }
+        assert_eq!(map.enabled_count(), 1);
+        assert_eq!(map.validate(), Err(BleError::InvalidParameters));
+        map.enable(0).unwrap();
+        assert_eq!(map.validate(), Ok(()));
+    }
+    #[test]
+    fn bd_addr() {
+        let addr = BdAddr::from_uid64(0x0012_4C1B, 0x26, 0x0080E1).unwrap();
+
+        assert_eq!(addr, BdAddr([0x1B, 0x4C, 0x26, 0xE1, 0x80, 0x00]));
+        assert_eq!(std::format!("{}", addr), "00:80:E1:26:4C:1B");
+        assert_eq!(BdAddr::from_uid64(0xFFFF_FFFF, 0x26, 0x0080E1), None);
+
+        // ordered as displayed, most significant byte first
+        assert!(BdAddr([0xFF, 0, 0, 0, 0, 0]) < BdAddr([0, 0, 0, 0, 0, 1]));
+        assert_eq!(BdAddr::from_le_bytes(addr.to_le_bytes()), addr);
+    }
+
+    #[test]
+    fn static_random_bd_addr() {
+        let addr = BdAddr::static_random_from_uid64(0x0080_E126_0012_4C1B);
+        assert_eq!(std::format!("{}", addr), "E1:26:00:12:4C:9B");
+        assert_ne!(BdAddr::static_random_from_uid64(0x0080_E126_0012_4C1C), addr);
+
+        // the random part is neither all zeros nor all ones
+        assert_eq!(BdAddr::static_random_from_uid64(0).to_le_bytes(), [1, 0, 0, 0, 0, 0xC0]);
+        assert_eq!(
+            BdAddr::static_random_from_uid64(u64::MAX >> 16).to_le_bytes(),
+            [0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn addr_type() {
+        assert_eq!(AddrType::try_from(0x02), Ok(AddrType::PublicIdentity));
+        assert_eq!(AddrType::try_from(0x04), Err(()));
+        assert_eq!(
+            AddrType::RandomIdentity.peer_address_type(),
+            Some(PeerAddressType::Random)
+        );
+        assert_eq!(AddrType::Anonymous.peer_address_type(), None);
+    }
+}
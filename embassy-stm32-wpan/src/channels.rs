@@ -48,49 +48,194 @@
 //!  |<----HW_IPCC_TRACES_CHANNEL----------------------|
 //!  |                                                 |
 //!
+//! The receive interrupt of a CPU2 to CPU1 channel is only unmasked while a task waits on it with
+//! `Ipcc::receive`, and the interrupt handler masks it again before waking the task. A channel that
+//! nobody receives from, such as [`cpu2::IPCC_TRACES_CHANNEL`] or the Thread channels, therefore
+//! stays masked: CPU2 may set it, but it never raises an interrupt on CPU1 and is simply left pending.
+//!
+//! The interrupt handlers of `embassy_stm32::ipcc` only mask the channels whose flag is set and wake
+//! the task waiting on them. They never clear a flag, so other channels can be used by the application
+//! with [`Ipcc::send`](embassy_stm32::ipcc::Ipcc::send) and [`Ipcc::receive`](embassy_stm32::ipcc::Ipcc::receive)
+//! alongside the mailbox, as long as they are not in [`CPU1_MAILBOX_CHANNELS`] or [`CPU2_MAILBOX_CHANNELS`].
+
+pub use embassy_stm32::ipcc::IpccChannel;
 
+use crate::ipcc::{Ipcc, IpccOps};
+
+/// CPU1 to CPU2 channels, set by CPU1 to pass a command or buffer to CPU2, and cleared by CPU2 once read
+///
+/// Several constants share a channel, for protocols which cannot run together on CPU2.
 pub mod cpu1 {
     use embassy_stm32::ipcc::IpccChannel;
 
+    /// HCI commands, in `BLE_CMD_BUFFER`
     pub const IPCC_BLE_CMD_CHANNEL: IpccChannel = IpccChannel::Channel1;
+    /// System commands, answered in place in `SYS_CMD_BUF`
     pub const IPCC_SYSTEM_CMD_RSP_CHANNEL: IpccChannel = IpccChannel::Channel2;
+    /// OpenThread commands, answered in place
     pub const IPCC_THREAD_OT_CMD_RSP_CHANNEL: IpccChannel = IpccChannel::Channel3;
-    #[allow(dead_code)] // Not used currently but reserved
+    /// Zigbee application commands, answered in place in `ZIGBEE_APPLI_CMD_BUFFER`
     pub const IPCC_ZIGBEE_CMD_APPLI_CHANNEL: IpccChannel = IpccChannel::Channel3;
+    /// 802.15.4 MAC commands, answered in place in `MAC_802_15_4_CMD_BUFFER`
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_MAC_802_15_4_CMD_RSP_CHANNEL: IpccChannel = IpccChannel::Channel3;
+    /// Event buffers released by the application, in `FREE_BUF_QUEUE`
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_MM_RELEASE_BUFFER_CHANNEL: IpccChannel = IpccChannel::Channel4;
+    /// Thread CLI commands
     pub const IPCC_THREAD_CLI_CMD_CHANNEL: IpccChannel = IpccChannel::Channel5;
+    /// LLD tests CLI commands
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_LLDTESTS_CLI_CMD_CHANNEL: IpccChannel = IpccChannel::Channel5;
+    /// BLE LLD commands
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_BLE_LLD_CMD_CHANNEL: IpccChannel = IpccChannel::Channel5;
+    /// ACL data packets, in `HCI_ACL_DATA_BUFFER`
     pub const IPCC_HCI_ACL_DATA_CHANNEL: IpccChannel = IpccChannel::Channel6;
 }
 
+/// CPU2 to CPU1 channels, set by CPU2 to pass an event or notification to CPU1, and cleared by CPU1 once read
+///
+/// Several constants share a channel, for protocols which cannot run together on CPU2.
 pub mod cpu2 {
     use embassy_stm32::ipcc::IpccChannel;
 
+    /// HCI events, in `EVT_QUEUE`
     pub const IPCC_BLE_EVENT_CHANNEL: IpccChannel = IpccChannel::Channel1;
+    /// System events, in `SYSTEM_EVT_QUEUE`
     pub const IPCC_SYSTEM_EVENT_CHANNEL: IpccChannel = IpccChannel::Channel2;
+    /// OpenThread notifications, acknowledged by clearing the channel
     pub const IPCC_THREAD_NOTIFICATION_ACK_CHANNEL: IpccChannel = IpccChannel::Channel3;
-    #[allow(dead_code)] // Not used currently but reserved
+    /// Zigbee notifications in `ZIGBEE_NOTIF_BUFFER`, acknowledged by clearing the channel
     pub const IPCC_ZIGBEE_APPLI_NOTIF_ACK_CHANNEL: IpccChannel = IpccChannel::Channel3;
+    /// 802.15.4 MAC notifications in `MAC_802_15_4_NOTIF_RSP_EVT_BUFFER`, acknowledged by clearing the channel
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_MAC_802_15_4_NOTIFICATION_ACK_CHANNEL: IpccChannel = IpccChannel::Channel3;
+    /// Commands of the LLD tests firmware
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_LDDTESTS_M0_CMD_CHANNEL: IpccChannel = IpccChannel::Channel3;
+    /// Commands of the BLE LLD firmware
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_BLE_LLDÇM0_CMD_CHANNEL: IpccChannel = IpccChannel::Channel3;
+    /// Traces of the wireless stack, in `TRACES_EVT_QUEUE`
     pub const IPCC_TRACES_CHANNEL: IpccChannel = IpccChannel::Channel4;
+    /// Thread CLI notifications, acknowledged by clearing the channel
     pub const IPCC_THREAD_CLI_NOTIFICATION_ACK_CHANNEL: IpccChannel = IpccChannel::Channel5;
+    /// LLD tests CLI responses
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_LLDTESTS_CLI_RSP_CHANNEL: IpccChannel = IpccChannel::Channel5;
+    /// BLE LLD CLI responses
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_BLE_LLD_CLI_RSP_CHANNEL: IpccChannel = IpccChannel::Channel5;
+    /// BLE LLD responses
     #[allow(dead_code)] // Not used currently but reserved
     pub const IPCC_BLE_LLD_RSP_CHANNEL: IpccChannel = IpccChannel::Channel5;
-    #[allow(dead_code)] // Not used currently but reserved
+    /// Zigbee requests in `ZIGBEE_REQUEST_BUFFER`, acknowledged by clearing the channel
     pub const IPCC_ZIGBEE_M0_REQUEST_CHANNEL: IpccChannel = IpccChannel::Channel5;
 }
+
+const fn bit(channel: IpccChannel) -> u8 {
+    1 << channel as u8
+}
+
+/// CPU1 to CPU2 channels used by the mailbox with the enabled features, bit 0 being channel 1
+///
+/// This includes the channel of the memory manager, used to release the event buffers.
+pub const CPU1_MAILBOX_CHANNELS: u8 = {
+    let mut mask = bit(cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL) | bit(cpu1::IPCC_MM_RELEASE_BUFFER_CHANNEL);
+    if cfg!(feature = "ble") {
+        mask |= bit(cpu1::IPCC_BLE_CMD_CHANNEL) | bit(cpu1::IPCC_HCI_ACL_DATA_CHANNEL);
+    }
+    if cfg!(feature = "mac") {
+        mask |= bit(cpu1::IPCC_MAC_802_15_4_CMD_RSP_CHANNEL);
+    }
+    if cfg!(feature = "zigbee") {
+        mask |= bit(cpu1::IPCC_ZIGBEE_CMD_APPLI_CHANNEL);
+    }
+
+    mask
+};
+
+/// CPU2 to CPU1 channels used by the mailbox with the enabled features, bit 0 being channel 1
+///
+/// This includes the traces channel, which CPU2 may set even though the mailbox does not read it.
+pub const CPU2_MAILBOX_CHANNELS: u8 = {
+    let mut mask = bit(cpu2::IPCC_SYSTEM_EVENT_CHANNEL) | bit(cpu2::IPCC_TRACES_CHANNEL);
+    if cfg!(feature = "ble") {
+        mask |= bit(cpu2::IPCC_BLE_EVENT_CHANNEL);
+    }
+    if cfg!(feature = "mac") {
+        mask |= bit(cpu2::IPCC_MAC_802_15_4_NOTIFICATION_ACK_CHANNEL);
+    }
+    if cfg!(feature = "zigbee") {
+        mask |= bit(cpu2::IPCC_ZIGBEE_APPLI_NOTIF_ACK_CHANNEL) | bit(cpu2::IPCC_ZIGBEE_M0_REQUEST_CHANNEL);
+    }
+
+    mask
+};
+
+/// Whether a CPU1 to CPU2 channel is left free by the mailbox
+pub const fn is_cpu1_channel_free(channel: IpccChannel) -> bool {
+    CPU1_MAILBOX_CHANNELS & bit(channel) == 0
+}
+
+/// Whether a CPU2 to CPU1 channel is left free by the mailbox
+pub const fn is_cpu2_channel_free(channel: IpccChannel) -> bool {
+    CPU2_MAILBOX_CHANNELS & bit(channel) == 0
+}
+
+/// All the IPCC channels, in order
+const CHANNELS: [IpccChannel; 6] = [
+    IpccChannel::Channel1,
+    IpccChannel::Channel2,
+    IpccChannel::Channel3,
+    IpccChannel::Channel4,
+    IpccChannel::Channel5,
+    IpccChannel::Channel6,
+];
+
+/// Flags of the IPCC channels, bit 0 being channel 1, as returned by
+/// [`TlMbox::pending_channels`](crate::TlMbox::pending_channels)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelFlags {
+    /// CPU1 to CPU2 channels set by CPU1 and not cleared by CPU2 yet, whose command CPU2 has not read
+    pub cpu1: u8,
+    /// CPU2 to CPU1 channels set by CPU2 and not cleared by CPU1 yet, whose event CPU1 has not read
+    pub cpu2: u8,
+}
+
+impl ChannelFlags {
+    /// Read the flags of all the channels
+    ///
+    /// The flags are read one channel at a time and may change meanwhile, which is fine for diagnostics.
+    pub fn read() -> Self {
+        let mut flags = Self::default();
+        for channel in CHANNELS {
+            if Ipcc::is_tx_pending(channel) {
+                flags.cpu1 |= bit(channel);
+            }
+            if Ipcc::is_rx_pending(channel) {
+                flags.cpu2 |= bit(channel);
+            }
+        }
+
+        flags
+    }
+
+    /// Whether a CPU1 to CPU2 channel is set
+    pub const fn is_cpu1_pending(&self, channel: IpccChannel) -> bool {
+        self.cpu1 & bit(channel) != 0
+    }
+
+    /// Whether a CPU2 to CPU1 channel is set
+    pub const fn is_cpu2_pending(&self, channel: IpccChannel) -> bool {
+        self.cpu2 & bit(channel) != 0
+    }
+
+    /// CPU2 to CPU1 channels set by CPU2 that the mailbox does not read, such as the traces channel
+    /// without the `traces` feature
+    pub const fn unhandled_cpu2(&self) -> u8 {
+        self.cpu2 & !CPU2_MAILBOX_CHANNELS
+    }
+}
@@ -1,6 +1,6 @@
 use core::ptr;
 
-use crate::consts::TlPacketType;
+use crate::consts::{TlPacketType, TL_ACL_DATA_MAX_PAYLOAD_SIZE, TL_CMD_MAX_PAYLOAD_SIZE};
 use crate::PacketHeader;
 
 #[derive(Copy, Clone)]
@@ -8,7 +8,7 @@ use crate::PacketHeader;
 pub struct Cmd {
     pub cmd_code: u16,
     pub payload_len: u8,
-    pub payload: [u8; 255],
+    pub payload: [u8; TL_CMD_MAX_PAYLOAD_SIZE],
 }
 
 impl Default for Cmd {
@@ -16,7 +16,7 @@ impl Default for Cmd {
         Self {
             cmd_code: 0,
             payload_len: 0,
-            payload: [0u8; 255],
+            payload: [0u8; TL_CMD_MAX_PAYLOAD_SIZE],
         }
     }
 }
@@ -44,7 +44,17 @@ pub struct CmdPacket {
 }
 
 impl CmdPacket {
+    /// Write a command into a command buffer
+    ///
+    /// Panics if `payload` is longer than [`TL_CMD_MAX_PAYLOAD_SIZE`], rather than overflowing into the
+    /// neighbouring shared buffers.
     pub unsafe fn write_into(cmd_buf: *mut CmdPacket, packet_type: TlPacketType, cmd_code: u16, payload: &[u8]) {
+        assert!(
+            payload.len() <= TL_CMD_MAX_PAYLOAD_SIZE,
+            "command payload of {} bytes does not fit in the command buffer",
+            payload.len()
+        );
+
         let p_cmd_serial = &mut (*cmd_buf).cmdserial as *mut _ as *mut CmdSerialStub;
         let p_payload = &mut (*cmd_buf).cmdserial.cmd.payload as *mut _;
 
@@ -86,7 +96,16 @@ pub struct AclDataPacket {
 }
 
 impl AclDataPacket {
+    /// Write an ACL data packet into an ACL data buffer
+    ///
+    /// Panics if `payload` is longer than [`TL_ACL_DATA_MAX_PAYLOAD_SIZE`].
     pub unsafe fn write_into(cmd_buf: *mut AclDataPacket, packet_type: TlPacketType, handle: u16, payload: &[u8]) {
+        assert!(
+            payload.len() <= TL_ACL_DATA_MAX_PAYLOAD_SIZE,
+            "ACL data payload of {} bytes does not fit in the ACL data buffer",
+            payload.len()
+        );
+
         let p_cmd_serial = &mut (*cmd_buf).acl_data_serial as *mut _ as *mut AclDataSerialStub;
         let p_payload = &mut (*cmd_buf).acl_data_serial.acl_data as *mut _;
 
@@ -102,3 +121,28 @@ impl AclDataPacket {
         ptr::copy_nonoverlapping(payload as *const _ as *const u8, p_payload, payload.len());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_command() {
+        let mut packet = CmdPacket::default();
+        unsafe { CmdPacket::write_into(&mut packet, TlPacketType::BleCmd, 0x0C03, &[1, 2, 3]) };
+
+        let cmd = packet.cmdserial.cmd;
+        assert_eq!({ cmd.cmd_code }, 0x0C03);
+        assert_eq!(cmd.payload_len, 3);
+        assert_eq!(cmd.payload[..3], [1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn reject_oversized_command() {
+        let mut packet = CmdPacket::default();
+        let payload = [0; TL_CMD_MAX_PAYLOAD_SIZE + 1];
+
+        unsafe { CmdPacket::write_into(&mut packet, TlPacketType::BleCmd, 0x0C03, &payload) };
+    }
+}
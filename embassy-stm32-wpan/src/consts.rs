@@ -1,7 +1,12 @@
-use crate::evt::CsEvt;
+use core::mem;
+
+use crate::cmd::{AclDataSerialStub, CmdPacket};
+use crate::evt::{CsEvt, EvtPacket, EvtStub};
+use crate::unsafe_linked_list::LinkedListNode;
 use crate::PacketHeader;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub enum TlPacketType {
     MacCmd = 0x00,
@@ -58,8 +63,36 @@ impl TryFrom<u8> for TlPacketType {
 }
 
 pub const TL_PACKET_HEADER_SIZE: usize = core::mem::size_of::<PacketHeader>();
+/// Packet type, event code and payload length
 pub const TL_EVT_HEADER_SIZE: usize = 3;
 pub const TL_CS_EVT_SIZE: usize = core::mem::size_of::<CsEvt>();
+/// Largest command payload, limited by the one-byte length field of the command
+pub const TL_CMD_MAX_PAYLOAD_SIZE: usize = 255;
+/// Packet type, connection handle and data length of an ACL data packet
+pub const TL_ACL_DATA_HEADER_SIZE: usize = 5;
+/// Largest ACL data payload sent to CPU2, which sizes the ACL data buffer
+///
+/// This is the maximum LE data length of the controller, unless the `small-acl-buffer` feature limits it
+/// to 27 bytes, the size every controller supports. Larger L2CAP PDUs, such as the ATT PDUs of up to
+/// `att_mtu` bytes plus the 4-byte L2CAP header, are split into several ACL data packets by
+/// `Ble::acl_write_pdu`, so this only trades RAM for the number of packets: with the default `att_mtu`
+/// of 156 bytes, a full ATT PDU takes one packet, or 6 packets with `small-acl-buffer`.
+#[cfg(not(feature = "small-acl-buffer"))]
+pub const TL_ACL_DATA_MAX_PAYLOAD_SIZE: usize = 251;
+#[cfg(feature = "small-acl-buffer")]
+pub const TL_ACL_DATA_MAX_PAYLOAD_SIZE: usize = 27;
+
+// The packet header is `TL_PacketHeader_t`, two pointers linking the packet in a CPU2 queue
+const _: () = assert!(TL_PACKET_HEADER_SIZE == 2 * mem::size_of::<*mut LinkedListNode>());
+// `CsEvt` is status, number of commands and opcode
+const _: () = assert!(TL_CS_EVT_SIZE == 4);
+const _: () = assert!(TL_EVT_HEADER_SIZE == mem::size_of::<EvtStub>() + 1);
+const _: () = assert!(TL_ACL_DATA_HEADER_SIZE == mem::size_of::<AclDataSerialStub>());
+// LE controllers accept ACL data payloads from 27 to 251 bytes
+const _: () = assert!(TL_ACL_DATA_MAX_PAYLOAD_SIZE >= 27 && TL_ACL_DATA_MAX_PAYLOAD_SIZE <= 251);
+// Packet header, packet type, opcode, payload length and payload
+const _: () = assert!(mem::size_of::<CmdPacket>() == TL_PACKET_HEADER_SIZE + 1 + 2 + 1 + TL_CMD_MAX_PAYLOAD_SIZE);
+const _: () = assert!(mem::size_of::<EvtPacket>() == TL_PACKET_HEADER_SIZE + TL_EVT_HEADER_SIZE + 255);
 
 /**
  * Queue length of BLE Event
@@ -76,19 +109,40 @@ pub const TL_CS_EVT_SIZE: usize = core::mem::size_of::<CsEvt>();
  */
 pub const CFG_TL_BLE_EVT_QUEUE_LENGTH: usize = 5;
 pub const CFG_TL_BLE_MOST_EVENT_PAYLOAD_SIZE: usize = 255;
+
+/// Maximum number of simultaneous BLE connections, `CFG_BLE_NUM_LINK`
+///
+/// This is the default `num_of_links` of [`ShciBleInitCmdParam`](crate::shci::ShciBleInitCmdParam), and
+/// the capacity of the tables kept by the crate for each connection. It is 2 as in ST's examples, and is
+/// raised by the `max-connections-4` and `max-connections-8` features.
+pub const CFG_BLE_NUM_LINK: u8 = if cfg!(feature = "max-connections-8") {
+    8
+} else if cfg!(feature = "max-connections-4") {
+    4
+} else {
+    2
+};
 pub const TL_BLE_EVENT_FRAME_SIZE: usize = TL_EVT_HEADER_SIZE + CFG_TL_BLE_MOST_EVENT_PAYLOAD_SIZE;
 
-pub const POOL_SIZE: usize = CFG_TL_BLE_EVT_QUEUE_LENGTH * 4 * divc(TL_PACKET_HEADER_SIZE + TL_BLE_EVENT_FRAME_SIZE, 4);
+pub const POOL_SIZE: usize = pool_size(CFG_TL_BLE_EVT_QUEUE_LENGTH);
 pub const C_SIZE_CMD_STRING: usize = 256;
 
+/// Size of an event pool of `evt_queue_len` buffers, each rounded up to a 4-byte boundary
+pub const fn pool_size(evt_queue_len: usize) -> usize {
+    evt_queue_len * 4 * divc(TL_PACKET_HEADER_SIZE + TL_BLE_EVENT_FRAME_SIZE, 4)
+}
+
 pub const fn divc(x: usize, y: usize) -> usize {
     (x + y - 1) / y
 }
 
 pub const TL_BLE_EVT_CS_PACKET_SIZE: usize = TL_EVT_HEADER_SIZE + TL_CS_EVT_SIZE;
-#[allow(dead_code)]
 pub const TL_BLE_EVT_CS_BUFFER_SIZE: usize = TL_PACKET_HEADER_SIZE + TL_BLE_EVT_CS_PACKET_SIZE;
 
+// Each event frame of the pool is rounded up to a 4-byte boundary
+const _: () = assert!(POOL_SIZE % 4 == 0);
+const _: () = assert!(POOL_SIZE >= CFG_TL_BLE_EVT_QUEUE_LENGTH * (TL_PACKET_HEADER_SIZE + TL_BLE_EVENT_FRAME_SIZE));
+
 pub const TL_BLEEVT_CC_OPCODE: u8 = 0x0E;
 pub const TL_BLEEVT_CS_OPCODE: u8 = 0x0F;
 pub const TL_BLEEVT_VS_OPCODE: u8 = 0xFF;
@@ -0,0 +1,292 @@
+//! Errors of the wireless stack, for applications handling all of them in one place
+//!
+//! The system commands of [`Sys`](crate::sub::sys::Sys) return [`Error`] directly. The BLE subsystem
+//! reports [`BleError`](crate::ble::typedefs::BleError), shared with the command builders and event
+//! parsers, which converts into [`Error`], so that an application function returning
+//! `Result<_, Error>` can use `?` on any of them.
+
+/// Error code of the HCI commands and events, as defined by the Bluetooth core specification
+///
+/// Codes 0x40 and above are reported as [`HciError::Other`]: the vendor commands of the wireless stack
+/// return ST status codes in that range, such as `BLE_STATUS_INSUFFICIENT_RESOURCES` (0x64), which
+/// overlap the codes added by later versions of the specification. Unassigned codes are reported as
+/// [`HciError::Other`] as well, and [`HciError::code`] always gives back the original code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HciError {
+    UnknownCommand,
+    /// No connection with this handle, also reported when a connection attempt is cancelled
+    UnknownConnectionId,
+    HardwareFailure,
+    PageTimeout,
+    AuthenticationFailure,
+    PinOrKeyMissing,
+    MemoryCapacityExceeded,
+    /// The link supervision timeout expired
+    ConnectionTimeout,
+    ConnectionLimitExceeded,
+    SynchronousConnectionLimitExceeded,
+    ConnectionAlreadyExists,
+    /// The command is not allowed in the current state of the controller
+    CommandDisallowed,
+    RejectedLimitedResources,
+    RejectedSecurityReasons,
+    RejectedUnacceptableBdAddr,
+    ConnectionAcceptTimeout,
+    UnsupportedFeatureOrParameter,
+    InvalidCommandParameters,
+    RemoteUserTerminated,
+    RemoteLowResources,
+    RemotePowerOff,
+    /// The connection was terminated by the local host
+    LocalHostTerminated,
+    RepeatedAttempts,
+    PairingNotAllowed,
+    UnknownLmpPdu,
+    UnsupportedRemoteFeature,
+    ScoOffsetRejected,
+    ScoIntervalRejected,
+    ScoAirModeRejected,
+    InvalidLlParameters,
+    Unspecified,
+    UnsupportedLlParameterValue,
+    RoleChangeNotAllowed,
+    /// The peer did not answer a link layer procedure in time
+    LlResponseTimeout,
+    LlProcedureCollision,
+    LmpPduNotAllowed,
+    EncryptionModeNotAcceptable,
+    LinkKeyCannotBeChanged,
+    QosNotSupported,
+    /// The instant of a link layer procedure was missed
+    InstantPassed,
+    PairingWithUnitKeyNotSupported,
+    DifferentTransactionCollision,
+    QosUnacceptableParameter,
+    QosRejected,
+    ChannelClassificationNotSupported,
+    InsufficientSecurity,
+    ParameterOutOfMandatoryRange,
+    RoleSwitchPending,
+    ReservedSlotViolation,
+    RoleSwitchFailed,
+    ExtendedInquiryResponseTooLarge,
+    SecureSimplePairingNotSupported,
+    HostBusyPairing,
+    NoSuitableChannelFound,
+    /// The controller is busy, the command can be retried later
+    ControllerBusy,
+    UnacceptableConnectionParameters,
+    /// Directed advertising or an advertising duration ended without connection
+    AdvertisingTimeout,
+    /// A packet failed the message integrity check of the encrypted link
+    MicFailure,
+    ConnectionFailedToBeEstablished,
+    /// Another error code, including the vendor status codes
+    Other(u8),
+}
+
+impl HciError {
+    /// Error code as sent by the controller
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::UnknownCommand => 0x01,
+            Self::UnknownConnectionId => 0x02,
+            Self::HardwareFailure => 0x03,
+            Self::PageTimeout => 0x04,
+            Self::AuthenticationFailure => 0x05,
+            Self::PinOrKeyMissing => 0x06,
+            Self::MemoryCapacityExceeded => 0x07,
+            Self::ConnectionTimeout => 0x08,
+            Self::ConnectionLimitExceeded => 0x09,
+            Self::SynchronousConnectionLimitExceeded => 0x0A,
+            Self::ConnectionAlreadyExists => 0x0B,
+            Self::CommandDisallowed => 0x0C,
+            Self::RejectedLimitedResources => 0x0D,
+            Self::RejectedSecurityReasons => 0x0E,
+            Self::RejectedUnacceptableBdAddr => 0x0F,
+            Self::ConnectionAcceptTimeout => 0x10,
+            Self::UnsupportedFeatureOrParameter => 0x11,
+            Self::InvalidCommandParameters => 0x12,
+            Self::RemoteUserTerminated => 0x13,
+            Self::RemoteLowResources => 0x14,
+            Self::RemotePowerOff => 0x15,
+            Self::LocalHostTerminated => 0x16,
+            Self::RepeatedAttempts => 0x17,
+            Self::PairingNotAllowed => 0x18,
+            Self::UnknownLmpPdu => 0x19,
+            Self::UnsupportedRemoteFeature => 0x1A,
+            Self::ScoOffsetRejected => 0x1B,
+            Self::ScoIntervalRejected => 0x1C,
+            Self::ScoAirModeRejected => 0x1D,
+            Self::InvalidLlParameters => 0x1E,
+            Self::Unspecified => 0x1F,
+            Self::UnsupportedLlParameterValue => 0x20,
+            Self::RoleChangeNotAllowed => 0x21,
+            Self::LlResponseTimeout => 0x22,
+            Self::LlProcedureCollision => 0x23,
+            Self::LmpPduNotAllowed => 0x24,
+            Self::EncryptionModeNotAcceptable => 0x25,
+            Self::LinkKeyCannotBeChanged => 0x26,
+            Self::QosNotSupported => 0x27,
+            Self::InstantPassed => 0x28,
+            Self::PairingWithUnitKeyNotSupported => 0x29,
+            Self::DifferentTransactionCollision => 0x2A,
+            Self::QosUnacceptableParameter => 0x2C,
+            Self::QosRejected => 0x2D,
+            Self::ChannelClassificationNotSupported => 0x2E,
+            Self::InsufficientSecurity => 0x2F,
+            Self::ParameterOutOfMandatoryRange => 0x30,
+            Self::RoleSwitchPending => 0x32,
+            Self::ReservedSlotViolation => 0x34,
+            Self::RoleSwitchFailed => 0x35,
+            Self::ExtendedInquiryResponseTooLarge => 0x36,
+            Self::SecureSimplePairingNotSupported => 0x37,
+            Self::HostBusyPairing => 0x38,
+            Self::NoSuitableChannelFound => 0x39,
+            Self::ControllerBusy => 0x3A,
+            Self::UnacceptableConnectionParameters => 0x3B,
+            Self::AdvertisingTimeout => 0x3C,
+            Self::MicFailure => 0x3D,
+            Self::ConnectionFailedToBeEstablished => 0x3E,
+            Self::Other(code) => *code,
+        }
+    }
+}
+
+impl From<u8> for HciError {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => Self::UnknownCommand,
+            0x02 => Self::UnknownConnectionId,
+            0x03 => Self::HardwareFailure,
+            0x04 => Self::PageTimeout,
+            0x05 => Self::AuthenticationFailure,
+            0x06 => Self::PinOrKeyMissing,
+            0x07 => Self::MemoryCapacityExceeded,
+            0x08 => Self::ConnectionTimeout,
+            0x09 => Self::ConnectionLimitExceeded,
+            0x0A => Self::SynchronousConnectionLimitExceeded,
+            0x0B => Self::ConnectionAlreadyExists,
+            0x0C => Self::CommandDisallowed,
+            0x0D => Self::RejectedLimitedResources,
+            0x0E => Self::RejectedSecurityReasons,
+            0x0F => Self::RejectedUnacceptableBdAddr,
+            0x10 => Self::ConnectionAcceptTimeout,
+            0x11 => Self::UnsupportedFeatureOrParameter,
+            0x12 => Self::InvalidCommandParameters,
+            0x13 => Self::RemoteUserTerminated,
+            0x14 => Self::RemoteLowResources,
+            0x15 => Self::RemotePowerOff,
+            0x16 => Self::LocalHostTerminated,
+            0x17 => Self::RepeatedAttempts,
+            0x18 => Self::PairingNotAllowed,
+            0x19 => Self::UnknownLmpPdu,
+            0x1A => Self::UnsupportedRemoteFeature,
+            0x1B => Self::ScoOffsetRejected,
+            0x1C => Self::ScoIntervalRejected,
+            0x1D => Self::ScoAirModeRejected,
+            0x1E => Self::InvalidLlParameters,
+            0x1F => Self::Unspecified,
+            0x20 => Self::UnsupportedLlParameterValue,
+            0x21 => Self::RoleChangeNotAllowed,
+            0x22 => Self::LlResponseTimeout,
+            0x23 => Self::LlProcedureCollision,
+            0x24 => Self::LmpPduNotAllowed,
+            0x25 => Self::EncryptionModeNotAcceptable,
+            0x26 => Self::LinkKeyCannotBeChanged,
+            0x27 => Self::QosNotSupported,
+            0x28 => Self::InstantPassed,
+            0x29 => Self::PairingWithUnitKeyNotSupported,
+            0x2A => Self::DifferentTransactionCollision,
+            0x2C => Self::QosUnacceptableParameter,
+            0x2D => Self::QosRejected,
+            0x2E => Self::ChannelClassificationNotSupported,
+            0x2F => Self::InsufficientSecurity,
+            0x30 => Self::ParameterOutOfMandatoryRange,
+            0x32 => Self::RoleSwitchPending,
+            0x34 => Self::ReservedSlotViolation,
+            0x35 => Self::RoleSwitchFailed,
+            0x36 => Self::ExtendedInquiryResponseTooLarge,
+            0x37 => Self::SecureSimplePairingNotSupported,
+            0x38 => Self::HostBusyPairing,
+            0x39 => Self::NoSuitableChannelFound,
+            0x3A => Self::ControllerBusy,
+            0x3B => Self::UnacceptableConnectionParameters,
+            0x3C => Self::AdvertisingTimeout,
+            0x3D => Self::MicFailure,
+            0x3E => Self::ConnectionFailedToBeEstablished,
+            code => Self::Other(code),
+        }
+    }
+}
+
+/// Error of any of the subsystems
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// An HCI or ACI command failed with this status, or an event reported it
+    Hci(HciError),
+    /// A system command failed with this SHCI status, which may not be a known
+    /// [`SchiCommandStatus`](crate::shci::SchiCommandStatus)
+    Shci(u8),
+    /// The wireless stack has no free buffer, the command can be retried later
+    PoolExhausted,
+    /// The wireless stack cannot accept the command or data yet, it can be retried later
+    Busy,
+    /// The wireless stack did not answer in time
+    Timeout,
+    /// The wireless stack is not running, e.g. CPU2 has not reported being ready or runs FUS
+    NotStarted,
+    /// The wireless stack has not reported being ready yet, see [`Sys::wait_ready`](crate::sub::sys::Sys::wait_ready)
+    NotReady,
+    /// CPU2 restarted before answering
+    Reset,
+    /// The response of the wireless stack could not be decoded
+    InvalidResponse,
+    /// The parameters were rejected before sending the command
+    InvalidParameters,
+    /// The running wireless stack does not support the requested feature
+    Unsupported,
+}
+
+impl From<HciError> for Error {
+    fn from(err: HciError) -> Self {
+        Self::Hci(err)
+    }
+}
+
+#[cfg(feature = "ble")]
+impl From<crate::ble::typedefs::BleError> for Error {
+    fn from(err: crate::ble::typedefs::BleError) -> Self {
+        use crate::ble::consts::BLE_STATUS_INSUFFICIENT_RESOURCES;
+        use crate::ble::typedefs::BleError;
+
+        match err {
+            BleError::Status(BLE_STATUS_INSUFFICIENT_RESOURCES) => Self::PoolExhausted,
+            BleError::Status(status) => Self::Hci(status.into()),
+            BleError::InvalidResponse => Self::InvalidResponse,
+            BleError::Unsupported => Self::Unsupported,
+            BleError::InvalidParameters => Self::InvalidParameters,
+            BleError::Busy => Self::Busy,
+            BleError::Timeout => Self::Timeout,
+            BleError::Reset => Self::Reset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hci_error_codes() {
+        for code in 0..=u8::MAX {
+            assert_eq!(HciError::from(code).code(), code);
+        }
+
+        assert_eq!(HciError::from(0x0C), HciError::CommandDisallowed);
+        assert_eq!(HciError::from(0x2B), HciError::Other(0x2B));
+        assert_eq!(HciError::from(0x64), HciError::Other(0x64));
+    }
+}
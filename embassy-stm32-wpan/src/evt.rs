@@ -1,8 +1,10 @@
 use core::marker::PhantomData;
-use core::{ptr, slice};
+use core::{mem, ptr, slice};
+
+use heapless::Vec;
 
 use super::PacketHeader;
-use crate::consts::TL_EVT_HEADER_SIZE;
+use crate::consts::{TL_ACL_DATA_HEADER_SIZE, TL_EVT_HEADER_SIZE};
 
 /**
  * The payload of `Evt` for a command status event
@@ -62,7 +64,8 @@ pub struct EvtSerial {
     pub evt: Evt,
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C, packed)]
 pub struct EvtStub {
     pub kind: u8,
@@ -142,6 +145,52 @@ impl<T: MemoryManager> EvtBox<T> {
             slice::from_raw_parts(evt_serial_buf, len)
         }
     }
+
+    /// Copy the [`serial`](Self::serial) bytes of the event, so that the box can be dropped right away
+    ///
+    /// The event keeps a buffer of the small event pool until its box is dropped. Copying it trades a copy
+    /// for giving the buffer back to CPU2 at once, which is worth it when the event is handed to another
+    /// task, stored, or logged by a slower consumer. Returns `None` if the event does not fit in `N` bytes.
+    pub fn to_owned<const N: usize>(&self) -> Option<Vec<u8, N>> {
+        Vec::from_slice(self.serial()).ok()
+    }
+
+    /// HCI ACL data packet held by the buffer in place of an event, starting with its handle field
+    ///
+    /// Only meaningful if the packet type of the [`stub`](Self::stub) is an ACL data packet.
+    pub fn acl_data(&self) -> &[u8] {
+        unsafe {
+            let evt_serial: *const EvtSerial = &(*self.ptr).evt_serial;
+            // the packet type indicator is followed by the handle field and the data length
+            let p_data: *const u8 = evt_serial.cast::<u8>().add(1);
+
+            let len = u16::from_le_bytes([ptr::read_volatile(p_data.add(2)), ptr::read_volatile(p_data.add(3))]);
+            let len = (TL_ACL_DATA_HEADER_SIZE - 1 + len as usize).min(mem::size_of::<EvtSerial>() - 1);
+
+            slice::from_raw_parts(p_data, len)
+        }
+    }
+
+    /// Give the event buffer back right away
+    ///
+    /// This is what dropping the box does, spelled out for code that is done with the event long before
+    /// the box goes out of scope: the event pool is small, and a buffer held meanwhile cannot be used by
+    /// CPU2 for the next events. The box is consumed, so the buffer cannot be released twice.
+    pub fn release(self) {
+        drop(self);
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: MemoryManager> defmt::Format for EvtBox<T> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "EvtBox {{ stub: {}, payload: {=[u8]:02x} }}",
+            self.stub(),
+            self.payload()
+        )
+    }
 }
 
 impl<T: MemoryManager> Drop for EvtBox<T> {
@@ -0,0 +1,217 @@
+//! IPCC operations used by the transport layer
+//!
+//! The subsystems reach CPU2 through [`Ipcc`], which is the IPCC driver of `embassy_stm32` on the
+//! target. In the host tests, it is replaced by [`mock::MockIpcc`], whose channels are set and cleared
+//! by the tests playing the part of CPU2, so that the command and event paths run without hardware.
+
+use embassy_stm32::ipcc::IpccChannel;
+
+/// Operations on the IPCC channels, see [`embassy_stm32::ipcc::Ipcc`] for their semantics
+pub(crate) trait IpccOps {
+    /// Wait for the CPU1 to CPU2 channel to be free, call `f` to write the packet and set the channel
+    async fn send(channel: IpccChannel, f: impl FnOnce());
+
+    /// Call `f` and set the CPU1 to CPU2 channel if it is free, returns false otherwise
+    #[cfg(feature = "ble")]
+    fn try_send(channel: IpccChannel, f: impl FnOnce()) -> bool;
+
+    /// Wait for CPU2 to clear the CPU1 to CPU2 channel
+    async fn flush(channel: IpccChannel);
+
+    /// Wait for the CPU2 to CPU1 channel to be set and call `f` until it returns a value, clearing the
+    /// channel each time it returns `None`
+    async fn receive<R>(channel: IpccChannel, f: impl FnMut() -> Option<R>) -> R;
+
+    /// Whether the CPU1 to CPU2 channel is set
+    fn is_tx_pending(channel: IpccChannel) -> bool;
+
+    /// Whether the CPU2 to CPU1 channel is set
+    fn is_rx_pending(channel: IpccChannel) -> bool;
+}
+
+/// IPCC peripheral shared with CPU2
+#[cfg(not(test))]
+pub(crate) struct HardwareIpcc;
+
+#[cfg(not(test))]
+impl IpccOps for HardwareIpcc {
+    async fn send(channel: IpccChannel, f: impl FnOnce()) {
+        embassy_stm32::ipcc::Ipcc::send(channel, f).await
+    }
+
+    #[cfg(feature = "ble")]
+    fn try_send(channel: IpccChannel, f: impl FnOnce()) -> bool {
+        embassy_stm32::ipcc::Ipcc::try_send(channel, f)
+    }
+
+    async fn flush(channel: IpccChannel) {
+        embassy_stm32::ipcc::Ipcc::flush(channel).await
+    }
+
+    async fn receive<R>(channel: IpccChannel, f: impl FnMut() -> Option<R>) -> R {
+        embassy_stm32::ipcc::Ipcc::receive(channel, f).await
+    }
+
+    fn is_tx_pending(channel: IpccChannel) -> bool {
+        embassy_stm32::ipcc::Ipcc::is_tx_pending(channel)
+    }
+
+    fn is_rx_pending(channel: IpccChannel) -> bool {
+        embassy_stm32::ipcc::Ipcc::is_rx_pending(channel)
+    }
+}
+
+#[cfg(not(test))]
+pub(crate) type Ipcc = HardwareIpcc;
+#[cfg(test)]
+pub(crate) type Ipcc = mock::MockIpcc;
+
+/// Host stand-in for the IPCC, driven by the tests on behalf of CPU2
+#[cfg(test)]
+pub(crate) mod mock {
+    use core::future::poll_fn;
+    use core::task::{Poll, Waker};
+    use std::sync::Mutex;
+
+    use super::*;
+
+    const CHANNELS: usize = 6;
+
+    struct State {
+        /// CPU1 to CPU2 channels set and not read by CPU2 yet
+        tx: [bool; CHANNELS],
+        /// CPU2 to CPU1 channels set and not cleared by CPU1 yet
+        rx: [bool; CHANNELS],
+        tx_wakers: [Option<Waker>; CHANNELS],
+        rx_wakers: [Option<Waker>; CHANNELS],
+        /// Packets sent on each CPU1 to CPU2 channel
+        sent: [usize; CHANNELS],
+    }
+
+    static STATE: Mutex<State> = Mutex::new(State {
+        tx: [false; CHANNELS],
+        rx: [false; CHANNELS],
+        tx_wakers: [None, None, None, None, None, None],
+        rx_wakers: [None, None, None, None, None, None],
+        sent: [0; CHANNELS],
+    });
+
+    fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+        f(&mut STATE.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub(crate) struct MockIpcc;
+
+    // CPU2 side of the channels, for the tests of the subsystems
+    #[cfg(feature = "ble")]
+    impl MockIpcc {
+        /// Clear all the channels, as after an IPCC reset
+        pub(crate) fn reset() {
+            with_state(|state| {
+                state.tx = [false; CHANNELS];
+                state.rx = [false; CHANNELS];
+                state.tx_wakers = [None, None, None, None, None, None];
+                state.rx_wakers = [None, None, None, None, None, None];
+                state.sent = [0; CHANNELS];
+            })
+        }
+
+        /// CPU2 reads the packet sent on a CPU1 to CPU2 channel and clears it, returns false if none was sent
+        pub(crate) fn cpu2_read(channel: IpccChannel) -> bool {
+            with_state(|state| {
+                let pending = core::mem::replace(&mut state.tx[channel as usize], false);
+                if let Some(waker) = state.tx_wakers[channel as usize].take() {
+                    waker.wake();
+                }
+
+                pending
+            })
+        }
+
+        /// CPU2 sets a CPU2 to CPU1 channel, after appending to the queue or writing the buffer behind it
+        pub(crate) fn cpu2_notify(channel: IpccChannel) {
+            with_state(|state| {
+                state.rx[channel as usize] = true;
+                if let Some(waker) = state.rx_wakers[channel as usize].take() {
+                    waker.wake();
+                }
+            })
+        }
+
+        /// Number of packets sent on a CPU1 to CPU2 channel since the last reset
+        pub(crate) fn sent(channel: IpccChannel) -> usize {
+            with_state(|state| state.sent[channel as usize])
+        }
+    }
+
+    impl IpccOps for MockIpcc {
+        async fn send(channel: IpccChannel, f: impl FnOnce()) {
+            Self::flush(channel).await;
+
+            f();
+            with_state(|state| {
+                state.tx[channel as usize] = true;
+                state.sent[channel as usize] += 1;
+            })
+        }
+
+        #[cfg(feature = "ble")]
+        fn try_send(channel: IpccChannel, f: impl FnOnce()) -> bool {
+            if Self::is_tx_pending(channel) {
+                return false;
+            }
+
+            f();
+            with_state(|state| {
+                state.tx[channel as usize] = true;
+                state.sent[channel as usize] += 1;
+            });
+
+            true
+        }
+
+        async fn flush(channel: IpccChannel) {
+            poll_fn(|cx| {
+                with_state(|state| {
+                    if state.tx[channel as usize] {
+                        state.tx_wakers[channel as usize] = Some(cx.waker().clone());
+                        Poll::Pending
+                    } else {
+                        Poll::Ready(())
+                    }
+                })
+            })
+            .await
+        }
+
+        async fn receive<R>(channel: IpccChannel, mut f: impl FnMut() -> Option<R>) -> R {
+            loop {
+                poll_fn(|cx| {
+                    with_state(|state| {
+                        if state.rx[channel as usize] {
+                            Poll::Ready(())
+                        } else {
+                            state.rx_wakers[channel as usize] = Some(cx.waker().clone());
+                            Poll::Pending
+                        }
+                    })
+                })
+                .await;
+
+                if let Some(ret) = f() {
+                    return ret;
+                }
+
+                with_state(|state| state.rx[channel as usize] = false);
+            }
+        }
+
+        fn is_tx_pending(channel: IpccChannel) -> bool {
+            with_state(|state| state.tx[channel as usize])
+        }
+
+        fn is_rx_pending(channel: IpccChannel) -> bool {
+            with_state(|state| state.rx[channel as usize])
+        }
+    }
+}
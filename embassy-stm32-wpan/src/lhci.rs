@@ -9,7 +9,14 @@ const TL_BLEEVT_CC_OPCODE: u8 = 0x0e;
 const LHCI_OPCODE_C1_DEVICE_INF: u16 = 0xfd62;
 
 const PACKAGE_DATA_PTR: *const u8 = 0x1FFF_7500 as _;
-const UID64_PTR: *const u32 = 0x1FFF_7580 as _;
+pub(crate) const UID64_PTR: *const u32 = 0x1FFF_7580 as _;
+
+/// Read `UID64`: the unique device number in the low word, then the device type and the ST company ID
+pub(crate) fn read_uid64() -> u64 {
+    let (udn, id) = unsafe { (ptr::read_volatile(UID64_PTR), ptr::read_volatile(UID64_PTR.add(1))) };
+
+    (id as u64) << 32 | udn as u64
+}
 
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
@@ -33,6 +40,46 @@ pub struct LhciC1DeviceInformationCcrp {
     pub app_fw_inf: u32,
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for LhciC1DeviceInformationCcrp {
+    fn format(&self, f: defmt::Formatter) {
+        let Self {
+            status,
+            rev_id,
+            dev_code_id,
+            package_type,
+            device_type_id,
+            st_company_id,
+            uid64,
+            uid96_0,
+            uid96_1,
+            uid96_2,
+            safe_boot_info_table,
+            rss_info_table,
+            wireless_fw_info_table,
+            app_fw_inf,
+        } = *self;
+        defmt::write!(
+            f,
+            "LhciC1DeviceInformationCcrp {{ status: {}, rev_id: {=u16:#x}, dev_code_id: {=u16:#x}, package_type: {}, device_type_id: {}, st_company_id: {=u32:#x}, uid64: {=u32:#x}, uid96: [{=u32:#x}, {=u32:#x}, {=u32:#x}], safe_boot_info_table: {}, rss_info_table: {}, wireless_fw_info_table: {}, app_fw_inf: {=u32:#x} }}",
+            status,
+            rev_id,
+            dev_code_id,
+            package_type,
+            device_type_id,
+            st_company_id,
+            uid64,
+            uid96_0,
+            uid96_1,
+            uid96_2,
+            safe_boot_info_table,
+            rss_info_table,
+            wireless_fw_info_table,
+            app_fw_inf
+        )
+    }
+}
+
 impl Default for LhciC1DeviceInformationCcrp {
     fn default() -> Self {
         let DeviceInfoTable {
@@ -1,16 +1,20 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![allow(async_fn_in_trait)]
 #![doc = include_str!("../README.md")]
 // #![warn(missing_docs)]
 
 // This must go FIRST so that all the other modules see its macros.
 mod fmt;
+mod ipcc;
+mod wait_queue;
 
 use core::mem::MaybeUninit;
+use core::ptr;
 use core::sync::atomic::{compiler_fence, Ordering};
 
 use embassy_hal_internal::{into_ref, Peripheral, PeripheralRef};
 use embassy_stm32::interrupt;
+use embassy_stm32::interrupt::typelevel::Interrupt;
 use embassy_stm32::ipcc::{Config, Ipcc, ReceiveInterruptHandler, TransmitInterruptHandler};
 use embassy_stm32::peripherals::IPCC;
 use sub::mm::MemoryManager;
@@ -18,9 +22,12 @@ use sub::sys::Sys;
 use tables::*;
 use unsafe_linked_list::LinkedListNode;
 
+#[cfg(feature = "ble")]
+pub mod ble;
 pub mod channels;
 pub mod cmd;
 pub mod consts;
+pub mod error;
 pub mod evt;
 pub mod lhci;
 pub mod shci;
@@ -31,11 +38,59 @@ pub mod unsafe_linked_list;
 #[cfg(feature = "mac")]
 pub mod mac;
 
+// The 802.15.4 MAC and Zigbee firmwares use the same IPCC channels, only one of them can run on CPU2
+#[cfg(all(feature = "mac", feature = "zigbee"))]
+compile_error!("the `mac` and `zigbee` features cannot be enabled together");
+
 #[cfg(feature = "ble")]
 pub use crate::sub::ble::hci;
 
 type PacketHeader = LinkedListNode;
 
+/// How CPU2 was found by [`TlMbox::init`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootKind {
+    /// CPU2 was not running: the shared memory was cleared and CPU2 booted, the wireless stack must be
+    /// started and configured after its ready event
+    Cold,
+    /// CPU2 kept running the wireless stack while CPU1 restarted: the mailbox was attached to the tables
+    /// of the stack without clearing them or resetting the IPCC
+    ///
+    /// The ready event was sent before the restart and is not received again, and the stack keeps its
+    /// configuration, advertising and connections. The event buffers held by the application before the
+    /// restart are lost, and the statistics of the memory manager start from zero.
+    WarmCpu2Alive,
+}
+
+/// Mailbox shared with CPU2
+///
+/// # Concurrency
+///
+/// The subsystems never poll CPU2. Each future waits on its IPCC channel through [`Ipcc::send`] or
+/// [`Ipcc::receive`], which register the task in an `AtomicWaker` kept by the IPCC driver for that channel
+/// and direction. The interrupt handlers bound in [`TlMbox::init`] only mask the channel and wake that
+/// waker: no queue is read and no pointer is dereferenced in interrupt context. The woken task then takes
+/// the packets from the shared queues, in short critical sections.
+///
+/// An `AtomicWaker` holds a single waker, so each channel should be awaited by one task at a time. Two
+/// tasks reading BLE events concurrently, for instance, keep replacing each other's waker and one of them
+/// may miss its wake-up.
+///
+/// # Polling
+///
+/// Applications without an executor can service the mailbox from their main loop instead, with the `poll`
+/// functions of the subsystems, such as [`Sys::poll`] and [`MemoryManager::poll`]. These poll the futures
+/// described above once, so the IPCC interrupts must still be bound to the handlers.
+///
+/// # Interrupt latency
+///
+/// Since events are decoded and handed out as `EvtBox` by the task awaiting them, the IPCC interrupts run
+/// for a few instructions only, and can be given any priority with [`TlMbox::set_interrupt_priority`]
+/// without delaying the other interrupts. The cost is the wake-up latency of that task: an event is only
+/// taken from the queue once the executor polls it, after the higher priority tasks and interrupts. An
+/// application that needs events handled within a bounded delay should await them from a task of an
+/// `InterruptExecutor` at a high priority, rather than raising the priority of the IPCC interrupts.
 pub struct TlMbox<'d> {
     _ipcc: PeripheralRef<'d, IPCC>,
 
@@ -45,9 +100,20 @@ pub struct TlMbox<'d> {
     pub ble_subsystem: sub::ble::Ble,
     #[cfg(feature = "mac")]
     pub mac_subsystem: sub::mac::Mac,
+    #[cfg(feature = "zigbee")]
+    pub zigbee_subsystem: sub::zigbee::Zigbee,
+    #[cfg(feature = "traces")]
+    pub traces_subsystem: sub::traces::Traces,
+
+    config: Config,
+    boot_kind: BootKind,
 }
 
 impl<'d> TlMbox<'d> {
+    /// Initialize the mailbox and boot CPU2
+    ///
+    /// If CPU2 kept running the wireless stack while CPU1 restarted, the mailbox is attached to it
+    /// instead, without clearing the shared memory: see [`TlMbox::boot_kind`].
     pub fn init(
         ipcc: impl Peripheral<P = IPCC> + 'd,
         _irqs: impl interrupt::typelevel::Binding<interrupt::typelevel::IPCC_C1_RX, ReceiveInterruptHandler>
@@ -56,20 +122,158 @@ impl<'d> TlMbox<'d> {
     ) -> Self {
         into_ref!(ipcc);
 
+        let boot_kind = Self::init_tables_unless_alive();
+
+        Self::enable_ipcc(config, boot_kind);
+
+        Self::new(ipcc, config, boot_kind)
+    }
+
+    /// Initialize the mailbox without binding the IPCC interrupts with `bind_interrupts!`
+    ///
+    /// This is meant for applications dispatching interrupts themselves (e.g. RTIC). Both IPCC
+    /// interrupts are set to `priority`, and left masked if `masked` is true, in which case they must be
+    /// enabled with `IPCC_C1_RX::enable()` and `IPCC_C1_TX::enable()` before communicating with CPU2.
+    ///
+    /// # Safety
+    ///
+    /// The `IPCC_C1_RX` interrupt handler must call [`ReceiveInterruptHandler`]'s `on_interrupt()`, and the
+    /// `IPCC_C1_TX` handler must call [`TransmitInterruptHandler`]'s `on_interrupt()`, before the interrupts
+    /// are unmasked. Otherwise the mailbox futures are never woken up.
+    pub unsafe fn init_with_handlers(
+        ipcc: impl Peripheral<P = IPCC> + 'd,
+        config: Config,
+        priority: interrupt::Priority,
+        masked: bool,
+    ) -> Self {
+        into_ref!(ipcc);
+
+        let boot_kind = Self::init_tables_unless_alive();
+
+        interrupt::typelevel::IPCC_C1_RX::set_priority(priority);
+        interrupt::typelevel::IPCC_C1_TX::set_priority(priority);
+
+        cortex_m::interrupt::free(|_| {
+            Self::enable_ipcc(config, boot_kind);
+
+            if masked {
+                interrupt::typelevel::IPCC_C1_RX::disable();
+                interrupt::typelevel::IPCC_C1_TX::disable();
+            }
+        });
+
+        Self::new(ipcc, config, boot_kind)
+    }
+
+    /// Initialize the mailbox in shared memory already cleared, without zeroing it again
+    ///
+    /// This is [`TlMbox::init`] without clearing the tables and buffers of the `MB_MEM1` and `MB_MEM2`
+    /// sections, for applications whose startup code already zeroes them, e.g. by placing them in a
+    /// `.bss`-like region. It saves the time taken to clear both sections, see also the
+    /// `skip-pool-zeroing` feature which only skips the event pool. The reference table is still written.
+    ///
+    /// # Safety
+    ///
+    /// Every byte of the `MB_MEM1` and `MB_MEM2` sections must be zero, and CPU2 must not have accessed
+    /// them since. Memory kept across a reset still holds the tables and buffers of the previous run: it
+    /// must be cleared before calling this, or [`TlMbox::init`] used instead.
+    pub unsafe fn init_assume_zeroed(
+        ipcc: impl Peripheral<P = IPCC> + 'd,
+        _irqs: impl interrupt::typelevel::Binding<interrupt::typelevel::IPCC_C1_RX, ReceiveInterruptHandler>
+            + interrupt::typelevel::Binding<interrupt::typelevel::IPCC_C1_TX, TransmitInterruptHandler>,
+        config: Config,
+    ) -> Self {
+        into_ref!(ipcc);
+
+        Self::init_ref_table();
+        compiler_fence(Ordering::SeqCst);
+
+        Ipcc::enable(config);
+
+        Self::new(ipcc, config, BootKind::Cold)
+    }
+
+    /// Initialize the tables shared with CPU2, unless it kept running through a restart of CPU1
+    fn init_tables_unless_alive() -> BootKind {
+        if Self::is_cpu2_alive() {
+            BootKind::WarmCpu2Alive
+        } else {
+            Self::init_tables();
+            BootKind::Cold
+        }
+    }
+
+    /// Tell whether CPU2 kept running the wireless stack, with the tables of this application
+    ///
+    /// CPU2 is allowed to boot by [`Ipcc::enable`], which a restart of CPU1 alone does not undo. Its tables
+    /// are only reused if the reference table still points to them and the wireless stack has filled the
+    /// device information table, which rules out the garbage found in the shared memory at power on and
+    /// the tables of another application.
+    fn is_cpu2_alive() -> bool {
+        if !Ipcc::is_cpu2_enabled() {
+            return false;
+        }
+
+        unsafe {
+            let ref_table = TL_REF_TABLE.as_ptr().read_volatile();
+            // The fields of the information tables are packed, read the whole table without referencing them
+            let info = ptr::read_unaligned(TL_DEVICE_INFO_TABLE.as_ptr());
+
+            ref_table.device_info_table == TL_DEVICE_INFO_TABLE.as_ptr()
+                && ref_table.sys_table == TL_SYS_TABLE.as_ptr()
+                && ref_table.mem_manager_table == TL_MEM_MANAGER_TABLE.as_ptr()
+                && info.wireless_fw_info_table.version != 0
+        }
+    }
+
+    fn enable_ipcc(config: Config, boot_kind: BootKind) {
+        match boot_kind {
+            BootKind::Cold => Ipcc::enable(config),
+            BootKind::WarmCpu2Alive => Ipcc::reattach(config),
+        }
+    }
+
+    /// Point the reference table to the other tables
+    fn init_ref_table() {
         unsafe {
             TL_REF_TABLE.as_mut_ptr().write_volatile(RefTable {
                 device_info_table: TL_DEVICE_INFO_TABLE.as_ptr(),
                 ble_table: TL_BLE_TABLE.as_ptr(),
+                #[cfg(not(feature = "omit-unused-tables"))]
                 thread_table: TL_THREAD_TABLE.as_ptr(),
+                #[cfg(feature = "omit-unused-tables")]
+                thread_table: core::ptr::null(),
                 sys_table: TL_SYS_TABLE.as_ptr(),
                 mem_manager_table: TL_MEM_MANAGER_TABLE.as_ptr(),
+                #[cfg(any(feature = "mac", feature = "traces", not(feature = "omit-unused-tables")))]
                 traces_table: TL_TRACES_TABLE.as_ptr(),
+                #[cfg(all(feature = "omit-unused-tables", not(any(feature = "mac", feature = "traces"))))]
+                traces_table: core::ptr::null(),
+                #[cfg(any(feature = "mac", not(feature = "omit-unused-tables")))]
                 mac_802_15_4_table: TL_MAC_802_15_4_TABLE.as_ptr(),
+                #[cfg(all(feature = "omit-unused-tables", not(feature = "mac")))]
+                mac_802_15_4_table: core::ptr::null(),
+                #[cfg(any(feature = "zigbee", not(feature = "omit-unused-tables")))]
                 zigbee_table: TL_ZIGBEE_TABLE.as_ptr(),
+                #[cfg(all(feature = "omit-unused-tables", not(feature = "zigbee")))]
+                zigbee_table: core::ptr::null(),
+                #[cfg(not(feature = "omit-unused-tables"))]
                 lld_tests_table: TL_LLD_TESTS_TABLE.as_ptr(),
+                #[cfg(feature = "omit-unused-tables")]
+                lld_tests_table: core::ptr::null(),
+                #[cfg(not(feature = "omit-unused-tables"))]
                 ble_lld_table: TL_BLE_LLD_TABLE.as_ptr(),
+                #[cfg(feature = "omit-unused-tables")]
+                ble_lld_table: core::ptr::null(),
             });
+        }
+    }
+
+    /// Point the reference table to the other tables, and clear the tables and buffers shared with CPU2
+    fn init_tables() {
+        Self::init_ref_table();
 
+        unsafe {
             TL_SYS_TABLE
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
@@ -79,6 +283,7 @@ impl<'d> TlMbox<'d> {
             TL_BLE_TABLE
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
+            #[cfg(not(feature = "omit-unused-tables"))]
             TL_THREAD_TABLE
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
@@ -86,34 +291,47 @@ impl<'d> TlMbox<'d> {
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
 
+            #[cfg(any(feature = "mac", feature = "traces", not(feature = "omit-unused-tables")))]
             TL_TRACES_TABLE
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
+            #[cfg(any(feature = "mac", not(feature = "omit-unused-tables")))]
             TL_MAC_802_15_4_TABLE
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
+            #[cfg(any(feature = "zigbee", not(feature = "omit-unused-tables")))]
             TL_ZIGBEE_TABLE
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
+            #[cfg(not(feature = "omit-unused-tables"))]
             TL_LLD_TESTS_TABLE
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
+            #[cfg(not(feature = "omit-unused-tables"))]
             TL_BLE_LLD_TABLE
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
 
+            // CPU2 formats the event pool and writes the spare buffers before handing them out, only the
+            // tables and the buffers written by CPU1 have to be initialized
+            #[cfg(all(
+                any(feature = "ble", feature = "mac", feature = "zigbee"),
+                not(feature = "skip-pool-zeroing")
+            ))]
             EVT_POOL
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
+            #[cfg(not(feature = "skip-pool-zeroing"))]
             SYS_SPARE_EVT_BUF
                 .as_mut_ptr()
                 .write_volatile(MaybeUninit::zeroed().assume_init());
-            CS_BUFFER
-                .as_mut_ptr()
-                .write_volatile(MaybeUninit::zeroed().assume_init());
 
             #[cfg(feature = "ble")]
             {
+                CS_BUFFER
+                    .as_mut_ptr()
+                    .write_volatile(MaybeUninit::zeroed().assume_init());
+                #[cfg(not(feature = "skip-pool-zeroing"))]
                 BLE_SPARE_EVT_BUF
                     .as_mut_ptr()
                     .write_volatile(MaybeUninit::zeroed().assume_init());
@@ -126,6 +344,19 @@ impl<'d> TlMbox<'d> {
                     .write_volatile(MaybeUninit::zeroed().assume_init());
             }
 
+            #[cfg(feature = "zigbee")]
+            {
+                ZIGBEE_APPLI_CMD_BUFFER
+                    .as_mut_ptr()
+                    .write_volatile(MaybeUninit::zeroed().assume_init());
+                ZIGBEE_NOTIF_BUFFER
+                    .as_mut_ptr()
+                    .write_volatile(MaybeUninit::zeroed().assume_init());
+                ZIGBEE_REQUEST_BUFFER
+                    .as_mut_ptr()
+                    .write_volatile(MaybeUninit::zeroed().assume_init());
+            }
+
             #[cfg(feature = "mac")]
             {
                 MAC_802_15_4_CMD_BUFFER
@@ -138,17 +369,185 @@ impl<'d> TlMbox<'d> {
         }
 
         compiler_fence(Ordering::SeqCst);
+    }
 
-        Ipcc::enable(config);
+    fn new(ipcc: PeripheralRef<'d, IPCC>, config: Config, boot_kind: BootKind) -> Self {
+        // Creating the subsystems initializes their tables and queues, which CPU2 still uses after a warm boot
+        match boot_kind {
+            BootKind::Cold => Self {
+                _ipcc: ipcc,
+                sys_subsystem: sub::sys::Sys::new(),
+                #[cfg(feature = "ble")]
+                ble_subsystem: sub::ble::Ble::new(),
+                #[cfg(feature = "mac")]
+                mac_subsystem: sub::mac::Mac::new(),
+                #[cfg(feature = "zigbee")]
+                zigbee_subsystem: sub::zigbee::Zigbee::new(),
+                #[cfg(feature = "traces")]
+                traces_subsystem: sub::traces::Traces::new(),
+                mm_subsystem: sub::mm::MemoryManager::new(),
+                config,
+                boot_kind,
+            },
+            BootKind::WarmCpu2Alive => Self {
+                _ipcc: ipcc,
+                sys_subsystem: sub::sys::Sys::attach(),
+                #[cfg(feature = "ble")]
+                ble_subsystem: sub::ble::Ble::attach(),
+                #[cfg(feature = "mac")]
+                mac_subsystem: sub::mac::Mac::new(),
+                #[cfg(feature = "zigbee")]
+                zigbee_subsystem: sub::zigbee::Zigbee::attach(),
+                #[cfg(feature = "traces")]
+                traces_subsystem: sub::traces::Traces::attach(),
+                mm_subsystem: sub::mm::MemoryManager::attach(),
+                config,
+                boot_kind,
+            },
+        }
+    }
 
-        Self {
-            _ipcc: ipcc,
-            sys_subsystem: sub::sys::Sys::new(),
-            #[cfg(feature = "ble")]
-            ble_subsystem: sub::ble::Ble::new(),
-            #[cfg(feature = "mac")]
-            mac_subsystem: sub::mac::Mac::new(),
-            mm_subsystem: sub::mm::MemoryManager::new(),
+    /// Whether [`TlMbox::init`] booted CPU2, or attached to the wireless stack it kept running
+    ///
+    /// After a [`BootKind::WarmCpu2Alive`] boot, the application must skip waiting for the ready event and
+    /// initializing the wireless stack.
+    pub fn boot_kind(&self) -> BootKind {
+        self.boot_kind
+    }
+
+    /// Set the priority of both IPCC interrupts
+    ///
+    /// The interrupt handlers only wake the tasks awaiting the mailbox, see the
+    /// [interrupt latency](TlMbox#interrupt-latency) notes.
+    pub fn set_interrupt_priority(&self, priority: interrupt::Priority) {
+        interrupt::typelevel::IPCC_C1_RX::set_priority(priority);
+        interrupt::typelevel::IPCC_C1_TX::set_priority(priority);
+    }
+
+    /// Wait for CPU2 to restart on its own, e.g. after a watchdog reset, and bring the mailbox back
+    ///
+    /// A restart shows as a `C2Ready` system event received while the wireless stack was running, which
+    /// is detected by [`Sys::read_sys_event`]. The commands waiting for an answer then fail on every
+    /// subsystem, with [`Error::Reset`](error::Error::Reset) or the reset error of the subsystem. This
+    /// returns right away if the restart was detected before it was called, and reads the system events
+    /// itself otherwise, the errors being still reported to [`Sys::wait_error`].
+    ///
+    /// CPU2 forgets the packets it held, so this then initializes again the event queues owned by CPU1 and
+    /// the tables pointing to them, as [`TlMbox::init`] does. The tables and buffers written by CPU2, such as
+    /// the device information table filled by the restarted stack, are left as they are, and so are the IPCC
+    /// channels of CPU2. The ready event has been read already: the wireless stack must be configured again
+    /// as after boot, without waiting for it.
+    ///
+    /// Returns [`Error::Busy`](error::Error::Busy), without touching the mailbox, if event buffers are
+    /// still held by the application: drop them and call this again.
+    pub async fn recover(&mut self) -> Result<(), error::Error> {
+        while !sub::sys::is_restart_pending() {
+            self.sys_subsystem.read_sys_event().await;
+        }
+
+        // the ACL data packets queued for the application hold buffers of the pool as well
+        #[cfg(feature = "ble")]
+        sub::ble::reset();
+
+        #[cfg(feature = "mac")]
+        let mac_event_held = sub::mac::Mac::is_event_held();
+        #[cfg(not(feature = "mac"))]
+        let mac_event_held = false;
+        if MemoryManager::usage().outstanding != 0 || mac_event_held {
+            warn!("mbox: event buffers still held after CPU2 restarted");
+            return Err(error::Error::Busy);
+        }
+
+        Ipcc::reattach(self.config);
+
+        // The subsystems are handles to static tables and queues, which creating them initializes
+        self.sys_subsystem = Sys::new();
+        self.mm_subsystem = MemoryManager::new();
+        #[cfg(feature = "ble")]
+        {
+            self.ble_subsystem = sub::ble::Ble::new();
+        }
+        #[cfg(feature = "mac")]
+        {
+            self.mac_subsystem = sub::mac::Mac::new();
         }
+        #[cfg(feature = "zigbee")]
+        {
+            self.zigbee_subsystem = sub::zigbee::Zigbee::new();
+        }
+        #[cfg(feature = "traces")]
+        {
+            self.traces_subsystem = sub::traces::Traces::new();
+        }
+
+        sub::sys::restart_recovered();
+
+        Ok(())
+    }
+
+    /// Read the tables shared with CPU2, to tell whether it has booted and filled them
+    pub fn debug_dump(&self) -> TableSnapshot {
+        unsafe { TableSnapshot::read() }
+    }
+
+    /// Read the flags of the IPCC channels, to tell which commands CPU2 has not read and which events
+    /// are waiting to be read
+    pub fn pending_channels(&self) -> channels::ChannelFlags {
+        channels::ChannelFlags::read()
+    }
+
+    /// Report the event buffers that have been handed out and not released yet
+    ///
+    /// Event buffers are given back to CPU2 when their `EvtBox` is dropped. A leaked `EvtBox` cannot be
+    /// recovered because it holds the only pointer to its buffer, so this only reports the pool usage:
+    /// `outstanding` growing towards `pool_size` while no events are held means buffers are being leaked.
+    pub fn reclaim_leaked(&self) -> sub::mm::PoolUsage {
+        sub::mm::MemoryManager::usage()
+    }
+
+    /// Report the occupancy of the event queue and buffers, and their peaks
+    ///
+    /// The peaks tell how close the application came to exhausting the event pool, to tune
+    /// [`CFG_TL_BLE_EVT_QUEUE_LENGTH`](consts::CFG_TL_BLE_EVT_QUEUE_LENGTH) from measurements.
+    pub fn stats(&self) -> sub::mm::MailboxStats {
+        sub::mm::MemoryManager::stats()
+    }
+
+    /// Restart the peaks reported by [`TlMbox::stats`] from the current occupancy
+    pub fn reset_stats(&self) {
+        sub::mm::MemoryManager::reset_stats()
+    }
+}
+
+/// Raw access to the tables shared with CPU2
+///
+/// CPU2 reads and writes these tables concurrently with the application, and their layout is tied to
+/// the wireless coprocessor firmware. Reading them must be done with volatile accesses, and writing them
+/// while CPU2 is running can break the mailbox.
+#[cfg(feature = "unstable-internals")]
+impl<'d> TlMbox<'d> {
+    /// Pointer to the reference table, whose address is given to CPU2 through the option bytes
+    pub fn ref_table(&self) -> *const RefTable {
+        unsafe { TL_REF_TABLE.as_ptr() }
+    }
+
+    /// Pointer to the device information table, filled by CPU2 when it boots
+    pub fn device_info_table(&self) -> *const DeviceInfoTable {
+        unsafe { TL_DEVICE_INFO_TABLE.as_ptr() }
+    }
+
+    /// Pointer to the BLE table, holding the BLE command, event and ACL buffers
+    pub fn ble_table(&self) -> *const BleTable {
+        unsafe { TL_BLE_TABLE.as_ptr() }
+    }
+
+    /// Pointer to the system table, holding the system command buffer and event queue
+    pub fn sys_table(&self) -> *const SysTable {
+        unsafe { TL_SYS_TABLE.as_ptr() }
+    }
+
+    /// Pointer to the memory manager table, describing the event buffer pool
+    pub fn mem_manager_table(&self) -> *const MemManagerTable {
+        unsafe { TL_MEM_MANAGER_TABLE.as_ptr() }
     }
 }
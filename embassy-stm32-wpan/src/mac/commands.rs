@@ -16,6 +16,7 @@ pub trait MacCommand: Sized {
 
 /// MLME ASSOCIATE Request used to request an association
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct AssociateRequest {
     /// the logical channel on which to attempt association
@@ -46,6 +47,7 @@ impl MacCommand for AssociateRequest {
 
 /// MLME DISASSOCIATE Request sed to request a disassociation
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct DisassociateRequest {
     /// device addressing mode used
@@ -74,7 +76,7 @@ impl MacCommand for DisassociateRequest {
 
 /// MLME GET Request used to request a PIB value
 #[repr(C)]
-#[derive(Default)]
+#[derive(Debug, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct GetRequest {
     /// the name of the PIB attribute to read
@@ -90,6 +92,7 @@ impl MacCommand for GetRequest {
 
 /// MLME GTS Request used to request and maintain GTSs
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct GtsRequest {
     /// the characteristics of the GTS
@@ -109,7 +112,7 @@ impl MacCommand for GtsRequest {
 }
 
 #[repr(C)]
-#[derive(Default)]
+#[derive(Debug, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ResetRequest {
     /// MAC PIB attributes are set to their default values or not during reset
@@ -125,6 +128,7 @@ impl MacCommand for ResetRequest {
 /// MLME RX ENABLE Request used to request that the receiver is either enabled
 /// for a finite period of time or disabled
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct RxEnableRequest {
     /// the request operation can be deferred or not
@@ -146,6 +150,7 @@ impl MacCommand for RxEnableRequest {
 
 /// MLME SCAN Request used to initiate a channel scan over a given list of channels
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ScanRequest {
     /// the type of scan to be performed
@@ -174,6 +179,7 @@ impl MacCommand for ScanRequest {
 
 /// MLME SET Request used to attempt to write the given value to the indicated PIB attribute
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct SetRequest {
     /// the pointer to the value of the PIB attribute to set
@@ -189,7 +195,7 @@ impl MacCommand for SetRequest {
 /// MLME START Request used by the FFDs to intiate a new PAN or to begin using a new superframe
 /// configuration
 #[repr(C)]
-#[derive(Default)]
+#[derive(Debug, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct StartRequest {
     /// PAN indentifier to used by the device
@@ -233,6 +239,7 @@ impl MacCommand for StartRequest {
 /// MLME SYNC Request used to synchronize with the coordinator by acquiring and, if
 /// specified, tracking its beacons
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct SyncRequest {
     /// the channel number on which to attempt coordinator synchronization
@@ -254,6 +261,7 @@ impl MacCommand for SyncRequest {
 
 /// MLME POLL Request propmts the device to request data from the coordinator
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct PollRequest {
     /// addressing mode of the coordinator
@@ -281,6 +289,7 @@ impl MacCommand for PollRequest {
 /// MLME DPS Request allows the next higher layer to request that the PHY utilize a
 /// given pair of preamble codes for a single use pending expiration of the DPSIndexDuration
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct DpsRequest {
     /// the index value for the transmitter
@@ -301,6 +310,7 @@ impl MacCommand for DpsRequest {
 /// MLME SOUNDING request primitive which is used by the next higher layer to request that
 /// the PHY respond with channel sounding information
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct SoundingRequest {
     /// byte stuffing to keep 32 bit alignment
@@ -314,6 +324,7 @@ impl MacCommand for SoundingRequest {
 /// MLME CALIBRATE request primitive which used  to obtain the results of a ranging
 /// calibration request from an RDEV
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct CalibrateRequest {
     /// byte stuffing to keep 32 bit alignment
@@ -326,6 +337,7 @@ impl MacCommand for CalibrateRequest {
 
 /// MCPS DATA Request used for MAC data related requests from the application
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct DataRequest {
     /// the handle assocated with the MSDU to be transmitted
@@ -408,6 +420,7 @@ impl MacCommand for DataRequest {
 
 /// for MCPS PURGE Request used to purge an MSDU from the transaction queue
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct PurgeRequest {
     /// the handle associated with the MSDU to be purged from the transaction
@@ -423,7 +436,7 @@ impl MacCommand for PurgeRequest {
 
 /// MLME ASSOCIATE Response used to initiate a response to an MLME-ASSOCIATE.indication
 #[repr(C)]
-#[derive(Default)]
+#[derive(Debug, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct AssociateResponse {
     /// extended address of the device requesting association
@@ -451,6 +464,7 @@ impl MacCommand for AssociateResponse {
 
 /// MLME ORPHAN Response used to respond to the MLME ORPHAN Indication
 #[repr(C)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct OrphanResponse {
     /// extended address of the orphaned device
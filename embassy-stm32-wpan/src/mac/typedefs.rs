@@ -10,6 +10,8 @@ pub enum MacError {
     NotSupported = 0x03,
     HardwareNotSupported = 0x04,
     Undefined = 0x05,
+    /// CPU2 restarted before answering, see [`TlMbox::recover`](crate::TlMbox::recover)
+    Reset,
 }
 
 impl From<u8> for MacError {
@@ -166,6 +168,7 @@ impl TryFrom<&[u8]> for MacAddress {
     }
 }
 
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct GtsCharacteristics {
     pub fields: u8,
@@ -263,6 +266,7 @@ numeric_enum! {
 
 #[cfg(not(feature = "defmt"))]
 bitflags::bitflags! {
+    #[derive(Debug)]
     pub struct Capabilities: u8 {
         /// 1 if the device is capabaleof becoming a PAN coordinator
         const IS_COORDINATOR_CAPABLE = 0b00000001;
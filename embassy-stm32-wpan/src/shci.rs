@@ -1,6 +1,7 @@
 use core::{mem, slice};
 
-use crate::consts::{TL_CS_EVT_SIZE, TL_EVT_HEADER_SIZE, TL_PACKET_HEADER_SIZE};
+use crate::consts::{CFG_BLE_NUM_LINK, TL_BLEEVT_VS_OPCODE, TL_CS_EVT_SIZE, TL_EVT_HEADER_SIZE, TL_PACKET_HEADER_SIZE};
+use crate::error::Error;
 
 const SHCI_OGF: u16 = 0x3F;
 
@@ -9,6 +10,7 @@ const fn opcode(ogf: u16, ocf: u16) -> isize {
 }
 
 #[allow(dead_code)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SchiCommandStatus {
     ShciSuccess = 0x00,
@@ -45,7 +47,18 @@ impl TryFrom<u8> for SchiCommandStatus {
     }
 }
 
+impl SchiCommandStatus {
+    /// `Ok` for [`SchiCommandStatus::ShciSuccess`], the status as an [`Error::Shci`] otherwise
+    pub fn result(self) -> Result<(), Error> {
+        match self {
+            Self::ShciSuccess => Ok(()),
+            status => Err(Error::Shci(status as u8)),
+        }
+    }
+}
+
 #[allow(dead_code)]
+#[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ShciOpcode {
     // 0x50 reserved
@@ -99,7 +112,147 @@ pub const SHCI_C2_CONFIG_EVTMASK1_BIT4_NVM_END_WRITE_ENABLE: u8 = 1 << 4;
 pub const SHCI_C2_CONFIG_EVTMASK1_BIT5_NVM_START_ERASE_ENABLE: u8 = 1 << 5;
 pub const SHCI_C2_CONFIG_EVTMASK1_BIT6_NVM_END_ERASE_ENABLE: u8 = 1 << 6;
 
-#[derive(Clone, Copy)]
+/// Semaphore used by CPU2 to signal the application that it is about to access the flash
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FlashActivityControl {
+    /// CPU2 sets the `PESD` bit of `FLASH_SR`, blocking the flash accesses of the application
+    Pes = 0x00,
+    /// CPU2 takes semaphore 7 of the HSEM peripheral, which the application should take before
+    /// erasing or writing the flash
+    Sem7 = 0x01,
+}
+
+pub const SHCI_SUB_EVT_CODE_READY: u16 = 0x9200;
+pub const SHCI_SUB_EVT_ERROR_NOTIF: u16 = 0x9201;
+pub const SHCI_SUB_EVT_BLE_NVM_RAM_UPDATE: u16 = 0x9202;
+pub const SHCI_SUB_EVT_NVM_START_WRITE: u16 = 0x9203;
+pub const SHCI_SUB_EVT_NVM_END_WRITE: u16 = 0x9204;
+pub const SHCI_SUB_EVT_NVM_START_ERASE: u16 = 0x9205;
+pub const SHCI_SUB_EVT_NVM_END_ERASE: u16 = 0x9206;
+
+/// System events reporting the NVM activity of CPU2
+///
+/// When [`ShciConfigParam::ble_nvm_ram_address`] is set, CPU2 keeps the BLE security database
+/// (bonded devices and their keys) in this RAM area instead of its own flash sectors, and reports every
+/// update with [`SysNvmEvent::BleNvmRamUpdate`]. The application then copies the area to flash, and
+/// restores it at the same address before the next `SHCI_C2_Config` to bring the bonds back after a
+/// reboot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SysNvmEvent {
+    /// The BLE NVM RAM area has been updated
+    BleNvmRamUpdate {
+        start_address: u32,
+        size: u32,
+    },
+    StartWrite {
+        number_of_words: u32,
+    },
+    EndWrite,
+    StartErase {
+        number_of_sectors: u32,
+    },
+    EndErase,
+}
+
+impl SysNvmEvent {
+    /// Decode the payload of a system event, starting with its sub event code
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let code = u16::from_le_bytes([*payload.first()?, *payload.get(1)?]);
+        let word = |index: usize| {
+            payload
+                .get(2 + 4 * index..6 + 4 * index)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        };
+
+        match code {
+            SHCI_SUB_EVT_BLE_NVM_RAM_UPDATE => Some(Self::BleNvmRamUpdate {
+                start_address: word(0)?,
+                size: word(1)?,
+            }),
+            SHCI_SUB_EVT_NVM_START_WRITE => Some(Self::StartWrite {
+                number_of_words: word(0)?,
+            }),
+            SHCI_SUB_EVT_NVM_END_WRITE => Some(Self::EndWrite),
+            SHCI_SUB_EVT_NVM_START_ERASE => Some(Self::StartErase {
+                number_of_sectors: word(0)?,
+            }),
+            SHCI_SUB_EVT_NVM_END_ERASE => Some(Self::EndErase),
+            _ => None,
+        }
+    }
+}
+
+/// Error reported by CPU2 with `SHCI_SUB_EVT_ERROR_NOTIF`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SysErrorCode {
+    /// The BLE stack failed to initialize, usually because of invalid `SHCI_C2_BLE_Init` parameters
+    BleInit,
+    /// The Thread low level driver hit a fatal error
+    ThreadLldFatalError,
+    /// The Thread stack received a command it does not know
+    ThreadUnknownCmd,
+    /// The Zigbee stack received a command it does not know
+    ZigbeeUnknownCmd,
+    /// An error code not documented by ST
+    Other(u8),
+}
+
+impl From<u8> for SysErrorCode {
+    fn from(v: u8) -> Self {
+        match v {
+            0x00 => Self::BleInit,
+            0x7D => Self::ThreadLldFatalError,
+            0x7E => Self::ThreadUnknownCmd,
+            0x7F => Self::ZigbeeUnknownCmd,
+            v => Self::Other(v),
+        }
+    }
+}
+
+/// Decoded system event, as returned by [`Sys::read_sys_event`](crate::sub::sys::Sys::read_sys_event)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SysEvent {
+    /// CPU2 has started the wireless stack and is ready to accept commands
+    CpuReady,
+    /// CPU2 has started the firmware upgrade service instead of the wireless stack
+    ///
+    /// This is expected after a FUS command, and means that no wireless stack is installed or that
+    /// it failed to start otherwise.
+    FusState,
+    /// CPU2 reported an error
+    ErrorNotif(SysErrorCode),
+    /// CPU2 is accessing its NVM, or has updated the BLE NVM RAM area
+    Nvm(SysNvmEvent),
+    /// A system event that is not decoded, identified by its sub event code
+    Other(u16),
+}
+
+impl SysEvent {
+    pub fn parse(evt_code: u8, payload: &[u8]) -> Option<Self> {
+        if evt_code != TL_BLEEVT_VS_OPCODE || payload.len() < 2 {
+            return None;
+        }
+
+        let code = u16::from_le_bytes([payload[0], payload[1]]);
+        let evt = match code {
+            SHCI_SUB_EVT_CODE_READY => match payload.get(2)? {
+                0x00 => Self::CpuReady,
+                _ => Self::FusState,
+            },
+            SHCI_SUB_EVT_ERROR_NOTIF => Self::ErrorNotif((*payload.get(2)?).into()),
+            _ => SysNvmEvent::parse(payload).map_or(Self::Other(code), Self::Nvm),
+        };
+
+        Some(evt)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 #[repr(C, packed)]
 pub struct ShciConfigParam {
     pub payload_cmd_size: u8,
@@ -139,7 +292,49 @@ impl Default for ShciConfigParam {
     }
 }
 
-#[derive(Clone, Copy)]
+/// Clock driving the radio timer while CPU2 sleeps, bit 2 of [`ShciBleInitCmdParam::ls_source`]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SleepClockSource {
+    /// 32.768 kHz LSE crystal, the usual choice on boards fitted with one
+    #[default]
+    Lse = 0,
+    /// HSE divided by 1024, for boards without LSE crystal, at the cost of a higher sleep current
+    HseDiv1024 = 1,
+}
+
+/// Whether CPU2 runs the BLE host, bit 0 of [`ShciBleInitCmdParam::options`]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LlOnly {
+    /// Link layer and host (GAP, GATT, security manager), as with the full BLE stack
+    #[default]
+    LlAndHost = 0,
+    /// Link layer only, the host running on the application side, as with the HCI-only stacks
+    LlOnly = 1,
+}
+
+/// Use of a Viterbi decoder in the link layer reception, see [`ShciBleInitCmdParam::viterbi_enable`]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ViterbiEnable {
+    Disabled = 0,
+    /// Better sensitivity on the coded PHY, recommended by ST
+    #[default]
+    Enabled = 1,
+}
+
+/// Options of [`ShciBleInitCmdParam::options`] only used by the BLE host
+const BLE_INIT_OPTIONS_HOST: u8 = 1 << 1 | 1 << 2 | 1 << 5 | 1 << 6;
+/// Bits of [`ShciBleInitCmdParam::ls_source`] defined by the wireless stack
+const BLE_INIT_LS_SOURCE_MASK: u8 = 0b111;
+/// Worst sleep clock accuracy allowed by the Bluetooth specification, in ppm
+const MAX_SLEEP_CLOCK_ACCURACY: u16 = 500;
+
+#[derive(Debug, Clone, Copy)]
 #[repr(C, packed)]
 pub struct ShciBleInitCmdParam {
     /// NOT USED - shall be set to 0
@@ -176,7 +371,7 @@ pub struct ShciBleInitCmdParam {
     pub attr_value_arr_size: u16,
     /// Maximum number of BLE links supported
     ///    - Min value: 1
-    ///    - Max value: 8
+    ///    - Max value: 8, and at most [`CFG_BLE_NUM_LINK`], the number of connections tracked by this crate
     pub num_of_links: u8,
     /// Disable/enable the extended packet length BLE 5.0 feature
     ///    - Disable: 0
@@ -200,6 +395,9 @@ pub struct ShciBleInitCmdParam {
     /// Maximum ATT MTU size supported
     ///     - Min value: 23
     ///     - Max value: 512
+    ///
+    /// ATT PDUs are not limited by the size of the ACL data buffer, see
+    /// [`TL_ACL_DATA_MAX_PAYLOAD_SIZE`](crate::consts::TL_ACL_DATA_MAX_PAYLOAD_SIZE).
     pub att_mtu: u16,
     /// The sleep clock accuracy (ppm value) that used in BLE connected slave mode to calculate the window widening
     /// (in combination with the sleep clock accuracy sent by master in CONNECT_REQ PDU),
@@ -243,8 +441,8 @@ pub struct ShciBleInitCmdParam {
     ///    - Max value:  820 (~2 ms). A higher value can be specified, but the value that implemented in stack is forced to ~2 ms
     pub hs_startup_time: u16,
     /// Viterbi implementation in BLE LL reception.
-    ///    - 0: Enable
-    ///    - 1: Disable
+    ///    - 0: Disable
+    ///    - 1: Enable
     pub viterbi_enable: u8,
     /// - bit 0:
     ///     - 1: LL only
@@ -340,20 +538,15 @@ pub struct ShciBleInitCmdParam {
 }
 
 impl ShciBleInitCmdParam {
-    pub fn payload<'a>(&'a self) -> &'a [u8] {
-        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<Self>()) }
-    }
-}
-
-impl Default for ShciBleInitCmdParam {
-    fn default() -> Self {
+    /// Parameters of ST's BLE examples, as returned by `default()`
+    pub const fn new() -> Self {
         Self {
             p_ble_buffer_address: 0,
             ble_buffer_size: 0,
             num_attr_record: 68,
             num_attr_serv: 8,
             attr_value_arr_size: 1344,
-            num_of_links: 2,
+            num_of_links: CFG_BLE_NUM_LINK,
             extended_packet_length_enable: 1,
             prepare_write_list_size: 0x3A,
             block_count: 0x79,
@@ -368,8 +561,137 @@ impl Default for ShciBleInitCmdParam {
             hw_version: 0,
         }
     }
+
+    pub fn payload<'a>(&'a self) -> &'a [u8] {
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<Self>()) }
+    }
+
+    /// Select the clock used while CPU2 sleeps, which must match the clock configured for the RF wakeup
+    pub const fn with_sleep_clock_source(mut self, source: SleepClockSource) -> Self {
+        self.ls_source = (self.ls_source & !(1 << 2)) | (source as u8) << 2;
+        self
+    }
+
+    /// Set the accuracy of the sleep clock, in ppm, for both the peripheral and central roles
+    ///
+    /// This is the accuracy of the LSE crystal of the board, or of HSE with [`SleepClockSource::HseDiv1024`].
+    /// A value better than the real accuracy makes the peripheral open its receive window too late, and
+    /// connections drop after a few seconds; a worse value only costs a bit of power. Accuracies above 500
+    /// ppm are out of the specification and rejected by [`Self::validate`].
+    pub const fn with_sleep_clock_accuracy(mut self, ppm: u16) -> Self {
+        self.slave_sca = ppm;
+        self.master_sca = match ppm {
+            0..=20 => 7,
+            21..=30 => 6,
+            31..=50 => 5,
+            51..=75 => 4,
+            76..=100 => 3,
+            101..=150 => 2,
+            151..=250 => 1,
+            _ => 0,
+        };
+        self
+    }
+
+    /// Set the maximum number of simultaneous connections, from 1 to [`CFG_BLE_NUM_LINK`]
+    pub const fn with_num_of_links(mut self, num_of_links: u8) -> Self {
+        self.num_of_links = num_of_links;
+        self
+    }
+
+    /// Select whether CPU2 runs the BLE host
+    pub const fn with_ll_only(mut self, mode: LlOnly) -> Self {
+        self.options = (self.options & !1) | mode as u8;
+        self
+    }
+
+    pub const fn with_viterbi(mut self, viterbi: ViterbiEnable) -> Self {
+        self.viterbi_enable = viterbi as u8;
+        self
+    }
+
+    /// Check the consistency of the parameters
+    ///
+    /// Returns [`Error::InvalidParameters`] for host options (no service changed descriptor, read-only
+    /// device name, reduced GATT database, GATT caching) set in link-layer-only mode, sleep clock settings
+    /// out of their ranges, reserved bits set, or more links than the crate can track. As a `const fn`, it can check a configuration at compile
+    /// time:
+    ///
+    /// ```ignore
+    /// const BLE_INIT: ShciBleInitCmdParam = ShciBleInitCmdParam::new().with_sleep_clock_accuracy(50);
+    /// const _: () = assert!(BLE_INIT.validate().is_ok());
+    /// ```
+    pub const fn validate(&self) -> Result<(), Error> {
+        let ll_only = self.options & 1 != 0;
+        let slave_sca = self.slave_sca;
+
+        if (ll_only && self.options & BLE_INIT_OPTIONS_HOST != 0)
+            || slave_sca > MAX_SLEEP_CLOCK_ACCURACY
+            || self.master_sca > 7
+            || self.ls_source & !BLE_INIT_LS_SOURCE_MASK != 0
+            || self.viterbi_enable > 1
+            || self.num_of_links == 0
+            || self.num_of_links > CFG_BLE_NUM_LINK
+        {
+            return Err(Error::InvalidParameters);
+        }
+
+        Ok(())
+    }
+}
+
+// The wireless stack supports up to 8 links, and the default parameters must fit the tables of the crate
+const _: () = assert!(CFG_BLE_NUM_LINK >= 1 && CFG_BLE_NUM_LINK <= 8);
+const _: () = assert!(ShciBleInitCmdParam::new().validate().is_ok());
+
+impl Default for ShciBleInitCmdParam {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub const TL_BLE_EVT_CS_PACKET_SIZE: usize = TL_EVT_HEADER_SIZE + TL_CS_EVT_SIZE;
 #[allow(dead_code)] // Not used currently but reserved
 const TL_BLE_EVT_CS_BUFFER_SIZE: usize = TL_PACKET_HEADER_SIZE + TL_BLE_EVT_CS_PACKET_SIZE;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ble_init_options() {
+        let param = ShciBleInitCmdParam::new()
+            .with_sleep_clock_source(SleepClockSource::HseDiv1024)
+            .with_sleep_clock_accuracy(50)
+            .with_viterbi(ViterbiEnable::Disabled);
+
+        assert_eq!({ param.ls_source }, 0b101);
+        assert_eq!(({ param.slave_sca }, param.master_sca), (50, 5));
+        assert_eq!(param.viterbi_enable, 0);
+        assert_eq!(param.validate(), Ok(()));
+
+        assert_eq!(
+            ShciBleInitCmdParam::new().with_sleep_clock_accuracy(501).validate(),
+            Err(Error::InvalidParameters)
+        );
+
+        // host options are meaningless without the host
+        let mut param = ShciBleInitCmdParam::new().with_ll_only(LlOnly::LlOnly);
+        assert_eq!(param.validate(), Ok(()));
+        param.options |= 1 << 6;
+        assert_eq!(param.validate(), Err(Error::InvalidParameters));
+
+        assert_eq!({ ShciBleInitCmdParam::new().num_of_links }, CFG_BLE_NUM_LINK);
+        assert_eq!(ShciBleInitCmdParam::new().with_num_of_links(1).validate(), Ok(()));
+        assert_eq!(
+            ShciBleInitCmdParam::new()
+                .with_num_of_links(CFG_BLE_NUM_LINK + 1)
+                .validate(),
+            Err(Error::InvalidParameters)
+        );
+        assert_eq!(
+            ShciBleInitCmdParam::new().with_num_of_links(0).validate(),
+            Err(Error::InvalidParameters)
+        );
+    }
+}
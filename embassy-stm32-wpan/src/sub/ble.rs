@@ -1,20 +1,306 @@
-use core::ptr;
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::task::Poll;
+use core::{mem, ptr};
 
-use embassy_stm32::ipcc::Ipcc;
+use embassy_futures::poll_once;
+use embassy_futures::select::{select, Either};
+use embassy_hal_internal::drop::OnDrop;
+use embassy_sync::blocking_mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use futures_util::stream::{self, Stream};
 use hci::Opcode;
+use heapless::{Deque, Vec};
 
-use crate::cmd::CmdPacket;
-use crate::consts::{TlPacketType, TL_BLEEVT_CC_OPCODE, TL_BLEEVT_CS_OPCODE};
+use crate::ble::acl::{
+    pack_handle, AclCredits, AclError, AclPacket, CompletedPackets, Fragments, OutstandingPackets, HANDLE_MASK,
+};
+#[cfg(feature = "hci-capture")]
+use crate::ble::capture::{self, Direction, H4_ACL_DATA, H4_COMMAND};
+use crate::ble::commands::{
+    AddToFilterAcceptList, AddToResolvingList, AdvData, AdvParams, BleCommand, CommandSlot, ConnectionUpdate,
+    CreateConnection, ExtAdvData, ExtAdvEnable, ExtAdvParams, ExtAdvSet, HciCommand, ScanEnable, ScanParams,
+    ScanRspData, SetDataLength, SetPhy,
+};
+use crate::ble::conn::{ConnectionState, ConnectionStates};
+use crate::ble::consts::{
+    ACL_RX_QUEUE_SIZE, DEFAULT_ATT_MTU, MAX_ADV_DATA_LEN, MAX_CONNECTIONS, MAX_EXT_ADV_DATA_FRAGMENT_LEN, MAX_PASS_KEY,
+    MAX_PENDING_ADV_REPORTS, MAX_PENDING_PROCEDURES, PENDING_EVENTS_SIZE,
+};
+#[cfg(feature = "embassy-time")]
+use crate::ble::consts::{HCI_COMMAND_DISALLOWED, HCI_UNKNOWN_CONNECTION_ID};
+use crate::ble::event;
+use crate::ble::event::{
+    AdvertisingReports, AttExchangeMtuResp, CommandComplete, CommandReply, CommandStatus, ConnectionComplete,
+    ConnectionUpdateComplete, DataLengthChange, DisconnectionComplete, EncryptionChange, EncryptionKeyRefreshComplete,
+    GattProcComplete, NumberOfCompletedPackets, PhyUpdateComplete, ReadRemoteFeaturesComplete, TxPoolAvailable,
+    VendorEvent,
+};
+#[cfg(feature = "gatt-server")]
+use crate::ble::gatt::{self, CharConfig, CharHandle, GapHandles, GapRole, GattDb, ServiceHandle, ServiceType, Uuid};
+use crate::ble::opcodes::{vendor_opcode, HciOpcode};
+#[cfg(feature = "periodic-adv")]
+use crate::ble::periodic::{self, SyncConfig, SyncEstablished, MAX_PERIODIC_ADV_DATA_FRAGMENT_LEN};
+use crate::ble::scan::{self, ExtAdvReassembly, ExtAdvReport, ExtAdvertisingReports, ExtScanConfig};
+use crate::ble::typedefs::{
+    AdvChannelMap, AdvConfig, AdvFilterPolicy, AdvReport, BdAddr, BleError, BondedDevice, ChannelMap, ConfigDataOffset,
+    ConnParams, DisconnectReason, EventMask, ExtAdvDataOperation, LeBufferSize, LeEventMask, LeFeatures, LocalVersion,
+    OwnAddressType, PeerAddressType, Phy, PhyCoding, ScanConfig, TxPower, TxPowerRange,
+};
+use crate::cmd::{AclDataPacket, CmdPacket};
+#[cfg(feature = "hci-capture")]
+use crate::consts::TL_EVT_HEADER_SIZE;
+use crate::consts::{
+    TlPacketType, TL_ACL_DATA_MAX_PAYLOAD_SIZE, TL_BLEEVT_CC_OPCODE, TL_BLEEVT_CS_OPCODE, TL_CMD_MAX_PAYLOAD_SIZE,
+};
 use crate::evt::{EvtBox, EvtPacket, EvtStub};
-use crate::sub::mm;
+use crate::ipcc::{Ipcc, IpccOps};
+use crate::lhci::read_uid64;
+use crate::sub::{mm, sys};
 use crate::tables::{BleTable, BLE_CMD_BUFFER, CS_BUFFER, EVT_QUEUE, HCI_ACL_DATA_BUFFER, TL_BLE_TABLE};
 use crate::unsafe_linked_list::LinkedListNode;
+use crate::wait_queue::WaitQueue;
 use crate::{channels, evt};
 
+/// ACL data packets the controller can still accept
+///
+/// Only enforced once [`Ble::hci_reset_and_configure`] has read the number of ACL buffers of the controller.
+static ACL_CREDITS: AclCredits = AclCredits::new();
+/// ACL data packets completed on each connection, for throughput statistics
+static COMPLETED_PACKETS: CompletedPackets<MAX_CONNECTIONS> = CompletedPackets::new();
+/// ACL data packets sent on each connection, whose credits are given back if it closes before they complete
+static OUTSTANDING_PACKETS: OutstandingPackets<MAX_CONNECTIONS> = OutstandingPackets::new();
+
+/// The command waiting for its `Command Complete` or `Command Status` event
+static COMMAND_SLOT: CommandSlot = CommandSlot::new();
+/// Where the answer to the command in flight goes, see [`CommandAnswer`]
+static COMMAND_ANSWER: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<CommandAnswer>> =
+    blocking_mutex::Mutex::new(RefCell::new(CommandAnswer::Application));
+
+/// A task is reading the BLE event queue, and hands the events to the others
+static EVENT_READER: AtomicBool = AtomicBool::new(false);
+/// Tasks waiting for an event read by another task, or for the reader to leave
+static EVENT_WAITERS: WaitQueue = WaitQueue::new();
+/// Number of times the state waited for changed without an event being read, see [`notify_waiters`]
+static WAITER_NOTIFICATIONS: AtomicU32 = AtomicU32::new(0);
+/// Events read while waiting for something else, waiting for [`Ble::tl_read`]
+static PENDING_EVENTS: blocking_mutex::Mutex<
+    CriticalSectionRawMutex,
+    RefCell<Deque<EvtBox<Ble>, PENDING_EVENTS_SIZE>>,
+> = blocking_mutex::Mutex::new(RefCell::new(Deque::new()));
+/// Procedures waiting for the event reporting their outcome, see [`ProcedureWait`]
+static PROCEDURES: blocking_mutex::Mutex<
+    CriticalSectionRawMutex,
+    RefCell<[Option<ProcedureEntry>; MAX_PENDING_PROCEDURES]>,
+> = blocking_mutex::Mutex::new(RefCell::new({
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NONE: Option<ProcedureEntry> = None;
+    [NONE; MAX_PENDING_PROCEDURES]
+}));
+
+/// Number of times the wireless stack reported free buffers after a notification failed for lack of them
+static TX_POOL_GENERATION: AtomicU32 = AtomicU32::new(0);
+
+/// Entry of [`ATT_MTUS`] not used by any connection
+const NO_CONNECTION: u32 = u32::MAX;
+/// ATT_MTU exchanged on each connection, as the connection handle in the high half and the MTU in the low half
+static ATT_MTUS: [AtomicU32; MAX_CONNECTIONS] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ENTRY: AtomicU32 = AtomicU32::new(NO_CONNECTION);
+    [ENTRY; MAX_CONNECTIONS]
+};
+
+/// ACL data packets received while reading events, waiting for [`Ble::read_acl`]
+static ACL_RX_CHANNEL: Channel<CriticalSectionRawMutex, AclDataBox, ACL_RX_QUEUE_SIZE> = Channel::new();
+
+/// Link state of each connection, updated while reading events
+static CONNECTION_STATES: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<ConnectionStates<MAX_CONNECTIONS>>> =
+    blocking_mutex::Mutex::new(RefCell::new(ConnectionStates::new()));
+
+/// Discard an event read while waiting for another one, counting it in the mailbox statistics
+macro_rules! drop_event {
+    ($($arg:tt)*) => {{
+        mm::MemoryManager::track_dropped_event();
+        trace!($($arg)*)
+    }};
+}
+
 pub struct Ble {
     _private: (),
 }
 
+/// Procedure started by a command, whose outcome is reported by a later event
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Procedure {
+    /// `LE Connection Complete` of [`Ble::connect`]
+    Connection,
+    /// `LE Connection Update Complete` of [`Ble::update_connection_params`] on a connection
+    ConnectionUpdate(u16),
+    /// `LE PHY Update Complete` of [`Ble::set_phy`] on a connection
+    PhyUpdate(u16),
+    /// `LE Read Remote Features Complete` of [`Ble::read_remote_features`] on a connection
+    RemoteFeatures(u16),
+    /// `Encryption Change` or `Encryption Key Refresh Complete` of [`Ble::start_encryption`] on a connection
+    Encryption(u16),
+    /// `ACI_GATT_PROC_COMPLETE_EVENT` of [`Ble::exchange_mtu`] on a connection
+    MtuExchange(u16),
+    /// `Disconnection Complete` of [`Ble::disconnect`] on a connection
+    Disconnection(u16),
+    /// `LE Periodic Advertising Sync Established` of [`Ble::le_periodic_adv_create_sync`]
+    #[cfg(feature = "periodic-adv")]
+    PeriodicSync,
+}
+
+impl Procedure {
+    /// Whether the event reports the outcome of the procedure
+    fn reported_by(&self, evt_code: u8, payload: &[u8]) -> bool {
+        match self {
+            Self::Connection => ConnectionComplete::parse(evt_code, payload).is_some(),
+            Self::ConnectionUpdate(handle) => ConnectionUpdateComplete::parse(evt_code, payload)
+                .is_some_and(|complete| complete.connection_handle == *handle),
+            Self::PhyUpdate(handle) => PhyUpdateComplete::parse(evt_code, payload)
+                .is_some_and(|complete| complete.connection_handle == *handle),
+            Self::RemoteFeatures(handle) => ReadRemoteFeaturesComplete::parse(evt_code, payload)
+                .is_some_and(|complete| complete.connection_handle == *handle),
+            Self::Encryption(handle) => encryption_status(*handle, evt_code, payload).is_some(),
+            Self::MtuExchange(handle) => VendorEvent::parse(evt_code, payload)
+                .and_then(|evt| GattProcComplete::parse(&evt))
+                .is_some_and(|complete| complete.connection_handle == *handle),
+            Self::Disconnection(handle) => DisconnectionComplete::parse(evt_code, payload)
+                .is_some_and(|complete| complete.connection_handle == *handle),
+            #[cfg(feature = "periodic-adv")]
+            Self::PeriodicSync => SyncEstablished::parse(evt_code, payload).is_some(),
+        }
+    }
+
+    /// Connection the procedure runs on, whose disconnection ends it
+    fn connection(&self) -> Option<u16> {
+        match self {
+            // the disconnection is the outcome of the procedure, rather than its end
+            Self::Connection | Self::Disconnection(_) => None,
+            #[cfg(feature = "periodic-adv")]
+            Self::PeriodicSync => None,
+            Self::ConnectionUpdate(handle)
+            | Self::PhyUpdate(handle)
+            | Self::RemoteFeatures(handle)
+            | Self::Encryption(handle)
+            | Self::MtuExchange(handle) => Some(*handle),
+        }
+    }
+}
+
+/// How a procedure ended
+enum Outcome {
+    /// The event reporting the outcome of the procedure
+    Event(EvtBox<Ble>),
+    /// The connection closed before the procedure completed, for this reason
+    Disconnected(u8),
+}
+
+impl Outcome {
+    /// The event reporting the outcome, or the reason of the disconnection as [`BleError::Status`]
+    fn event(self) -> Result<EvtBox<Ble>, BleError> {
+        match self {
+            Self::Event(evt_box) => Ok(evt_box),
+            Self::Disconnected(reason) => Err(BleError::Status(reason)),
+        }
+    }
+}
+
+struct ProcedureEntry {
+    procedure: Procedure,
+    outcome: Option<Outcome>,
+}
+
+/// Registration of a procedure waiting for its outcome, removed when dropped
+///
+/// The procedure is registered before sending the command starting it, so that the event reporting its
+/// outcome goes to it whichever task reads the events, and is not kept for [`Ble::tl_read`].
+struct ProcedureWait {
+    index: usize,
+}
+
+impl ProcedureWait {
+    /// Register `procedure`, returns [`BleError::Busy`] if it is already waiting or too many procedures are
+    fn start(procedure: Procedure) -> Result<Self, BleError> {
+        PROCEDURES.lock(|procedures| {
+            let mut procedures = procedures.borrow_mut();
+            if procedures.iter().flatten().any(|entry| entry.procedure == procedure) {
+                return Err(BleError::Busy);
+            }
+
+            let index = procedures.iter().position(Option::is_none).ok_or(BleError::Busy)?;
+            procedures[index] = Some(ProcedureEntry {
+                procedure,
+                outcome: None,
+            });
+
+            Ok(Self { index })
+        })
+    }
+
+    /// Wait for the outcome of the procedure, reading the events meanwhile if no other task does
+    async fn outcome(&self) -> Outcome {
+        read_until(|| {
+            PROCEDURES.lock(|procedures| {
+                procedures.borrow_mut()[self.index]
+                    .as_mut()
+                    .and_then(|entry| entry.outcome.take())
+            })
+        })
+        .await
+    }
+}
+
+impl Drop for ProcedureWait {
+    fn drop(&mut self) {
+        let entry = PROCEDURES.lock(|procedures| procedures.borrow_mut()[self.index].take());
+
+        // the outcome arrived after the task gave up, the application may still be interested
+        if let Some(ProcedureEntry {
+            outcome: Some(Outcome::Event(evt_box)),
+            ..
+        }) = entry
+        {
+            queue_pending_event(evt_box);
+            EVENT_WAITERS.wake_all();
+        }
+    }
+}
+
+/// Receiver of the answer to the command in flight
+enum CommandAnswer {
+    /// The command was sent with [`Ble::tl_write`] or [`Ble::try_write`], the answer goes to [`Ble::tl_read`]
+    Application,
+    /// A task waits for the answer in [`Ble::command_reply`]
+    Awaited,
+    /// The answer arrived and has not been taken by the waiting task yet
+    Received(EvtBox<Ble>),
+    /// The waiting task gave up, the answer is dropped when it arrives
+    Abandoned,
+}
+
+/// Received HCI ACL data packet, which gives its buffer back to CPU2 when dropped
+pub struct AclDataBox(EvtBox<Ble>);
+
+impl AclDataBox {
+    /// The packet, starting with its handle field, as taken by [`AclPacket::parse`] and
+    /// [`Reassembler::push`](crate::ble::acl::Reassembler::push)
+    pub fn data(&self) -> &[u8] {
+        self.0.acl_data()
+    }
+
+    pub fn parse(&self) -> Result<AclPacket<'_>, AclError> {
+        AclPacket::parse(self.data())
+    }
+
+    /// Give the buffer back right away, see [`EvtBox::release`]
+    pub fn release(self) {
+        self.0.release();
+    }
+}
+
 impl Ble {
     pub(crate) fn new() -> Self {
         unsafe {
@@ -30,30 +316,320 @@ impl Ble {
 
         Self { _private: () }
     }
+
+    /// Handle to the BLE channels of a CPU2 that kept running, leaving its table and event queue as they are
+    pub(crate) fn attach() -> Self {
+        Self { _private: () }
+    }
+
+    /// Head of the BLE event queue, for firmware whose events this crate cannot decode
+    ///
+    /// The nodes of the queue are [`EvtPacket`]s, and can be walked with
+    /// [`Nodes`](crate::unsafe_linked_list::Nodes) without taking them. CPU2 appends events to the queue
+    /// until it signals the BLE event channel, and leaves the queue alone until [`Ble::tl_read`] clears the
+    /// channel: the queue may only be read in between. Events still belong to the queue, [`Ble::tl_read`]
+    /// takes them in order and hands each to an [`EvtBox`] which releases the buffer to CPU2 when dropped,
+    /// so pointers to the nodes must not be used after the event was read.
+    #[cfg(feature = "unstable-internals")]
+    pub fn raw_ble_event_queue(&self) -> *const LinkedListNode {
+        unsafe { EVT_QUEUE.as_ptr() }
+    }
+
     /// `HW_IPCC_BLE_EvtNot`
+    ///
+    /// The answers to the commands sent with [`Ble::command`] and the like go to the tasks waiting for
+    /// them, whichever task reads the event queue, and all the other events are returned here in order.
+    /// Up to [`PENDING_EVENTS_SIZE`] events read by other tasks are kept until then.
     pub async fn tl_read(&self) -> EvtBox<Self> {
-        Ipcc::receive(channels::cpu2::IPCC_BLE_EVENT_CHANNEL, || unsafe {
-            if let Some(node_ptr) = LinkedListNode::remove_head(EVT_QUEUE.as_mut_ptr()) {
-                Some(EvtBox::new(node_ptr.cast()))
-            } else {
-                None
+        read_until(|| PENDING_EVENTS.lock(|events| events.borrow_mut().pop_front())).await
+    }
+
+    /// Wait for the next received ACL data packet
+    ///
+    /// ACL data packets come in the BLE event queue, and are moved to a queue of their own as events are
+    /// read: a task must keep reading events with [`Ble::tl_read`], while another one reads the data with
+    /// this. Up to [`ACL_RX_QUEUE_SIZE`] packets are kept, the following ones being dropped until some are
+    /// read, and each one holds a buffer of the event pool until dropped.
+    pub async fn read_acl(&self) -> AclDataBox {
+        ACL_RX_CHANNEL.receive().await
+    }
+
+    /// Wait for the next event, giving up after `timeout`
+    ///
+    /// Returns `None` if no event was received in time. No event is lost on timeout: events are taken
+    /// from the queue synchronously when the read completes, so the dropped read had not taken any. This
+    /// is equivalent to a `select` between [`Ble::tl_read`] and a timer.
+    #[cfg(feature = "embassy-time")]
+    pub async fn read_timeout(&self, timeout: embassy_time::Duration) -> Option<EvtBox<Self>> {
+        embassy_time::with_timeout(timeout, self.tl_read()).await.ok()
+    }
+
+    /// Take the next event if one is already queued, without waiting
+    ///
+    /// This is [`Ble::tl_read`] polled once, for applications servicing the mailbox from a loop rather
+    /// than from tasks.
+    pub fn poll(&self) -> Option<EvtBox<Self>> {
+        match poll_once(self.tl_read()) {
+            Poll::Ready(evt_box) => Some(evt_box),
+            Poll::Pending => None,
+        }
+    }
+
+    /// Wait for an event, then move all the events already queued into `out` without waiting
+    ///
+    /// Returns the number of events added, at least one unless `out` is full. Events that do not fit in
+    /// `out` stay queued for the next read. Each event keeps its buffer until dropped, as with
+    /// [`Ble::tl_read`].
+    pub async fn drain<const N: usize>(&self, out: &mut Vec<EvtBox<Self>, N>) -> usize {
+        if out.is_full() {
+            return 0;
+        }
+
+        let first = self.tl_read().await;
+        let start = out.len();
+        let _ = out.push(first);
+
+        // The receive channel stays occupied until a read finds the queue empty, so CPU2 does not append
+        // to the queue meanwhile and the loop is bounded by the events already queued.
+        while !out.is_full() {
+            match self.poll() {
+                Some(evt_box) => {
+                    let _ = out.push(evt_box);
+                }
+                None => break,
             }
-        })
-        .await
+        }
+
+        out.len() - start
+    }
+
+    /// Take the first event of the queue, moving the ACL data packets before it to their own queue
+    ///
+    /// SAFETY: the BLE event channel must be occupied, so that CPU2 does not modify the queue
+    unsafe fn take_event() -> Option<EvtBox<Self>> {
+        let evt_box = loop {
+            let depth = LinkedListNode::count_up_to(EVT_QUEUE.as_mut_ptr(), mm::MAX_COUNTED_EVENTS);
+            let node_ptr = LinkedListNode::remove_head(EVT_QUEUE.as_mut_ptr())?;
+            mm::MemoryManager::track_event_queue_depth(depth);
+            if Self::is_pool_event(node_ptr.cast()) {
+                mm::MemoryManager::track_event_packet();
+            }
+
+            let evt_box = EvtBox::new(node_ptr.cast());
+            if evt_box.stub().kind != TlPacketType::AclData as u8 {
+                break evt_box;
+            }
+
+            queue_acl_data(AclDataBox(evt_box));
+        };
+        #[cfg(feature = "hci-capture")]
+        {
+            // the event serial is the packet type indicator followed by the HCI event
+            let serial = evt_box.serial();
+            capture::record(
+                Direction::Received,
+                &serial[..TL_EVT_HEADER_SIZE],
+                &serial[TL_EVT_HEADER_SIZE..],
+            );
+        }
+
+        let evt_code = evt_box.stub().evt_code;
+        if let Some(nocp) = NumberOfCompletedPackets::parse(evt_code, evt_box.payload()) {
+            for (handle, count) in nocp.iter() {
+                if !COMPLETED_PACKETS.record(handle, count) {
+                    warn!("ble: no room to count the packets completed on connection {:x}", handle);
+                }
+                OUTSTANDING_PACKETS.completed(handle, count);
+                record_activity(handle);
+            }
+            Self::release_acl_credits(nocp.total());
+        } else if let Some(complete) = DisconnectionComplete::parse(evt_code, evt_box.payload()) {
+            set_att_mtu(complete.connection_handle, None);
+            COMPLETED_PACKETS.remove(complete.connection_handle);
+            // the controller drops the packets of a closed connection without reporting them completed,
+            // while a failed disconnection leaves the connection and its packets in place
+            if complete.status == 0 {
+                Self::release_acl_credits(OUTSTANDING_PACKETS.remove(complete.connection_handle));
+            }
+            update_connection_states(|states| states.remove(complete.connection_handle));
+        } else if let Some(complete) = ConnectionComplete::parse(evt_code, evt_box.payload()) {
+            if !update_connection_states(|states| states.connect(&complete)) {
+                warn!(
+                    "ble: no room to record the state of connection {:x}",
+                    complete.connection_handle
+                );
+            }
+            record_activity(complete.connection_handle);
+        } else if let Some(update) = ConnectionUpdateComplete::parse(evt_code, evt_box.payload()) {
+            update_connection_states(|states| states.update_params(&update));
+        } else if let Some(update) = PhyUpdateComplete::parse(evt_code, evt_box.payload()) {
+            update_connection_states(|states| states.update_phy(&update));
+        } else if let Some(change) = DataLengthChange::parse(evt_code, evt_box.payload()) {
+            update_connection_states(|states| states.update_data_length(&change));
+        } else if let Some(evt) = VendorEvent::parse(evt_code, evt_box.payload()) {
+            if let Some(resp) = AttExchangeMtuResp::parse(&evt) {
+                set_att_mtu(resp.connection_handle, Some(resp.mtu));
+                update_connection_states(|states| states.update_att_mtu(resp.connection_handle, resp.mtu));
+            } else if TxPoolAvailable::parse(&evt).is_some() {
+                TX_POOL_GENERATION.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        Some(evt_box)
+    }
+
+    /// Command status and command complete events are not allocated from the event pool
+    ///
+    /// SAFETY: `evt` must point to an event packet taken from the event queue
+    unsafe fn is_pool_event(evt: *const EvtPacket) -> bool {
+        let stub = unsafe {
+            let p_evt_stub = &(*evt).evt_serial as *const _ as *const EvtStub;
+
+            ptr::read_volatile(p_evt_stub)
+        };
+
+        // the second byte of an ACL data packet is part of its handle, not an event code
+        stub.kind == TlPacketType::AclData as u8
+            || !(stub.evt_code == TL_BLEEVT_CS_OPCODE || stub.evt_code == TL_BLEEVT_CC_OPCODE)
+    }
+
+    /// Number of ACL data packets that can be sent without waiting for the controller
+    ///
+    /// This is 0 until [`Ble::hci_reset_and_configure`] has completed, ACL data packets are not
+    /// flow controlled until then.
+    pub fn acl_credits(&self) -> usize {
+        ACL_CREDITS.available()
+    }
+
+    /// Number of ACL data packets the controller reported completed on a connection
+    ///
+    /// The count starts from 0 when the connection is established and wraps around on overflow. Sampling
+    /// it periodically gives the outgoing throughput of the connection, in packets.
+    pub fn completed_packets(&self, handle: u16) -> u32 {
+        COMPLETED_PACKETS.get(handle)
+    }
+
+    fn release_acl_credits(completed: usize) {
+        if completed != 0 {
+            ACL_CREDITS.release(completed);
+            notify_waiters();
+        }
+    }
+
+    /// Wait for a free ACL buffer in the controller, reading the events meanwhile if no other task does
+    async fn acquire_acl_credit(&self) {
+        read_until(|| ACL_CREDITS.try_acquire().then_some(())).await
+    }
+
+    /// Free the command slot whichever command is in flight, dropping its answer if not taken yet
+    fn release_command_slot() {
+        let answer = COMMAND_ANSWER.lock(|answer| answer.replace(CommandAnswer::Application));
+        COMMAND_SLOT.release();
+        drop(answer);
     }
 
     /// `TL_BLE_SendCmd`
-    pub async fn tl_write(&self, opcode: u16, payload: &[u8]) {
+    ///
+    /// The controller accepts a single command at a time: this waits until the previous command has
+    /// been answered by its `Command Complete` or `Command Status` event, which must be read meanwhile,
+    /// e.g. by another task calling [`Ble::tl_read`].
+    ///
+    /// Returns [`BleError::InvalidParameters`] if `payload` does not fit in the command buffer.
+    pub async fn tl_write(&self, opcode: u16, payload: &[u8]) -> Result<(), BleError> {
+        self.write_command(opcode, payload, CommandAnswer::Application).await
+    }
+
+    /// Send a command once the slot is free, its answer going to `answer`
+    async fn write_command(&self, opcode: u16, payload: &[u8], answer: CommandAnswer) -> Result<(), BleError> {
+        if payload.len() > TL_CMD_MAX_PAYLOAD_SIZE {
+            return Err(BleError::InvalidParameters);
+        }
+
+        COMMAND_SLOT.acquire(opcode).await;
+        // free the slot if the command is cancelled before being sent
+        let slot = OnDrop::new(Self::release_command_slot);
+        COMMAND_ANSWER.lock(|current| *current.borrow_mut() = answer);
+
+        // No critical section is needed: `Ipcc::send` only calls the closure once CPU2 has released the
+        // channel, so CPU2 does not read the buffer meanwhile, and the command slot keeps other commands
+        // from writing it. Dropping an `EvtBox` only touches the event buffer queues.
         Ipcc::send(channels::cpu1::IPCC_BLE_CMD_CHANNEL, || unsafe {
             CmdPacket::write_into(BLE_CMD_BUFFER.as_mut_ptr(), TlPacketType::BleCmd, opcode, payload);
         })
         .await;
+        slot.defuse();
+
+        #[cfg(feature = "hci-capture")]
+        Self::capture_command(opcode, payload);
+
+        Ok(())
+    }
+
+    /// Send a command if the command buffer is free, without waiting
+    ///
+    /// Returns [`BleError::Busy`] if the previous command has not been answered yet. The command complete
+    /// or status event answering the command is then returned by [`Ble::poll`]. Tasks waiting in
+    /// [`Ble::tl_write`] are not affected by a failed attempt.
+    pub fn try_write(&self, opcode: u16, payload: &[u8]) -> Result<(), BleError> {
+        Self::try_write_command(opcode, payload, CommandAnswer::Application)
+    }
+
+    /// Send a command if the command buffer is free, its answer going to `answer`
+    fn try_write_command(opcode: u16, payload: &[u8], answer: CommandAnswer) -> Result<(), BleError> {
+        if payload.len() > TL_CMD_MAX_PAYLOAD_SIZE {
+            return Err(BleError::InvalidParameters);
+        }
+
+        if !COMMAND_SLOT.try_acquire(opcode) {
+            return Err(BleError::Busy);
+        }
+        COMMAND_ANSWER.lock(|current| *current.borrow_mut() = answer);
+
+        // same reasoning as in `tl_write`, the closure is only called if CPU2 has released the channel
+        let sent = Ipcc::try_send(channels::cpu1::IPCC_BLE_CMD_CHANNEL, || unsafe {
+            CmdPacket::write_into(BLE_CMD_BUFFER.as_mut_ptr(), TlPacketType::BleCmd, opcode, payload);
+        });
+        if !sent {
+            Self::release_command_slot();
+            return Err(BleError::Busy);
+        }
+
+        #[cfg(feature = "hci-capture")]
+        Self::capture_command(opcode, payload);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "hci-capture")]
+    fn capture_command(opcode: u16, payload: &[u8]) {
+        let [opcode_lo, opcode_hi] = opcode.to_le_bytes();
+        capture::record(
+            Direction::Sent,
+            &[H4_COMMAND, opcode_lo, opcode_hi, payload.len() as u8],
+            payload,
+        );
     }
 
     /// `TL_BLE_SendAclData`
+    ///
+    /// `handle` is the first field of the ACL data packet, including the packet boundary flag. Once
+    /// the controller has been configured, this waits for a free ACL buffer in the controller. Any number
+    /// of tasks may wait, and the events are read meanwhile if no other task reads them.
     pub async fn acl_write(&self, handle: u16, payload: &[u8]) {
+        self.acquire_acl_credit().await;
+
+        let connection = handle & HANDLE_MASK;
+        if !OUTSTANDING_PACKETS.sent(connection) {
+            warn!("ble: no room to track the packets sent on connection {:x}", connection);
+        }
+        // give the credit back if the packet is cancelled before being sent
+        let credit = OnDrop::new(|| {
+            OUTSTANDING_PACKETS.completed(connection, 1);
+            Self::release_acl_credits(1);
+        });
+
         Ipcc::send(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL, || unsafe {
-            CmdPacket::write_into(
+            AclDataPacket::write_into(
                 HCI_ACL_DATA_BUFFER.as_mut_ptr() as *mut _,
                 TlPacketType::AclData,
                 handle,
@@ -61,35 +637,2137 @@ impl Ble {
             );
         })
         .await;
+        credit.defuse();
+
+        #[cfg(feature = "hci-capture")]
+        {
+            let [handle_lo, handle_hi] = handle.to_le_bytes();
+            let [len_lo, len_hi] = (payload.len() as u16).to_le_bytes();
+            capture::record(
+                Direction::Sent,
+                &[H4_ACL_DATA, handle_lo, handle_hi, len_lo, len_hi],
+                payload,
+            );
+        }
     }
-}
 
-impl evt::MemoryManager for Ble {
-    /// SAFETY: passing a pointer to something other than a managed event packet is UB
-    unsafe fn drop_event_packet(evt: *mut EvtPacket) {
-        let stub = unsafe {
-            let p_evt_stub = &(*evt).evt_serial as *const _ as *const EvtStub;
+    /// Send an ACL data packet built with [`AclPacket::new`]
+    pub async fn acl_send(&self, packet: &AclPacket<'_>) {
+        self.acl_write(packet.handle_field(), packet.payload()).await;
+    }
 
-            ptr::read_volatile(p_evt_stub)
+    /// Send an L2CAP PDU on a connection, fragmented in as many ACL data packets as needed
+    ///
+    /// `buffer_size` is the result of [`Ble::read_buffer_size`].
+    pub async fn acl_write_pdu(&self, handle: u16, buffer_size: &LeBufferSize, pdu: &[u8]) {
+        // a zero length means the LE and BR/EDR buffers are shared, which the wireless stack does not do
+        let max_len = match buffer_size.acl_data_packet_length as usize {
+            0 => TL_ACL_DATA_MAX_PAYLOAD_SIZE,
+            len => len.min(TL_ACL_DATA_MAX_PAYLOAD_SIZE),
         };
 
-        if !(stub.evt_code == TL_BLEEVT_CS_OPCODE || stub.evt_code == TL_BLEEVT_CC_OPCODE) {
-            mm::MemoryManager::drop_event_packet(evt);
+        for (boundary, fragment) in Fragments::new(pdu, max_len) {
+            self.acl_write(pack_handle(handle, boundary), fragment).await;
         }
     }
-}
 
-pub extern crate stm32wb_hci as hci;
+    /// Send an HCI command and wait for its `Command Complete` event
+    ///
+    /// Returns [`BleError::InvalidResponse`] if the command is answered by a `Command Status` event
+    /// instead: such commands are sent with [`Ble::pending_command`]. Events that do not answer this
+    /// command are kept for [`Ble::tl_read`].
+    pub async fn command(&self, opcode: HciOpcode, payload: &[u8]) -> Result<EvtBox<Self>, BleError> {
+        self.raw_command(opcode as u16, payload).await
+    }
 
-impl hci::Controller for Ble {
-    async fn controller_write(&mut self, opcode: Opcode, payload: &[u8]) {
-        self.tl_write(opcode.0, payload).await;
+    async fn raw_command(&self, opcode: u16, payload: &[u8]) -> Result<EvtBox<Self>, BleError> {
+        match self.raw_command_reply(opcode, payload).await? {
+            (CommandReply::Complete, evt_box) => Ok(evt_box),
+            (CommandReply::Status, _) => Err(BleError::InvalidResponse),
+        }
     }
 
-    async fn controller_read_into(&self, buf: &mut [u8]) {
-        let evt_box = self.tl_read().await;
-        let evt_serial = evt_box.serial();
+    /// Send an HCI command and wait for the event answering it, whichever it is
+    ///
+    /// Commands carried out at once are answered by a `Command Complete` event, which holds their
+    /// return parameters. The others are answered by a `Command Status` event when they start, and
+    /// their outcome is reported later by a dedicated event, such as `LE Connection Complete`. Events
+    /// that do not answer this command are kept for [`Ble::tl_read`], which may run in another task
+    /// meanwhile: the answer goes to this command whichever task reads it.
+    pub async fn command_reply(
+        &self,
+        opcode: HciOpcode,
+        payload: &[u8],
+    ) -> Result<(CommandReply, EvtBox<Self>), BleError> {
+        self.raw_command_reply(opcode as u16, payload).await
+    }
 
-        buf[..evt_serial.len()].copy_from_slice(evt_serial);
+    async fn raw_command_reply(&self, opcode: u16, payload: &[u8]) -> Result<(CommandReply, EvtBox<Self>), BleError> {
+        let generation = sys::restart_generation();
+
+        let command = async {
+            self.write_command(opcode, payload, CommandAnswer::Awaited).await?;
+            // the answer is dropped when it arrives if this gives up waiting
+            let _abandon = OnDrop::new(abandon_command);
+
+            let evt_box = read_until(take_command_answer).await;
+            match event::command_result(opcode, evt_box.stub().evt_code, evt_box.payload()) {
+                Some(result) => result.map(|reply| (reply, evt_box)),
+                None => Err(BleError::InvalidResponse),
+            }
+        };
+
+        // CPU2 does not answer the commands sent before it restarted, checked first so that an event of
+        // the restarted stack is not taken for the answer
+        match select(sys::restarted_since(generation), command).await {
+            Either::First(()) => Err(BleError::Reset),
+            Either::Second(result) => result,
+        }
+    }
+
+    /// Send an HCI command answered by a `Command Status` event, and wait for it
+    ///
+    /// The outcome of such commands is reported later by a dedicated event. A `Command Complete` event
+    /// is accepted as well, for commands the wireless stack carries out at once. Events received while
+    /// waiting are kept for [`Ble::tl_read`].
+    pub async fn pending_command(&self, opcode: HciOpcode, payload: &[u8]) -> Result<(), BleError> {
+        self.command_reply(opcode, payload).await?;
+
+        Ok(())
+    }
+
+    /// Bring the controller to a known state
+    ///
+    /// This issues `HCI_Reset`, programs the default event masks, reads the number of ACL buffers used
+    /// for flow control and reads the LE features supported by the controller, waiting for each command
+    /// to complete.
+    pub async fn hci_reset_and_configure(&self) -> Result<LeFeatures, BleError> {
+        ACL_CREDITS.disable();
+        self.command(HciOpcode::Reset, &[]).await?;
+
+        let buffer_size = self.read_buffer_size().await?;
+        ACL_CREDITS.reset(buffer_size.total_num_acl_data_packets as usize);
+        OUTSTANDING_PACKETS.clear();
+        notify_waiters();
+
+        self.set_event_mask(EventMask::default()).await?;
+        self.set_le_event_mask(LeEventMask::default()).await?;
+
+        self.read_local_features().await
+    }
+
+    /// Select the events reported by the controller
+    ///
+    /// LE events are only reported if [`EventMask::LE_META`] is set, and also need to be enabled with
+    /// [`Ble::set_le_event_mask`].
+    pub async fn set_event_mask(&self, mask: EventMask) -> Result<(), BleError> {
+        self.command(HciOpcode::SetEventMask, &mask.0.to_le_bytes()).await?;
+
+        Ok(())
+    }
+
+    /// Select the LE events reported by the controller
+    pub async fn set_le_event_mask(&self, mask: LeEventMask) -> Result<(), BleError> {
+        self.command(HciOpcode::LeSetEventMask, &mask.0.to_le_bytes()).await?;
+
+        Ok(())
+    }
+
+    /// Send a command assembled with [`HciCommand`] and wait for its `Command Complete` event
+    pub async fn send_hci_command<const N: usize>(&self, cmd: &HciCommand<N>) -> Result<EvtBox<Self>, BleError> {
+        self.raw_command(cmd.opcode_bits(), cmd.payload()).await
+    }
+
+    /// Send a typed HCI command and wait for its `Command Complete` event
+    pub async fn send_command<T: BleCommand>(&self, cmd: &T) -> Result<EvtBox<Self>, BleError> {
+        self.command(T::OPCODE, cmd.payload()).await
+    }
+
+    /// Send an ST vendor command (`ACI_*`) not wrapped by this crate, and return its return parameters
+    ///
+    /// `ocf` is the command field of the opcode, the opcode group being `0x3F`. The status is checked and
+    /// left out of the returned parameters. Returns [`BleError::InvalidParameters`] if `ocf` does not fit
+    /// in 10 bits, and [`BleError::InvalidResponse`] if the return parameters do not fit in `N` bytes. As
+    /// with [`Ble::command`], the command waits for the previous one to be answered, and events received
+    /// while waiting are kept for [`Ble::tl_read`].
+    pub async fn aci_command<const N: usize>(&self, ocf: u16, params: &[u8]) -> Result<Vec<u8, N>, BleError> {
+        if ocf > 0x03FF {
+            return Err(BleError::InvalidParameters);
+        }
+
+        let evt_box = self.raw_command(vendor_opcode(ocf), params).await?;
+
+        Vec::from_slice(return_params(&evt_box)?).map_err(|_| BleError::InvalidResponse)
+    }
+
+    /// Send an HCI command and copy its return parameters into `response`, returning their length
+    ///
+    /// This is [`Ble::command`] for callers that only need the return parameters, without the status: the
+    /// event is released before returning. Returns [`BleError::InvalidResponse`] if they do not fit in
+    /// `response`.
+    pub async fn command_into(
+        &self,
+        opcode: HciOpcode,
+        payload: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, BleError> {
+        let evt_box = self.command(opcode, payload).await?;
+
+        copy_return_params(&evt_box, response)
+    }
+
+    /// Send an ST vendor command (`ACI_*`) and copy its return parameters into `response`
+    ///
+    /// This is [`Ble::aci_command`] writing into a buffer of the caller instead of a `Vec`, and returning
+    /// the length of the return parameters. Returns [`BleError::InvalidResponse`] if they do not fit in
+    /// `response`.
+    pub async fn aci_command_into(&self, ocf: u16, params: &[u8], response: &mut [u8]) -> Result<usize, BleError> {
+        if ocf > 0x03FF {
+            return Err(BleError::InvalidParameters);
+        }
+
+        let evt_box = self.raw_command(vendor_opcode(ocf), params).await?;
+
+        copy_return_params(&evt_box, response)
+    }
+
+    /// Check that the wireless stack still answers commands
+    ///
+    /// This sends `HCI_Read_Local_Version_Information`, which has no side effect, and returns
+    /// [`BleError::Timeout`] if it is not answered within `timeout`. A stack that stopped answering can
+    /// only be recovered by resetting the device, [`Sys::wait_error`](crate::sub::sys::Sys::wait_error)
+    /// may tell why it stopped. Events received while waiting are kept for [`Ble::tl_read`].
+    #[cfg(feature = "embassy-time")]
+    pub async fn ping(&self, timeout: embassy_time::Duration) -> Result<(), BleError> {
+        embassy_time::with_timeout(timeout, self.read_local_version())
+            .await
+            .map_err(|_| BleError::Timeout)??;
+
+        Ok(())
+    }
+
+    /// Read the LE features supported by the running wireless stack
+    pub async fn read_local_features(&self) -> Result<LeFeatures, BleError> {
+        let evt_box = self.command(HciOpcode::LeReadLocalSupportedFeatures, &[]).await?;
+
+        LeFeatures::from_bytes(return_params(&evt_box)?)
+    }
+
+    /// Read the version information of the running wireless stack
+    pub async fn read_local_version(&self) -> Result<LocalVersion, BleError> {
+        let evt_box = self.command(HciOpcode::ReadLocalVersionInformation, &[]).await?;
+
+        LocalVersion::from_bytes(return_params(&evt_box)?)
+    }
+
+    /// Read the size and number of the ACL data buffers of the controller
+    pub async fn read_buffer_size(&self) -> Result<LeBufferSize, BleError> {
+        let evt_box = self.command(HciOpcode::LeReadBufferSize, &[]).await?;
+
+        LeBufferSize::from_bytes(return_params(&evt_box)?)
+    }
+
+    /// Read the minimum and maximum TX power supported by the controller
+    pub async fn read_tx_power_range(&self) -> Result<TxPowerRange, BleError> {
+        let evt_box = self.command(HciOpcode::LeReadTransmitPower, &[]).await?;
+
+        TxPowerRange::from_bytes(return_params(&evt_box)?)
+    }
+
+    /// Set the output power of the radio, used for advertising, scanning and connections
+    ///
+    /// Returns [`BleError::InvalidParameters`] without changing the power if `power` is out of the range
+    /// reported by [`Ble::read_tx_power_range`], rather than letting the controller clamp it.
+    pub async fn set_tx_power(&self, power: TxPower) -> Result<(), BleError> {
+        if !self.read_tx_power_range().await?.contains(power) {
+            return Err(BleError::InvalidParameters);
+        }
+
+        // the PA levels are those of the high power mode, which ST applications always select
+        self.command(HciOpcode::AciHalSetTxPowerLevel, &[0x01, power as u8])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Read 8 random bytes from the random number generator of the controller
+    pub async fn le_rand(&self) -> Result<[u8; 8], BleError> {
+        let evt_box = self.command(HciOpcode::LeRand, &[]).await?;
+
+        fixed_return_params(&evt_box)
+    }
+
+    /// Encrypt a block with AES-128 in the controller
+    ///
+    /// The key, the plaintext and the result are in the byte order of the HCI parameters, least significant
+    /// octet first, which is the reverse of the FIPS 197 notation.
+    pub async fn le_encrypt(&self, key: [u8; 16], plaintext: [u8; 16]) -> Result<[u8; 16], BleError> {
+        let mut payload = [0; 32];
+        payload[..16].copy_from_slice(&key);
+        payload[16..].copy_from_slice(&plaintext);
+        let evt_box = self.command(HciOpcode::LeEncrypt, &payload).await?;
+
+        fixed_return_params(&evt_box)
+    }
+
+    /// Classify the data channels for the adaptive frequency hopping of all the connections
+    ///
+    /// The channels disabled in `map` are those the host knows to be bad, e.g. because of interference,
+    /// and the controller stops using them on the connections where it is central. Returns
+    /// [`BleError::InvalidParameters`] if fewer than [`ChannelMap::MIN_ENABLED`] channels are enabled.
+    pub async fn set_channel_map(&self, map: ChannelMap) -> Result<(), BleError> {
+        map.validate()?;
+
+        self.command(HciOpcode::LeSetHostChannelClassification, &map.to_bytes())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Read the data channels currently used by a connection
+    pub async fn read_channel_map(&self, handle: u16) -> Result<ChannelMap, BleError> {
+        let evt_box = self.command(HciOpcode::LeReadChannelMap, &handle.to_le_bytes()).await?;
+        let params = return_params(&evt_box)?;
+
+        ChannelMap::from_bytes(params.get(2..).ok_or(BleError::InvalidResponse)?)
+    }
+
+    /// Read the public address used by the controller
+    pub async fn read_bd_addr(&self) -> Result<BdAddr, BleError> {
+        let evt_box = self.command(HciOpcode::ReadBdAddr, &[]).await?;
+
+        BdAddr::from_bytes(return_params(&evt_box)?)
+    }
+
+    /// Read the static random address generated by the wireless stack from the device unique ID
+    ///
+    /// This address stays the same across resets and can be used when no public address has been assigned.
+    pub async fn read_static_random_address(&self) -> Result<BdAddr, BleError> {
+        let data = self.read_config_data(ConfigDataOffset::StaticRandomAddress).await?;
+
+        BdAddr::from_bytes(&data)
+    }
+
+    /// Read the public address written to the configuration data of the wireless stack
+    ///
+    /// The address is all zeros until the application writes one.
+    pub async fn read_public_address(&self) -> Result<BdAddr, BleError> {
+        let data = self.read_config_data(ConfigDataOffset::PublicAddress).await?;
+
+        BdAddr::from_bytes(&data)
+    }
+
+    /// Read an entry of the configuration data of the wireless stack
+    pub async fn read_config_data(&self, offset: ConfigDataOffset) -> Result<Vec<u8, 16>, BleError> {
+        let evt_box = self.command(HciOpcode::AciHalReadConfigData, &[offset as u8]).await?;
+
+        Vec::from_slice(config_data(&evt_box, offset)?).map_err(|_| BleError::InvalidResponse)
+    }
+
+    /// Read an entry of the configuration data of the wireless stack into `data`, returning its length
+    ///
+    /// Returns [`BleError::InvalidResponse`] if the entry does not fit in `data`, see
+    /// [`ConfigDataOffset::size`].
+    pub async fn read_config_data_into(&self, offset: ConfigDataOffset, data: &mut [u8]) -> Result<usize, BleError> {
+        let evt_box = self.command(HciOpcode::AciHalReadConfigData, &[offset as u8]).await?;
+        let entry = config_data(&evt_box, offset)?;
+
+        data.get_mut(..entry.len())
+            .ok_or(BleError::InvalidResponse)?
+            .copy_from_slice(entry);
+
+        Ok(entry.len())
+    }
+
+    /// Set the random address used when advertising, scanning or connecting with a random address type
+    pub async fn set_random_address(&self, addr: BdAddr) -> Result<(), BleError> {
+        self.command(HciOpcode::LeSetRandomAddress, addr.as_bytes()).await?;
+
+        Ok(())
+    }
+
+    /// Derive the public address assigned by ST from the 64-bit unique device number
+    ///
+    /// The controller only uses it once it has been written to its configuration data. Returns `None` if
+    /// the unique device number is erased.
+    pub fn factory_bd_addr(&self) -> Option<BdAddr> {
+        let uid = read_uid64();
+        let (udn, id) = (uid as u32, (uid >> 32) as u32);
+
+        // the second word holds the device type in its low byte, then the ST company ID
+        BdAddr::from_uid64(udn, id as u8, id >> 8)
+    }
+
+    /// Derive a static random address from the 64-bit unique device number
+    ///
+    /// The address is the same at every boot and differs between devices, see
+    /// [`BdAddr::static_random_from_uid64`]. It is used once given to [`Ble::set_random_address`].
+    pub fn derive_static_random_address(&self) -> BdAddr {
+        BdAddr::static_random_from_uid64(read_uid64())
+    }
+
+    /// Read the devices stored in the security database of the wireless stack into `devices`
+    ///
+    /// Returns the number of devices written, which is limited by the length of `devices`.
+    pub async fn get_bonded_devices(&self, devices: &mut [BondedDevice]) -> Result<usize, BleError> {
+        let evt_box = self.command(HciOpcode::AciGapGetBondedDevices, &[]).await?;
+        let params = return_params(&evt_box)?;
+        let count = *params.first().ok_or(BleError::InvalidResponse)? as usize;
+
+        let entries = params[1..].chunks_exact(BondedDevice::SIZE).take(count);
+        let mut written = 0;
+        for (device, entry) in devices.iter_mut().zip(entries) {
+            *device = BondedDevice::from_bytes(entry)?;
+            written += 1;
+        }
+
+        Ok(written)
+    }
+
+    /// Remove all the bonded devices from the security database of the wireless stack
+    pub async fn clear_security_db(&self) -> Result<(), BleError> {
+        self.command(HciOpcode::AciGapClearSecurityDb, &[]).await?;
+
+        Ok(())
+    }
+
+    /// Answer a [`PassKeyReq`](event::PassKeyReq) event with the pass key of the connection
+    ///
+    /// The pass key is the 6-digit number shown or typed by the user, from 0 to 999999.
+    pub async fn pass_key_response(&self, handle: u16, pass_key: u32) -> Result<(), BleError> {
+        if pass_key > MAX_PASS_KEY {
+            return Err(BleError::InvalidParameters);
+        }
+
+        let mut payload = [0; 6];
+        payload[..2].copy_from_slice(&handle.to_le_bytes());
+        payload[2..].copy_from_slice(&pass_key.to_le_bytes());
+        self.command(HciOpcode::AciGapPassKeyResp, &payload).await?;
+
+        Ok(())
+    }
+
+    /// Answer a [`NumericComparisonValue`](event::NumericComparisonValue) event, `confirm` telling whether both devices show the same value
+    pub async fn numeric_comparison_confirm(&self, handle: u16, confirm: bool) -> Result<(), BleError> {
+        let [handle_lo, handle_hi] = handle.to_le_bytes();
+        self.command(
+            HciOpcode::AciGapNumericComparisonValueConfirmYesNo,
+            &[handle_lo, handle_hi, confirm as u8],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Answer a [`LongTermKeyRequest`](event::LongTermKeyRequest) event with the key of the bonded peer
+    pub async fn ltk_request_reply(&self, handle: u16, ltk: [u8; 16]) -> Result<(), BleError> {
+        let mut payload = [0; 18];
+        payload[..2].copy_from_slice(&handle.to_le_bytes());
+        payload[2..].copy_from_slice(&ltk);
+        self.command(HciOpcode::LeLongTermKeyRequestReply, &payload).await?;
+
+        Ok(())
+    }
+
+    /// Answer a [`LongTermKeyRequest`](event::LongTermKeyRequest) event when no key is known for the peer
+    ///
+    /// The controller then fails the encryption, and the central usually pairs again.
+    pub async fn ltk_request_negative_reply(&self, handle: u16) -> Result<(), BleError> {
+        self.command(HciOpcode::LeLongTermKeyRequestNegativeReply, &handle.to_le_bytes())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Let a bonded peer pair again after a [`BondLost`](event::BondLost) event
+    pub async fn allow_rebond(&self, handle: u16) -> Result<(), BleError> {
+        self.command(HciOpcode::AciGapAllowRebond, &handle.to_le_bytes())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Connect to an advertising peripheral as a central
+    ///
+    /// A scan started with [`Ble::start_scan`] is stopped first, the wireless stack not creating a
+    /// connection while scanning. This waits until the connection is established, which may take forever
+    /// if the peer stops advertising: use [`Ble::cancel_connect`] from another task, drop the future, or
+    /// use `Ble::connect_timeout`. Dropping the future cancels the attempt if no other command is in
+    /// flight, [`Ble::cancel_connect`] must be called otherwise. Returns [`BleError::Busy`] if another
+    /// connection attempt is pending. Events received while waiting are kept for [`Ble::tl_read`].
+    pub async fn connect(
+        &self,
+        peer_address_type: PeerAddressType,
+        peer_address: &BdAddr,
+        params: &ConnParams,
+    ) -> Result<ConnectionComplete, BleError> {
+        let procedure = self.start_connection(peer_address_type, peer_address, params).await?;
+        let cancel = OnDrop::new(abandon_connection);
+
+        let result = connection_complete(procedure.outcome().await);
+        cancel.defuse();
+
+        result
+    }
+
+    /// Connect to an advertising peripheral as a central, giving up after `timeout`
+    ///
+    /// Once `timeout` has elapsed, the connection attempt is cancelled and [`BleError::Timeout`] is
+    /// returned, unless the connection was established meanwhile.
+    #[cfg(feature = "embassy-time")]
+    pub async fn connect_timeout(
+        &self,
+        peer_address_type: PeerAddressType,
+        peer_address: &BdAddr,
+        params: &ConnParams,
+        timeout: embassy_time::Duration,
+    ) -> Result<ConnectionComplete, BleError> {
+        let procedure = self.start_connection(peer_address_type, peer_address, params).await?;
+        let cancel = OnDrop::new(abandon_connection);
+
+        if let Ok(outcome) = embassy_time::with_timeout(timeout, procedure.outcome()).await {
+            cancel.defuse();
+            return connection_complete(outcome);
+        }
+
+        // Both outcomes of the cancel command are followed by a connection complete event: either the
+        // attempt is cancelled, or the command is disallowed as the connection has just been established.
+        let cancelled = self.command(HciOpcode::LeCreateConnectionCancel, &[]).await;
+        cancel.defuse();
+        match cancelled {
+            Ok(_) | Err(BleError::Status(HCI_COMMAND_DISALLOWED)) => {}
+            Err(err) => return Err(err),
+        }
+
+        match connection_complete(procedure.outcome().await) {
+            Err(BleError::Status(HCI_UNKNOWN_CONNECTION_ID)) => Err(BleError::Timeout),
+            result => result,
+        }
+    }
+
+    /// Cancel the connection attempt of [`Ble::connect`]
+    ///
+    /// The pending [`Ble::connect`] then returns [`BleError::Status`] with
+    /// [`HCI_UNKNOWN_CONNECTION_ID`](crate::ble::consts::HCI_UNKNOWN_CONNECTION_ID), unless the connection was established meanwhile.
+    /// The event ending the attempt goes to the pending [`Ble::connect`], whichever task reads the events.
+    pub async fn cancel_connect(&self) -> Result<(), BleError> {
+        self.command(HciOpcode::LeCreateConnectionCancel, &[]).await?;
+
+        Ok(())
+    }
+
+    /// Register the connection procedure and start it, stopping the scan first
+    async fn start_connection(
+        &self,
+        peer_address_type: PeerAddressType,
+        peer_address: &BdAddr,
+        params: &ConnParams,
+    ) -> Result<ProcedureWait, BleError> {
+        params.validate()?;
+
+        let procedure = ProcedureWait::start(Procedure::Connection)?;
+        self.stop_scan().await?;
+
+        let cmd = CreateConnection::new(peer_address_type, peer_address, params);
+        self.pending_command(CreateConnection::OPCODE, cmd.payload()).await?;
+
+        Ok(procedure)
+    }
+
+    /// Request new parameters for a connection and wait for the controller to apply them
+    ///
+    /// Returns the parameters in use once the procedure is complete, which the peer may have chosen
+    /// within the requested ranges. Returns [`BleError::Status`] with the disconnection reason if the
+    /// connection closes meanwhile, and [`BleError::Busy`] if an update is already pending on it. Events
+    /// received while waiting are kept for [`Ble::tl_read`].
+    pub async fn update_connection_params(
+        &self,
+        handle: u16,
+        params: &ConnParams,
+    ) -> Result<ConnectionUpdateComplete, BleError> {
+        params.validate()?;
+
+        let procedure = ProcedureWait::start(Procedure::ConnectionUpdate(handle))?;
+        let cmd = ConnectionUpdate::new(handle, params);
+        self.pending_command(ConnectionUpdate::OPCODE, cmd.payload()).await?;
+
+        let evt_box = procedure.outcome().await.event()?;
+        match ConnectionUpdateComplete::parse(evt_box.stub().evt_code, evt_box.payload()) {
+            Some(complete) if complete.status != 0 => Err(BleError::Status(complete.status)),
+            Some(complete) => Ok(complete),
+            None => Err(BleError::InvalidResponse),
+        }
+    }
+
+    /// Terminate a connection and wait for the controller to report it closed
+    ///
+    /// `reason` is sent to the peer, and must be one of the reasons accepted by `HCI_Disconnect`. Returns
+    /// [`BleError::Busy`] if the connection is already being terminated. The `Disconnection Complete`
+    /// event goes to this call whichever task reads the events, the other events received while waiting
+    /// are kept for [`Ble::tl_read`].
+    pub async fn disconnect(&self, handle: u16, reason: DisconnectReason) -> Result<(), BleError> {
+        if !reason.is_valid_for_disconnect() {
+            return Err(BleError::InvalidParameters);
+        }
+
+        let procedure = ProcedureWait::start(Procedure::Disconnection(handle))?;
+        let [handle_lo, handle_hi] = handle.to_le_bytes();
+        self.pending_command(HciOpcode::Disconnect, &[handle_lo, handle_hi, reason.code()])
+            .await?;
+
+        let evt_box = procedure.outcome().await.event()?;
+        match DisconnectionComplete::parse(evt_box.stub().evt_code, evt_box.payload()) {
+            Some(complete) if complete.status != 0 => Err(BleError::Status(complete.status)),
+            Some(_) => Ok(()),
+            None => Err(BleError::InvalidResponse),
+        }
+    }
+
+    /// Suggest the largest link layer packets to send on a connection
+    ///
+    /// `tx_octets` is the payload size, from 27 to 251 bytes, and `tx_time` the packet duration, from
+    /// 328 to 17040 µs. The controller negotiates the sizes with the peer and reports them with a
+    /// [`DataLengthChange`](event::DataLengthChange) event if they changed, which this does not wait for.
+    pub async fn set_data_length(&self, handle: u16, tx_octets: u16, tx_time: u16) -> Result<(), BleError> {
+        let cmd = SetDataLength::new(handle, tx_octets, tx_time)?;
+        self.send_command(&cmd).await?;
+
+        Ok(())
+    }
+
+    /// Start or refresh the encryption of a connection as the central, with a key agreed when bonding
+    ///
+    /// `random_number` and `encrypted_diversifier` identify the long term key for legacy pairing, and are
+    /// zero for LE Secure Connections. Waits for the [`EncryptionChange`](event::EncryptionChange) event,
+    /// or the [`EncryptionKeyRefreshComplete`](event::EncryptionKeyRefreshComplete) one if the link was
+    /// already encrypted, and returns [`BleError::Status`] if the peripheral rejected the key or with the
+    /// disconnection reason if the connection closes meanwhile. Returns [`BleError::Busy`] if the
+    /// encryption is already being started. Events received while waiting are kept for [`Ble::tl_read`].
+    pub async fn start_encryption(
+        &self,
+        handle: u16,
+        random_number: u64,
+        encrypted_diversifier: u16,
+        ltk: [u8; 16],
+    ) -> Result<(), BleError> {
+        let mut payload = [0; 28];
+        payload[..2].copy_from_slice(&handle.to_le_bytes());
+        payload[2..10].copy_from_slice(&random_number.to_le_bytes());
+        payload[10..12].copy_from_slice(&encrypted_diversifier.to_le_bytes());
+        payload[12..].copy_from_slice(&ltk);
+
+        let procedure = ProcedureWait::start(Procedure::Encryption(handle))?;
+        self.pending_command(HciOpcode::LeEnableEncryption, &payload).await?;
+
+        let evt_box = procedure.outcome().await.event()?;
+        match encryption_status(handle, evt_box.stub().evt_code, evt_box.payload()) {
+            Some(0) => Ok(()),
+            Some(status) => Err(BleError::Status(status)),
+            None => Err(BleError::InvalidResponse),
+        }
+    }
+
+    /// Read the LE features supported by the peer of a connection
+    ///
+    /// The controller asks the peer, and reports its features with a `LE Read Remote Features Complete`
+    /// event. Returns [`BleError::Busy`] if the features are already being read, and [`BleError::Status`]
+    /// with the disconnection reason if the connection closes meanwhile. Events received while waiting
+    /// are kept for [`Ble::tl_read`].
+    pub async fn read_remote_features(&self, handle: u16) -> Result<LeFeatures, BleError> {
+        let procedure = ProcedureWait::start(Procedure::RemoteFeatures(handle))?;
+        self.pending_command(HciOpcode::LeReadRemoteFeatures, &handle.to_le_bytes())
+            .await?;
+
+        let evt_box = procedure.outcome().await.event()?;
+        match ReadRemoteFeaturesComplete::parse(evt_box.stub().evt_code, evt_box.payload()) {
+            Some(complete) if complete.status != 0 => Err(BleError::Status(complete.status)),
+            Some(complete) => Ok(complete.features),
+            None => Err(BleError::InvalidResponse),
+        }
+    }
+
+    /// Request the PHYs of a connection and wait for the controller to apply them
+    ///
+    /// The peer may not support the requested PHYs, in which case the returned event gives the PHYs
+    /// kept by the controller. `coding` is only used when transmitting on the LE Coded PHY. Returns
+    /// [`BleError::Unsupported`] if the controller does not support the requested PHYs,
+    /// [`BleError::Busy`] if a PHY update is already pending on the connection, and [`BleError::Status`]
+    /// with the disconnection reason if the connection closes meanwhile. Events received while waiting
+    /// are kept for [`Ble::tl_read`].
+    pub async fn set_phy(
+        &self,
+        handle: u16,
+        tx: Phy,
+        rx: Phy,
+        coding: PhyCoding,
+    ) -> Result<PhyUpdateComplete, BleError> {
+        let features = self.read_local_features().await?;
+        let supported = |phy| match phy {
+            Phy::Le1M => true,
+            Phy::Le2M => features.le_2m_phy(),
+            Phy::LeCoded => features.le_coded_phy(),
+        };
+        if !supported(tx) || !supported(rx) {
+            return Err(BleError::Unsupported);
+        }
+
+        let procedure = ProcedureWait::start(Procedure::PhyUpdate(handle))?;
+        let cmd = SetPhy::new(handle, tx, rx, coding);
+        self.pending_command(SetPhy::OPCODE, cmd.payload()).await?;
+
+        let evt_box = procedure.outcome().await.event()?;
+        match PhyUpdateComplete::parse(evt_box.stub().evt_code, evt_box.payload()) {
+            Some(complete) if complete.status != 0 => Err(BleError::Status(complete.status)),
+            Some(complete) => Ok(complete),
+            None => Err(BleError::InvalidResponse),
+        }
+    }
+
+    /// Add a device to the filter accept list of the controller
+    ///
+    /// The list is used by the advertising, scanning and connection filter policies. It cannot be
+    /// changed while one of them uses it, in which case the controller returns a
+    /// [`BleError::Status`] error.
+    pub async fn add_to_filter_accept_list(
+        &self,
+        address_type: PeerAddressType,
+        address: &BdAddr,
+    ) -> Result<(), BleError> {
+        self.send_command(&AddToFilterAcceptList::new(address_type, address))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Remove all the devices from the filter accept list of the controller
+    pub async fn clear_filter_accept_list(&self) -> Result<(), BleError> {
+        self.command(HciOpcode::LeClearFilterAcceptList, &[]).await?;
+
+        Ok(())
+    }
+
+    /// Add a device to the resolving list of the controller, to resolve its private addresses
+    ///
+    /// `address` is the identity address of the device, and the keys are the identity resolving keys
+    /// exchanged when bonding. A zero `local_irk` makes the controller use the identity address of the
+    /// local device rather than a resolvable private address.
+    pub async fn add_to_resolving_list(
+        &self,
+        address_type: PeerAddressType,
+        address: &BdAddr,
+        peer_irk: [u8; 16],
+        local_irk: [u8; 16],
+    ) -> Result<(), BleError> {
+        self.send_command(&AddToResolvingList::new(address_type, address, peer_irk, local_irk))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Remove all the devices from the resolving list of the controller
+    pub async fn clear_resolving_list(&self) -> Result<(), BleError> {
+        self.command(HciOpcode::LeClearResolvingList, &[]).await?;
+
+        Ok(())
+    }
+
+    /// Initialize the GATT server of the wireless stack, before [`Ble::gap_init`] and adding services
+    #[cfg(feature = "gatt-server")]
+    pub async fn gatt_init(&self) -> Result<(), BleError> {
+        self.command(HciOpcode::AciGattInit, &[]).await?;
+
+        Ok(())
+    }
+
+    /// Add the GAP service, then set its device name and appearance characteristics
+    ///
+    /// This follows [`Ble::gatt_init`]. `appearance` is one of the values assigned by the Bluetooth SIG,
+    /// 0 for an unknown appearance. The returned handles can be used to update the characteristics later
+    /// with [`Ble::gatt_update_char_value`].
+    #[cfg(feature = "gatt-server")]
+    pub async fn gap_init(&self, role: GapRole, name: &str, appearance: u16) -> Result<GapHandles, BleError> {
+        let evt_box = self.send_hci_command(&gatt::gap_init(role, name.len())?).await?;
+        let handles = GapHandles::from_bytes(return_params(&evt_box)?)?;
+
+        self.gatt_update_char_value(handles.service, handles.device_name, 0, name.as_bytes())
+            .await?;
+        self.gatt_update_char_value(handles.service, handles.appearance, 0, &appearance.to_le_bytes())
+            .await?;
+
+        Ok(handles)
+    }
+
+    /// Add a service to the GATT server, returning its handle
+    #[cfg(feature = "gatt-server")]
+    pub async fn gatt_add_service(
+        &self,
+        uuid: &Uuid,
+        service_type: ServiceType,
+        max_attribute_records: u8,
+    ) -> Result<u16, BleError> {
+        let evt_box = self
+            .send_hci_command(&gatt::add_service(uuid, service_type, max_attribute_records))
+            .await?;
+
+        handle_param(return_params(&evt_box)?)
+    }
+
+    /// Add a characteristic to a service of the GATT server, returning the handle of its declaration
+    ///
+    /// The value of the characteristic follows its declaration, at the returned handle + 1.
+    #[cfg(feature = "gatt-server")]
+    pub async fn gatt_add_char(&self, service_handle: u16, config: &CharConfig) -> Result<u16, BleError> {
+        let evt_box = self.send_hci_command(&gatt::add_char(service_handle, config)?).await?;
+
+        handle_param(return_params(&evt_box)?)
+    }
+
+    /// Add a service to the GATT server, recording its handle in `db` under `uuid`
+    ///
+    /// The service is not added when `db` is full.
+    #[cfg(feature = "gatt-server")]
+    pub async fn gatt_db_add_service<const N: usize>(
+        &self,
+        db: &mut GattDb<N>,
+        uuid: &Uuid,
+        service_type: ServiceType,
+        max_attribute_records: u8,
+    ) -> Result<ServiceHandle, BleError> {
+        if db.len() == N {
+            return Err(BleError::InvalidParameters);
+        }
+
+        let handle = ServiceHandle(self.gatt_add_service(uuid, service_type, max_attribute_records).await?);
+        db.insert_service(*uuid, handle)?;
+
+        Ok(handle)
+    }
+
+    /// Add a characteristic to a service of the GATT server, recording its handle in `db` under its UUID
+    ///
+    /// The characteristic is not added when `db` is full.
+    #[cfg(feature = "gatt-server")]
+    pub async fn gatt_db_add_char<const N: usize>(
+        &self,
+        db: &mut GattDb<N>,
+        service: ServiceHandle,
+        config: &CharConfig,
+    ) -> Result<CharHandle, BleError> {
+        if db.len() == N {
+            return Err(BleError::InvalidParameters);
+        }
+
+        let handle = CharHandle {
+            service,
+            declaration: self.gatt_add_char(service.0, config).await?,
+        };
+        db.insert_char(config.uuid, handle)?;
+
+        Ok(handle)
+    }
+
+    /// Update the value of a characteristic, notifying or indicating it to the subscribed clients
+    #[cfg(feature = "gatt-server")]
+    pub async fn gatt_update_char_value(
+        &self,
+        service_handle: u16,
+        char_handle: u16,
+        offset: u8,
+        value: &[u8],
+    ) -> Result<(), BleError> {
+        self.send_hci_command(&gatt::update_char_value(service_handle, char_handle, offset, value)?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Notify or indicate a new value of a characteristic to the subscribed clients
+    ///
+    /// The whole value is replaced, and must fit in the ATT_MTU of the connections to be notified in full.
+    /// [`BleError::Busy`] is returned when the wireless stack has no free buffer for the notification, in
+    /// which case it can be sent again once the pending notifications have been transmitted.
+    #[cfg(feature = "gatt-server")]
+    pub async fn notify(&self, service_handle: u16, char_handle: u16, data: &[u8]) -> Result<(), BleError> {
+        use crate::ble::consts::BLE_STATUS_INSUFFICIENT_RESOURCES;
+
+        match self.gatt_update_char_value(service_handle, char_handle, 0, data).await {
+            Err(BleError::Status(BLE_STATUS_INSUFFICIENT_RESOURCES)) => Err(BleError::Busy),
+            result => result,
+        }
+    }
+
+    /// [`Ble::notify`] for a characteristic recorded in a [`GattDb`]
+    #[cfg(feature = "gatt-server")]
+    pub async fn notify_char(&self, handle: CharHandle, data: &[u8]) -> Result<(), BleError> {
+        self.notify(handle.service.0, handle.declaration, data).await
+    }
+
+    /// Let the read request of a [`ReadPermitReq`](gatt::ReadPermitReq) proceed
+    #[cfg(feature = "gatt-server")]
+    pub async fn gatt_allow_read(&self, connection_handle: u16) -> Result<(), BleError> {
+        self.send_hci_command(&gatt::allow_read(connection_handle)).await?;
+
+        Ok(())
+    }
+
+    /// Accept or reject the write request of a [`WritePermitReq`](gatt::WritePermitReq), see
+    /// [`gatt::write_resp`]
+    #[cfg(feature = "gatt-server")]
+    pub async fn gatt_write_resp(
+        &self,
+        connection_handle: u16,
+        attr_handle: u16,
+        error_code: Option<u8>,
+        value: &[u8],
+    ) -> Result<(), BleError> {
+        self.send_hci_command(&gatt::write_resp(connection_handle, attr_handle, error_code, value)?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Notify or indicate a new value of a characteristic, waiting for free buffers if needed
+    ///
+    /// Unlike [`Ble::notify`], a notification failing for lack of buffers is sent again once the wireless
+    /// stack reports buffers available with a [`TxPoolAvailable`](event::TxPoolAvailable) event, so that
+    /// a loop of notifications is throttled to what the connections can carry. Several tasks may notify
+    /// at the same time. The events are read while waiting if no other task reads them, and are kept for
+    /// [`Ble::tl_read`], the [`TxPoolAvailable`](event::TxPoolAvailable) event included.
+    #[cfg(feature = "gatt-server")]
+    pub async fn notify_blocking(&self, service_handle: u16, char_handle: u16, data: &[u8]) -> Result<(), BleError> {
+        loop {
+            // read before sending, so that buffers released in between are not missed
+            let generation = TX_POOL_GENERATION.load(Ordering::SeqCst);
+
+            match self.notify(service_handle, char_handle, data).await {
+                Err(BleError::Busy) => tx_pool_since(generation).await,
+                result => return result,
+            }
+        }
+    }
+
+    /// Exchange the ATT_MTU with the peer, returning the MTU agreed for the connection
+    ///
+    /// The MTU proposed to the peer is the `att_mtu` given to `shci_c2_ble_init`. Returns
+    /// [`BleError::Busy`] if an exchange is already pending on the connection, and [`BleError::Status`]
+    /// with the disconnection reason if the connection closes meanwhile. Events received while waiting
+    /// are kept for [`Ble::tl_read`].
+    pub async fn exchange_mtu(&self, handle: u16) -> Result<u16, BleError> {
+        let procedure = ProcedureWait::start(Procedure::MtuExchange(handle))?;
+        self.pending_command(HciOpcode::AciGattExchangeConfig, &handle.to_le_bytes())
+            .await?;
+
+        let evt_box = procedure.outcome().await.event()?;
+        let complete = VendorEvent::parse(evt_box.stub().evt_code, evt_box.payload())
+            .and_then(|evt| GattProcComplete::parse(&evt))
+            .ok_or(BleError::InvalidResponse)?;
+        if complete.error_code != 0 {
+            return Err(BleError::Status(complete.error_code));
+        }
+
+        // the MTU has been recorded when reading the exchange response, just before
+        Ok(self.att_mtu(handle))
+    }
+
+    /// ATT_MTU in use on a connection
+    ///
+    /// This is the MTU reported by the last exchange on the connection, whichever device started it, or
+    /// the default of 23 bytes.
+    pub fn att_mtu(&self, handle: u16) -> u16 {
+        ATT_MTUS
+            .iter()
+            .map(|entry| entry.load(Ordering::Relaxed))
+            .find(|entry| *entry != NO_CONNECTION && (entry >> 16) as u16 == handle)
+            .map_or(DEFAULT_ATT_MTU, |entry| entry as u16)
+    }
+
+    /// Link state of a connection: parameters, PHYs, data length and ATT_MTU
+    ///
+    /// The state is updated as the events of the connection are read, whichever task reads them, and
+    /// forgotten once it is disconnected. Returns `None` for an unknown connection, or if more than
+    /// [`MAX_CONNECTIONS`] are established.
+    pub fn connection_state(&self, handle: u16) -> Option<ConnectionState> {
+        CONNECTION_STATES.lock(|states| states.borrow().get(handle))
+    }
+
+    /// Wait until no data has flowed on a connection for `percent` % of its supervision timeout
+    ///
+    /// The controller closes a connection once the peer has been silent for the whole supervision timeout.
+    /// This reports the silence earlier, e.g. to send an application-level keepalive or to prepare the
+    /// reconnection. Data flow is seen from the ACL data packets received and those the controller
+    /// reports completed, as the events are read: another task must keep reading events with
+    /// [`Ble::tl_read`]. A connection without data traffic, where only empty packets are exchanged, looks
+    /// idle even though the link is alive.
+    ///
+    /// Returns [`BleError::InvalidParameters`] if `percent` is not between 1 and 100, and the
+    /// `Unknown Connection Identifier` status if the connection is not tracked, or is closed meanwhile.
+    #[cfg(feature = "embassy-time")]
+    pub async fn wait_link_idle(&self, handle: u16, percent: u8) -> Result<(), BleError> {
+        if !(1..=100).contains(&percent) {
+            return Err(BleError::InvalidParameters);
+        }
+
+        loop {
+            let (state, last_activity) = CONNECTION_STATES
+                .lock(|states| {
+                    let states = states.borrow();
+                    Some((states.get(handle)?, states.last_activity(handle)?))
+                })
+                .ok_or(BleError::Status(HCI_UNKNOWN_CONNECTION_ID))?;
+
+            let idle =
+                embassy_time::Duration::from_millis(state.supervision_timeout_ms() as u64 * percent as u64 / 100);
+            let deadline = embassy_time::Instant::from_ticks(last_activity) + idle;
+            if embassy_time::Instant::now() >= deadline {
+                return Ok(());
+            }
+
+            // data may flow meanwhile, which moves the deadline
+            embassy_time::Timer::at(deadline).await;
+        }
+    }
+
+    /// Start legacy advertising with a public address on all the advertising channels
+    ///
+    /// The advertising and scan response data are set as with [`Ble::set_adv_payload`].
+    pub async fn start_advertising(&self, cfg: &AdvConfig<'_>) -> Result<(), BleError> {
+        if cfg.data.len() > MAX_ADV_DATA_LEN || cfg.scan_response.is_some_and(|data| data.len() > MAX_ADV_DATA_LEN) {
+            return Err(BleError::InvalidParameters);
+        }
+
+        let params = AdvParams {
+            adv_interval_min: cfg.interval_min.to_le_bytes(),
+            adv_interval_max: cfg.interval_max.to_le_bytes(),
+            adv_type: if cfg.connectable { 0x00 } else { 0x03 },
+            own_address_type: OwnAddressType::Public,
+            peer_address_type: PeerAddressType::Public,
+            peer_address: BdAddr::default(),
+            adv_channel_map: AdvChannelMap::ALL,
+            adv_filter_policy: AdvFilterPolicy::None,
+        };
+
+        self.send_command(&params).await?;
+        self.set_adv_payload(cfg.data, cfg.scan_response).await?;
+        self.command(HciOpcode::LeSetAdvertisingEnable, &[1]).await?;
+
+        Ok(())
+    }
+
+    /// Set the legacy advertising data, and the scan response data if given
+    ///
+    /// Both are checked against the [`MAX_ADV_DATA_LEN`] limit before sending either command, so that
+    /// invalid data leaves the previous payload in place. This must be called while advertising is
+    /// disabled: otherwise the controller may advertise the new data with the previous scan response
+    /// between both commands. [`AdvDataBuilder`](crate::ble::adv::AdvDataBuilder) builds the data from AD structures.
+    pub async fn set_adv_payload(&self, adv: &[u8], scan_rsp: Option<&[u8]>) -> Result<(), BleError> {
+        if adv.len() > MAX_ADV_DATA_LEN || scan_rsp.is_some_and(|data| data.len() > MAX_ADV_DATA_LEN) {
+            return Err(BleError::InvalidParameters);
+        }
+
+        self.send_command(&AdvData::new(adv)).await?;
+        if let Some(scan_rsp) = scan_rsp {
+            self.send_command(&ScanRspData::new(scan_rsp)).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn stop_advertising(&self) -> Result<(), BleError> {
+        self.command(HciOpcode::LeSetAdvertisingEnable, &[0]).await?;
+
+        Ok(())
+    }
+
+    /// Start scanning, returning a stream of the advertising reports
+    ///
+    /// Whether scanning is allowed while advertising depends on the LE states supported by the controller:
+    /// if it is not, the controller answers with `Command Disallowed` (`0x0C`), which is returned as
+    /// an error without changing the advertising state.
+    ///
+    /// The stream reads the BLE events and drops the events other than advertising reports, so it
+    /// should not be polled while waiting for other events. Call [`Ble::stop_scan`] once it is dropped.
+    pub async fn start_scan(&self, cfg: &ScanConfig) -> Result<impl Stream<Item = AdvReport> + '_, BleError> {
+        let params = ScanParams {
+            scan_type: cfg.active as u8,
+            scan_interval: cfg.interval.to_le_bytes(),
+            scan_window: cfg.window.to_le_bytes(),
+            own_address_type: OwnAddressType::Public,
+            scanning_filter_policy: 0x00,
+        };
+
+        self.send_command(&params).await?;
+        self.send_command(&ScanEnable {
+            enable: true,
+            filter_duplicates: cfg.filter_duplicates,
+        })
+        .await?;
+
+        let pending: Deque<AdvReport, MAX_PENDING_ADV_REPORTS> = Deque::new();
+
+        Ok(stream::unfold((self, pending), |(ble, mut pending)| async move {
+            loop {
+                if let Some(report) = pending.pop_front() {
+                    return Some((report, (ble, pending)));
+                }
+
+                let evt_box = ble.tl_read().await;
+                if let Some(reports) = AdvertisingReports::parse(evt_box.stub().evt_code, evt_box.payload()) {
+                    for report in reports {
+                        if pending.push_back(report).is_err() {
+                            warn!("ble: too many advertising reports in one event, dropping the others");
+                            break;
+                        }
+                    }
+                }
+            }
+        }))
+    }
+
+    pub async fn stop_scan(&self) -> Result<(), BleError> {
+        self.send_command(&ScanEnable {
+            enable: false,
+            filter_duplicates: false,
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Start scanning with the extended scanning commands, returning a stream of the advertising reports
+    ///
+    /// Both the extended advertisements and the legacy advertisements are reported, as
+    /// [`ExtAdvReport`]s. The data of an advertisement spanning several events is reassembled, see
+    /// [`ExtAdvReassembly`]. As with [`Ble::start_scan`], the stream drops the other events, and
+    /// [`Ble::stop_extended_scan`] must be called once it is dropped.
+    ///
+    /// Fails with [`BleError::Unsupported`] if the wireless stack does not support extended advertising.
+    pub async fn start_extended_scan(
+        &self,
+        cfg: &ExtScanConfig,
+    ) -> Result<impl Stream<Item = ExtAdvReport> + '_, BleError> {
+        let params = scan::set_ext_scan_params(cfg)?;
+        if !self.read_local_features().await?.le_extended_advertising() {
+            return Err(BleError::Unsupported);
+        }
+
+        self.send_hci_command(&params).await?;
+        self.send_hci_command(&scan::set_ext_scan_enable(true, cfg)).await?;
+
+        // the event being decoded and the index of its next report, to avoid queueing large reports
+        let current: Option<(EvtBox<Self>, usize)> = None;
+
+        Ok(stream::unfold(
+            (self, current, ExtAdvReassembly::new()),
+            |(ble, mut current, mut reassembly)| async move {
+                loop {
+                    if let Some((evt_box, index)) = current.as_mut() {
+                        let report = ExtAdvertisingReports::parse(evt_box.stub().evt_code, evt_box.payload())
+                            .and_then(|mut reports| reports.nth(*index));
+                        *index += 1;
+
+                        if let Some(report) = report {
+                            match reassembly.push(report) {
+                                Some(report) => return Some((report, (ble, current, reassembly))),
+                                None => continue,
+                            }
+                        }
+                    }
+
+                    // release the buffer of the last event before waiting for the next one
+                    current = None;
+
+                    let evt_box = ble.tl_read().await;
+                    if ExtAdvertisingReports::parse(evt_box.stub().evt_code, evt_box.payload()).is_some() {
+                        current = Some((evt_box, 0));
+                    }
+                }
+            },
+        ))
+    }
+
+    /// Stop a scan started with [`Ble::start_extended_scan`]
+    pub async fn stop_extended_scan(&self) -> Result<(), BleError> {
+        self.send_hci_command(&scan::set_ext_scan_enable(false, &ExtScanConfig::default()))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Configure an extended advertising set, returning the TX power selected by the controller in dBm
+    ///
+    /// Fails with [`BleError::Unsupported`] if the wireless stack does not support extended advertising.
+    pub async fn le_set_ext_adv_params(&self, params: &ExtAdvParams) -> Result<i8, BleError> {
+        if !self.read_local_features().await?.le_extended_advertising() {
+            return Err(BleError::Unsupported);
+        }
+
+        let evt_box = self.send_command(params).await?;
+
+        return_params(&evt_box)?
+            .first()
+            .map(|tx_power| *tx_power as i8)
+            .ok_or(BleError::InvalidResponse)
+    }
+
+    /// Set the advertising data of an extended advertising set, splitting it in as many commands as needed
+    pub async fn le_set_ext_adv_data(&self, adv_handle: u8, data: &[u8]) -> Result<(), BleError> {
+        if data.len() <= MAX_EXT_ADV_DATA_FRAGMENT_LEN {
+            self.send_command(&ExtAdvData::new(adv_handle, ExtAdvDataOperation::Complete, data))
+                .await?;
+
+            return Ok(());
+        }
+
+        let last = (data.len() - 1) / MAX_EXT_ADV_DATA_FRAGMENT_LEN;
+        for (i, fragment) in data.chunks(MAX_EXT_ADV_DATA_FRAGMENT_LEN).enumerate() {
+            let operation = match i {
+                0 => ExtAdvDataOperation::FirstFragment,
+                i if i == last => ExtAdvDataOperation::LastFragment,
+                _ => ExtAdvDataOperation::IntermediateFragment,
+            };
+
+            self.send_command(&ExtAdvData::new(adv_handle, operation, fragment))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Enable or disable extended advertising sets
+    pub async fn le_set_ext_adv_enable(&self, enable: bool, sets: &[ExtAdvSet]) -> Result<(), BleError> {
+        self.send_command(&ExtAdvEnable::new(enable, sets)).await?;
+
+        Ok(())
+    }
+
+    /// Configure the periodic advertising of an extended advertising set
+    ///
+    /// Fails with [`BleError::Unsupported`] if the wireless stack does not support periodic advertising.
+    #[cfg(feature = "periodic-adv")]
+    pub async fn le_set_periodic_adv_params(
+        &self,
+        adv_handle: u8,
+        interval_min: u16,
+        interval_max: u16,
+        include_tx_power: bool,
+    ) -> Result<(), BleError> {
+        let cmd = periodic::set_params(adv_handle, interval_min, interval_max, include_tx_power)?;
+        if !self.read_local_features().await?.le_periodic_advertising() {
+            return Err(BleError::Unsupported);
+        }
+
+        self.send_hci_command(&cmd).await?;
+
+        Ok(())
+    }
+
+    /// Set the periodic advertising data of an advertising set, splitting it in as many commands as needed
+    #[cfg(feature = "periodic-adv")]
+    pub async fn le_set_periodic_adv_data(&self, adv_handle: u8, data: &[u8]) -> Result<(), BleError> {
+        if data.len() <= MAX_PERIODIC_ADV_DATA_FRAGMENT_LEN {
+            self.send_hci_command(&periodic::set_data(adv_handle, ExtAdvDataOperation::Complete, data)?)
+                .await?;
+
+            return Ok(());
+        }
+
+        let last = (data.len() - 1) / MAX_PERIODIC_ADV_DATA_FRAGMENT_LEN;
+        for (i, fragment) in data.chunks(MAX_PERIODIC_ADV_DATA_FRAGMENT_LEN).enumerate() {
+            let operation = match i {
+                0 => ExtAdvDataOperation::FirstFragment,
+                i if i == last => ExtAdvDataOperation::LastFragment,
+                _ => ExtAdvDataOperation::IntermediateFragment,
+            };
+
+            self.send_hci_command(&periodic::set_data(adv_handle, operation, fragment)?)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Enable or disable the periodic advertising of an advertising set
+    #[cfg(feature = "periodic-adv")]
+    pub async fn le_set_periodic_adv_enable(&self, adv_handle: u8, enable: bool) -> Result<(), BleError> {
+        self.send_hci_command(&periodic::set_enable(adv_handle, enable)).await?;
+
+        Ok(())
+    }
+
+    /// Synchronize to the periodic advertising of another device, and wait until synchronized
+    ///
+    /// Scanning must be enabled with the extended scanning commands for the controller to find the
+    /// periodic advertising train. The reports are then received as
+    /// [`PeriodicAdvReport`](periodic::PeriodicAdvReport) events. This waits until synchronized, or until
+    /// [`Ble::le_periodic_adv_create_sync_cancel`] is called from another task. Returns [`BleError::Busy`]
+    /// if another synchronization is pending. Events received while waiting are kept for [`Ble::tl_read`].
+    ///
+    /// Fails with [`BleError::Unsupported`] if the wireless stack does not support periodic advertising.
+    #[cfg(feature = "periodic-adv")]
+    pub async fn le_periodic_adv_create_sync(&self, config: &SyncConfig) -> Result<SyncEstablished, BleError> {
+        let cmd = periodic::create_sync(config)?;
+        if !self.read_local_features().await?.le_periodic_advertising() {
+            return Err(BleError::Unsupported);
+        }
+
+        let procedure = ProcedureWait::start(Procedure::PeriodicSync)?;
+        self.raw_command_reply(cmd.opcode_bits(), cmd.payload()).await?;
+
+        let evt_box = procedure.outcome().await.event()?;
+        match SyncEstablished::parse(evt_box.stub().evt_code, evt_box.payload()) {
+            Some(established) if established.status != 0 => Err(BleError::Status(established.status)),
+            Some(established) => Ok(established),
+            None => Err(BleError::InvalidResponse),
+        }
+    }
+
+    /// Cancel the synchronization attempt of [`Ble::le_periodic_adv_create_sync`]
+    #[cfg(feature = "periodic-adv")]
+    pub async fn le_periodic_adv_create_sync_cancel(&self) -> Result<(), BleError> {
+        self.command(HciOpcode::LePeriodicAdvertisingCreateSyncCancel, &[])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stop receiving the periodic advertising reports of a synchronization
+    #[cfg(feature = "periodic-adv")]
+    pub async fn le_periodic_adv_terminate_sync(&self, sync_handle: u16) -> Result<(), BleError> {
+        self.send_hci_command(&periodic::terminate_sync(sync_handle)).await?;
+
+        Ok(())
+    }
+}
+
+/// Attribute handle returned by the `ACI_GATT_Add_*` commands
+#[cfg(feature = "gatt-server")]
+fn handle_param(params: &[u8]) -> Result<u16, BleError> {
+    match params {
+        [lo, hi, ..] => Ok(u16::from_le_bytes([*lo, *hi])),
+        _ => Err(BleError::InvalidResponse),
+    }
+}
+
+/// Forget the state of the controller after CPU2 restarted
+pub(crate) fn reset() {
+    ACL_CREDITS.disable();
+    COMPLETED_PACKETS.clear();
+    OUTSTANDING_PACKETS.clear();
+    Ble::release_command_slot();
+    for entry in ATT_MTUS.iter() {
+        entry.store(NO_CONNECTION, Ordering::Relaxed);
+    }
+    update_connection_states(|states| states.clear());
+    ACL_RX_CHANNEL.clear();
+    PENDING_EVENTS.lock(|events| while events.borrow_mut().pop_front().is_some() {});
+
+    // buffers are all free after the restart, the pending notifications are sent again
+    TX_POOL_GENERATION.fetch_add(1, Ordering::SeqCst);
+    notify_waiters();
+}
+
+/// Wait until `take` returns a value, reading the BLE events meanwhile if no other task does
+///
+/// A single task reads the event queue at a time, handing each event to the task waiting for it, or keeping
+/// it for [`Ble::tl_read`]. The other tasks call `take` again each time an event was handed out, and one of
+/// them takes over the reading if the reader is done or dropped.
+async fn read_until<R>(mut take: impl FnMut() -> Option<R>) -> R {
+    loop {
+        if let Some(ret) = take() {
+            return ret;
+        }
+
+        if !EVENT_READER.swap(true, Ordering::Acquire) {
+            let _reader = OnDrop::new(|| {
+                EVENT_READER.store(false, Ordering::Release);
+                EVENT_WAITERS.wake_all();
+            });
+
+            let notifications = WAITER_NOTIFICATIONS.load(Ordering::SeqCst);
+            let read = Ipcc::receive(channels::cpu2::IPCC_BLE_EVENT_CHANNEL, || loop {
+                if let Some(ret) = take() {
+                    return Some(ret);
+                }
+
+                dispatch_event(unsafe { Ble::take_event() }?);
+            });
+            // the state `take` depends on may also change without an event, e.g. on reset
+            let notified = EVENT_WAITERS.wait_until(|| WAITER_NOTIFICATIONS.load(Ordering::SeqCst) != notifications);
+
+            match select(read, notified).await {
+                Either::First(ret) => return ret,
+                Either::Second(()) => continue,
+            }
+        }
+
+        let mut taken = None;
+        EVENT_WAITERS
+            .wait_until(|| {
+                taken = take();
+                taken.is_some() || !EVENT_READER.load(Ordering::Acquire)
+            })
+            .await;
+        if let Some(ret) = taken {
+            return ret;
+        }
+    }
+}
+
+/// Wake the tasks of [`read_until`] after changing the state they wait for outside of an event
+fn notify_waiters() {
+    WAITER_NOTIFICATIONS.fetch_add(1, Ordering::SeqCst);
+    EVENT_WAITERS.wake_all();
+}
+
+/// Hand an event read from the queue to the task waiting for it, or keep it for [`Ble::tl_read`]
+fn dispatch_event(evt_box: EvtBox<Ble>) {
+    let evt_code = evt_box.stub().evt_code;
+    let answered = match CommandComplete::parse(evt_code, evt_box.payload()) {
+        Some(complete) => Some(complete.opcode),
+        None => CommandStatus::parse(evt_code, evt_box.payload()).map(|status| status.opcode),
+    };
+
+    // the answer to a command which is no longer in flight goes to the application as any other event
+    let pending = match answered {
+        Some(opcode) if COMMAND_SLOT.in_flight() == Some(opcode) => COMMAND_ANSWER.lock(|answer| {
+            let mut answer = answer.borrow_mut();
+            match *answer {
+                CommandAnswer::Awaited => {
+                    *answer = CommandAnswer::Received(evt_box);
+                    None
+                }
+                CommandAnswer::Abandoned => {
+                    *answer = CommandAnswer::Application;
+                    COMMAND_SLOT.answered(opcode);
+                    drop_event!("ble: drop the answer to command {:x}, no longer awaited", opcode);
+                    None
+                }
+                CommandAnswer::Application | CommandAnswer::Received(_) => {
+                    COMMAND_SLOT.answered(opcode);
+                    Some(evt_box)
+                }
+            }
+        }),
+        _ => Some(evt_box),
+    };
+
+    if let Some(evt_box) = pending.and_then(deliver_outcome) {
+        queue_pending_event(evt_box);
+    }
+
+    EVENT_WAITERS.wake_all();
+}
+
+/// Hand an event to the procedure whose outcome it reports, returns it back if none waits for it
+///
+/// A disconnection ends all the procedures running on the connection, and goes to the application unless
+/// [`Ble::disconnect`] waits for it.
+fn deliver_outcome(evt_box: EvtBox<Ble>) -> Option<EvtBox<Ble>> {
+    PROCEDURES.lock(|procedures| {
+        let mut procedures = procedures.borrow_mut();
+
+        let (evt_code, payload) = (evt_box.stub().evt_code, evt_box.payload());
+        if let Some(complete) = DisconnectionComplete::parse(evt_code, payload).filter(|complete| complete.status == 0)
+        {
+            for entry in procedures.iter_mut().flatten().filter(|entry| {
+                entry.outcome.is_none() && entry.procedure.connection() == Some(complete.connection_handle)
+            }) {
+                entry.outcome = Some(Outcome::Disconnected(complete.reason.code()));
+            }
+        }
+
+        let reported = procedures
+            .iter_mut()
+            .flatten()
+            .find(|entry| entry.outcome.is_none() && entry.procedure.reported_by(evt_code, payload));
+        match reported {
+            Some(entry) => {
+                entry.outcome = Some(Outcome::Event(evt_box));
+                None
+            }
+            None => Some(evt_box),
+        }
+    })
+}
+
+/// Result of a connection attempt, from the `LE Connection Complete` event ending it
+fn connection_complete(outcome: Outcome) -> Result<ConnectionComplete, BleError> {
+    let evt_box = outcome.event()?;
+
+    match ConnectionComplete::parse(evt_box.stub().evt_code, evt_box.payload()) {
+        Some(complete) if complete.status != 0 => Err(BleError::Status(complete.status)),
+        Some(complete) => Ok(complete),
+        None => Err(BleError::InvalidResponse),
+    }
+}
+
+/// Status of the encryption of a connection, if the event reports it
+fn encryption_status(handle: u16, evt_code: u8, payload: &[u8]) -> Option<u8> {
+    if let Some(change) = EncryptionChange::parse(evt_code, payload) {
+        (change.connection_handle == handle).then_some(change.status)
+    } else {
+        EncryptionKeyRefreshComplete::parse(evt_code, payload)
+            .filter(|refresh| refresh.connection_handle == handle)
+            .map(|refresh| refresh.status)
+    }
+}
+
+/// Cancel the attempt of a [`Ble::connect`] which gave up waiting, if no other command is in flight
+fn abandon_connection() {
+    let opcode = HciOpcode::LeCreateConnectionCancel as u16;
+    if Ble::try_write_command(opcode, &[], CommandAnswer::Abandoned).is_err() {
+        warn!("ble: command in flight, the connection attempt goes on until cancelled");
+    }
+}
+
+/// Keep an event for [`Ble::tl_read`], dropping the oldest one if too many are waiting
+fn queue_pending_event(evt_box: EvtBox<Ble>) {
+    let dropped = PENDING_EVENTS.lock(|events| {
+        let mut events = events.borrow_mut();
+        let dropped = if events.is_full() { events.pop_front() } else { None };
+        let _ = events.push_back(evt_box);
+        dropped
+    });
+    if dropped.is_some() {
+        drop_event!("ble: too many events waiting for the application, dropping the oldest");
+    }
+}
+
+/// Take the answer to the command awaited by [`Ble::command_reply`], freeing the command slot
+fn take_command_answer() -> Option<EvtBox<Ble>> {
+    let evt_box = COMMAND_ANSWER.lock(|answer| {
+        let mut answer = answer.borrow_mut();
+        match mem::replace(&mut *answer, CommandAnswer::Application) {
+            CommandAnswer::Received(evt_box) => Some(evt_box),
+            other => {
+                *answer = other;
+                None
+            }
+        }
+    })?;
+    COMMAND_SLOT.release();
+
+    Some(evt_box)
+}
+
+/// Forget the command awaited by [`Ble::command_reply`], which gave up waiting
+fn abandon_command() {
+    let answer = COMMAND_ANSWER.lock(|answer| {
+        let mut answer = answer.borrow_mut();
+        match *answer {
+            CommandAnswer::Awaited => {
+                *answer = CommandAnswer::Abandoned;
+                false
+            }
+            CommandAnswer::Received(_) => true,
+            _ => false,
+        }
+    });
+
+    // the answer has been read meanwhile, the slot is free for the next command
+    if answer {
+        Ble::release_command_slot();
+    }
+}
+
+/// Wait for the wireless stack to report free buffers after `generation`
+///
+/// Any number of tasks may wait, the event reporting the buffers being read by one of them if the
+/// application does not read the events meanwhile.
+#[cfg(feature = "gatt-server")]
+async fn tx_pool_since(generation: u32) {
+    read_until(|| (TX_POOL_GENERATION.load(Ordering::SeqCst) != generation).then_some(())).await
+}
+
+/// Note that data flowed on a connection, for [`Ble::wait_link_idle`]
+fn record_activity(_handle: u16) {
+    #[cfg(feature = "embassy-time")]
+    update_connection_states(|states| states.record_activity(_handle, embassy_time::Instant::now().as_ticks()));
+}
+
+/// Hand a received ACL data packet to [`Ble::read_acl`], or drop it if too many are waiting
+fn queue_acl_data(acl: AclDataBox) {
+    #[cfg(feature = "hci-capture")]
+    capture::record(Direction::Received, &[H4_ACL_DATA], acl.data());
+    if let Ok(packet) = acl.parse() {
+        record_activity(packet.handle());
+    }
+
+    if ACL_RX_CHANNEL.try_send(acl).is_err() {
+        mm::MemoryManager::track_dropped_acl_packet();
+        warn!("ble: ACL data queue full, dropping a received packet");
+    } else {
+        mm::MemoryManager::track_acl_rx_queue_depth(ACL_RX_CHANNEL.len());
+    }
+}
+
+/// Record the ATT_MTU of a connection, or forget it once disconnected
+///
+/// This is only called while reading events, so entries are not modified concurrently.
+fn set_att_mtu(handle: u16, mtu: Option<u16>) {
+    let matches = |entry: &&AtomicU32| {
+        let entry = entry.load(Ordering::Relaxed);
+        entry != NO_CONNECTION && (entry >> 16) as u16 == handle
+    };
+
+    match mtu {
+        Some(mtu) => {
+            let entry = ATT_MTUS.iter().find(matches).or_else(|| {
+                ATT_MTUS
+                    .iter()
+                    .find(|entry| entry.load(Ordering::Relaxed) == NO_CONNECTION)
+            });
+
+            match entry {
+                Some(entry) => entry.store(((handle as u32) << 16) | mtu as u32, Ordering::Relaxed),
+                None => warn!("ble: no room to record the ATT_MTU of connection {:x}", handle),
+            }
+        }
+        None => {
+            if let Some(entry) = ATT_MTUS.iter().find(matches) {
+                entry.store(NO_CONNECTION, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Modify the link state of the connections
+fn update_connection_states<R>(f: impl FnOnce(&mut ConnectionStates<MAX_CONNECTIONS>) -> R) -> R {
+    CONNECTION_STATES.lock(|states| f(&mut states.borrow_mut()))
+}
+
+/// Copy the return parameters of a `Command Complete` event into `response`, returning their length
+fn copy_return_params(evt_box: &EvtBox<Ble>, response: &mut [u8]) -> Result<usize, BleError> {
+    let params = return_params(evt_box)?;
+    response
+        .get_mut(..params.len())
+        .ok_or(BleError::InvalidResponse)?
+        .copy_from_slice(params);
+
+    Ok(params.len())
+}
+
+/// Entry read by `ACI_HAL_Read_Config_Data`, checked against the size expected at `offset`
+fn config_data(evt_box: &EvtBox<Ble>, offset: ConfigDataOffset) -> Result<&[u8], BleError> {
+    let params = return_params(evt_box)?;
+
+    // the data is preceded by its length
+    let (&len, data) = params.split_first().ok_or(BleError::InvalidResponse)?;
+    let data = data.get(..len as usize).ok_or(BleError::InvalidResponse)?;
+    if data.len() != offset.size() {
+        return Err(BleError::InvalidResponse);
+    }
+
+    Ok(data)
+}
+
+/// Return parameters of a `Command Complete` event, after the status
+fn return_params(evt_box: &EvtBox<Ble>) -> Result<&[u8], BleError> {
+    CommandComplete::parse(evt_box.stub().evt_code, evt_box.payload())
+        .map(|cc| cc.params)
+        .ok_or(BleError::InvalidResponse)
+}
+
+/// Return parameters of a command answering a fixed number of bytes
+fn fixed_return_params<const N: usize>(evt_box: &EvtBox<Ble>) -> Result<[u8; N], BleError> {
+    return_params(evt_box)?
+        .get(..N)
+        .and_then(|params| params.try_into().ok())
+        .ok_or(BleError::InvalidResponse)
+}
+
+impl EvtBox<Ble> {
+    /// Connection handle of the event, if it is scoped to a connection
+    ///
+    /// See [`connection_handle`](crate::ble::event::connection_handle) for the events recognized.
+    pub fn connection_handle(&self) -> Option<u16> {
+        event::connection_handle(self.stub().evt_code, self.payload())
+    }
+}
+
+impl evt::MemoryManager for Ble {
+    /// SAFETY: passing a pointer to something other than a managed event packet is UB
+    unsafe fn drop_event_packet(evt: *mut EvtPacket) {
+        if Self::is_pool_event(evt) {
+            mm::MemoryManager::drop_event_packet(evt);
+        }
+    }
+}
+
+pub extern crate stm32wb_hci as hci;
+
+impl hci::Controller for Ble {
+    async fn controller_write(&mut self, opcode: Opcode, payload: &[u8]) {
+        // the commands built by `stm32wb_hci` always fit in the command buffer
+        unwrap!(self.tl_write(opcode.0, payload).await);
+    }
+
+    async fn controller_read_into(&self, buf: &mut [u8]) {
+        let evt_box = self.tl_read().await;
+        let evt_serial = evt_box.serial();
+
+        buf[..evt_serial.len()].copy_from_slice(evt_serial);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::Future;
+    use core::pin::{pin, Pin};
+    use core::sync::atomic::AtomicUsize;
+    use core::task::{Context, Waker};
+    use std::boxed::Box;
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::task::Wake;
+
+    use super::*;
+    use crate::ble::consts;
+    use crate::evt::{Evt, EvtSerial};
+    use crate::ipcc::mock::MockIpcc;
+
+    /// The mailbox is a singleton, tests using it run one at a time
+    static MAILBOX: Mutex<()> = Mutex::new(());
+
+    const PEER: BdAddr = BdAddr([0; 6]);
+    const PARAMS: ConnParams = ConnParams {
+        interval_min: 24,
+        interval_max: 40,
+        max_latency: 4,
+        supervision_timeout: 100,
+    };
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn counting_waker() -> (Arc<CountingWaker>, Waker) {
+        let count = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(count.clone());
+
+        (count, waker)
+    }
+
+    fn poll<F: Future>(future: Pin<&mut F>, waker: &Waker) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(waker))
+    }
+
+    /// Take the mailbox, with the channels clear and the controller state forgotten
+    fn setup() -> (MutexGuard<'static, ()>, Ble) {
+        let guard = MAILBOX.lock().unwrap_or_else(|e| e.into_inner());
+
+        MockIpcc::reset();
+        let _ = mm::MemoryManager::new();
+        let ble = Ble::new();
+        reset();
+
+        (guard, ble)
+    }
+
+    /// Append an event to the BLE event queue, as CPU2 does before signalling the event channel
+    fn queue_event(evt_code: u8, payload: &[u8]) {
+        let mut evt = Evt {
+            evt_code,
+            payload_len: payload.len() as u8,
+            payload: [0; 255],
+        };
+        evt.payload[..payload.len()].copy_from_slice(payload);
+
+        let packet = Box::leak(Box::new(EvtPacket {
+            header: LinkedListNode::default(),
+            evt_serial: EvtSerial {
+                kind: TlPacketType::BleEvt as u8,
+                evt,
+            },
+        }));
+
+        unsafe { LinkedListNode::insert_tail(EVT_QUEUE.as_mut_ptr(), packet as *mut EvtPacket as *mut _) };
+    }
+
+    fn command_complete(opcode: HciOpcode, params: &[u8]) {
+        command_failed(opcode, 0, params);
+    }
+
+    fn command_failed(opcode: HciOpcode, status: u8, params: &[u8]) {
+        let [opcode_lo, opcode_hi] = (opcode as u16).to_le_bytes();
+        let mut payload = std::vec![1, opcode_lo, opcode_hi, status];
+        payload.extend_from_slice(params);
+
+        queue_event(TL_BLEEVT_CC_OPCODE, &payload);
+    }
+
+    fn command_status(opcode: HciOpcode) {
+        let [opcode_lo, opcode_hi] = (opcode as u16).to_le_bytes();
+
+        queue_event(TL_BLEEVT_CS_OPCODE, &[0, 1, opcode_lo, opcode_hi]);
+    }
+
+    /// `LE Connection Complete` with a 50 ms interval and a 4 s supervision timeout
+    fn connection_complete_event(status: u8, handle: u16) {
+        let [handle_lo, handle_hi] = handle.to_le_bytes();
+        let mut payload = std::vec![0x01, status, handle_lo, handle_hi, 0x00, 0x00, 1, 2, 3, 4, 5, 6];
+        payload.extend_from_slice(&[0x28, 0x00, 0x00, 0x00, 0x90, 0x01, 0x00]);
+
+        queue_event(0x3E, &payload);
+    }
+
+    /// CPU2 reads the command sent and answers it with `answer`
+    fn cpu2_answer(answer: impl FnOnce()) {
+        assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_BLE_CMD_CHANNEL));
+        answer();
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+    }
+
+    #[test]
+    fn command_matches_opcode() {
+        let (_mailbox, ble) = setup();
+        let (_, waker) = counting_waker();
+        let dropped = mm::MemoryManager::stats().dropped_events;
+
+        let mut command = pin!(ble.command(HciOpcode::ReadBdAddr, &[]));
+        assert!(poll(command.as_mut(), &waker).is_pending());
+        assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_BLE_CMD_CHANNEL));
+
+        // the answer to a command which gave up waiting comes first, and must not be taken for this one
+        command_complete(HciOpcode::Reset, &[]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(poll(command.as_mut(), &waker).is_pending());
+        assert!(COMMAND_SLOT.is_in_flight());
+
+        command_complete(HciOpcode::ReadBdAddr, &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+
+        let Poll::Ready(result) = poll(command.as_mut(), &waker) else {
+            panic!("command not answered");
+        };
+        let evt_box = result.unwrap();
+        assert_eq!(return_params(&evt_box).unwrap(), &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        assert!(!COMMAND_SLOT.is_in_flight());
+
+        // the stale answer is left to the application
+        let stale = ble.poll().unwrap();
+        assert_eq!(
+            CommandComplete::parse(stale.stub().evt_code, stale.payload()).map(|cc| cc.opcode),
+            Some(HciOpcode::Reset as u16)
+        );
+        assert_eq!(mm::MemoryManager::stats().dropped_events, dropped);
+    }
+
+    #[test]
+    fn unrelated_events_kept() {
+        let (_mailbox, ble) = setup();
+        let (_, waker) = counting_waker();
+
+        let mut command = pin!(ble.command(HciOpcode::ReadBdAddr, &[]));
+        assert!(poll(command.as_mut(), &waker).is_pending());
+
+        // Disconnection Complete of connection 1, received while waiting for the answer
+        queue_event(0x05, &[0x00, 0x01, 0x00, 0x13]);
+        command_complete(HciOpcode::ReadBdAddr, &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(poll(command.as_mut(), &waker).is_ready());
+
+        let evt_box = ble.poll().unwrap();
+        assert_eq!(evt_box.stub().evt_code, 0x05);
+        assert!(ble.poll().is_none());
+    }
+
+    #[test]
+    fn answer_read_by_another_task() {
+        let (_mailbox, ble) = setup();
+        let (_, reader_waker) = counting_waker();
+        let (woken, waker) = counting_waker();
+
+        // the application reads the events while the command waits
+        let mut read = pin!(ble.tl_read());
+        assert!(poll(read.as_mut(), &reader_waker).is_pending());
+        let mut command = pin!(ble.command(HciOpcode::ReadBdAddr, &[]));
+        assert!(poll(command.as_mut(), &waker).is_pending());
+
+        command_complete(HciOpcode::ReadBdAddr, &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(poll(read.as_mut(), &reader_waker).is_pending());
+        assert_eq!(woken.0.load(Ordering::Relaxed), 1);
+
+        let Poll::Ready(result) = poll(command.as_mut(), &waker) else {
+            panic!("command not answered");
+        };
+        assert_eq!(
+            return_params(&result.unwrap()).unwrap(),
+            &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]
+        );
+        assert!(!COMMAND_SLOT.is_in_flight());
+
+        // the reader gets the next event
+        queue_event(0x05, &[0x00, 0x01, 0x00, 0x13]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(poll(read.as_mut(), &reader_waker).is_ready());
+    }
+
+    #[test]
+    fn abandoned_answer_dropped() {
+        let (_mailbox, ble) = setup();
+        let (_, waker) = counting_waker();
+        let dropped = mm::MemoryManager::stats().dropped_events;
+
+        let mut command = Box::pin(ble.command(HciOpcode::ReadBdAddr, &[]));
+        assert!(poll(command.as_mut(), &waker).is_pending());
+        drop(command);
+        // the command was sent, the slot stays taken until it is answered
+        assert!(COMMAND_SLOT.is_in_flight());
+
+        command_complete(HciOpcode::ReadBdAddr, &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(ble.poll().is_none());
+        assert!(!COMMAND_SLOT.is_in_flight());
+        assert_eq!(mm::MemoryManager::stats().dropped_events, dropped + 1);
+    }
+
+    #[test]
+    fn cancelled_command_frees_slot() {
+        let (_mailbox, ble) = setup();
+        let (_, waker_a) = counting_waker();
+        let (_, waker_b) = counting_waker();
+        let (woken_c, waker_c) = counting_waker();
+
+        // CPU2 answers the first command before clearing its channel, the next command waits for the channel
+        let mut a = pin!(ble.command(HciOpcode::Reset, &[]));
+        assert!(poll(a.as_mut(), &waker_a).is_pending());
+        command_complete(HciOpcode::Reset, &[]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(poll(a.as_mut(), &waker_a).is_ready());
+
+        let mut b = Box::pin(ble.command(HciOpcode::ReadBdAddr, &[]));
+        let mut c = pin!(ble.command(HciOpcode::ReadLocalVersionInformation, &[]));
+        assert!(poll(b.as_mut(), &waker_b).is_pending());
+        assert!(poll(c.as_mut(), &waker_c).is_pending());
+        assert_eq!(woken_c.0.load(Ordering::Relaxed), 0);
+
+        // the command times out before being written, the waiting one takes its place
+        drop(b);
+        assert_eq!(woken_c.0.load(Ordering::Relaxed), 1);
+
+        // written once CPU2 reads the first command, then waiting for its answer
+        assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_BLE_CMD_CHANNEL));
+        assert!(poll(c.as_mut(), &waker_c).is_pending());
+        assert_eq!(MockIpcc::sent(channels::cpu1::IPCC_BLE_CMD_CHANNEL), 2);
+        assert!(COMMAND_SLOT.is_in_flight());
+    }
+
+    #[cfg(feature = "gatt-server")]
+    #[test]
+    fn notify_waits_for_tx_pool() {
+        use crate::ble::consts::BLE_STATUS_INSUFFICIENT_RESOURCES;
+        use crate::consts::TL_BLEEVT_VS_OPCODE;
+
+        let (_mailbox, ble) = setup();
+        let (woken_a, waker_a) = counting_waker();
+        let (woken_b, waker_b) = counting_waker();
+
+        // both notifications find the buffers of the wireless stack exhausted
+        let mut a = pin!(ble.notify_blocking(0x000C, 0x000E, &[1]));
+        let mut b = pin!(ble.notify_blocking(0x000C, 0x000E, &[2]));
+        assert!(poll(a.as_mut(), &waker_a).is_pending());
+        assert!(poll(b.as_mut(), &waker_b).is_pending());
+        for _ in 0..2 {
+            assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_BLE_CMD_CHANNEL));
+            command_failed(
+                HciOpcode::AciGattUpdateCharValue,
+                BLE_STATUS_INSUFFICIENT_RESOURCES,
+                &[],
+            );
+            MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+            assert!(poll(a.as_mut(), &waker_a).is_pending());
+            assert!(poll(b.as_mut(), &waker_b).is_pending());
+        }
+        assert_eq!(MockIpcc::sent(channels::cpu1::IPCC_BLE_CMD_CHANNEL), 2);
+
+        // buffers are released, both notifications are sent again
+        let (a_woken, b_woken) = (woken_a.0.load(Ordering::Relaxed), woken_b.0.load(Ordering::Relaxed));
+        queue_event(TL_BLEEVT_VS_OPCODE, &[0x16, 0x0C, 0x01, 0x08, 0x03, 0x00]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(woken_a.0.load(Ordering::Relaxed) > a_woken);
+        assert!(poll(a.as_mut(), &waker_a).is_pending());
+        assert!(woken_b.0.load(Ordering::Relaxed) > b_woken);
+        assert!(poll(b.as_mut(), &waker_b).is_pending());
+        assert_eq!(MockIpcc::sent(channels::cpu1::IPCC_BLE_CMD_CHANNEL), 3);
+
+        assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_BLE_CMD_CHANNEL));
+        command_complete(HciOpcode::AciGattUpdateCharValue, &[]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(matches!(poll(a.as_mut(), &waker_a), Poll::Ready(Ok(()))));
+
+        assert!(poll(b.as_mut(), &waker_b).is_pending());
+        assert_eq!(MockIpcc::sent(channels::cpu1::IPCC_BLE_CMD_CHANNEL), 4);
+        assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_BLE_CMD_CHANNEL));
+        command_complete(HciOpcode::AciGattUpdateCharValue, &[]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(matches!(poll(b.as_mut(), &waker_b), Poll::Ready(Ok(()))));
+
+        // the event reporting the buffers is left to the application as well
+        let evt_box = ble.poll().unwrap();
+        assert_eq!(evt_box.stub().evt_code, TL_BLEEVT_VS_OPCODE);
+    }
+
+    #[test]
+    fn connect_cancelled_by_another_task() {
+        let (_mailbox, ble) = setup();
+        let (_, waker) = counting_waker();
+        let (_, cancel_waker) = counting_waker();
+
+        let mut connect = pin!(ble.connect(PeerAddressType::Public, &PEER, &PARAMS));
+        assert!(poll(connect.as_mut(), &waker).is_pending());
+        cpu2_answer(|| command_complete(HciOpcode::LeSetScanEnable, &[]));
+        assert!(poll(connect.as_mut(), &waker).is_pending());
+        cpu2_answer(|| command_status(HciOpcode::LeCreateConnection));
+        assert!(poll(connect.as_mut(), &waker).is_pending());
+
+        // a second attempt is refused while the first one is pending
+        let mut second = pin!(ble.connect(PeerAddressType::Public, &PEER, &PARAMS));
+        assert!(matches!(
+            poll(second.as_mut(), &waker),
+            Poll::Ready(Err(BleError::Busy))
+        ));
+
+        // the cancel command and the connection complete event are both read by the connecting task
+        let mut cancel = pin!(ble.cancel_connect());
+        assert!(poll(cancel.as_mut(), &cancel_waker).is_pending());
+        cpu2_answer(|| {
+            command_complete(HciOpcode::LeCreateConnectionCancel, &[]);
+            connection_complete_event(consts::HCI_UNKNOWN_CONNECTION_ID, 0x0000);
+        });
+        assert!(matches!(
+            poll(connect.as_mut(), &waker),
+            Poll::Ready(Err(BleError::Status(consts::HCI_UNKNOWN_CONNECTION_ID)))
+        ));
+        assert!(matches!(poll(cancel.as_mut(), &cancel_waker), Poll::Ready(Ok(()))));
+        assert!(ble.poll().is_none());
+    }
+
+    #[test]
+    fn dropped_connect_cancels() {
+        let (_mailbox, ble) = setup();
+        let (_, waker) = counting_waker();
+        let dropped = mm::MemoryManager::stats().dropped_events;
+
+        let mut connect = Box::pin(ble.connect(PeerAddressType::Public, &PEER, &PARAMS));
+        assert!(poll(connect.as_mut(), &waker).is_pending());
+        cpu2_answer(|| command_complete(HciOpcode::LeSetScanEnable, &[]));
+        assert!(poll(connect.as_mut(), &waker).is_pending());
+        cpu2_answer(|| command_status(HciOpcode::LeCreateConnection));
+        assert!(poll(connect.as_mut(), &waker).is_pending());
+        assert_eq!(MockIpcc::sent(channels::cpu1::IPCC_BLE_CMD_CHANNEL), 2);
+
+        drop(connect);
+        assert_eq!(MockIpcc::sent(channels::cpu1::IPCC_BLE_CMD_CHANNEL), 3);
+        assert_eq!(
+            COMMAND_SLOT.in_flight(),
+            Some(HciOpcode::LeCreateConnectionCancel as u16)
+        );
+
+        // the answer to the cancel command is dropped, the end of the attempt goes to the application
+        cpu2_answer(|| {
+            command_complete(HciOpcode::LeCreateConnectionCancel, &[]);
+            connection_complete_event(consts::HCI_UNKNOWN_CONNECTION_ID, 0x0000);
+        });
+        let evt_box = ble.poll().unwrap();
+        assert!(ConnectionComplete::parse(evt_box.stub().evt_code, evt_box.payload()).is_some());
+        assert!(!COMMAND_SLOT.is_in_flight());
+        assert_eq!(mm::MemoryManager::stats().dropped_events, dropped + 1);
+    }
+
+    #[test]
+    fn update_ended_by_disconnection() {
+        let (_mailbox, ble) = setup();
+        let (_, waker) = counting_waker();
+
+        let mut update = pin!(ble.update_connection_params(0x0001, &PARAMS));
+        assert!(poll(update.as_mut(), &waker).is_pending());
+        cpu2_answer(|| command_status(HciOpcode::LeConnectionUpdate));
+        assert!(poll(update.as_mut(), &waker).is_pending());
+
+        // the peer closes the connection (Remote User Terminated Connection) before the update completes
+        queue_event(0x05, &[0x00, 0x01, 0x00, 0x13]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert!(matches!(
+            poll(update.as_mut(), &waker),
+            Poll::Ready(Err(BleError::Status(0x13)))
+        ));
+
+        // the disconnection still goes to the application
+        let evt_box = ble.poll().unwrap();
+        assert!(DisconnectionComplete::parse(evt_box.stub().evt_code, evt_box.payload()).is_some());
+    }
+
+    #[test]
+    fn acl_credits() {
+        let (_mailbox, ble) = setup();
+        let (_, waker) = counting_waker();
+        let (woken, waker_c) = counting_waker();
+
+        ACL_CREDITS.reset(2);
+
+        for _ in 0..2 {
+            assert!(poll(pin!(ble.acl_write(0x0001, &[1, 2, 3])), &waker).is_ready());
+            assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL));
+        }
+        assert_eq!(ble.acl_credits(), 0);
+
+        let mut write = pin!(ble.acl_write(0x0001, &[4, 5, 6]));
+        assert!(poll(write.as_mut(), &waker_c).is_pending());
+        assert_eq!(MockIpcc::sent(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL), 2);
+
+        // Number Of Completed Packets: 2 packets on connection 1, read by the waiting write
+        queue_event(0x13, &[1, 0x01, 0x00, 0x02, 0x00]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert_eq!(woken.0.load(Ordering::Relaxed), 1);
+
+        assert!(poll(write.as_mut(), &waker_c).is_ready());
+        assert_eq!(ble.completed_packets(0x0001), 2);
+        assert_eq!(ble.acl_credits(), 1);
+        assert_eq!(MockIpcc::sent(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL), 3);
+        assert!(ble.poll().is_some());
+    }
+
+    #[test]
+    fn disconnection_returns_acl_credits() {
+        let (_mailbox, ble) = setup();
+        let (_, waker) = counting_waker();
+        let (woken_a, waker_a) = counting_waker();
+        let (woken_b, waker_b) = counting_waker();
+
+        ACL_CREDITS.reset(2);
+
+        for _ in 0..2 {
+            assert!(poll(pin!(ble.acl_write(0x0001, &[1, 2, 3])), &waker).is_ready());
+            assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL));
+        }
+
+        // both writes wait, the first one reading the events
+        let mut a = pin!(ble.acl_write(0x0002, &[4]));
+        let mut b = pin!(ble.acl_write(0x0002, &[5]));
+        assert!(poll(a.as_mut(), &waker_a).is_pending());
+        assert!(poll(b.as_mut(), &waker_b).is_pending());
+
+        // connection 1 closes before its packets are reported completed
+        queue_event(0x05, &[0x00, 0x01, 0x00, 0x13]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        assert_eq!(woken_a.0.load(Ordering::Relaxed), 1);
+        assert!(poll(a.as_mut(), &waker_a).is_ready());
+        assert!(woken_b.0.load(Ordering::Relaxed) >= 1);
+        assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL));
+        assert!(poll(b.as_mut(), &waker_b).is_ready());
+        assert_eq!(ble.acl_credits(), 0);
+        assert_eq!(MockIpcc::sent(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL), 4);
+
+        // the packets of the connection still open complete as usual
+        assert!(MockIpcc::cpu2_read(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL));
+        queue_event(0x13, &[1, 0x02, 0x00, 0x02, 0x00]);
+        MockIpcc::cpu2_notify(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
+        while ble.poll().is_some() {}
+        assert_eq!(ble.acl_credits(), 2);
     }
 }
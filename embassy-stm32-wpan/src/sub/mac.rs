@@ -4,15 +4,17 @@ use core::sync::atomic::{AtomicBool, Ordering};
 use core::task::Poll;
 
 use embassy_futures::poll_once;
-use embassy_stm32::ipcc::Ipcc;
+use embassy_futures::select::{select, Either};
 use embassy_sync::waitqueue::AtomicWaker;
 
 use crate::cmd::CmdPacket;
 use crate::consts::TlPacketType;
 use crate::evt::{EvtBox, EvtPacket};
+use crate::ipcc::{Ipcc, IpccOps};
 use crate::mac::commands::MacCommand;
 use crate::mac::event::MacEvent;
 use crate::mac::typedefs::MacError;
+use crate::sub::sys;
 use crate::tables::{MAC_802_15_4_CMD_BUFFER, MAC_802_15_4_NOTIF_RSP_EVT_BUFFER};
 use crate::{channels, evt};
 
@@ -28,6 +30,11 @@ impl Mac {
         Self { _private: () }
     }
 
+    /// Whether the application holds the event returned by [`Mac::tl_read`]
+    pub(crate) fn is_event_held() -> bool {
+        MAC_EVT_OUT.load(Ordering::SeqCst)
+    }
+
     /// `HW_IPCC_MAC_802_15_4_EvtNot`
     ///
     /// This function will stall if the previous `EvtBox` has not been dropped
@@ -80,11 +87,18 @@ impl Mac {
         .await;
     }
 
+    /// Send a command and check its status, failing with [`MacError::Reset`] if CPU2 restarts meanwhile
     pub async fn send_command<T>(&self, cmd: &T) -> Result<(), MacError>
     where
         T: MacCommand,
     {
-        let response = self.tl_write_and_get_response(T::OPCODE as u16, cmd.payload()).await;
+        let generation = sys::restart_generation();
+        let command = self.tl_write_and_get_response(T::OPCODE as u16, cmd.payload());
+
+        let response = match select(sys::restarted_since(generation), command).await {
+            Either::First(()) => return Err(MacError::Reset),
+            Either::Second(response) => response,
+        };
 
         if response == 0x00 {
             Ok(())
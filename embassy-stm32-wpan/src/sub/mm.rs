@@ -1,24 +1,94 @@
 //! Memory manager routines
 use core::future::poll_fn;
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use core::task::Poll;
 
 use aligned::{Aligned, A4};
-use cortex_m::interrupt;
-use embassy_stm32::ipcc::Ipcc;
+use embassy_futures::poll_once;
 use embassy_sync::waitqueue::AtomicWaker;
 
+use crate::consts::CFG_TL_BLE_EVT_QUEUE_LENGTH;
+#[cfg(any(feature = "ble", feature = "mac", feature = "zigbee"))]
 use crate::consts::POOL_SIZE;
-use crate::evt::EvtPacket;
+use crate::evt::{EvtBox, EvtPacket};
+use crate::ipcc::{Ipcc, IpccOps};
 #[cfg(feature = "ble")]
 use crate::tables::BLE_SPARE_EVT_BUF;
-use crate::tables::{MemManagerTable, EVT_POOL, FREE_BUF_QUEUE, SYS_SPARE_EVT_BUF, TL_MEM_MANAGER_TABLE};
+#[cfg(any(feature = "ble", feature = "mac", feature = "zigbee"))]
+use crate::tables::EVT_POOL;
+use crate::tables::{MemManagerTable, FREE_BUF_QUEUE, SYS_SPARE_EVT_BUF, TL_MEM_MANAGER_TABLE};
 use crate::unsafe_linked_list::LinkedListNode;
 use crate::{channels, evt};
 
 static MM_WAKER: AtomicWaker = AtomicWaker::new();
 static mut LOCAL_FREE_BUF_QUEUE: Aligned<A4, MaybeUninit<LinkedListNode>> = Aligned(MaybeUninit::uninit());
 
+/// Number of event buffers handed to the application and not released yet.
+///
+/// Every event buffer taken from a CPU2 queue must eventually go through `drop_event_packet`, which is what
+/// `EvtBox` does on drop. A buffer whose `EvtBox` is leaked (e.g. with `core::mem::forget`) is never given back
+/// to CPU2, and once the pool is exhausted CPU2 stops reporting events.
+static OUTSTANDING_BUFFERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Largest value reached by [`OUTSTANDING_BUFFERS`] since boot or the last [`MemoryManager::reset_stats`]
+static PEAK_OUTSTANDING_BUFFERS: AtomicUsize = AtomicUsize::new(0);
+/// Events in the BLE event queue when it was last read, and the largest such value
+///
+/// Both saturate at [`MAX_COUNTED_EVENTS`].
+static EVENT_QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+static PEAK_EVENT_QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+/// Events read by the crate while waiting for another one, and discarded
+static DROPPED_EVENTS: AtomicUsize = AtomicUsize::new(0);
+/// Received ACL data packets waiting to be read when the last one was queued, and the largest such value
+static ACL_RX_QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+static PEAK_ACL_RX_QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+/// Received ACL data packets discarded because their queue was full
+static DROPPED_ACL_PACKETS: AtomicUsize = AtomicUsize::new(0);
+
+/// Events counted in the BLE event queue on each read, so that counting stays cheap
+#[cfg(feature = "ble")]
+pub(crate) const MAX_COUNTED_EVENTS: usize = 16;
+
+/// The BLE and system spare buffers can be handed out on top of the pool
+const SPARE_EVT_BUFFERS: usize = 2;
+
+/// Event buffer pool usage
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PoolUsage {
+    /// Event buffers held by the application
+    pub outstanding: usize,
+    /// Number of event buffers in the pool
+    pub pool_size: usize,
+}
+
+/// Occupancy of the mailbox queues and buffers, to size them from what is observed at runtime
+///
+/// Peaks are kept since boot or the last [`TlMbox::reset_stats`](crate::TlMbox::reset_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MailboxStats {
+    /// Events waiting in the BLE event queue when it was last read, including the event read
+    ///
+    /// The count stops at 16, so a deeper queue is reported as 16 events.
+    pub event_queue_depth: usize,
+    /// Largest number of events found in the BLE event queue when reading it, also capped at 16
+    pub peak_event_queue_depth: usize,
+    /// Event buffers held by the application
+    pub outstanding_buffers: usize,
+    /// Largest number of event buffers held by the application at once
+    pub peak_outstanding_buffers: usize,
+    /// Events discarded by the BLE helpers while waiting for the event answering them
+    pub dropped_events: usize,
+    /// Received ACL data packets waiting to be read when the last one was queued, including that one
+    pub acl_rx_queue_depth: usize,
+    /// Largest number of received ACL data packets waiting to be read at once
+    pub peak_acl_rx_queue_depth: usize,
+    /// Received ACL data packets discarded because they were not read fast enough
+    pub dropped_acl_packets: usize,
+}
+
 pub struct MemoryManager {
     _private: (),
 }
@@ -35,8 +105,14 @@ impl MemoryManager {
                 #[cfg(not(feature = "ble"))]
                 spare_ble_buffer: core::ptr::null(),
                 spare_sys_buffer: SYS_SPARE_EVT_BUF.as_ptr().cast(),
+                #[cfg(any(feature = "ble", feature = "mac", feature = "zigbee"))]
                 blepool: EVT_POOL.as_ptr().cast(),
+                #[cfg(any(feature = "ble", feature = "mac", feature = "zigbee"))]
                 blepoolsize: POOL_SIZE as u32,
+                #[cfg(not(any(feature = "ble", feature = "mac", feature = "zigbee")))]
+                blepool: core::ptr::null(),
+                #[cfg(not(any(feature = "ble", feature = "mac", feature = "zigbee")))]
+                blepoolsize: 0,
                 pevt_free_buffer_queue: FREE_BUF_QUEUE.as_mut_ptr(),
                 traces_evt_pool: core::ptr::null(),
                 tracespoolsize: 0,
@@ -46,6 +122,94 @@ impl MemoryManager {
         Self { _private: () }
     }
 
+    /// Handle to the event pool of a CPU2 that kept running, leaving the free buffer queues as they are
+    pub(crate) fn attach() -> Self {
+        Self { _private: () }
+    }
+
+    /// Returns the number of event buffers currently held by the application
+    pub fn usage() -> PoolUsage {
+        PoolUsage {
+            outstanding: OUTSTANDING_BUFFERS.load(Ordering::Relaxed),
+            pool_size: CFG_TL_BLE_EVT_QUEUE_LENGTH,
+        }
+    }
+
+    /// Occupancy of the queues and buffers, see [`MailboxStats`]
+    pub fn stats() -> MailboxStats {
+        MailboxStats {
+            event_queue_depth: EVENT_QUEUE_DEPTH.load(Ordering::Relaxed),
+            peak_event_queue_depth: PEAK_EVENT_QUEUE_DEPTH.load(Ordering::Relaxed),
+            outstanding_buffers: OUTSTANDING_BUFFERS.load(Ordering::Relaxed),
+            peak_outstanding_buffers: PEAK_OUTSTANDING_BUFFERS.load(Ordering::Relaxed),
+            dropped_events: DROPPED_EVENTS.load(Ordering::Relaxed),
+            acl_rx_queue_depth: ACL_RX_QUEUE_DEPTH.load(Ordering::Relaxed),
+            peak_acl_rx_queue_depth: PEAK_ACL_RX_QUEUE_DEPTH.load(Ordering::Relaxed),
+            dropped_acl_packets: DROPPED_ACL_PACKETS.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Restart the peaks from the current values and clear the counts of dropped events and packets
+    pub fn reset_stats() {
+        PEAK_EVENT_QUEUE_DEPTH.store(EVENT_QUEUE_DEPTH.load(Ordering::Relaxed), Ordering::Relaxed);
+        PEAK_OUTSTANDING_BUFFERS.store(OUTSTANDING_BUFFERS.load(Ordering::Relaxed), Ordering::Relaxed);
+        PEAK_ACL_RX_QUEUE_DEPTH.store(ACL_RX_QUEUE_DEPTH.load(Ordering::Relaxed), Ordering::Relaxed);
+        DROPPED_EVENTS.store(0, Ordering::Relaxed);
+        DROPPED_ACL_PACKETS.store(0, Ordering::Relaxed);
+    }
+
+    /// Record the number of events found in the BLE event queue when reading it
+    #[cfg(feature = "ble")]
+    pub(crate) fn track_event_queue_depth(depth: usize) {
+        EVENT_QUEUE_DEPTH.store(depth, Ordering::Relaxed);
+        PEAK_EVENT_QUEUE_DEPTH.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    /// Account for an event read and discarded by the crate
+    #[cfg(feature = "ble")]
+    pub(crate) fn track_dropped_event() {
+        DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the number of received ACL data packets waiting to be read
+    #[cfg(feature = "ble")]
+    pub(crate) fn track_acl_rx_queue_depth(depth: usize) {
+        ACL_RX_QUEUE_DEPTH.store(depth, Ordering::Relaxed);
+        PEAK_ACL_RX_QUEUE_DEPTH.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    /// Account for a received ACL data packet discarded for lack of room in its queue
+    #[cfg(feature = "ble")]
+    pub(crate) fn track_dropped_acl_packet() {
+        DROPPED_ACL_PACKETS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Account for an event buffer handed to the application
+    pub(crate) fn track_event_packet() {
+        let outstanding = OUTSTANDING_BUFFERS.fetch_add(1, Ordering::Relaxed) + 1;
+        PEAK_OUTSTANDING_BUFFERS.fetch_max(outstanding, Ordering::Relaxed);
+
+        if outstanding > CFG_TL_BLE_EVT_QUEUE_LENGTH {
+            warn!(
+                "mm: {} event buffers outstanding for a pool of {}, an EvtBox may have been leaked",
+                outstanding, CFG_TL_BLE_EVT_QUEUE_LENGTH
+            );
+        }
+
+        debug_assert!(
+            outstanding <= CFG_TL_BLE_EVT_QUEUE_LENGTH + SPARE_EVT_BUFFERS,
+            "more event buffers outstanding than CPU2 can allocate"
+        );
+    }
+
+    /// Give the dropped event buffers back to CPU2, if the release channel is free
+    ///
+    /// This does what [`MemoryManager::run_queue`] does, without waiting: an application not running
+    /// `run_queue` in a task must call this regularly, or CPU2 runs out of event buffers.
+    pub fn poll(&self) {
+        let _ = poll_once(self.run_queue());
+    }
+
     pub async fn run_queue(&self) {
         loop {
             poll_fn(|cx| unsafe {
@@ -58,26 +222,87 @@ impl MemoryManager {
             })
             .await;
 
-            Ipcc::send(channels::cpu1::IPCC_MM_RELEASE_BUFFER_CHANNEL, || {
-                interrupt::free(|_| unsafe {
-                    // CS required while moving nodes
-                    while let Some(node_ptr) = LinkedListNode::remove_head(LOCAL_FREE_BUF_QUEUE.as_mut_ptr()) {
-                        LinkedListNode::insert_head(FREE_BUF_QUEUE.as_mut_ptr(), node_ptr);
-                    }
-                })
-            })
-            .await;
+            self.send_released_buffers().await;
         }
     }
+
+    /// Release an event buffer and give it back to CPU2 at once
+    ///
+    /// Dropping an [`EvtBox`] only queues its buffer locally, and the buffer reaches CPU2 on the next
+    /// pass of [`MemoryManager::run_queue`] or [`MemoryManager::poll`]. This drops `evt` and signals the
+    /// release channel right away, for applications that need the buffer back in the pool promptly.
+    ///
+    /// `evt` is consumed, so its buffer is released once: the drop handler of `T` does the release, and
+    /// buffers which are not taken from the pool, such as command complete events, are left alone as
+    /// when dropping it. The other buffers already dropped are given back as well.
+    pub async fn release_event_buffer<T: evt::MemoryManager>(&self, evt: EvtBox<T>) {
+        drop(evt);
+
+        self.send_released_buffers().await;
+    }
+
+    /// Number of event buffers dropped by the application and not given back to CPU2 yet
+    pub fn pending_releases(&self) -> usize {
+        unsafe { LinkedListNode::get_size(LOCAL_FREE_BUF_QUEUE.as_mut_ptr()) }
+    }
+
+    async fn send_released_buffers(&self) {
+        Ipcc::send(channels::cpu1::IPCC_MM_RELEASE_BUFFER_CHANNEL, || unsafe {
+            release_buffers(LOCAL_FREE_BUF_QUEUE.as_mut_ptr(), FREE_BUF_QUEUE.as_mut_ptr());
+        })
+        .await;
+    }
+}
+
+/// Move the buffers dropped by the application to the free buffer queue read by CPU2
+///
+/// Each node is moved in its own critical section rather than the whole queue at once, to keep the
+/// interrupt latency independent of the number of buffers. CPU2 does not access the free buffer queue
+/// until the IPCC channel is set, and a buffer dropped meanwhile is either moved now or at the next run.
+///
+/// SAFETY: both pointers must be initialized list heads
+unsafe fn release_buffers(local_queue: *mut LinkedListNode, free_queue: *mut LinkedListNode) {
+    while let Some(node_ptr) = LinkedListNode::remove_head(local_queue) {
+        LinkedListNode::insert_head(free_queue, node_ptr);
+    }
 }
 
 impl evt::MemoryManager for MemoryManager {
     /// SAFETY: passing a pointer to something other than a managed event packet is UB
     unsafe fn drop_event_packet(evt: *mut EvtPacket) {
-        interrupt::free(|_| unsafe {
-            LinkedListNode::insert_head(LOCAL_FREE_BUF_QUEUE.as_mut_ptr(), evt as *mut _);
-        });
+        LinkedListNode::insert_head(LOCAL_FREE_BUF_QUEUE.as_mut_ptr(), evt as *mut _);
+
+        OUTSTANDING_BUFFERS.fetch_sub(1, Ordering::Relaxed);
 
         MM_WAKER.wake();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_all_buffers() {
+        let mut local_queue = LinkedListNode::default();
+        let mut free_queue = LinkedListNode::default();
+        let mut buffers = [LinkedListNode::default(); 3];
+
+        unsafe {
+            LinkedListNode::init_head(&mut local_queue);
+            LinkedListNode::init_head(&mut free_queue);
+            for buffer in buffers.iter_mut() {
+                LinkedListNode::insert_head(&mut local_queue, buffer);
+            }
+
+            release_buffers(&mut local_queue, &mut free_queue);
+
+            assert!(LinkedListNode::is_empty(&mut local_queue));
+            assert_eq!(LinkedListNode::get_size(&mut free_queue), buffers.len());
+
+            // Releasing again is a no-op
+            release_buffers(&mut local_queue, &mut free_queue);
+            assert_eq!(LinkedListNode::get_size(&mut free_queue), buffers.len());
+        }
+    }
+}
@@ -4,3 +4,7 @@ pub mod ble;
 pub mod mac;
 pub mod mm;
 pub mod sys;
+#[cfg(feature = "traces")]
+pub mod traces;
+#[cfg(feature = "zigbee")]
+pub mod zigbee;
@@ -1,14 +1,137 @@
 use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::task::Poll;
+
+use embassy_futures::poll_once;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
 
 use crate::cmd::CmdPacket;
 use crate::consts::TlPacketType;
+use crate::error::Error;
 use crate::evt::{CcEvt, EvtBox, EvtPacket};
+use crate::ipcc::{Ipcc, IpccOps};
 #[allow(unused_imports)]
-use crate::shci::{SchiCommandStatus, ShciBleInitCmdParam, ShciOpcode};
+use crate::shci::{FlashActivityControl, SchiCommandStatus, ShciBleInitCmdParam, ShciOpcode, SysErrorCode, SysEvent};
 use crate::sub::mm;
-use crate::tables::{SysTable, WirelessFwInfoTable};
+use crate::tables::{DeviceInfoTable, RssInfoTable, SafeBootInfoTable, SysTable, WirelessFwInfoTable};
 use crate::unsafe_linked_list::LinkedListNode;
-use crate::{channels, Ipcc, SYSTEM_EVT_QUEUE, SYS_CMD_BUF, TL_DEVICE_INFO_TABLE, TL_SYS_TABLE};
+use crate::wait_queue::WaitQueue;
+use crate::{channels, SYSTEM_EVT_QUEUE, SYS_CMD_BUF, TL_DEVICE_INFO_TABLE, TL_SYS_TABLE};
+
+/// State of CPU2, as seen from its device information table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoprocessorState {
+    /// CPU2 has not filled the device information table yet, it may still be booting
+    NotStarted,
+    /// CPU2 runs the firmware upgrade service, but no wireless stack is installed or started
+    FusOnly,
+    /// CPU2 runs the wireless stack
+    WirelessReady,
+}
+
+/// Image started by CPU2, as reported by its ready event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RunningImage {
+    /// The firmware upgrade service
+    Fus,
+    /// The wireless stack, which accepts the initialization commands such as `SHCI_C2_BLE_Init`
+    WirelessStack,
+}
+
+/// Last error reported by CPU2 and not yet returned by [`Sys::wait_error`]
+static SYS_ERROR: Signal<CriticalSectionRawMutex, SysErrorCode> = Signal::new();
+
+/// Held from writing a system command until its response is read from the shared command buffer
+///
+/// CPU2 answers in place, so a command sent by another task meanwhile would overwrite the response. The
+/// interrupt handlers do not take it, they only wake the task waiting on the channel.
+static SYS_CMD_MUTEX: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+
+/// A [`FlashWindow`] was dropped and CPU2 has not been told yet that the flash erase is over
+static FLASH_ERASE_END_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// CPU2 reported that the wireless stack is running, so that another ready event means that it restarted
+static STACK_RUNNING: AtomicBool = AtomicBool::new(false);
+/// Number of times CPU2 restarted on its own, the commands sent before a restart are never answered
+static RESTART_GENERATION: AtomicU32 = AtomicU32::new(0);
+/// CPU2 restarted and [`TlMbox::recover`](crate::TlMbox::recover) has not reinitialized the mailbox yet
+static RESTART_PENDING: AtomicBool = AtomicBool::new(false);
+/// Tasks waiting for a command answer on any subsystem, or for the restart itself
+static RESTART_WAITERS: WaitQueue = WaitQueue::new();
+
+/// Generation to give to [`restarted_since`] before sending a command
+pub(crate) fn restart_generation() -> u32 {
+    RESTART_GENERATION.load(Ordering::SeqCst)
+}
+
+/// Wait until CPU2 restarts after `generation`, to fail the command sent meanwhile
+pub(crate) async fn restarted_since(generation: u32) {
+    wait_restart_until(|| RESTART_GENERATION.load(Ordering::SeqCst) != generation).await
+}
+
+/// CPU2 has restarted and the mailbox has not been recovered yet
+pub(crate) fn is_restart_pending() -> bool {
+    RESTART_PENDING.load(Ordering::SeqCst)
+}
+
+/// The mailbox has been recovered, the next ready event of a running stack is a new restart
+pub(crate) fn restart_recovered() {
+    RESTART_PENDING.store(false, Ordering::SeqCst);
+}
+
+async fn wait_restart_until(done: impl Fn() -> bool) {
+    RESTART_WAITERS.wait_until(done).await
+}
+
+/// Fail the commands waiting for an answer and let [`TlMbox::recover`](crate::TlMbox::recover) proceed
+fn restarted() {
+    RESTART_PENDING.store(true, Ordering::SeqCst);
+    RESTART_GENERATION.fetch_add(1, Ordering::SeqCst);
+    RESTART_WAITERS.wake_all();
+}
+
+/// Flash erase window opened with [`Sys::acquire_flash_window`], closed when dropped
+///
+/// While the window is open, CPU2 keeps its radio activity away from the flash being erased, which
+/// degrades the connections: the window must be held for the flash operation only. Dropping the window
+/// tells CPU2 that the erase is over, right away if no other system command is in flight, or before the
+/// next system command otherwise. [`FlashWindow::release`] waits for CPU2 to acknowledge it.
+#[must_use = "the window is closed as soon as it is dropped"]
+pub struct FlashWindow<'a> {
+    sys: &'a Sys,
+}
+
+impl<'a> FlashWindow<'a> {
+    /// Close the window and wait for CPU2 to acknowledge it
+    ///
+    /// A status other than [`SchiCommandStatus::ShciSuccess`] is returned as [`Error::Shci`].
+    pub async fn release(self) -> Result<(), Error> {
+        let sys = self.sys;
+        FLASH_ERASE_END_PENDING.store(true, Ordering::Relaxed);
+        core::mem::forget(self);
+
+        let _guard = SYS_CMD_MUTEX.lock().await;
+        sys.send_pending_flash_erase_end().await;
+        Ipcc::flush(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL).await;
+
+        sys.command_status()?.result()
+    }
+}
+
+impl Drop for FlashWindow<'_> {
+    fn drop(&mut self) {
+        FLASH_ERASE_END_PENDING.store(true, Ordering::Relaxed);
+        // a task in the middle of a command sends it before its next one
+        if let Ok(_guard) = SYS_CMD_MUTEX.try_lock() {
+            let _ = poll_once(self.sys.send_pending_flash_erase_end());
+        }
+    }
+}
 
 pub struct Sys {
     _private: (),
@@ -29,9 +152,20 @@ impl Sys {
         Self { _private: () }
     }
 
+    /// Handle to the system channel of a CPU2 that kept running, leaving its table and event queue as they are
+    pub(crate) fn attach() -> Self {
+        // the ready event was read before CPU1 restarted, the next one comes from a restart of CPU2
+        STACK_RUNNING.store(true, Ordering::SeqCst);
+
+        Self { _private: () }
+    }
+
     /// Returns CPU2 wireless firmware information (if present).
+    ///
+    /// `None` does not mean that the chip is broken: use [`Sys::coprocessor_state`] to tell whether CPU2
+    /// has not started yet or runs without a wireless stack.
     pub fn wireless_fw_info(&self) -> Option<WirelessFwInfoTable> {
-        let info = unsafe { TL_DEVICE_INFO_TABLE.as_mut_ptr().read_volatile().wireless_fw_info_table };
+        let info = Self::device_info_table().wireless_fw_info_table;
 
         // Zero version indicates that CPU2 wasn't active and didn't fill the information table
         if info.version != 0 {
@@ -41,7 +175,94 @@ impl Sys {
         }
     }
 
+    /// Wait for CPU2 to fill its wireless firmware information, without the async machinery
+    ///
+    /// This spins for up to about `timeout_cycles` CPU cycles, so that `main` can check that CPU2 is alive
+    /// and print its firmware version before the executor runs. Returns `None` on timeout, in the same
+    /// cases as [`Sys::wireless_fw_info`].
+    pub fn wait_wireless_fw_info(&self, timeout_cycles: u32) -> Option<WirelessFwInfoTable> {
+        const POLL_CYCLES: u32 = 1_000;
+
+        let mut remaining = timeout_cycles;
+        loop {
+            if let Some(info) = self.wireless_fw_info() {
+                return Some(info);
+            }
+            if remaining == 0 {
+                return None;
+            }
+
+            // the delay is an assembly block, which also makes the compiler read the table again
+            let cycles = remaining.min(POLL_CYCLES);
+            cortex_m::asm::delay(cycles);
+            remaining -= cycles;
+        }
+    }
+
+    /// Returns the version information of the firmware upgrade service, if CPU2 has started
+    pub fn fus_info(&self) -> Option<RssInfoTable> {
+        let info = Self::device_info_table().rss_info_table;
+
+        if info.version != 0 {
+            Some(info)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the version information of the safe boot, if CPU2 has started
+    ///
+    /// This is the version checked by ST's secure firmware update flow before upgrading the FUS.
+    pub fn safe_boot_info(&self) -> Option<SafeBootInfoTable> {
+        let info = Self::device_info_table().safe_boot_info_table;
+
+        if info.version() != 0 {
+            Some(info)
+        } else {
+            None
+        }
+    }
+
+    /// Returns what CPU2 is running, according to the device information table it fills when booting
+    pub fn coprocessor_state(&self) -> CoprocessorState {
+        let table = Self::device_info_table();
+        let wireless_version = table.wireless_fw_info_table.version;
+        let fus_version = table.rss_info_table.version;
+
+        if wireless_version != 0 {
+            CoprocessorState::WirelessReady
+        } else if fus_version != 0 {
+            CoprocessorState::FusOnly
+        } else {
+            CoprocessorState::NotStarted
+        }
+    }
+
+    /// Returns the 64-bit unique device identifier `UID64`
+    ///
+    /// The low word is the unique device number, and the high word the device type followed by the ST
+    /// company ID. Unlike the information tables, it is readable before CPU2 starts.
+    pub fn unique_id(&self) -> u64 {
+        crate::lhci::read_uid64()
+    }
+
+    fn device_info_table() -> DeviceInfoTable {
+        // The fields of the information tables are packed, read the whole table without referencing them
+        unsafe { ptr::read_unaligned(TL_DEVICE_INFO_TABLE.as_ptr()) }
+    }
+
+    /// Send a system command without waiting for its response
+    ///
+    /// The system commands of all the tasks are serialized: this waits for the command in flight, if any,
+    /// to be answered.
     pub async fn write(&self, opcode: ShciOpcode, payload: &[u8]) {
+        let _guard = SYS_CMD_MUTEX.lock().await;
+        self.send_command(opcode, payload).await;
+    }
+
+    async fn send_command(&self, opcode: ShciOpcode, payload: &[u8]) {
+        self.send_pending_flash_erase_end().await;
+
         Ipcc::send(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL, || unsafe {
             CmdPacket::write_into(SYS_CMD_BUF.as_mut_ptr(), TlPacketType::SysCmd, opcode as u16, payload);
         })
@@ -49,32 +270,82 @@ impl Sys {
     }
 
     /// `HW_IPCC_SYS_CmdEvtNot`
-    pub async fn write_and_get_response(&self, opcode: ShciOpcode, payload: &[u8]) -> Result<SchiCommandStatus, ()> {
-        self.write(opcode, payload).await;
-        Ipcc::flush(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL).await;
+    ///
+    /// Several tasks can send system commands: each command waits for the previous one to be answered.
+    /// A status which is not a known [`SchiCommandStatus`] is returned as [`Error::Shci`], and
+    /// [`Error::Reset`] is returned if CPU2 restarts before answering.
+    pub async fn write_and_get_response(&self, opcode: ShciOpcode, payload: &[u8]) -> Result<SchiCommandStatus, Error> {
+        let generation = restart_generation();
 
-        unsafe {
+        let command = async {
+            let _guard = SYS_CMD_MUTEX.lock().await;
+            self.send_command(opcode, payload).await;
+            Ipcc::flush(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL).await;
+
+            self.command_status()
+        };
+
+        // checked first: once the IPCC is reset, the command channel looks free and the buffer answered
+        match select(restarted_since(generation), command).await {
+            Either::First(()) => Err(Error::Reset),
+            Either::Second(result) => result,
+        }
+    }
+
+    /// Status of the last command, once CPU2 has written its response in the command buffer
+    fn command_status(&self) -> Result<SchiCommandStatus, Error> {
+        let status = unsafe {
             let p_event_packet = SYS_CMD_BUF.as_ptr() as *const EvtPacket;
             let p_command_event = &((*p_event_packet).evt_serial.evt.payload) as *const _ as *const CcEvt;
             let p_payload = &((*p_command_event).payload) as *const u8;
 
-            ptr::read_volatile(p_payload).try_into()
+            ptr::read_volatile(p_payload)
+        };
+
+        status.try_into().map_err(|_| Error::Shci(status))
+    }
+
+    /// `SHCI_C2_FUS_StartWs`
+    ///
+    /// Ask the firmware upgrade service to reboot CPU2 into the installed wireless stack, and wait for
+    /// the stack to report that it is ready. [`Error::NotStarted`] is returned if CPU2 came back running
+    /// the firmware upgrade service, e.g. because no valid stack is installed.
+    ///
+    /// CPU2 fills the device information table again when it reboots: the values read before this
+    /// command, such as [`Sys::wireless_fw_info`], are stale and must be read again once it returns.
+    /// The system events must not be read by another task meanwhile, see [`Sys::wait_ready`].
+    pub async fn start_wireless_stack(&self) -> Result<SchiCommandStatus, Error> {
+        let status = self
+            .write_and_get_response(ShciOpcode::FusStartWirelessStack, &[])
+            .await?;
+        if !matches!(status, SchiCommandStatus::ShciSuccess) {
+            return Ok(status);
+        }
+
+        match self.wait_ready().await {
+            RunningImage::WirelessStack => Ok(status),
+            RunningImage::Fus => Err(Error::NotStarted),
         }
     }
 
     #[cfg(feature = "mac")]
-    pub async fn shci_c2_mac_802_15_4_init(&self) -> Result<SchiCommandStatus, ()> {
+    pub async fn shci_c2_mac_802_15_4_init(&self) -> Result<SchiCommandStatus, Error> {
         use crate::tables::{
-            Mac802_15_4Table, TracesTable, MAC_802_15_4_CMD_BUFFER, MAC_802_15_4_NOTIF_RSP_EVT_BUFFER,
-            TL_MAC_802_15_4_TABLE, TL_TRACES_TABLE, TRACES_EVT_QUEUE,
+            Mac802_15_4Table, MAC_802_15_4_CMD_BUFFER, MAC_802_15_4_NOTIF_RSP_EVT_BUFFER, TL_MAC_802_15_4_TABLE,
         };
+        #[cfg(not(feature = "traces"))]
+        use crate::tables::{TracesTable, TL_TRACES_TABLE, TRACES_EVT_QUEUE};
 
         unsafe {
-            LinkedListNode::init_head(TRACES_EVT_QUEUE.as_mut_ptr() as *mut _);
+            // With the `traces` feature, the traces queue is set up with the mailbox and may hold traces already
+            #[cfg(not(feature = "traces"))]
+            {
+                LinkedListNode::init_head(TRACES_EVT_QUEUE.as_mut_ptr() as *mut _);
 
-            TL_TRACES_TABLE.as_mut_ptr().write_volatile(TracesTable {
-                traces_queue: TRACES_EVT_QUEUE.as_ptr() as *const _,
-            });
+                TL_TRACES_TABLE.as_mut_ptr().write_volatile(TracesTable {
+                    traces_queue: TRACES_EVT_QUEUE.as_ptr() as *const _,
+                });
+            }
 
             TL_MAC_802_15_4_TABLE.as_mut_ptr().write_volatile(Mac802_15_4Table {
                 p_cmdrsp_buffer: MAC_802_15_4_CMD_BUFFER.as_mut_ptr().cast(),
@@ -86,15 +357,102 @@ impl Sys {
         self.write_and_get_response(ShciOpcode::Mac802_15_4Init, &[]).await
     }
 
+    /// `SHCI_C2_ZIGBEE_Init`
+    #[cfg(feature = "zigbee")]
+    pub async fn shci_c2_zigbee_init(&self) -> Result<SchiCommandStatus, Error> {
+        self.write_and_get_response(ShciOpcode::ZigbeeInit, &[]).await
+    }
+
+    /// `SHCI_C2_BLE_Init`
+    ///
+    /// The command is not sent to CPU2 if the parameters are rejected by [`ShciBleInitCmdParam::validate`],
+    /// which returns [`Error::InvalidParameters`], or if the wireless stack has not reported being ready,
+    /// which returns [`Error::NotReady`]: CPU2 would drop the command and never start the stack. Wait for
+    /// [`Sys::wait_ready`] to return [`RunningImage::WirelessStack`] before calling this, and start the
+    /// wireless stack with [`Sys::start_wireless_stack`] if CPU2 runs the firmware upgrade service.
     #[cfg(feature = "ble")]
-    pub async fn shci_c2_ble_init(&self, param: ShciBleInitCmdParam) -> Result<SchiCommandStatus, ()> {
+    pub async fn shci_c2_ble_init(&self, param: ShciBleInitCmdParam) -> Result<SchiCommandStatus, Error> {
+        if param.validate().is_err() {
+            error!("sys: inconsistent BLE init parameters");
+            return Err(Error::InvalidParameters);
+        }
+
+        if !STACK_RUNNING.load(Ordering::SeqCst) {
+            error!("sys: BLE init sent before the wireless stack reported being ready");
+            return Err(Error::NotReady);
+        }
+
         self.write_and_get_response(ShciOpcode::BleInit, param.payload()).await
     }
 
+    /// `SHCI_C2_SetFlashActivityControl`
+    ///
+    /// Select how CPU2 signals its flash accesses. With [`FlashActivityControl::Sem7`], the application
+    /// must take semaphore 7 before erasing or writing the flash, e.g. to persist the BLE bonds reported
+    /// by a [`SysNvmEvent`](crate::shci::SysNvmEvent), and release it as soon as the operation is over.
+    pub async fn shci_c2_set_flash_activity_control(
+        &self,
+        control: FlashActivityControl,
+    ) -> Result<SchiCommandStatus, Error> {
+        self.write_and_get_response(ShciOpcode::SetFlashActivityControl, &[control as u8])
+            .await
+    }
+
+    /// `SHCI_C2_FLASH_EraseActivity`
+    ///
+    /// Tell CPU2 that the application is erasing the flash, so that it keeps the radio timings safe.
+    pub async fn shci_c2_flash_erase_activity(&self, erasing: bool) -> Result<SchiCommandStatus, Error> {
+        self.write_and_get_response(ShciOpcode::FlashEraseActivity, &[erasing as u8])
+            .await
+    }
+
+    /// Tell CPU2 that the application is about to erase the flash, and keep it informed until the
+    /// returned window is dropped
+    ///
+    /// This is [`Sys::shci_c2_flash_erase_activity`] with the end of the erase sent on drop, so that it
+    /// cannot be forgotten, e.g. on an early return. Hold the window for the erase only: the radio
+    /// activity of CPU2 is restricted meanwhile, and active connections may suffer from it.
+    ///
+    /// If CPU2 rejects the command, its status is returned as [`Error::Shci`] and no window is open.
+    pub async fn acquire_flash_window(&self) -> Result<FlashWindow<'_>, Error> {
+        self.shci_c2_flash_erase_activity(true).await?.result()?;
+
+        Ok(FlashWindow { sys: self })
+    }
+
+    /// Send the end of erase of a dropped [`FlashWindow`], if any
+    async fn send_pending_flash_erase_end(&self) {
+        Ipcc::flush(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL).await;
+
+        if FLASH_ERASE_END_PENDING.swap(false, Ordering::Relaxed) {
+            Ipcc::send(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL, || unsafe {
+                CmdPacket::write_into(
+                    SYS_CMD_BUF.as_mut_ptr(),
+                    TlPacketType::SysCmd,
+                    ShciOpcode::FlashEraseActivity as u16,
+                    &[0],
+                );
+            })
+            .await;
+        }
+    }
+
+    /// Head of the system event queue, for firmware whose events this crate cannot decode
+    ///
+    /// As for [`Ble::raw_ble_event_queue`](crate::sub::ble::Ble::raw_ble_event_queue), the nodes are
+    /// [`EvtPacket`]s which may only be read while the system event channel is signaled, and which belong
+    /// to the queue until [`Sys::read`] takes them.
+    #[cfg(feature = "unstable-internals")]
+    pub fn raw_sys_event_queue(&self) -> *const LinkedListNode {
+        unsafe { SYSTEM_EVT_QUEUE.as_ptr() }
+    }
+
     /// `HW_IPCC_SYS_EvtNot`
     pub async fn read(&self) -> EvtBox<mm::MemoryManager> {
         Ipcc::receive(channels::cpu2::IPCC_SYSTEM_EVENT_CHANNEL, || unsafe {
             if let Some(node_ptr) = LinkedListNode::remove_head(SYSTEM_EVT_QUEUE.as_mut_ptr()) {
+                mm::MemoryManager::track_event_packet();
+
                 Some(EvtBox::new(node_ptr.cast()))
             } else {
                 None
@@ -102,4 +460,72 @@ impl Sys {
         })
         .await
     }
+
+    /// Take the next system event if one is already queued, without waiting
+    pub fn poll(&self) -> Option<EvtBox<mm::MemoryManager>> {
+        match poll_once(self.read()) {
+            Poll::Ready(evt_box) => Some(evt_box),
+            Poll::Pending => None,
+        }
+    }
+
+    /// Wait for the next system event and decode it
+    ///
+    /// System events are delivered on their own IPCC channel, independently of the BLE events. The
+    /// event buffer is released before returning. Events that cannot be decoded are reported as
+    /// [`SysEvent::Other`].
+    ///
+    /// A [`SysEvent::CpuReady`] received while the wireless stack was running means that CPU2 restarted
+    /// on its own: the commands waiting for an answer on all the subsystems fail with a reset error, and
+    /// [`TlMbox::recover`](crate::TlMbox::recover) can bring the mailbox back.
+    pub async fn read_sys_event(&self) -> SysEvent {
+        let evt_box = self.read().await;
+        let stub = evt_box.stub();
+
+        let evt = SysEvent::parse(stub.evt_code, evt_box.payload()).unwrap_or_else(|| {
+            warn!("sys: undecoded event {:x}", stub.evt_code);
+
+            SysEvent::Other(0)
+        });
+
+        match evt {
+            SysEvent::ErrorNotif(code) => {
+                error!("sys: CPU2 reported {:?}", code);
+                SYS_ERROR.signal(code);
+            }
+            SysEvent::CpuReady => {
+                if STACK_RUNNING.swap(true, Ordering::SeqCst) {
+                    error!("sys: CPU2 restarted");
+                    restarted();
+                }
+            }
+            SysEvent::FusState => STACK_RUNNING.store(false, Ordering::SeqCst),
+            _ => {}
+        }
+
+        evt
+    }
+
+    /// Wait until CPU2 reports that it has started, and return the image it runs
+    ///
+    /// CPU2 sends a ready event once after booting, before which the initialization commands fail. The
+    /// system events received before it are discarded, except for the errors which are still reported to
+    /// [`Sys::wait_error`]. This must not run concurrently with another reader of the system events.
+    pub async fn wait_ready(&self) -> RunningImage {
+        loop {
+            match self.read_sys_event().await {
+                SysEvent::CpuReady => return RunningImage::WirelessStack,
+                SysEvent::FusState => return RunningImage::Fus,
+                evt => trace!("sys: discarding {:?} while waiting for CPU2", evt),
+            }
+        }
+    }
+
+    /// Wait until CPU2 reports an error
+    ///
+    /// Errors are picked up by [`Sys::read_sys_event`], which must keep being called, e.g. from another
+    /// task. If several errors are reported before this is called, only the last one is returned.
+    pub async fn wait_error(&self) -> SysErrorCode {
+        SYS_ERROR.wait().await
+    }
 }
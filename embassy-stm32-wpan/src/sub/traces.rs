@@ -0,0 +1,67 @@
+//! Traces of the wireless stack running on CPU2
+use embassy_futures::yield_now;
+
+use crate::channels;
+use crate::evt::EvtBox;
+use crate::fmt::Bytes;
+use crate::ipcc::{Ipcc, IpccOps};
+use crate::sub::mm;
+use crate::tables::{TracesTable, TL_TRACES_TABLE, TRACES_EVT_QUEUE};
+use crate::unsafe_linked_list::LinkedListNode;
+
+pub struct Traces {
+    _private: (),
+}
+
+impl Traces {
+    /// `TL_TRACES_Init`
+    pub(crate) fn new() -> Self {
+        unsafe {
+            LinkedListNode::init_head(TRACES_EVT_QUEUE.as_mut_ptr());
+
+            TL_TRACES_TABLE.as_mut_ptr().write_volatile(TracesTable {
+                traces_queue: TRACES_EVT_QUEUE.as_ptr().cast(),
+            });
+        }
+
+        Self { _private: () }
+    }
+
+    /// Handle to the traces of a CPU2 that kept running, leaving its table and queue as they are
+    pub(crate) fn attach() -> Self {
+        Self { _private: () }
+    }
+
+    /// `HW_IPCC_TRACES_EvtNot`
+    ///
+    /// The trace buffers come from the event pool: dropping the returned event gives its buffer back to
+    /// the memory manager, as for the other events.
+    pub async fn read(&self) -> EvtBox<mm::MemoryManager> {
+        Ipcc::receive(channels::cpu2::IPCC_TRACES_CHANNEL, || unsafe {
+            if let Some(node_ptr) = LinkedListNode::remove_head(TRACES_EVT_QUEUE.as_mut_ptr()) {
+                mm::MemoryManager::track_event_packet();
+
+                Some(EvtBox::new(node_ptr.cast()))
+            } else {
+                None
+            }
+        })
+        .await
+    }
+
+    /// Log the traces of CPU2 as they arrive, through `defmt` or `log`
+    ///
+    /// This never returns and is meant to be run from its own task. Each trace is logged at the `info`
+    /// level and its buffer released at once, and the task yields between traces so that a burst of them
+    /// does not delay the tasks reading the BLE or system events. The released buffers reach CPU2 through
+    /// [`MemoryManager::run_queue`](mm::MemoryManager::run_queue), which must run as well.
+    pub async fn run_trace_logger(&self) {
+        loop {
+            let evt = self.read().await;
+            info!("cpu2: {:x}", Bytes(evt.payload()));
+            drop(evt);
+
+            yield_now().await;
+        }
+    }
+}
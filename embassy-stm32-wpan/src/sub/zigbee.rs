@@ -0,0 +1,208 @@
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
+
+use embassy_futures::poll_once;
+use embassy_futures::select::{select, Either};
+use embassy_stm32::ipcc::IpccChannel;
+use embassy_sync::waitqueue::AtomicWaker;
+
+use crate::channels;
+use crate::cmd::CmdPacket;
+use crate::consts::TlPacketType;
+use crate::error::Error;
+use crate::evt::EvtPacket;
+use crate::ipcc::{Ipcc, IpccOps};
+use crate::sub::sys;
+use crate::tables::{
+    ZigbeeTable, TL_ZIGBEE_TABLE, ZIGBEE_APPLI_CMD_BUFFER, ZIGBEE_NOTIF_BUFFER, ZIGBEE_REQUEST_BUFFER,
+};
+
+/// Size of the payload of the Zigbee buffers
+pub const ZIGBEE_PAYLOAD_SIZE: usize = 255;
+
+static NOTIF_WAKER: AtomicWaker = AtomicWaker::new();
+static NOTIF_OUT: AtomicBool = AtomicBool::new(false);
+static REQUEST_WAKER: AtomicWaker = AtomicWaker::new();
+static REQUEST_OUT: AtomicBool = AtomicBool::new(false);
+
+pub struct Zigbee {
+    _private: (),
+}
+
+impl Zigbee {
+    /// `TL_ZIGBEE_Init`
+    pub(crate) fn new() -> Self {
+        unsafe {
+            TL_ZIGBEE_TABLE.as_mut_ptr().write_volatile(ZigbeeTable {
+                notif_m0_to_m4_buffer: ZIGBEE_NOTIF_BUFFER.as_ptr().cast(),
+                appli_cmd_m4_to_m0_buffer: ZIGBEE_APPLI_CMD_BUFFER.as_ptr().cast(),
+                request_m0_to_m4_buffer: ZIGBEE_REQUEST_BUFFER.as_ptr().cast(),
+            });
+        }
+
+        Self { _private: () }
+    }
+
+    /// Handle to the Zigbee channels of a CPU2 that kept running, leaving its table as it is
+    pub(crate) fn attach() -> Self {
+        Self { _private: () }
+    }
+
+    /// `TL_ZIGBEE_SendM4RequestToM0`
+    ///
+    /// `payload` is the application command, starting with the `Zigbee_Cmd_Request_t` header of the ST
+    /// Zigbee firmware: the command identifier and the number of 32-bit arguments that follow.
+    pub async fn tl_write(&self, payload: &[u8]) {
+        Ipcc::send(channels::cpu1::IPCC_ZIGBEE_CMD_APPLI_CHANNEL, || unsafe {
+            CmdPacket::write_into(ZIGBEE_APPLI_CMD_BUFFER.as_mut_ptr(), TlPacketType::OtCmd, 0, payload);
+        })
+        .await;
+    }
+
+    /// Send an application command and copy the beginning of its response into `response`
+    ///
+    /// The Zigbee firmware does not give the length of the response, which has the same layout as the
+    /// command: `response` is filled with up to [`ZIGBEE_PAYLOAD_SIZE`] bytes. [`Error::Reset`] is
+    /// returned if CPU2 restarts before answering.
+    pub async fn tl_write_and_get_response(&self, payload: &[u8], response: &mut [u8]) -> Result<(), Error> {
+        let generation = sys::restart_generation();
+
+        let command = async {
+            self.tl_write(payload).await;
+            Ipcc::flush(channels::cpu1::IPCC_ZIGBEE_CMD_APPLI_CHANNEL).await;
+        };
+
+        match select(sys::restarted_since(generation), command).await {
+            Either::First(()) => return Err(Error::Reset),
+            Either::Second(()) => {}
+        }
+
+        unsafe {
+            let p_event_packet = ZIGBEE_APPLI_CMD_BUFFER.as_ptr() as *const EvtPacket;
+            read_payload(p_event_packet, response);
+        }
+
+        Ok(())
+    }
+
+    /// `HW_IPCC_ZIGBEE_RecvAppliAckFromM0`
+    ///
+    /// Wait for a notification of the Zigbee stack. It is acknowledged when the returned message is
+    /// dropped, and this stalls until then if called again.
+    pub async fn read_notification(&self) -> M0Message<'_> {
+        M0Message::receive(
+            channels::cpu2::IPCC_ZIGBEE_APPLI_NOTIF_ACK_CHANNEL,
+            unsafe { ZIGBEE_NOTIF_BUFFER.as_mut_ptr().cast() },
+            &NOTIF_WAKER,
+            &NOTIF_OUT,
+        )
+        .await
+    }
+
+    /// `HW_IPCC_ZIGBEE_RecvM0RequestToM4`
+    ///
+    /// Wait for a request of the Zigbee stack. The answer is written in the message with
+    /// [`M0Message::payload_mut`], and sent back when the message is dropped. This stalls until then
+    /// if called again.
+    pub async fn read_request(&self) -> M0Message<'_> {
+        M0Message::receive(
+            channels::cpu2::IPCC_ZIGBEE_M0_REQUEST_CHANNEL,
+            unsafe { ZIGBEE_REQUEST_BUFFER.as_mut_ptr().cast() },
+            &REQUEST_WAKER,
+            &REQUEST_OUT,
+        )
+        .await
+    }
+}
+
+/// Copy the payload of an event packet, whose length is not given by the Zigbee firmware
+///
+/// SAFETY: `evt` must point to a buffer of at least `size_of::<EvtPacket>()` bytes
+unsafe fn read_payload(evt: *const EvtPacket, buf: &mut [u8]) {
+    let p_payload = ptr::addr_of!((*evt).evt_serial.evt.payload) as *const u8;
+    let len = buf.len().min(ZIGBEE_PAYLOAD_SIZE);
+
+    ptr::copy_nonoverlapping(p_payload, buf.as_mut_ptr(), len);
+}
+
+/// Notification or request sent by the Zigbee stack, acknowledged to CPU2 when dropped
+pub struct M0Message<'a> {
+    evt: *mut EvtPacket,
+    channel: IpccChannel,
+    waker: &'static AtomicWaker,
+    out: &'static AtomicBool,
+    _zigbee: PhantomData<&'a Zigbee>,
+}
+
+impl<'a> M0Message<'a> {
+    async fn receive(
+        channel: IpccChannel,
+        evt: *mut EvtPacket,
+        waker: &'static AtomicWaker,
+        out: &'static AtomicBool,
+    ) -> Self {
+        // Wait for the previous message to be acknowledged
+        poll_fn(|cx| {
+            waker.register(cx.waker());
+            if out.load(Ordering::SeqCst) {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
+
+        Ipcc::receive(channel, || {
+            out.store(true, Ordering::SeqCst);
+
+            Some(Self {
+                evt,
+                channel,
+                waker,
+                out,
+                _zigbee: PhantomData,
+            })
+        })
+        .await
+    }
+
+    /// Payload of the message, starting with the `Zigbee_Cmd_Request_t` header
+    ///
+    /// The Zigbee firmware does not give the length of the message, this is the whole buffer.
+    pub fn payload(&self) -> &[u8] {
+        unsafe {
+            let p_payload = ptr::addr_of!((*self.evt).evt_serial.evt.payload) as *const u8;
+
+            core::slice::from_raw_parts(p_payload, ZIGBEE_PAYLOAD_SIZE)
+        }
+    }
+
+    /// Payload of the message, to write the answer to a request
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            let p_payload = ptr::addr_of_mut!((*self.evt).evt_serial.evt.payload) as *mut u8;
+
+            core::slice::from_raw_parts_mut(p_payload, ZIGBEE_PAYLOAD_SIZE)
+        }
+    }
+}
+
+impl Drop for M0Message<'_> {
+    /// `TL_ZIGBEE_SendM4AckToM0Notify` and `TL_ZIGBEE_SendM4AckToM0Request`
+    fn drop(&mut self) {
+        unsafe {
+            let p_kind = ptr::addr_of_mut!((*self.evt).evt_serial.kind);
+            ptr::write_volatile(p_kind, TlPacketType::OtAck as u8);
+        }
+
+        // Clear the rx flag
+        let _ = poll_once(Ipcc::receive::<()>(self.channel, || None));
+
+        // Allow a new read call
+        self.out.store(false, Ordering::SeqCst);
+        self.waker.wake();
+    }
+}
@@ -1,12 +1,15 @@
-use core::mem::MaybeUninit;
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
 
 use aligned::{Aligned, A4};
 use bit_field::BitField;
 
 use crate::cmd::{AclDataPacket, CmdPacket};
-#[cfg(feature = "mac")]
-use crate::consts::C_SIZE_CMD_STRING;
-use crate::consts::{POOL_SIZE, TL_CS_EVT_SIZE, TL_EVT_HEADER_SIZE, TL_PACKET_HEADER_SIZE};
+use crate::consts::{
+    divc, pool_size, CFG_TL_BLE_EVT_QUEUE_LENGTH, C_SIZE_CMD_STRING, POOL_SIZE, TL_ACL_DATA_HEADER_SIZE,
+    TL_ACL_DATA_MAX_PAYLOAD_SIZE, TL_BLE_EVT_CS_BUFFER_SIZE, TL_EVT_HEADER_SIZE, TL_PACKET_HEADER_SIZE,
+};
+use crate::evt::CsEvt;
 use crate::unsafe_linked_list::LinkedListNode;
 
 #[derive(Debug, Copy, Clone)]
@@ -15,6 +18,37 @@ pub struct SafeBootInfoTable {
     version: u32,
 }
 
+// The derive would take references to the packed fields
+#[cfg(feature = "defmt")]
+impl defmt::Format for SafeBootInfoTable {
+    fn format(&self, f: defmt::Formatter) {
+        let version = self.version;
+        defmt::write!(f, "SafeBootInfoTable {{ version: {=u32:#x} }}", version)
+    }
+}
+
+impl SafeBootInfoTable {
+    /// Version of the safe boot, encoded as the version of the wireless firmware
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn version_major(&self) -> u8 {
+        let version = self.version;
+        (version.get_bits(24..31) & 0xff) as u8
+    }
+
+    pub fn version_minor(&self) -> u8 {
+        let version = self.version;
+        (version.get_bits(16..23) & 0xff) as u8
+    }
+
+    pub fn subversion(&self) -> u8 {
+        let version = self.version;
+        (version.get_bits(8..15) & 0xff) as u8
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
 pub struct RssInfoTable {
@@ -23,6 +57,24 @@ pub struct RssInfoTable {
     pub rss_info: u32,
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for RssInfoTable {
+    fn format(&self, f: defmt::Formatter) {
+        let Self {
+            version,
+            memory_size,
+            rss_info,
+        } = *self;
+        defmt::write!(
+            f,
+            "RssInfoTable {{ version: {=u32:#x}, memory_size: {=u32:#x}, rss_info: {=u32:#x} }}",
+            version,
+            memory_size,
+            rss_info
+        )
+    }
+}
+
 /**
  * Version
  * [0:3]   = Build - 0: Untracked - 15:Released - x: Tracked version
@@ -46,6 +98,26 @@ pub struct WirelessFwInfoTable {
     pub ble_info: u32,
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for WirelessFwInfoTable {
+    fn format(&self, f: defmt::Formatter) {
+        let Self {
+            version,
+            memory_size,
+            thread_info,
+            ble_info,
+        } = *self;
+        defmt::write!(
+            f,
+            "WirelessFwInfoTable {{ version: {=u32:#x}, memory_size: {=u32:#x}, thread_info: {=u32:#x}, ble_info: {=u32:#x} }}",
+            version,
+            memory_size,
+            thread_info,
+            ble_info
+        )
+    }
+}
+
 impl WirelessFwInfoTable {
     pub fn version_major(&self) -> u8 {
         let version = self.version;
@@ -82,6 +154,7 @@ impl WirelessFwInfoTable {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct DeviceInfoTable {
     pub safe_boot_info_table: SafeBootInfoTable,
@@ -89,7 +162,31 @@ pub struct DeviceInfoTable {
     pub wireless_fw_info_table: WirelessFwInfoTable,
 }
 
+impl core::fmt::Display for DeviceInfoTable {
+    /// Versions of the safe boot, of the firmware upgrade service and of the wireless firmware
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let safe_boot = self.safe_boot_info_table.version;
+        let fus = self.rss_info_table.version;
+        let wireless = self.wireless_fw_info_table.version;
+
+        write!(
+            f,
+            "safe boot {}.{}.{}, FUS {}.{}.{}, wireless {}.{}.{}",
+            safe_boot >> 24,
+            (safe_boot >> 16) & 0xff,
+            (safe_boot >> 8) & 0xff,
+            fus >> 24,
+            (fus >> 16) & 0xff,
+            (fus >> 8) & 0xff,
+            wireless >> 24,
+            (wireless >> 16) & 0xff,
+            (wireless >> 8) & 0xff,
+        )
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct BleTable {
     pub pcmd_buffer: *mut CmdPacket,
@@ -99,6 +196,7 @@ pub struct BleTable {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct ThreadTable {
     pub nostack_buffer: *const u8,
@@ -107,6 +205,7 @@ pub struct ThreadTable {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct LldTestsTable {
     pub clicmdrsp_buffer: *const u8,
@@ -115,22 +214,24 @@ pub struct LldTestsTable {
 
 // TODO: use later
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct BleLldTable {
     pub cmdrsp_buffer: *const u8,
     pub m0cmd_buffer: *const u8,
 }
 
-// TODO: use later
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct ZigbeeTable {
     pub notif_m0_to_m4_buffer: *const u8,
-    pub appli_cmd_m4_to_m0_bufer: *const u8,
+    pub appli_cmd_m4_to_m0_buffer: *const u8,
     pub request_m0_to_m4_buffer: *const u8,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct SysTable {
     pub pcmd_buffer: *mut CmdPacket,
@@ -138,6 +239,7 @@ pub struct SysTable {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct MemManagerTable {
     pub spare_ble_buffer: *const u8,
@@ -153,12 +255,14 @@ pub struct MemManagerTable {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct TracesTable {
     pub traces_queue: *const u8,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct Mac802_15_4Table {
     pub p_cmdrsp_buffer: *const u8,
@@ -168,6 +272,7 @@ pub struct Mac802_15_4Table {
 
 /// Reference table. Contains pointers to all other tables.
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct RefTable {
     pub device_info_table: *const DeviceInfoTable,
@@ -182,6 +287,74 @@ pub struct RefTable {
     pub ble_lld_table: *const BleLldTable,
 }
 
+/// Head of a queue shared with CPU2, as addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QueueSnapshot {
+    pub head: u32,
+    pub next: u32,
+    pub prev: u32,
+}
+
+impl QueueSnapshot {
+    /// SAFETY: `head` must point to an initialized list head
+    unsafe fn read(head: *const LinkedListNode) -> Self {
+        let node = ptr::read_volatile(head);
+
+        Self {
+            head: head as u32,
+            next: node.next as u32,
+            prev: node.prev as u32,
+        }
+    }
+
+    /// An empty queue points back to its head
+    pub fn is_empty(&self) -> bool {
+        self.next == self.head
+    }
+}
+
+/// Contents of the tables shared with CPU2, as returned by [`TlMbox::debug_dump`](crate::TlMbox::debug_dump)
+///
+/// The versions stay zero until CPU2 fills the device information table when it boots.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TableSnapshot {
+    /// Address of the reference table, which CPU2 reads the other table addresses from
+    pub ref_table: u32,
+    pub safe_boot_version: u32,
+    pub fus_version: u32,
+    pub fus_memory_size: u32,
+    pub wireless_fw_version: u32,
+    pub wireless_fw_memory_size: u32,
+    /// Event buffers released by the application and not taken back by CPU2 yet
+    pub free_buffer_queue: QueueSnapshot,
+    pub sys_event_queue: QueueSnapshot,
+    #[cfg(feature = "ble")]
+    pub ble_event_queue: QueueSnapshot,
+}
+
+impl TableSnapshot {
+    /// SAFETY: the mailbox must have been initialized
+    pub(crate) unsafe fn read() -> Self {
+        // The fields of the information tables are packed, read the whole table without referencing them
+        let info = ptr::read_unaligned(TL_DEVICE_INFO_TABLE.as_ptr());
+
+        Self {
+            ref_table: TL_REF_TABLE.as_ptr() as u32,
+            safe_boot_version: info.safe_boot_info_table.version,
+            fus_version: info.rss_info_table.version,
+            fus_memory_size: info.rss_info_table.memory_size,
+            wireless_fw_version: info.wireless_fw_info_table.version,
+            wireless_fw_memory_size: info.wireless_fw_info_table.memory_size,
+            free_buffer_queue: QueueSnapshot::read(FREE_BUF_QUEUE.as_ptr()),
+            sys_event_queue: QueueSnapshot::read(SYSTEM_EVT_QUEUE.as_ptr()),
+            #[cfg(feature = "ble")]
+            ble_event_queue: QueueSnapshot::read(EVT_QUEUE.as_ptr()),
+        }
+    }
+}
+
 // --------------------- ref table ---------------------
 #[link_section = "TL_REF_TABLE"]
 pub static mut TL_REF_TABLE: MaybeUninit<RefTable> = MaybeUninit::uninit();
@@ -192,12 +365,15 @@ pub static mut TL_DEVICE_INFO_TABLE: Aligned<A4, MaybeUninit<DeviceInfoTable>> =
 #[link_section = "MB_MEM1"]
 pub static mut TL_BLE_TABLE: Aligned<A4, MaybeUninit<BleTable>> = Aligned(MaybeUninit::uninit());
 
+#[cfg(not(feature = "omit-unused-tables"))]
 #[link_section = "MB_MEM1"]
 pub static mut TL_THREAD_TABLE: Aligned<A4, MaybeUninit<ThreadTable>> = Aligned(MaybeUninit::uninit());
 
+#[cfg(not(feature = "omit-unused-tables"))]
 #[link_section = "MB_MEM1"]
 pub static mut TL_LLD_TESTS_TABLE: Aligned<A4, MaybeUninit<LldTestsTable>> = Aligned(MaybeUninit::uninit());
 
+#[cfg(not(feature = "omit-unused-tables"))]
 #[link_section = "MB_MEM1"]
 pub static mut TL_BLE_LLD_TABLE: Aligned<A4, MaybeUninit<BleLldTable>> = Aligned(MaybeUninit::uninit());
 
@@ -207,12 +383,15 @@ pub static mut TL_SYS_TABLE: Aligned<A4, MaybeUninit<SysTable>> = Aligned(MaybeU
 #[link_section = "MB_MEM1"]
 pub static mut TL_MEM_MANAGER_TABLE: Aligned<A4, MaybeUninit<MemManagerTable>> = Aligned(MaybeUninit::uninit());
 
+#[cfg(any(feature = "mac", feature = "traces", not(feature = "omit-unused-tables")))]
 #[link_section = "MB_MEM1"]
 pub static mut TL_TRACES_TABLE: Aligned<A4, MaybeUninit<TracesTable>> = Aligned(MaybeUninit::uninit());
 
+#[cfg(any(feature = "mac", not(feature = "omit-unused-tables")))]
 #[link_section = "MB_MEM1"]
 pub static mut TL_MAC_802_15_4_TABLE: Aligned<A4, MaybeUninit<Mac802_15_4Table>> = Aligned(MaybeUninit::uninit());
 
+#[cfg(any(feature = "zigbee", not(feature = "omit-unused-tables")))]
 #[link_section = "MB_MEM1"]
 pub static mut TL_ZIGBEE_TABLE: Aligned<A4, MaybeUninit<ZigbeeTable>> = Aligned(MaybeUninit::uninit());
 
@@ -221,13 +400,15 @@ pub static mut TL_ZIGBEE_TABLE: Aligned<A4, MaybeUninit<ZigbeeTable>> = Aligned(
 pub static mut FREE_BUF_QUEUE: Aligned<A4, MaybeUninit<LinkedListNode>> = Aligned(MaybeUninit::uninit());
 
 #[allow(dead_code)]
+#[cfg(any(feature = "mac", feature = "traces", not(feature = "omit-unused-tables")))]
 #[link_section = "MB_MEM1"]
 pub static mut TRACES_EVT_QUEUE: Aligned<A4, MaybeUninit<LinkedListNode>> = Aligned(MaybeUninit::uninit());
 
+#[cfg(feature = "ble")]
 #[link_section = "MB_MEM2"]
-pub static mut CS_BUFFER: Aligned<A4, MaybeUninit<[u8; TL_PACKET_HEADER_SIZE + TL_EVT_HEADER_SIZE + TL_CS_EVT_SIZE]>> =
-    Aligned(MaybeUninit::uninit());
+pub static mut CS_BUFFER: Aligned<A4, MaybeUninit<[u8; TL_BLE_EVT_CS_BUFFER_SIZE]>> = Aligned(MaybeUninit::uninit());
 
+#[cfg(feature = "ble")]
 #[link_section = "MB_MEM2"]
 pub static mut EVT_QUEUE: Aligned<A4, MaybeUninit<LinkedListNode>> = Aligned(MaybeUninit::uninit());
 
@@ -245,6 +426,25 @@ pub static mut MAC_802_15_4_NOTIF_RSP_EVT_BUFFER: MaybeUninit<
     Aligned<A4, [u8; TL_PACKET_HEADER_SIZE + TL_EVT_HEADER_SIZE + 255]>,
 > = MaybeUninit::uninit();
 
+#[cfg(feature = "zigbee")]
+#[link_section = "MB_MEM2"]
+pub static mut ZIGBEE_APPLI_CMD_BUFFER: Aligned<A4, MaybeUninit<CmdPacket>> = Aligned(MaybeUninit::uninit());
+
+#[cfg(feature = "zigbee")]
+#[link_section = "MB_MEM2"]
+pub static mut ZIGBEE_NOTIF_BUFFER: MaybeUninit<Aligned<A4, [u8; TL_PACKET_HEADER_SIZE + TL_EVT_HEADER_SIZE + 255]>> =
+    MaybeUninit::uninit();
+
+#[cfg(feature = "zigbee")]
+#[link_section = "MB_MEM2"]
+pub static mut ZIGBEE_REQUEST_BUFFER: MaybeUninit<Aligned<A4, [u8; TL_PACKET_HEADER_SIZE + TL_EVT_HEADER_SIZE + 255]>> =
+    MaybeUninit::uninit();
+
+/// Pool of the event buffers allocated by the wireless stacks
+///
+/// CPU2 sends the system events in dedicated buffers, so the pool is left out when no wireless stack is
+/// used, e.g. in firmware only talking to FUS.
+#[cfg(any(feature = "ble", feature = "mac", feature = "zigbee"))]
 #[link_section = "MB_MEM2"]
 pub static mut EVT_POOL: Aligned<A4, MaybeUninit<[u8; POOL_SIZE]>> = Aligned(MaybeUninit::uninit());
 
@@ -271,6 +471,190 @@ pub static mut BLE_SPARE_EVT_BUF: Aligned<A4, MaybeUninit<[u8; TL_PACKET_HEADER_
 
 #[cfg(feature = "ble")]
 #[link_section = "MB_MEM2"]
-//                                 fuck these "magic" numbers from ST ---v---v
-pub static mut HCI_ACL_DATA_BUFFER: Aligned<A4, MaybeUninit<[u8; TL_PACKET_HEADER_SIZE + 5 + 251]>> =
-    Aligned(MaybeUninit::uninit());
+pub static mut HCI_ACL_DATA_BUFFER: Aligned<
+    A4,
+    MaybeUninit<[u8; TL_PACKET_HEADER_SIZE + TL_ACL_DATA_HEADER_SIZE + TL_ACL_DATA_MAX_PAYLOAD_SIZE]>,
+> = Aligned(MaybeUninit::uninit());
+
+// --------------------- shared memory footprint ---------------------
+/// Size of the `TL_REF_TABLE` section
+pub const TL_REF_TABLE_SIZE: usize = size_of::<RefTable>();
+
+/// Protocols and buffer sizes determining the footprint of the shared memory sections
+///
+/// [`SharedMemoryConfig::CURRENT`] describes this build. Other configurations can be evaluated, e.g. by
+/// a build script sizing the linker regions of several applications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SharedMemoryConfig {
+    /// `ble` feature
+    pub ble: bool,
+    /// `mac` feature
+    pub mac: bool,
+    /// `zigbee` feature
+    pub zigbee: bool,
+    /// `traces` feature
+    pub traces: bool,
+    /// `omit-unused-tables` feature
+    pub omit_unused_tables: bool,
+    /// Number of event buffers of the pool, [`CFG_TL_BLE_EVT_QUEUE_LENGTH`]
+    pub evt_queue_len: usize,
+    /// Largest ACL data payload, [`TL_ACL_DATA_MAX_PAYLOAD_SIZE`]
+    pub acl_payload: usize,
+}
+
+impl SharedMemoryConfig {
+    /// Configuration of this build
+    pub const CURRENT: Self = Self {
+        ble: cfg!(feature = "ble"),
+        mac: cfg!(feature = "mac"),
+        zigbee: cfg!(feature = "zigbee"),
+        traces: cfg!(feature = "traces"),
+        omit_unused_tables: cfg!(feature = "omit-unused-tables"),
+        evt_queue_len: CFG_TL_BLE_EVT_QUEUE_LENGTH,
+        acl_payload: TL_ACL_DATA_MAX_PAYLOAD_SIZE,
+    };
+}
+
+/// Size of a shared buffer of `len` bytes, rounded up to its 4-byte alignment
+const fn aligned_buffer(len: usize) -> usize {
+    divc(len, 4) * 4
+}
+
+/// Size of an event buffer, of the largest event
+const EVT_BUFFER_SIZE: usize = aligned_buffer(TL_PACKET_HEADER_SIZE + TL_EVT_HEADER_SIZE + 255);
+
+/// Combined size of the tables and buffers placed in the `MB_MEM1` section for `config`
+///
+/// Every symbol is 4-byte aligned, and the sizes below include the padding to that alignment:
+///
+/// | Symbol                  | Contents             | Present                                              |
+/// |-------------------------|----------------------|------------------------------------------------------|
+/// | `TL_DEVICE_INFO_TABLE`  | [`DeviceInfoTable`]  | always                                               |
+/// | `TL_BLE_TABLE`          | [`BleTable`]         | always                                               |
+/// | `TL_SYS_TABLE`          | [`SysTable`]         | always                                               |
+/// | `TL_MEM_MANAGER_TABLE`  | [`MemManagerTable`]  | always                                               |
+/// | `FREE_BUF_QUEUE`        | [`LinkedListNode`]   | always                                               |
+/// | `TL_THREAD_TABLE`       | [`ThreadTable`]      | without `omit-unused-tables`                         |
+/// | `TL_LLD_TESTS_TABLE`    | [`LldTestsTable`]    | without `omit-unused-tables`                         |
+/// | `TL_BLE_LLD_TABLE`      | [`BleLldTable`]      | without `omit-unused-tables`                         |
+/// | `TL_TRACES_TABLE`       | [`TracesTable`]      | with `mac`, `traces` or without `omit-unused-tables` |
+/// | `TL_MAC_802_15_4_TABLE` | [`Mac802_15_4Table`] | with `mac` or without `omit-unused-tables`           |
+/// | `TRACES_EVT_QUEUE`      | [`LinkedListNode`]   | with `mac`, `traces` or without `omit-unused-tables` |
+/// | `TL_ZIGBEE_TABLE`       | [`ZigbeeTable`]      | with `zigbee` or without `omit-unused-tables`        |
+/// | `BLE_CMD_BUFFER`        | [`CmdPacket`]        | with `ble`                                           |
+pub const fn required_mb_mem1(config: SharedMemoryConfig) -> usize {
+    let mut size = size_of::<Aligned<A4, MaybeUninit<DeviceInfoTable>>>()
+        + size_of::<Aligned<A4, MaybeUninit<BleTable>>>()
+        + size_of::<Aligned<A4, MaybeUninit<SysTable>>>()
+        + size_of::<Aligned<A4, MaybeUninit<MemManagerTable>>>()
+        + size_of::<Aligned<A4, MaybeUninit<LinkedListNode>>>();
+    if !config.omit_unused_tables {
+        size += size_of::<Aligned<A4, MaybeUninit<ThreadTable>>>()
+            + size_of::<Aligned<A4, MaybeUninit<LldTestsTable>>>()
+            + size_of::<Aligned<A4, MaybeUninit<BleLldTable>>>();
+    }
+    if config.mac || config.traces || !config.omit_unused_tables {
+        size +=
+            size_of::<Aligned<A4, MaybeUninit<TracesTable>>>() + size_of::<Aligned<A4, MaybeUninit<LinkedListNode>>>();
+    }
+    if config.mac || !config.omit_unused_tables {
+        size += size_of::<Aligned<A4, MaybeUninit<Mac802_15_4Table>>>();
+    }
+    if config.zigbee || !config.omit_unused_tables {
+        size += size_of::<Aligned<A4, MaybeUninit<ZigbeeTable>>>();
+    }
+    if config.ble {
+        size += size_of::<Aligned<A4, MaybeUninit<CmdPacket>>>();
+    }
+
+    size
+}
+
+/// Combined size of the buffers placed in the `MB_MEM2` section for `config`
+///
+/// Every symbol is 4-byte aligned, and the sizes below include the padding to that alignment. An event
+/// buffer holds the largest event: packet header, event header and 255 bytes of payload.
+///
+/// | Symbol                              | Contents                               | Present           |
+/// |-------------------------------------|----------------------------------------|-------------------|
+/// | `SYSTEM_EVT_QUEUE`                  | [`LinkedListNode`]                     | always            |
+/// | `SYS_CMD_BUF`                       | [`CmdPacket`]                          | always            |
+/// | `SYS_SPARE_EVT_BUF`                 | event buffer                           | always            |
+/// | `EVT_POOL`                          | [`pool_size`]`(evt_queue_len)` bytes   | with any protocol |
+/// | `EVT_QUEUE`                         | [`LinkedListNode`]                     | with `ble`        |
+/// | `CS_BUFFER`                         | [`TL_BLE_EVT_CS_BUFFER_SIZE`] bytes    | with `ble`        |
+/// | `BLE_SPARE_EVT_BUF`                 | event buffer                           | with `ble`        |
+/// | `HCI_ACL_DATA_BUFFER`               | ACL data packet of `acl_payload` bytes | with `ble`        |
+/// | `MAC_802_15_4_CMD_BUFFER`           | [`CmdPacket`]                          | with `mac`        |
+/// | `MAC_802_15_4_NOTIF_RSP_EVT_BUFFER` | event buffer                           | with `mac`        |
+/// | `MAC_802_15_4_CNFINDNOT`            | [`C_SIZE_CMD_STRING`] bytes            | with `mac`        |
+/// | `ZIGBEE_APPLI_CMD_BUFFER`           | [`CmdPacket`]                          | with `zigbee`     |
+/// | `ZIGBEE_NOTIF_BUFFER`               | event buffer                           | with `zigbee`     |
+/// | `ZIGBEE_REQUEST_BUFFER`             | event buffer                           | with `zigbee`     |
+pub const fn required_mb_mem2(config: SharedMemoryConfig) -> usize {
+    let mut size = size_of::<Aligned<A4, MaybeUninit<LinkedListNode>>>()
+        + size_of::<Aligned<A4, MaybeUninit<CmdPacket>>>()
+        + EVT_BUFFER_SIZE;
+    if config.ble || config.mac || config.zigbee {
+        size += aligned_buffer(pool_size(config.evt_queue_len));
+    }
+    if config.ble {
+        size += size_of::<Aligned<A4, MaybeUninit<LinkedListNode>>>()
+            + aligned_buffer(TL_BLE_EVT_CS_BUFFER_SIZE)
+            + EVT_BUFFER_SIZE
+            + aligned_buffer(TL_PACKET_HEADER_SIZE + TL_ACL_DATA_HEADER_SIZE + config.acl_payload);
+    }
+    if config.mac {
+        size += size_of::<Aligned<A4, MaybeUninit<CmdPacket>>>() + EVT_BUFFER_SIZE + aligned_buffer(C_SIZE_CMD_STRING);
+    }
+    if config.zigbee {
+        size += size_of::<Aligned<A4, MaybeUninit<CmdPacket>>>() + 2 * EVT_BUFFER_SIZE;
+    }
+
+    size
+}
+
+/// Combined size of the tables and buffers placed in the `MB_MEM1` section
+pub const MB_MEM1_SIZE: usize = required_mb_mem1(SharedMemoryConfig::CURRENT);
+
+/// Combined size of the buffers placed in the `MB_MEM2` section
+pub const MB_MEM2_SIZE: usize = required_mb_mem2(SharedMemoryConfig::CURRENT);
+
+// The computations above must follow the types of the statics
+const _: () = assert!(size_of::<Aligned<A4, MaybeUninit<[u8; POOL_SIZE]>>>() == aligned_buffer(POOL_SIZE));
+const _: () = assert!(
+    size_of::<Aligned<A4, MaybeUninit<[u8; TL_PACKET_HEADER_SIZE + TL_EVT_HEADER_SIZE + 255]>>>() == EVT_BUFFER_SIZE
+);
+const _: () = assert!(
+    size_of::<Aligned<A4, MaybeUninit<[u8; TL_BLE_EVT_CS_BUFFER_SIZE]>>>() == aligned_buffer(TL_BLE_EVT_CS_BUFFER_SIZE)
+);
+const _: () =
+    assert!(size_of::<Aligned<A4, MaybeUninit<[u8; C_SIZE_CMD_STRING]>>>() == aligned_buffer(C_SIZE_CMD_STRING));
+
+// The linker scripts provided by this crate reserve 10K of SRAM2a for all the sections, or 10K of SRAM2b
+// (4K on STM32WB1x) for `MB_MEM1` and `MB_MEM2` with the `extended` feature. Alignment padding between
+// the sections is not accounted for.
+#[cfg(all(not(feature = "custom-linker-script"), not(feature = "extended")))]
+const _: () = assert!(TL_REF_TABLE_SIZE + MB_MEM1_SIZE + MB_MEM2_SIZE <= 10 * 1024);
+#[cfg(all(
+    not(feature = "custom-linker-script"),
+    feature = "extended",
+    not(any(feature = "stm32wb10cc", feature = "stm32wb15cc"))
+))]
+const _: () = assert!(MB_MEM1_SIZE + MB_MEM2_SIZE <= 10 * 1024);
+#[cfg(all(
+    not(feature = "custom-linker-script"),
+    feature = "extended",
+    any(feature = "stm32wb10cc", feature = "stm32wb15cc")
+))]
+const _: () = assert!(MB_MEM1_SIZE + MB_MEM2_SIZE <= 4 * 1024);
+
+// --------------------- layout checks ---------------------
+// CPU2 writes a full command status event into the CS buffer
+const _: () =
+    assert!(TL_BLE_EVT_CS_BUFFER_SIZE >= TL_PACKET_HEADER_SIZE + TL_EVT_HEADER_SIZE + core::mem::size_of::<CsEvt>());
+// Shared buffers are handed to CPU2 as word-aligned addresses
+const _: () = assert!(core::mem::align_of::<Aligned<A4, MaybeUninit<[u8; POOL_SIZE]>>>() >= 4);
+const _: () = assert!(core::mem::align_of::<Aligned<A4, MaybeUninit<CmdPacket>>>() >= 4);
+const _: () = assert!(core::mem::align_of::<Aligned<A4, MaybeUninit<LinkedListNode>>>() >= 4);
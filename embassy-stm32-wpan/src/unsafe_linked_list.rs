@@ -1,5 +1,12 @@
 //! Unsafe linked list.
 //! Translated from ST's C by `c2rust` tool.
+//!
+//! Every operation runs in its own short critical section. The lists are statics shared between
+//! tasks that may run at different priorities (e.g. with an `InterruptExecutor`), so a `RawMutex`
+//! chosen by the application would not protect them. The sections only cover a constant number of
+//! node updates, or a caller-chosen number of nodes for [`LinkedListNode::count_up_to`], except for
+//! the consistency checks, which walk the whole list and only run in debug builds or when called
+//! explicitly.
 
 #![allow(
     dead_code,
@@ -13,8 +20,29 @@
 
 use core::ptr;
 
+#[cfg(not(test))]
 use cortex_m::interrupt;
 
+/// Host stand-in for the critical section, so that list operations can be unit-tested
+#[cfg(test)]
+mod interrupt {
+    pub fn free<R>(f: impl FnOnce(&()) -> R) -> R {
+        f(&())
+    }
+}
+
+/// Upper bound on the number of nodes visited when checking a list, to detect broken rings
+const MAX_CHECKED_NODES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkedListError {
+    /// The `next`/`prev` pointers of the list do not form a ring back to the list head
+    Corrupted,
+    /// The node is already linked in the list
+    AlreadyLinked,
+}
+
 #[derive(Copy, Clone)]
 #[repr(C, packed(4))]
 pub struct LinkedListNode {
@@ -48,6 +76,8 @@ impl LinkedListNode {
 
     /// Insert `node` after `list_head` and before the next node
     pub unsafe fn insert_head(mut p_list_head: *mut LinkedListNode, mut p_node: *mut LinkedListNode) {
+        debug_assert!(!Self::contains(p_list_head, p_node), "node is already linked");
+
         interrupt::free(|_| {
             let mut list_head = ptr::read_volatile(p_list_head);
             if p_list_head != list_head.next {
@@ -78,10 +108,14 @@ impl LinkedListNode {
                 ptr::write_volatile(p_list_head, list_head);
             }
         });
+
+        Self::debug_check(p_list_head);
     }
 
     /// Insert `node` before `list_tail` and after the second-to-last node
     pub unsafe fn insert_tail(mut p_list_tail: *mut LinkedListNode, mut p_node: *mut LinkedListNode) {
+        debug_assert!(!Self::contains(p_list_tail, p_node), "node is already linked");
+
         interrupt::free(|_| {
             let mut list_tail = ptr::read_volatile(p_list_tail);
             if p_list_tail != list_tail.prev {
@@ -112,6 +146,8 @@ impl LinkedListNode {
                 ptr::write_volatile(p_list_tail, list_tail);
             }
         });
+
+        Self::debug_check(p_list_tail);
     }
 
     /// Remove `node` from the linked list
@@ -145,7 +181,7 @@ impl LinkedListNode {
 
     /// Remove `list_head` and return a pointer to the `node`.
     pub unsafe fn remove_head(mut p_list_head: *mut LinkedListNode) -> Option<*mut LinkedListNode> {
-        interrupt::free(|_| {
+        let p_node = interrupt::free(|_| {
             let list_head = ptr::read_volatile(p_list_head);
 
             if list_head.next == p_list_head {
@@ -157,12 +193,16 @@ impl LinkedListNode {
 
                 Some(p_node)
             }
-        })
+        });
+
+        Self::debug_check(p_list_head);
+
+        p_node
     }
 
     /// Remove `list_tail` and return a pointer to the `node`.
     pub unsafe fn remove_tail(mut p_list_tail: *mut LinkedListNode) -> Option<*mut LinkedListNode> {
-        interrupt::free(|_| {
+        let p_node = interrupt::free(|_| {
             let list_tail = ptr::read_volatile(p_list_tail);
 
             if list_tail.prev == p_list_tail {
@@ -174,7 +214,11 @@ impl LinkedListNode {
 
                 Some(p_node)
             }
-        })
+        });
+
+        Self::debug_check(p_list_tail);
+
+        p_node
     }
 
     pub unsafe fn insert_node_after(mut node: *mut LinkedListNode, mut ref_node: *mut LinkedListNode) {
@@ -199,21 +243,119 @@ impl LinkedListNode {
         todo!("this function has not been converted to volatile semantics");
     }
 
+    /// Number of nodes in the list, not counting `list_head`
+    ///
+    /// Panics if the list is corrupted.
     pub unsafe fn get_size(mut list_head: *mut LinkedListNode) -> usize {
+        unwrap!(Self::check(list_head))
+    }
+
+    /// Walk the list and verify that every node links back to its predecessor
+    ///
+    /// Returns the number of nodes in the list, not counting `list_head`.
+    pub unsafe fn check(p_list_head: *mut LinkedListNode) -> Result<usize, LinkedListError> {
+        interrupt::free(|_| {
+            let mut size = 0;
+            let mut p_prev = p_list_head;
+            let mut current = ptr::read_volatile(p_list_head);
+
+            loop {
+                if current.next.is_null() || size > MAX_CHECKED_NODES {
+                    return Err(LinkedListError::Corrupted);
+                }
+
+                let p_current = current.next;
+                // apparently linked list nodes are not always aligned
+                let next = ptr::read_unaligned(p_current);
+                if next.prev != p_prev {
+                    return Err(LinkedListError::Corrupted);
+                }
+
+                if p_current == p_list_head {
+                    return Ok(size);
+                }
+
+                size += 1;
+                p_prev = p_current;
+                current = next;
+            }
+        })
+    }
+
+    /// Whether `p_node` is linked in the list starting at `p_list_head`
+    pub unsafe fn contains(p_list_head: *mut LinkedListNode, p_node: *mut LinkedListNode) -> bool {
+        interrupt::free(|_| {
+            let mut p_current = ptr::read_volatile(p_list_head).next;
+            let mut visited = 0;
+
+            while p_current != p_list_head && !p_current.is_null() && visited <= MAX_CHECKED_NODES {
+                if p_current == p_node {
+                    return true;
+                }
+
+                p_current = ptr::read_unaligned(p_current).next;
+                visited += 1;
+            }
+
+            false
+        })
+    }
+
+    /// Number of nodes in the list, not counting `list_head`, stopping at `max`
+    ///
+    /// Unlike [`check`](Self::check), the links are not verified, so this is cheap enough to call on
+    /// every read of a queue.
+    ///
+    /// # Safety
+    ///
+    /// `p_list_head` must point to an initialized list head whose nodes stay valid during the call.
+    pub unsafe fn count_up_to(p_list_head: *mut LinkedListNode, max: usize) -> usize {
         interrupt::free(|_| {
+            let mut p_current = ptr::read_volatile(p_list_head).next;
             let mut size = 0;
-            let mut temp: *mut LinkedListNode = core::ptr::null_mut::<LinkedListNode>();
 
-            temp = (*list_head).next;
-            while temp != list_head {
+            while size < max && p_current != p_list_head && !p_current.is_null() {
+                p_current = ptr::read_unaligned(p_current).next;
                 size += 1;
-                temp = (*temp).next
             }
 
             size
-        });
+        })
+    }
 
-        todo!("this function has not been converted to volatile semantics");
+    /// Insert `node` at the end of the list, after checking the list and that `node` is not in it already
+    pub unsafe fn try_insert_tail(
+        p_list_head: *mut LinkedListNode,
+        p_node: *mut LinkedListNode,
+    ) -> Result<(), LinkedListError> {
+        Self::check(p_list_head)?;
+        if Self::contains(p_list_head, p_node) {
+            return Err(LinkedListError::AlreadyLinked);
+        }
+
+        Self::insert_tail(p_list_head, p_node);
+
+        Ok(())
+    }
+
+    /// Remove the first node of the list, after checking the list
+    pub unsafe fn try_remove_head(
+        p_list_head: *mut LinkedListNode,
+    ) -> Result<Option<*mut LinkedListNode>, LinkedListError> {
+        Self::check(p_list_head)?;
+
+        Ok(Self::remove_head(p_list_head))
+    }
+
+    /// Consistency check run after each mutation in debug builds
+    unsafe fn debug_check(p_list_head: *mut LinkedListNode) {
+        #[cfg(debug_assertions)]
+        if let Err(e) = Self::check(p_list_head) {
+            panic!("linked list {:x} is corrupted: {:?}", p_list_head as usize, e);
+        }
+
+        #[cfg(not(debug_assertions))]
+        let _ = p_list_head;
     }
 
     pub unsafe fn get_next_node(mut p_ref_node: *mut LinkedListNode) -> *mut LinkedListNode {
@@ -235,23 +377,330 @@ impl LinkedListNode {
     }
 }
 
+/// Checked handle to a list head
+///
+/// Every operation verifies that the list is consistent, and insertions that the node is not linked
+/// already, reporting an error instead of corrupting memory shared with CPU2.
+pub struct LinkedList {
+    head: *mut LinkedListNode,
+}
+
+impl LinkedList {
+    /// SAFETY: `head` must point to a list head initialized with `LinkedListNode::init_head`, that stays
+    /// valid for as long as the returned handle is used
+    pub const unsafe fn from_head(head: *mut LinkedListNode) -> Self {
+        Self { head }
+    }
+
+    pub fn len(&self) -> Result<usize, LinkedListError> {
+        unsafe { LinkedListNode::check(self.head) }
+    }
+
+    pub fn is_empty(&self) -> Result<bool, LinkedListError> {
+        Ok(self.len()? == 0)
+    }
+
+    // `node` is only compared with the nodes of the list, never dereferenced
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn contains(&self, node: *mut LinkedListNode) -> bool {
+        unsafe { LinkedListNode::contains(self.head, node) }
+    }
+
+    /// SAFETY: `node` must point to a node that stays valid while it is linked in the list
+    pub unsafe fn push_back(&self, node: *mut LinkedListNode) -> Result<(), LinkedListError> {
+        LinkedListNode::try_insert_tail(self.head, node)
+    }
+
+    pub fn pop_front(&self) -> Result<Option<*mut LinkedListNode>, LinkedListError> {
+        unsafe { LinkedListNode::try_remove_head(self.head) }
+    }
+}
+
+/// Read-only iterator over the nodes of a list, from the head to the tail
+///
+/// The list is read with volatile accesses but not locked: the caller must make sure nobody modifies it
+/// while iterating, see [`Nodes::new`].
+pub struct Nodes {
+    head: *const LinkedListNode,
+    next: *const LinkedListNode,
+}
+
+impl Nodes {
+    /// # Safety
+    ///
+    /// `head` must point to a list head initialized with `LinkedListNode::init_head`, and neither CPU2
+    /// nor the application may insert or remove nodes until the iterator is dropped.
+    pub unsafe fn new(head: *const LinkedListNode) -> Self {
+        Self {
+            head,
+            next: ptr::read_volatile(head).next,
+        }
+    }
+}
+
+impl Iterator for Nodes {
+    type Item = *const LinkedListNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.head {
+            return None;
+        }
+
+        let node = self.next;
+        self.next = unsafe { ptr::read_volatile(node) }.next;
+
+        Some(node)
+    }
+}
+
 #[allow(dead_code)]
 unsafe fn debug_linked_list(mut p_node: *mut LinkedListNode) {
     info!("iterating list from node: {:x}", p_node);
     let mut p_current_node = p_node;
     let mut i = 0;
     loop {
-        let current_node = ptr::read_volatile(p_current_node);
+        let LinkedListNode { next, prev } = ptr::read_volatile(p_current_node);
         info!(
             "node (prev, current, next): {:x}, {:x}, {:x}",
-            current_node.prev, p_current_node, current_node.next
+            prev, p_current_node, next
         );
 
         i += 1;
-        if i > 10 || current_node.next == p_node {
+        if i > 10 || next == p_node {
             break;
         }
 
-        p_current_node = current_node.next;
+        p_current_node = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    const POOL_LEN: usize = 5;
+
+    struct Pool {
+        head: LinkedListNode,
+        nodes: [LinkedListNode; POOL_LEN],
+    }
+
+    impl Pool {
+        fn new() -> Self {
+            Self {
+                head: LinkedListNode::default(),
+                nodes: [LinkedListNode::default(); POOL_LEN],
+            }
+        }
+
+        fn head(&mut self) -> *mut LinkedListNode {
+            &mut self.head
+        }
+
+        fn node(&mut self, i: usize) -> *mut LinkedListNode {
+            &mut self.nodes[i]
+        }
+
+        fn index_of(&self, node: *mut LinkedListNode) -> usize {
+            self.nodes
+                .iter()
+                .position(|n| n as *const _ == node as *const _)
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn empty_list() {
+        let mut pool = Pool::new();
+        let head = pool.head();
+
+        unsafe {
+            LinkedListNode::init_head(head);
+
+            assert!(LinkedListNode::is_empty(head));
+            assert_eq!(LinkedListNode::check(head), Ok(0));
+            assert_eq!(LinkedListNode::remove_head(head), None);
+            assert_eq!(LinkedListNode::remove_tail(head), None);
+        }
+    }
+
+    #[test]
+    fn fifo_order() {
+        let mut pool = Pool::new();
+        let head = pool.head();
+
+        unsafe {
+            LinkedListNode::init_head(head);
+            for i in 0..POOL_LEN {
+                LinkedListNode::insert_tail(head, pool.node(i));
+                assert_eq!(LinkedListNode::get_size(head), i + 1);
+            }
+
+            let mut order = Vec::new();
+            while let Some(node) = LinkedListNode::remove_head(head) {
+                order.push(pool.index_of(node));
+            }
+
+            assert_eq!(order, [0, 1, 2, 3, 4]);
+            assert!(LinkedListNode::is_empty(head));
+        }
+    }
+
+    #[test]
+    fn iterate_nodes() {
+        let mut pool = Pool::new();
+        let head = pool.head();
+
+        unsafe {
+            LinkedListNode::init_head(head);
+            assert_eq!(Nodes::new(head).count(), 0);
+
+            for i in [3, 1, 4] {
+                LinkedListNode::insert_tail(head, pool.node(i));
+            }
+
+            let order: Vec<_> = Nodes::new(head).map(|node| pool.index_of(node as *mut _)).collect();
+            assert_eq!(order, [3, 1, 4]);
+
+            // iterating leaves the nodes in the list
+            assert_eq!(LinkedListNode::get_size(head), 3);
+        }
+    }
+
+    #[test]
+    fn bounded_count() {
+        let mut pool = Pool::new();
+        let head = pool.head();
+
+        unsafe {
+            LinkedListNode::init_head(head);
+            assert_eq!(LinkedListNode::count_up_to(head, 2), 0);
+
+            for i in 0..POOL_LEN {
+                LinkedListNode::insert_tail(head, pool.node(i));
+            }
+
+            assert_eq!(LinkedListNode::count_up_to(head, 2), 2);
+            assert_eq!(LinkedListNode::count_up_to(head, POOL_LEN + 1), POOL_LEN);
+        }
+    }
+
+    #[test]
+    fn lifo_order() {
+        let mut pool = Pool::new();
+        let head = pool.head();
+
+        unsafe {
+            LinkedListNode::init_head(head);
+            for i in 0..POOL_LEN {
+                LinkedListNode::insert_head(head, pool.node(i));
+            }
+
+            let mut order = Vec::new();
+            while let Some(node) = LinkedListNode::remove_head(head) {
+                order.push(pool.index_of(node));
+            }
+
+            assert_eq!(order, [4, 3, 2, 1, 0]);
+        }
+    }
+
+    #[test]
+    fn remove_tail() {
+        let mut pool = Pool::new();
+        let head = pool.head();
+
+        unsafe {
+            LinkedListNode::init_head(head);
+            for i in 0..3 {
+                LinkedListNode::insert_tail(head, pool.node(i));
+            }
+
+            assert_eq!(LinkedListNode::remove_tail(head), Some(pool.node(2)));
+            assert_eq!(LinkedListNode::remove_head(head), Some(pool.node(0)));
+            assert_eq!(LinkedListNode::remove_tail(head), Some(pool.node(1)));
+            assert_eq!(LinkedListNode::check(head), Ok(0));
+        }
+    }
+
+    #[test]
+    fn recycle_buffers() {
+        let mut free = Pool::new();
+        let mut queue = LinkedListNode::default();
+        let free_head = free.head();
+        let queue_head: *mut LinkedListNode = &mut queue;
+
+        unsafe {
+            LinkedListNode::init_head(free_head);
+            LinkedListNode::init_head(queue_head);
+            for i in 0..POOL_LEN {
+                LinkedListNode::insert_tail(free_head, free.node(i));
+            }
+
+            // Wrap around the pool several times, moving buffers between the free and event queues
+            for round in 0..3 * POOL_LEN {
+                let node = LinkedListNode::remove_head(free_head).unwrap();
+                assert_eq!(free.index_of(node), round % POOL_LEN);
+
+                LinkedListNode::insert_tail(queue_head, node);
+                assert_eq!(LinkedListNode::get_size(queue_head), 1);
+                assert_eq!(LinkedListNode::get_size(free_head), POOL_LEN - 1);
+
+                let node = LinkedListNode::remove_head(queue_head).unwrap();
+                LinkedListNode::insert_tail(free_head, node);
+                assert_eq!(LinkedListNode::get_size(free_head), POOL_LEN);
+            }
+
+            // Exhaust the pool
+            for _ in 0..POOL_LEN {
+                let node = LinkedListNode::remove_head(free_head).unwrap();
+                LinkedListNode::insert_tail(queue_head, node);
+            }
+            assert_eq!(LinkedListNode::remove_head(free_head), None);
+            assert_eq!(LinkedListNode::get_size(queue_head), POOL_LEN);
+        }
+    }
+
+    #[test]
+    fn checked_handle() {
+        let mut pool = Pool::new();
+        let head = pool.head();
+
+        unsafe {
+            LinkedListNode::init_head(head);
+            let list = LinkedList::from_head(head);
+
+            assert_eq!(list.is_empty(), Ok(true));
+            list.push_back(pool.node(0)).unwrap();
+            list.push_back(pool.node(1)).unwrap();
+            assert_eq!(list.push_back(pool.node(0)), Err(LinkedListError::AlreadyLinked));
+            assert!(list.contains(pool.node(1)));
+            assert!(!list.contains(pool.node(2)));
+            assert_eq!(list.len(), Ok(2));
+
+            assert_eq!(list.pop_front(), Ok(Some(pool.node(0))));
+            assert_eq!(list.pop_front(), Ok(Some(pool.node(1))));
+            assert_eq!(list.pop_front(), Ok(None));
+        }
+    }
+
+    #[test]
+    fn detect_corruption() {
+        let mut pool = Pool::new();
+        let head = pool.head();
+
+        unsafe {
+            LinkedListNode::init_head(head);
+            LinkedListNode::insert_tail(head, pool.node(0));
+            LinkedListNode::insert_tail(head, pool.node(1));
+
+            // Break the back link of the second node
+            (*pool.node(1)).prev = pool.node(3);
+
+            assert_eq!(LinkedListNode::check(head), Err(LinkedListError::Corrupted));
+            assert_eq!(LinkedList::from_head(head).pop_front(), Err(LinkedListError::Corrupted));
+        }
     }
 }
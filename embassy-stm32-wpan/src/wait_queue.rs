@@ -0,0 +1,237 @@
+//! Tasks waiting for a condition, in any number
+
+use core::cell::{RefCell, UnsafeCell};
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::pin::Pin;
+use core::ptr;
+use core::task::{Context, Poll, Waker};
+
+use embassy_sync::blocking_mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+/// Queue of the tasks waiting for a condition, all woken when it may have changed
+///
+/// Each waiting future holds the entry of its task, linked into the queue while the future is
+/// pending, so the number of waiting tasks is not bounded by a fixed-size registration. A task is only
+/// registered once it found the condition false, and stays so until woken or dropped: polling it again
+/// does not evict another task.
+pub(crate) struct WaitQueue {
+    list: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<List>>,
+}
+
+struct List {
+    head: *mut Waiter,
+}
+
+// SAFETY: the waiters are only accessed with the mutex held
+unsafe impl Send for List {}
+
+/// Entry of a waiting task, owned by its [`WaitUntil`] future
+struct Waiter {
+    waker: Option<Waker>,
+    prev: *mut Waiter,
+    next: *mut Waiter,
+    linked: bool,
+}
+
+impl WaitQueue {
+    pub(crate) const fn new() -> Self {
+        Self {
+            list: blocking_mutex::Mutex::new(RefCell::new(List { head: ptr::null_mut() })),
+        }
+    }
+
+    /// Wait until `done` returns true, which is checked again each time the queue is woken
+    pub(crate) fn wait_until<F: FnMut() -> bool>(&self, done: F) -> WaitUntil<'_, F> {
+        WaitUntil {
+            queue: self,
+            done,
+            waiter: UnsafeCell::new(Waiter {
+                waker: None,
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+                linked: false,
+            }),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Wake all the waiting tasks, which register again if their condition is still false
+    pub(crate) fn wake_all(&self) {
+        self.list.lock(|list| {
+            let mut list = list.borrow_mut();
+            let mut node = core::mem::replace(&mut list.head, ptr::null_mut());
+
+            while !node.is_null() {
+                // SAFETY: linked waiters are alive, they unlink themselves before being dropped
+                let waiter = unsafe { &mut *node };
+                node = waiter.next;
+
+                waiter.prev = ptr::null_mut();
+                waiter.next = ptr::null_mut();
+                waiter.linked = false;
+                if let Some(waker) = waiter.waker.take() {
+                    waker.wake();
+                }
+            }
+        })
+    }
+
+    /// Link `waiter` if it is not yet, to be woken through `waker`
+    ///
+    /// SAFETY: `waiter` must stay at the same address until removed
+    unsafe fn insert(&self, waiter: *mut Waiter, waker: &Waker) {
+        self.list.lock(|list| {
+            let mut list = list.borrow_mut();
+            let entry = unsafe { &mut *waiter };
+
+            match &entry.waker {
+                Some(registered) if registered.will_wake(waker) => {}
+                _ => entry.waker = Some(waker.clone()),
+            }
+
+            if !entry.linked {
+                entry.prev = ptr::null_mut();
+                entry.next = list.head;
+                if !list.head.is_null() {
+                    unsafe { (*list.head).prev = waiter };
+                }
+                list.head = waiter;
+                entry.linked = true;
+            }
+        })
+    }
+
+    /// Unlink `waiter` if it is linked
+    ///
+    /// SAFETY: `waiter` must point to a live waiter, of this queue if linked
+    unsafe fn remove(&self, waiter: *mut Waiter) {
+        self.list.lock(|list| {
+            let mut list = list.borrow_mut();
+            let entry = unsafe { &mut *waiter };
+
+            if entry.linked {
+                if entry.prev.is_null() {
+                    list.head = entry.next;
+                } else {
+                    unsafe { (*entry.prev).next = entry.next };
+                }
+                if !entry.next.is_null() {
+                    unsafe { (*entry.next).prev = entry.prev };
+                }
+
+                entry.prev = ptr::null_mut();
+                entry.next = ptr::null_mut();
+                entry.linked = false;
+            }
+            entry.waker = None;
+        })
+    }
+}
+
+/// Future returned by [`WaitQueue::wait_until`]
+pub(crate) struct WaitUntil<'a, F> {
+    queue: &'a WaitQueue,
+    done: F,
+    waiter: UnsafeCell<Waiter>,
+    _pinned: PhantomPinned,
+}
+
+impl<F: FnMut() -> bool> Future for WaitUntil<'_, F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // SAFETY: nothing is moved out of the future, whose waiter is unlinked before it is dropped
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if !(this.done)() {
+            unsafe { this.queue.insert(this.waiter.get(), cx.waker()) };
+
+            // the condition may have changed before the task was registered
+            if !(this.done)() {
+                return Poll::Pending;
+            }
+        }
+
+        unsafe { this.queue.remove(this.waiter.get()) };
+
+        Poll::Ready(())
+    }
+}
+
+impl<F> Drop for WaitUntil<'_, F> {
+    fn drop(&mut self) {
+        unsafe { self.queue.remove(self.waiter.get()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::vec::Vec;
+
+    use super::*;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn wake_every_waiter_once() {
+        let queue = WaitQueue::new();
+        let ready = AtomicBool::new(false);
+        let counts: Vec<_> = (0..8).map(|_| Arc::new(CountingWaker(AtomicUsize::new(0)))).collect();
+        let wakers: Vec<Waker> = counts.iter().map(|count| Waker::from(count.clone())).collect();
+        let mut futures: Vec<_> = (0..8)
+            .map(|_| Box::pin(queue.wait_until(|| ready.load(Ordering::Relaxed))))
+            .collect();
+
+        // polling again does not evict the other waiters, nor wake them
+        for _ in 0..2 {
+            for (future, waker) in futures.iter_mut().zip(&wakers) {
+                assert!(future.as_mut().poll(&mut Context::from_waker(waker)).is_pending());
+            }
+        }
+        assert!(counts.iter().all(|count| count.0.load(Ordering::Relaxed) == 0));
+
+        // a cancelled waiter is not woken
+        futures.remove(3);
+        queue.wake_all();
+        for (i, count) in counts.iter().enumerate() {
+            assert_eq!(count.0.load(Ordering::Relaxed), (i != 3) as usize);
+        }
+
+        // woken waiters whose condition is still false wait again
+        assert!(futures[0]
+            .as_mut()
+            .poll(&mut Context::from_waker(&wakers[0]))
+            .is_pending());
+        ready.store(true, Ordering::Relaxed);
+        queue.wake_all();
+        assert_eq!(counts[0].0.load(Ordering::Relaxed), 2);
+        assert_eq!(counts[1].0.load(Ordering::Relaxed), 1);
+        assert!(futures[1]
+            .as_mut()
+            .poll(&mut Context::from_waker(&wakers[1]))
+            .is_ready());
+    }
+
+    #[test]
+    fn ready_without_registering() {
+        let queue = WaitQueue::new();
+        let waker = Waker::from(Arc::new(CountingWaker(AtomicUsize::new(0))));
+
+        assert!(pin!(queue.wait_until(|| true))
+            .poll(&mut Context::from_waker(&waker))
+            .is_ready());
+        queue.list.lock(|list| assert!(list.borrow().head.is_null()));
+    }
+}
@@ -100,8 +100,31 @@ pub struct Ipcc;
 
 impl Ipcc {
     /// Enable IPCC.
-    pub fn enable(_config: Config) {
+    ///
+    /// The tasks waiting on a channel are woken up, since the reset masked the interrupts they were
+    /// waiting for: they check their channel again and unmask it.
+    pub fn enable(config: Config) {
         rcc::enable_and_reset::<IPCC>();
+        Self::start(config);
+
+        IPCC::state().wake_all();
+    }
+
+    /// Enable IPCC without resetting it, when CPU2 kept running while CPU1 restarted.
+    ///
+    /// The channel flags set before the restart are kept, so that the commands and events in flight are
+    /// not seen as handled by either CPU.
+    pub fn reattach(config: Config) {
+        rcc::enable_without_reset::<IPCC>();
+        Self::start(config);
+    }
+
+    /// Returns true if CPU2 has been allowed to boot, by [`Ipcc::enable`] or before CPU1 restarted.
+    pub fn is_cpu2_enabled() -> bool {
+        IPCC::is_cpu2_enabled()
+    }
+
+    fn start(_config: Config) {
         IPCC::set_cpu2(true);
 
         // set RF wake-up clock = LSE
@@ -136,6 +159,26 @@ impl Ipcc {
         regs.cpu(0).scr().write(|w| w.set_chs(channel as usize, true));
     }
 
+    /// Send data to an IPCC channel if it is clear, without waiting.
+    ///
+    /// Returns false, without calling the closure, if the other core has not read the previous data yet.
+    pub fn try_send(channel: IpccChannel, f: impl FnOnce()) -> bool {
+        let regs = IPCC::regs();
+
+        if regs.cpu(0).sr().read().chf(channel as usize) {
+            return false;
+        }
+
+        f();
+
+        compiler_fence(Ordering::SeqCst);
+
+        trace!("ipcc: ch {}: send data", channel as u8);
+        regs.cpu(0).scr().write(|w| w.set_chs(channel as usize, true));
+
+        true
+    }
+
     /// Wait for the tx channel to become clear
     pub async fn flush(channel: IpccChannel) {
         let regs = IPCC::regs();
@@ -164,6 +207,16 @@ impl Ipcc {
         .await;
     }
 
+    /// Whether the tx channel is occupied, i.e. data was sent that the other core has not read yet
+    pub fn is_tx_pending(channel: IpccChannel) -> bool {
+        IPCC::regs().cpu(0).sr().read().chf(channel as usize)
+    }
+
+    /// Whether the rx channel is occupied, i.e. the other core sent data that has not been read yet
+    pub fn is_rx_pending(channel: IpccChannel) -> bool {
+        IPCC::regs().cpu(1).sr().read().chf(channel as usize)
+    }
+
     /// Receive data from an IPCC channel. The closure is called to read the data when appropriate.
     pub async fn receive<R>(channel: IpccChannel, mut f: impl FnMut() -> Option<R>) -> R {
         let regs = IPCC::regs();
@@ -216,6 +269,10 @@ impl SealedInstance for crate::peripherals::IPCC {
         crate::pac::PWR.cr4().modify(|w| w.set_c2boot(enabled));
     }
 
+    fn is_cpu2_enabled() -> bool {
+        crate::pac::PWR.cr4().read().c2boot()
+    }
+
     fn state() -> &'static State {
         static STATE: State = State::new();
         &STATE
@@ -237,6 +294,12 @@ impl State {
         }
     }
 
+    fn wake_all(&self) {
+        for waker in self.rx_wakers.iter().chain(self.tx_wakers.iter()) {
+            waker.wake();
+        }
+    }
+
     const fn rx_waker_for(&self, channel: IpccChannel) -> &AtomicWaker {
         match channel {
             IpccChannel::Channel1 => &self.rx_wakers[0],
@@ -263,5 +326,6 @@ impl State {
 trait SealedInstance: crate::rcc::RccPeripheral {
     fn regs() -> crate::pac::ipcc::Ipcc;
     fn set_cpu2(enabled: bool);
+    fn is_cpu2_enabled() -> bool;
     fn state() -> &'static State;
 }
@@ -135,7 +135,11 @@ impl RccInfo {
     }
 
     // TODO: should this be `unsafe`?
-    pub(crate) fn enable_and_reset_with_cs(&self, _cs: CriticalSection) {
+    pub(crate) fn enable_and_reset_with_cs(&self, cs: CriticalSection) {
+        self.enable_with_cs(cs, true)
+    }
+
+    fn enable_with_cs(&self, _cs: CriticalSection, reset: bool) {
         if self.refcount_idx_or_0xff != 0xff {
             let refcount_idx = self.refcount_idx_or_0xff as usize;
 
@@ -164,7 +168,7 @@ impl RccInfo {
         }
 
         // set the xxxRST bit
-        let reset_ptr = self.reset_ptr();
+        let reset_ptr = self.reset_ptr().filter(|_| reset);
         if let Some(reset_ptr) = reset_ptr {
             unsafe {
                 let val = reset_ptr.read_volatile();
@@ -240,6 +244,11 @@ impl RccInfo {
         critical_section::with(|cs| self.enable_and_reset_with_cs(cs))
     }
 
+    // TODO: should this be `unsafe`?
+    pub(crate) fn enable_without_reset(&self) {
+        critical_section::with(|cs| self.enable_with_cs(cs, false))
+    }
+
     // TODO: should this be `unsafe`?
     pub(crate) fn disable(&self) {
         critical_section::with(|cs| self.disable_with_cs(cs))
@@ -327,6 +336,12 @@ pub fn enable_and_reset<T: RccPeripheral>() {
     T::RCC_INFO.enable_and_reset();
 }
 
+/// Enables peripheral `T` without resetting it, keeping the state of its registers.
+// TODO: should this be `unsafe`?
+pub fn enable_without_reset<T: RccPeripheral>() {
+    T::RCC_INFO.enable_without_reset();
+}
+
 /// Disables peripheral `T`.
 ///
 /// # Safety
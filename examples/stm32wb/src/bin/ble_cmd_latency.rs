@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::DWT;
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_stm32::bind_interrupts;
+use embassy_stm32::ipcc::{Config, ReceiveInterruptHandler, TransmitInterruptHandler};
+use embassy_stm32::rcc::WPAN_DEFAULT;
+use embassy_stm32_wpan::ble::opcodes::HciOpcode;
+use embassy_stm32_wpan::TlMbox;
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs{
+    IPCC_C1_RX => ReceiveInterruptHandler;
+    IPCC_C1_TX => TransmitInterruptHandler;
+});
+
+const ROUNDS: u32 = 100;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    /*
+        Measure the cost of submitting BLE commands, in CPU1 cycles.
+
+        This needs the BLE wireless stack, see the tl_mbox_ble example. "submit" is the time spent writing
+        the command buffer and setting the IPCC channel, which is done without a critical section. "round
+        trip" adds the time CPU2 takes to answer, which dominates.
+    */
+
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
+    let mut config = embassy_stm32::Config::default();
+    config.rcc = WPAN_DEFAULT;
+    let p = embassy_stm32::init(config);
+
+    let config = Config::default();
+    let mbox = TlMbox::init(p.IPCC, Irqs, config);
+
+    let image = mbox.sys_subsystem.wait_ready().await;
+    info!("CPU2 ready: {}", image);
+
+    let _ = mbox.sys_subsystem.shci_c2_ble_init(Default::default()).await;
+    mbox.ble_subsystem.command(HciOpcode::Reset, &[]).await.unwrap();
+
+    let mut submit = 0;
+    let mut round_trip = 0;
+    for _ in 0..ROUNDS {
+        let start = DWT::cycle_count();
+        mbox.ble_subsystem
+            .tl_write(HciOpcode::ReadLocalVersionInformation as u16, &[])
+            .await
+            .unwrap();
+        submit += DWT::cycle_count().wrapping_sub(start);
+
+        // wait for the answer before sending the next command
+        let _ = mbox.ble_subsystem.tl_read().await;
+
+        let start = DWT::cycle_count();
+        mbox.ble_subsystem.read_local_version().await.unwrap();
+        round_trip += DWT::cycle_count().wrapping_sub(start);
+    }
+
+    info!("submit: {} cycles", submit / ROUNDS);
+    info!("round trip: {} cycles", round_trip / ROUNDS);
+
+    info!("Test OK");
+    cortex_m::asm::bkpt();
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_stm32::bind_interrupts;
+use embassy_stm32::ipcc::{Config, ReceiveInterruptHandler, TransmitInterruptHandler};
+use embassy_stm32::rcc::WPAN_DEFAULT;
+use embassy_stm32_wpan::ble::typedefs::ScanConfig;
+use embassy_stm32_wpan::TlMbox;
+use embassy_time::{Duration, Ticker};
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs{
+    IPCC_C1_RX => ReceiveInterruptHandler;
+    IPCC_C1_TX => TransmitInterruptHandler;
+});
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    /*
+        Wait for BLE events and other work at the same time.
+
+        This needs the BLE wireless stack, see the tl_mbox_ble example. The events are read with a
+        timeout first, then with `select` against a ticker. Dropping the pending read when the other
+        future completes does not lose an event: events are only taken from the queue when the read
+        completes.
+    */
+
+    let mut config = embassy_stm32::Config::default();
+    config.rcc = WPAN_DEFAULT;
+    let p = embassy_stm32::init(config);
+
+    let config = Config::default();
+    let mbox = TlMbox::init(p.IPCC, Irqs, config);
+    let ble = &mbox.ble_subsystem;
+
+    let image = mbox.sys_subsystem.wait_ready().await;
+    info!("CPU2 ready: {}", image);
+
+    let _ = mbox.sys_subsystem.shci_c2_ble_init(Default::default()).await;
+    unwrap!(ble.hci_reset_and_configure().await);
+
+    // nothing happens until the scan starts
+    match ble.read_timeout(Duration::from_millis(500)).await {
+        Some(evt) => info!("unexpected event {:x}", evt.stub().evt_code),
+        None => info!("no event for 500 ms"),
+    }
+
+    // the advertising reports are counted from the raw events rather than read from the stream
+    drop(unwrap!(ble.start_scan(&ScanConfig::default()).await));
+
+    let mut ticker = Ticker::every(Duration::from_secs(1));
+    let mut events = 0;
+    for _ in 0..10 {
+        loop {
+            match select(ble.tl_read(), ticker.next()).await {
+                Either::First(_evt) => events += 1,
+                Either::Second(()) => break,
+            }
+        }
+
+        info!("{} events received", events);
+    }
+
+    unwrap!(ble.stop_scan().await);
+
+    info!("Test OK");
+    cortex_m::asm::bkpt();
+}
@@ -7,7 +7,6 @@ use embassy_stm32::bind_interrupts;
 use embassy_stm32::ipcc::{Config, ReceiveInterruptHandler, TransmitInterruptHandler};
 use embassy_stm32::rcc::WPAN_DEFAULT;
 use embassy_stm32_wpan::TlMbox;
-use embassy_time::Timer;
 use {defmt_rtt as _, panic_probe as _};
 
 bind_interrupts!(struct Irqs{
@@ -48,28 +47,24 @@ async fn main(_spawner: Spawner) {
     let config = Config::default();
     let mbox = TlMbox::init(p.IPCC, Irqs, config);
 
-    loop {
-        let wireless_fw_info = mbox.sys_subsystem.wireless_fw_info();
-        match wireless_fw_info {
-            None => info!("not yet initialized"),
-            Some(fw_info) => {
-                let version_major = fw_info.version_major();
-                let version_minor = fw_info.version_minor();
-                let subversion = fw_info.subversion();
+    let image = mbox.sys_subsystem.wait_ready().await;
+    info!("CPU2 running {}", image);
 
-                let sram2a_size = fw_info.sram2a_size();
-                let sram2b_size = fw_info.sram2b_size();
+    match mbox.sys_subsystem.wireless_fw_info() {
+        None => info!("no wireless stack"),
+        Some(fw_info) => {
+            let version_major = fw_info.version_major();
+            let version_minor = fw_info.version_minor();
+            let subversion = fw_info.subversion();
 
-                info!(
-                    "version {}.{}.{} - SRAM2a {} - SRAM2b {}",
-                    version_major, version_minor, subversion, sram2a_size, sram2b_size
-                );
+            let sram2a_size = fw_info.sram2a_size();
+            let sram2b_size = fw_info.sram2b_size();
 
-                break;
-            }
+            info!(
+                "version {}.{}.{} - SRAM2a {} - SRAM2b {}",
+                version_major, version_minor, subversion, sram2a_size, sram2b_size
+            );
         }
-
-        Timer::after_millis(50).await;
     }
 
     info!("Test OK");
@@ -47,13 +47,13 @@ async fn main(_spawner: Spawner) {
     let config = Config::default();
     let mbox = TlMbox::init(p.IPCC, Irqs, config);
 
-    let sys_event = mbox.sys_subsystem.read().await;
-    info!("sys event: {}", sys_event.payload());
+    let image = mbox.sys_subsystem.wait_ready().await;
+    info!("CPU2 ready: {}", image);
 
     let _ = mbox.sys_subsystem.shci_c2_ble_init(Default::default()).await;
 
     info!("starting ble...");
-    mbox.ble_subsystem.tl_write(0x0c, &[]).await;
+    mbox.ble_subsystem.tl_write(0x0c, &[]).await.unwrap();
 
     info!("waiting for ble...");
     let ble_event = mbox.ble_subsystem.tl_read().await;
@@ -49,8 +49,8 @@ async fn main(spawner: Spawner) {
 
     spawner.spawn(run_mm_queue(mbox.mm_subsystem)).unwrap();
 
-    let sys_event = mbox.sys_subsystem.read().await;
-    info!("sys event: {}", sys_event.payload());
+    let image = mbox.sys_subsystem.wait_ready().await;
+    info!("CPU2 ready: {}", image);
 
     let fw_info = mbox.sys_subsystem.wireless_fw_info().unwrap();
     let version_major = fw_info.version_major();